@@ -218,7 +218,13 @@ suspend fun CalendarPermissionScope.newCalendar(name: String, color: Color): Int
                         "${CalendarContract.Events.AVAILABILITY_FREE}," +
                         "${CalendarContract.Events.AVAILABILITY_TENTATIVE}"
             )
-            this.put(CalendarContract.Calendars.ALLOWED_ATTENDEE_TYPES, CalendarContract.Attendees.TYPE_NONE)
+            this.put(
+                CalendarContract.Calendars.ALLOWED_ATTENDEE_TYPES,
+                "${CalendarContract.Attendees.TYPE_NONE}," +
+                        "${CalendarContract.Attendees.TYPE_REQUIRED}," +
+                        "${CalendarContract.Attendees.TYPE_OPTIONAL}," +
+                        "${CalendarContract.Attendees.TYPE_RESOURCE}"
+            )
         }) ?: run {
             Log.e("newCalendar", "Failed to add calendar \"$name\"")
             return@newCalendar null
@@ -328,9 +334,11 @@ suspend fun CalendarPermissionScope.copyExternalCalendar(calendar: ExternalUserC
     }
 
     // Get events before writing calendar in case there is an error
+    // Exceptions are sorted after the recurring events (ORIGINAL_ID is NULL for those), so the ID of their copied event is already known.
     val eventsCursor = this.context.getCursor<CopyEventsProjection>(
         CalendarContract.Events.CONTENT_URI,
-        "(${CalendarContract.Events.CALENDAR_ID} = ?)", arrayOf(calendar.id.toString())
+        "(${CalendarContract.Events.CALENDAR_ID} = ?)", arrayOf(calendar.id.toString()),
+        sort = "${CalendarContract.Events.ORIGINAL_ID} ASC"
     ) ?: run {
         Log.e("copyExternalCalendar", "Error getting Events for Calendar with ID ${calendar.id} and name \"${calendar.name}\" in Content Provider")
         return null
@@ -359,21 +367,34 @@ suspend fun CalendarPermissionScope.copyExternalCalendar(calendar: ExternalUserC
             return null
         }
 
+    // Maps the IDs of the Events in the copied Calendar to the IDs of the new Events.
+    val copiedEventIds = mutableMapOf<Long, Long>()
     while (eventsCursor.moveToNext()) {
         val oldEventId = eventsCursor.getString(CopyCalendarsProjection.ID.ordinal)
+        val data = eventsCursor.loadRowData<CopyEventsProjection>().apply {
+            this.remove(CopyEventsProjection.ID.column)
+            this.put(CalendarContract.Events.CALENDAR_ID, newCalId)
+        }
+        // If event is an exception, link it to the copy of the recurring event it belongs to
+        val originalId = eventsCursor.getLongOrNull(CopyEventsProjection.ORIGINAL_ID.ordinal)
+        if (originalId != null) {
+            val copiedId = copiedEventIds[originalId]
+            if (copiedId == null) {
+                Log.e("copyExternalCalendar", "Recurring Event with ID $originalId of exception $oldEventId was not copied.")
+                continue
+            }
+            data.put(CalendarContract.Events.ORIGINAL_ID, copiedId)
+        }
         // -- COPY EVENTS from the copied Calendar
         val eventId = client.insert(
             CalendarContract.Events.CONTENT_URI.asSyncAdapter(accountName),
-            eventsCursor.loadRowData<CopyEventsProjection>().apply {
-                this.remove(CopyEventsProjection.ID.column)
-                this.put(CalendarContract.Events.CALENDAR_ID, newCalId)
-                // TODO: If event is an exception, find the ID of the copied event for which this will become an exception, and write it to ORIGINAL_ID
-            }
+            data
         ) ?.let { uri -> ContentUris.parseId(uri) }
         if (eventId == null) {
             Log.e("copyExternalCalendar", "Failed to insert Events from copied data.")
             continue
         }
+        copiedEventIds[oldEventId.toLong()] = eventId
 
         // -- COPY REMINDERS from the copied Event
         this.context.getCursor<CopyRemindersProjection>(
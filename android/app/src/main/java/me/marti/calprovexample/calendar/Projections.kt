@@ -107,19 +107,22 @@ internal enum class CopyEventsProjection(override val column: String): Projectio
     R_DATE(CalendarContract.Events.RDATE),
     EX_RULE(CalendarContract.Events.EXRULE),
     EX_DATE(CalendarContract.Events.EXDATE),
-    // TODO: If event is an exception, find the ID of the copied event for which this will become an exception.
-    // ORIGINAL_ID(CalendarContract.Events.ORIGINAL_ID),
+    // If the event is an exception, this is the ID of the recurring event it belongs to, which must be replaced by the ID of the copied event.
+    ORIGINAL_ID(CalendarContract.Events.ORIGINAL_ID),
+    // Sync IDs belong to the account of the calendar, so they are not copied.
     // ORIGINAL_SYNC_ID(CalendarContract.Events.ORIGINAL_SYNC_ID),
-    // ORIGINAL_INSTANCE_TIME(CalendarContract.Events.ORIGINAL_INSTANCE_TIME),
-    // ORIGINAL_ALL_DAY(CalendarContract.Events.ORIGINAL_ALL_DAY),
+    ORIGINAL_INSTANCE_TIME(CalendarContract.Events.ORIGINAL_INSTANCE_TIME),
+    ORIGINAL_ALL_DAY(CalendarContract.Events.ORIGINAL_ALL_DAY),
     ACCESS_LEVEL(CalendarContract.Events.ACCESS_LEVEL),
     AVAILABILITY(CalendarContract.Events.AVAILABILITY),
+    // Cancelled exceptions remove an instance of the recurring event.
+    STATUS(CalendarContract.Events.STATUS),
     GUESTS_CAN_MODIFY(CalendarContract.Events.GUESTS_CAN_MODIFY),
     GUESTS_CAN_INVITE_OTHERS(CalendarContract.Events.GUESTS_CAN_INVITE_OTHERS),
     GUESTS_CAN_SEE_GUESTS(CalendarContract.Events.GUESTS_CAN_SEE_GUESTS),
     // CalendarContract.Events.CUSTOM_APP_PACKAGE
     // CalendarContract.Events.CUSTOM_APP_URI
-    // CalendarContract.Events.UID_2445
+    UID(CalendarContract.Events.UID_2445),
 }
 
 internal enum class CopyRemindersProjection(override val column: String): ProjectionEntry {
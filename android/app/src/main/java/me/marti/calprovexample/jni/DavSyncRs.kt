@@ -16,13 +16,16 @@ import me.marti.calprovexample.launch
 import me.marti.calprovexample.ui.AsyncDialog
 import me.marti.calprovexample.ui.CalendarPermissionScope
 import me.marti.calprovexample.ui.DEFAULT_CALENDAR_COLOR
+import me.marti.calprovexample.ui.EventConflictAction
 import me.marti.calprovexample.ui.ImportFileExistsAction
 import me.marti.calprovexample.ui.MainActivity
 import me.marti.calprovexample.ui.calendarWorkThread
 import java.io.File as Path
 
 /** Rust functions that can be called from Java.
- * All the extern functions declared in this class are defined in `project root/rust/src/lib.rs` */
+ * All the extern functions declared in this class are defined in `project root/rust/src/lib.rs`
+ *
+ * The functions throw a [DavSyncException] when they fail. */
 @Suppress("FunctionName")
 object DavSyncRs {
     init { System.loadLibrary("davsync") }
@@ -34,24 +37,52 @@ object DavSyncRs {
     external fun merge_dirs(activity: MainActivity, externalDirUri: Uri)
 
     /** Copy file's content into the internal *app's directory*.
+     * Calendar files are copied to the `calendars` directory and contacts files to the `contacts` directory,
+     * depending on the type detected from the file's content, MIME type, and extension.
      *
-     * After a *successful* call to this function,
-     * the caller should create a file in the external directory and call [`import_file_external()`].
+     * After a *successful* call to this function, the caller must call [import_file_external] to finish the import.
+     * Otherwise, the import is undone the next time the App starts (see [recover_journal]),
+     * and every other file operation throws [DavSyncException.Conflict] until then.
      *
      * @param fileUri is the *Document Uri* of the file to be imported.
      * @param fileName If not `NULL`, the file will be imported with this name instead of the *fileName* of [fileUri].
      *
-     * @return Returns [ImportFileResult.FileExists] if the file couldn't be imported because a file with that name already exists in the local directory. */
+     * @return Returns [ImportFileResult.FileExists] if the file couldn't be imported because a file with that name already exists in the local directory,
+     * and [ImportFileResult.Unsupported] if the file is neither a calendar nor a contacts file. */
     external fun import_file_internal(context: Context, fileUri: Uri, fileName: String? = null): ImportFileResult
 
-    /** Copy a file named **file_name** from the *internal directory* to the **external directory** in Shared Storage. */
-    external fun import_file_external(context: Context, fileName: String, externalDirUri: Uri)
+    /** Import a spreadsheet in CSV as a new calendar file in the internal *app's directory*, converting each row to an event.
+     * Like with [import_file_internal], the caller must call [import_file_external] to finish the import.
+     *
+     * @param columns are the names of the columns of the CSV header that have the fields of the events.
+     *
+     * @return The result of the import, and the errors of the rows that were skipped because they couldn't be converted. */
+    external fun import_csv_internal(context: Context, fileUri: Uri, fileName: String?, columns: CsvColumns = CsvColumns()): CsvImportResult
+
+    /** Finish importing a file named [fileName] that was copied to the *internal directory* by [import_file_internal].
+     * The file is copied to the **external directory** in Shared Storage (if [externalDirUri] is not `NULL`) and its data is written to the *Content Provider*.
+     *
+     * Calendar files are only written if [perm] is not `NULL`, and Contacts files only if the `WRITE_CONTACTS` permission was granted.
+     * If any of these steps fails, the whole import is undone. */
+    external fun import_file_external(context: Context, perm: CalendarPermissionScope?, fileName: String, externalDirUri: Uri?)
 
-    /** Create the files in internal and external storage for a new Calendar the user created.
+    /** Create the files in internal and external storage for a new Calendar the user created,
+     * and create the Calendar in the *Content Provider* if [perm] is not `NULL`.
      *
      * If [externalDirUri] is **`NULL`**, only the file in app storage will be created.
+     * If any of these steps fails, the files and Calendar that were created are deleted.
      * @param fileName is the name of the file that will be created in each directory (e.g. `"name.ics"`). */
-    external fun create_calendar_files(context: Context, fileName: String, color: Color, externalDirUri: Uri?)
+    external fun create_calendar_files(context: Context, perm: CalendarPermissionScope?, fileName: String, color: Color, externalDirUri: Uri?)
+    /** Delete the Calendar with [name] from the *Content Provider* and delete its files,
+     * putting a copy of the internal file in the *recycle bin* (the `deleted` directory) so that it can be restored.
+     *
+     * If any of these steps fails, everything that was deleted is restored. */
+    external fun delete_calendar_files(perm: CalendarPermissionScope, name: String, externalDirUri: Uri?)
+    /** Undo the file operation (e.g. an import) that was interrupted the last time the App ran, or finish it if it was complete.
+     *
+     * Must be called when the App starts, before the files and *Content Provider* are read.
+     * Calendars deleted by the interrupted operation are only restored if [perm] is not `NULL`. */
+    external fun recover_journal(context: Context, perm: CalendarPermissionScope?)
 
     // /** Create a new Calendar entry in the Content Provider by reading the contents of a calendar file.
     //  * This function will find the file in the [internal directory][Context.getFilesDir].
@@ -60,9 +91,92 @@ object DavSyncRs {
     // @Throws(ElementExistsException::class)
     // external fun new_calendar_from_file(context: Context, name: String): InternalUserCalendar
 
-    external fun write_calendar_data_to_file(name: String)
+    /** Read the data of the Calendar with [name] from the *Content Provider* and write it to the Calendar's file
+     * in the **internal** directory, and in the **external** directory if [externalDirUri] is not `NULL`. */
+    external fun write_calendar_data_to_file(perm: CalendarPermissionScope, name: String, externalDirUri: Uri?)
     external fun write_file_data_to_calendar(perm: CalendarPermissionScope, name: String, color: Color = Color(DEFAULT_CALENDAR_COLOR))
-    external fun write_color_to_calendar_file(name: String, color: Color)
+
+    /** Build an *iTIP* `REQUEST` for the event with [uid] in the Calendar with [name], to send to its attendees after it changed.
+     * The `SEQUENCE` of the event is incremented.
+     *
+     * Like the other iTIP functions that change the Calendar, its file is written the same way as [write_calendar_data_to_file]:
+     * also to the **external** directory if [externalDirUri] is not `NULL`, and the changes are uploaded the next time the Calendar is synced.
+     * @return The message, as the content of a `.ics` file that can be shared. */
+    external fun itip_request(perm: CalendarPermissionScope, name: String, uid: String, externalDirUri: Uri?): String
+    /** Build an *iTIP* `CANCEL` for the event with [uid] in the Calendar with [name] (or only its instance with [recurrenceId]),
+     * and mark the event as cancelled.
+     * @return The message, as the content of a `.ics` file that can be shared. */
+    external fun itip_cancel(perm: CalendarPermissionScope, name: String, uid: String, recurrenceId: String?, externalDirUri: Uri?): String
+    /** Apply an *iTIP* [message] (a `REPLY`, `COUNTER` or `DECLINECOUNTER`) to the Calendar with [name].
+     * @return A description of the message that can be shown to the user. */
+    external fun process_itip_message(perm: CalendarPermissionScope, name: String, message: String, externalDirUri: Uri?): String
+    /** Apply the changes proposed in the *iTIP* `COUNTER` [message] to the Calendar with [name].
+     * @return The `REQUEST` to send to the attendees, as the content of a `.ics` file. */
+    external fun itip_accept_counter(perm: CalendarPermissionScope, name: String, message: String, externalDirUri: Uri?): String
+    /** Build the *iTIP* `DECLINECOUNTER` that rejects the `COUNTER` [message] about an event in the Calendar with [name].
+     * @return The message, as the content of a `.ics` file that can be shared. */
+    external fun itip_decline_counter(context: Context, name: String, message: String): String
+
+    /** Build a `VFREEBUSY` with the times between [from] and [to] (in milliseconds since the epoch)
+     * when the user is busy because of the events of the Calendars with [names].
+     * Only the busy times are included, not the events.
+     * @return The content of a `.ics` file that can be shared. */
+    external fun free_busy_from_calendars(context: Context, names: Array<String>, from: Long, to: Long): String
+
+    /** Write the Calendar with [name] to the file at [fileUri] (e.g. one the user picked with `ACTION_CREATE_DOCUMENT`) in a [format]:
+     * `"ics"` (*iCalendar*), `"json"` (*jCal*) or `"xml"` (*xCal*). */
+    external fun export_calendar(context: Context, name: String, format: String, fileUri: Uri)
+
+    /** Write the events of the Calendar with [name] to the file at [fileUri] as a spreadsheet in CSV,
+     * with the default [CsvColumns] so that it can be imported back. */
+    external fun export_calendar_csv(context: Context, name: String, fileUri: Uri)
+
+    /** Set the color of the Calendar with [name] in its file in the **internal** directory,
+     * and in the **external** directory if [externalDirUri] is not `NULL`. */
+    external fun write_color_to_calendar_file(context: Context, name: String, color: Color, externalDirUri: Uri?)
+
+    /** Sync all the calendars of the user in the *CalDAV* server at [serverUrl] with the calendar files in the **internal** directory.
+     *
+     * Each calendar collection is stored in the file named with the collection's *display name*,
+     * followed by the last segment of its URL if another collection has the same name.
+     * The data of the files is **not** written to the *Content Provider*.
+     * The changes in the outbox of each calendar are uploaded first, asking the user which version to keep when they conflict.
+     * The resources that changed in the server are downloaded in batches of [multigetBatchSize] (or 50 if it is not positive).
+     *
+     * The credentials of the account are read from [store], and refreshed *OAuth 2.0* tokens are saved back to it. */
+    external fun sync_caldav(context: Context, serverUrl: String, store: DavCredentialStore, multigetBatchSize: Int = 50)
+
+    /** Create a read-only Calendar with [name] that mirrors the feed at [url] (`webcal://`, `https://` or `http://`),
+     * e.g. public holidays. If the feed doesn't have a color, the Calendar is created with [color].
+     *
+     * If any of the steps fails, the file and the Calendar that were created are deleted. */
+    external fun subscribe_calendar(perm: CalendarPermissionScope, name: String, url: String, color: Color?)
+    /** Fetch the feed of the Calendar with [name] (see [subscribe_calendar]),
+     * and if it changed, replace the Calendar's file and its events in the *Content Provider*.
+     * @return Whether the feed had changed.
+     * @throws DavSyncException.NotFound if the Calendar is not subscribed to a feed. */
+    external fun refresh_subscription(perm: CalendarPermissionScope, name: String): Boolean
+
+    /** Read the *Contacts file* (`.vcf`) with [name] and write its contacts to the Contacts *Content Provider*,
+     * replacing the contacts that were previously written from that file.
+     *
+     * Requires the `WRITE_CONTACTS` permission. */
+    external fun write_file_data_to_contacts(context: Context, name: String)
+    /** Read the contacts that came from the *Contacts file* with [name] from the *Content Provider* and write them to the file
+     * in the **internal** directory, and in the **external** directory if [externalDirUri] is not `NULL`.
+     *
+     * Requires the `READ_CONTACTS` permission. */
+    external fun write_contacts_data_to_file(context: Context, name: String, externalDirUri: Uri?)
+
+    /** Sync all the address books of the user in the *CardDAV* server at [serverUrl] with the contacts files in the **internal** directory.
+     *
+     * Each address book collection is stored in the file named with the collection's *display name*,
+     * followed by the last segment of its URL if another collection has the same name.
+     * The data of the files is **not** written to the *Content Provider*.
+     * The resources that changed in the server are downloaded in batches of [multigetBatchSize] (or 50 if it is not positive).
+     *
+     * The credentials of the account are read from [store], and refreshed *OAuth 2.0* tokens are saved back to it. */
+    external fun sync_carddav(context: Context, serverUrl: String, store: DavCredentialStore, multigetBatchSize: Int = 50)
 }
 
 @Suppress("unused")
@@ -105,6 +219,30 @@ fun showFileConflictDialog(calName: String): FileConflictResponse {
         response
     }
 }
+sealed class EventConflictResponse {
+    data object Canceled: EventConflictResponse()
+    data object KeepInternal: EventConflictResponse()
+    data object KeepExternal: EventConflictResponse()
+}
+/** Show a [Dialog][AsyncDialog] asking the user which version of an *event* they want to keep
+ * when it was changed in both the **internal** and **external** *calendar files* since they were last synced.
+ *
+ * This function *blocks* the thread until the user responds. */
+fun showEventConflictDialog(calName: String, eventSummary: String): EventConflictResponse {
+    return runBlocking {
+        var response: EventConflictResponse = EventConflictResponse.Canceled
+        AsyncDialog.promptDialog { close ->
+            EventConflictAction(
+                calName = calName,
+                eventSummary = eventSummary,
+                keepInternal = { response = EventConflictResponse.KeepInternal },
+                keepExternal = { response = EventConflictResponse.KeepExternal },
+                close = close,
+            )
+        }
+        response
+    }
+}
 /** Shows a `SnackBar` to the user as a result of them choosing [`OverWrite`][FileConflictResponse.OverWrite] in [showFileConflictDialog].
  *
  * This is only called by [DavSyncRs.merge_dirs], and when [MainActivity.userCalendars] is `null`. */
@@ -134,15 +272,65 @@ fun MainActivity.showOverwriteSnackBar(deletedFilePath: String, internalFilePath
 
 /** Result from calling Native function [DavSyncRs.import_file_internal].
  *
- * [code] is the return code from the Native function.
  * `calName` is the name of the imported Calendar as it should appear in the Content Provider.
- * If [code] is:
- * * **`0`**, there was an error and an Exception was was thrown.
- * * **`1`**, the file was imported successfully.
- * * **`2`**, an imported calendar with that name already exists, ask user to *overwrite* or *pick another name*. */
+ * `fileName` is the name the file was imported with, whose extension is `.ics` for calendar files and `.vcf` for contacts files.
+ * * **[Error]**, there was an error and an Exception was was thrown.
+ * * **[Success]**, the file was imported successfully.
+ * * **[FileExists]**, an imported calendar with that name already exists, ask user to *overwrite* or *pick another name*.
+ * * **[Unsupported]**, the file was not imported because it is neither a calendar nor a contacts file. */
 @Suppress("ConvertObjectToDataObject")
 sealed class ImportFileResult {
     object Error: ImportFileResult()
-    class Success(val calName: String): ImportFileResult()
-    class FileExists(val calName: String): ImportFileResult()
+    class Success(val calName: String, val fileName: String): ImportFileResult()
+    class FileExists(val calName: String, val fileName: String): ImportFileResult()
+    class Unsupported(val fileName: String): ImportFileResult()
+}
+
+/** The names of the columns of a CSV header that have the fields of the events, used by [DavSyncRs.import_csv_internal].
+ * Only [title] and [start] are required; the other columns are not used if they are `null`.
+ *
+ * Dates are `YYYY-MM-DD` and times are `HH:MM[:SS]`. The [end] date of all-day events is inclusive.
+ * If there is no [allDay] column, events whose start has no time are all-day. */
+data class CsvColumns(
+    val title: String = "Title",
+    val start: String = "Start",
+    val end: String? = "End",
+    val allDay: String? = "All day",
+    val location: String? = "Location",
+    val description: String? = "Description",
+    val timezone: String? = "Time zone",
+)
+
+/** Result from calling Native function [DavSyncRs.import_csv_internal]:
+ * the [result] of the import (like [DavSyncRs.import_file_internal]), and the errors of the rows that were not imported (e.g. `"Row 3: The title is empty"`). */
+class CsvImportResult(val result: ImportFileResult, val rowErrors: Array<String>)
+
+/** How [DavSyncRs.sync_caldav] and [DavSyncRs.sync_carddav] authenticate with the server of an account.
+ * * **[Basic]** sends the username and password with every request.
+ * * **[Digest]** only sends a hash of the password, after the server asks for it.
+ * * **[OAuth2]** sends the [accessToken][OAuth2.accessToken], and gets a new one from the [tokenUrl][OAuth2.tokenUrl] with the [refreshToken][OAuth2.refreshToken] when it expires.
+ *   [expiresAt][OAuth2.expiresAt] is in seconds since the epoch, or `0` if it's unknown.
+ *
+ * The secrets must not be logged, so these classes don't have a `toString()` that shows them. */
+sealed class DavAuth {
+    class Basic(val username: String, val password: String): DavAuth()
+    class Digest(val username: String, val password: String): DavAuth()
+    class OAuth2(
+        val accessToken: String,
+        val expiresAt: Long = 0,
+        val refreshToken: String?,
+        val tokenUrl: String,
+        val clientId: String,
+        val clientSecret: String? = null,
+    ): DavAuth()
+}
+
+/** Where the credentials of an account are kept (e.g. encrypted with a key in the *Android Keystore*).
+ * The native functions read them with [load] only when they connect to the server, instead of getting them as arguments.
+ * @see KeystoreCredentialStore */
+interface DavCredentialStore {
+    fun load(): DavAuth
+    /** Replace the tokens of a [DavAuth.OAuth2] account after the access token was refreshed.
+     * The [refreshToken] can be a new one if the authorization server rotates them, so it must be saved too. */
+    fun saveOAuth2Tokens(accessToken: String, expiresAt: Long, refreshToken: String)
 }
@@ -63,8 +63,14 @@ public final class DavSyncRsHelpers {
             throw new RuntimeException("Unreachable: utf-8 is a valid encoding");
         }
     }
-//    /** Returns the Uri of the Document's parent Document */
-//    static @NonNull Uri docUriParent(@NonNull Uri docUri) {
-//
-//    }
+    /** Returns the Uri of the Document's parent Document (in the same Document Tree). */
+    static @NonNull Uri docUriParent(@NonNull Uri docUri) {
+        String docId = DocumentsContract.getDocumentId(docUri);
+        int end = docId.lastIndexOf('/');
+        // Documents at the root of the storage volume only have the "primary:" part left
+        String parentId = end == -1
+            ? docId.substring(0, docId.indexOf(':') + 1)
+            : docId.substring(0, end);
+        return DocumentsContract.buildDocumentUriUsingTree(docUri, parentId);
+    }
 }
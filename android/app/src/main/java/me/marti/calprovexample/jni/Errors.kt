@@ -0,0 +1,24 @@
+package me.marti.calprovexample.jni
+
+/** Exceptions thrown by the native functions in [DavSyncRs] when they fail.
+ * Each subclass corresponds to a variant of the `Error` enum in `rust/src/error.rs`. */
+sealed class DavSyncException(message: String): Exception(message) {
+    /** Reading or writing a file or directory failed. */
+    class Io(message: String): DavSyncException(message)
+    /** A *Document Uri* (or a URL) is not valid, or doesn't point to what was expected (e.g. a directory). */
+    class InvalidUri(message: String): DavSyncException(message)
+    /** The App is not allowed to access a *Content Provider*, a file, or a server. */
+    class Permission(message: String): DavSyncException(message)
+    /** The content of a file (or of a row of the *Content Provider*) could not be parsed. */
+    class Parse(message: String): DavSyncException(message)
+    /** A file or calendar conflicts with one that already exists. */
+    class Conflict(message: String): DavSyncException(message)
+    /** A file, calendar or subscription that the operation needs does not exist. */
+    class NotFound(message: String): DavSyncException(message)
+    /** A query to a *Content Provider* failed. */
+    class Provider(message: String): DavSyncException(message)
+    /** Couldn't communicate with a *CalDAV*/*CardDAV* server. */
+    class Network(message: String): DavSyncException(message)
+    /** A call between Rust and Java through the JNI failed. */
+    class Jni(message: String): DavSyncException(message)
+}
@@ -0,0 +1,111 @@
+package me.marti.calprovexample.jni
+
+import android.content.Context
+import android.security.keystore.KeyGenParameterSpec
+import android.security.keystore.KeyProperties
+import android.util.Base64
+import androidx.core.content.edit
+import org.json.JSONObject
+import java.security.KeyStore
+import javax.crypto.Cipher
+import javax.crypto.KeyGenerator
+import javax.crypto.SecretKey
+import javax.crypto.spec.GCMParameterSpec
+
+/** A [DavCredentialStore] that keeps the credentials of the [account] in the `DavCredentials` *`SharedPreferences`*,
+ * encrypted with an *AES* key that never leaves the *Android Keystore*.
+ *
+ * The credentials are first stored with [save] (e.g. after the user signs in), and can then be passed to [DavSyncRs.sync_caldav] and [DavSyncRs.sync_carddav]. */
+class KeystoreCredentialStore(context: Context, private val account: String): DavCredentialStore {
+    private val preferences = context.getSharedPreferences("DavCredentials", Context.MODE_PRIVATE)
+
+    /** Replace the credentials of the [account]. */
+    fun save(auth: DavAuth) {
+        val json = when (auth) {
+            is DavAuth.Basic -> JSONObject()
+                .put("type", "basic")
+                .put("username", auth.username)
+                .put("password", auth.password)
+            is DavAuth.Digest -> JSONObject()
+                .put("type", "digest")
+                .put("username", auth.username)
+                .put("password", auth.password)
+            is DavAuth.OAuth2 -> JSONObject()
+                .put("type", "oauth2")
+                .put("accessToken", auth.accessToken)
+                .put("expiresAt", auth.expiresAt)
+                .put("refreshToken", auth.refreshToken)
+                .put("tokenUrl", auth.tokenUrl)
+                .put("clientId", auth.clientId)
+                .put("clientSecret", auth.clientSecret)
+        }
+        this.preferences.edit { this.putString(account, encrypt(json.toString())) }
+    }
+
+    /** Delete the credentials of the [account] (e.g. when the user signs out). */
+    fun remove() {
+        this.preferences.edit { this.remove(account) }
+    }
+
+    /** @throws DavSyncException.Permission if there are no credentials for the [account]. */
+    override fun load(): DavAuth {
+        val encrypted = this.preferences.getString(account, null)
+            ?: throw DavSyncException.Permission("There are no credentials for account \"$account\"")
+        val json = JSONObject(decrypt(encrypted))
+        return when (val type = json.getString("type")) {
+            "basic" -> DavAuth.Basic(json.getString("username"), json.getString("password"))
+            "digest" -> DavAuth.Digest(json.getString("username"), json.getString("password"))
+            "oauth2" -> DavAuth.OAuth2(
+                accessToken = json.getString("accessToken"),
+                expiresAt = json.optLong("expiresAt", 0),
+                refreshToken = json.optStringOrNull("refreshToken"),
+                tokenUrl = json.getString("tokenUrl"),
+                clientId = json.getString("clientId"),
+                clientSecret = json.optStringOrNull("clientSecret"),
+            )
+            else -> throw DavSyncException.Parse("Unknown type of credentials \"$type\" for account \"$account\"")
+        }
+    }
+
+    override fun saveOAuth2Tokens(accessToken: String, expiresAt: Long, refreshToken: String) {
+        val auth = this.load() as? DavAuth.OAuth2
+            ?: throw DavSyncException.Conflict("Account \"$account\" does not use OAuth 2.0")
+        this.save(DavAuth.OAuth2(accessToken, expiresAt, refreshToken, auth.tokenUrl, auth.clientId, auth.clientSecret))
+    }
+
+    private companion object {
+        const val KEY_ALIAS = "DavCredentials"
+        const val TRANSFORMATION = "AES/GCM/NoPadding"
+        const val IV_LENGTH = 12
+
+        /** Get the key that encrypts the credentials, creating it the first time. */
+        fun key(): SecretKey {
+            val keyStore = KeyStore.getInstance("AndroidKeyStore").apply { this.load(null) }
+            (keyStore.getKey(KEY_ALIAS, null) as? SecretKey)?.let { return it }
+
+            val generator = KeyGenerator.getInstance(KeyProperties.KEY_ALGORITHM_AES, "AndroidKeyStore")
+            generator.init(KeyGenParameterSpec.Builder(KEY_ALIAS, KeyProperties.PURPOSE_ENCRYPT or KeyProperties.PURPOSE_DECRYPT)
+                .setBlockModes(KeyProperties.BLOCK_MODE_GCM)
+                .setEncryptionPaddings(KeyProperties.ENCRYPTION_PADDING_NONE)
+                .build())
+            return generator.generateKey()
+        }
+
+        /** @return The IV followed by the ciphertext, in *Base64*. */
+        fun encrypt(plaintext: String): String {
+            val cipher = Cipher.getInstance(TRANSFORMATION).apply { this.init(Cipher.ENCRYPT_MODE, key()) }
+            return Base64.encodeToString(cipher.iv + cipher.doFinal(plaintext.toByteArray()), Base64.NO_WRAP)
+        }
+
+        fun decrypt(encrypted: String): String {
+            val bytes = Base64.decode(encrypted, Base64.NO_WRAP)
+            val cipher = Cipher.getInstance(TRANSFORMATION).apply {
+                this.init(Cipher.DECRYPT_MODE, key(), GCMParameterSpec(128, bytes, 0, IV_LENGTH))
+            }
+            return String(cipher.doFinal(bytes, IV_LENGTH, bytes.size - IV_LENGTH))
+        }
+
+        /** `null` if the value is missing or `null`, unlike [JSONObject.optString], which returns `"null"`. */
+        fun JSONObject.optStringOrNull(name: String): String? = if (this.isNull(name)) null else this.getString(name)
+    }
+}
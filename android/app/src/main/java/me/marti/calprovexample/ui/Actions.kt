@@ -396,6 +396,44 @@ fun ImportFileExistsAction(
     }
 }
 
+/** A dialog that prompts the user which version of an event to keep
+ * when it was changed in both the **internal** and **external** calendar files.
+ *
+ * @param calName The name of the calendar the event belongs to.
+ * @param eventSummary The title of the event (or its UID if it has none).
+ * @param keepInternal The user decided to keep the event as it is in the internal file.
+ * @param keepExternal The user decided to keep the event as it is in the external file. */
+@Composable
+fun EventConflictAction(
+    modifier: Modifier = Modifier,
+    calName: String,
+    eventSummary: String,
+    keepInternal: () -> Unit,
+    keepExternal: () -> Unit,
+    close: () -> Unit
+) {
+    AlertDialog(
+        modifier = modifier,
+        onDismissRequest = close,
+        text = { Text("The event \"$eventSummary\" of calendar \"$calName\" was changed in both the internal and external files. Which version do you want to keep?") },
+        confirmButton = {
+            Row(Modifier.fillMaxWidth(), horizontalArrangement = Arrangement.SpaceBetween) {
+                TextButton(onClick = close) { Text("Cancel") }
+                Row {
+                    TextButton(onClick = {
+                        close()
+                        keepInternal()
+                    }) { Text("Keep internal") }
+                    TextButton(onClick = {
+                        close()
+                        keepExternal()
+                    }) { Text("Keep external") }
+                }
+            }
+        },
+    )
+}
+
 @Composable
 private fun ColorPickerDialog(
     modifier: Modifier = Modifier,
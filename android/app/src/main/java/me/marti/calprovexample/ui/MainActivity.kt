@@ -30,7 +30,6 @@ import androidx.compose.runtime.rememberCoroutineScope
 import androidx.compose.runtime.saveable.rememberSaveable
 import androidx.compose.runtime.setValue
 import androidx.core.net.toUri
-import androidx.documentfile.provider.DocumentFile
 import androidx.navigation.compose.NavHost
 import androidx.navigation.compose.composable
 import androidx.navigation.compose.rememberNavController
@@ -39,6 +38,7 @@ import kotlinx.coroutines.channels.Channel
 import kotlinx.coroutines.launch
 import me.marti.calprovexample.BooleanUserPreference
 import me.marti.calprovexample.Color
+import me.marti.calprovexample.jni.DavSyncException
 import me.marti.calprovexample.jni.DavSyncRs
 import me.marti.calprovexample.ElementExistsException
 import me.marti.calprovexample.ILLEGAL_FILE_CHARACTERS
@@ -54,12 +54,10 @@ import me.marti.calprovexample.calendar.AllData
 import me.marti.calprovexample.calendar.ExternalUserCalendar
 import me.marti.calprovexample.calendar.InternalUserCalendar
 import me.marti.calprovexample.calendar.copyExternalCalendar
-import me.marti.calprovexample.calendar.deleteCalendarByName
 import me.marti.calprovexample.calendar.editCalendar
 import me.marti.calprovexample.calendar.externalUserCalendars
 import me.marti.calprovexample.calendar.getData
 import me.marti.calprovexample.calendar.internalUserCalendars
-import me.marti.calprovexample.calendar.newCalendar
 import me.marti.calprovexample.destinationDir
 import me.marti.calprovexample.externalFile
 import me.marti.calprovexample.fileNameWithoutExtension
@@ -171,6 +169,14 @@ class MainActivity : ComponentActivity() {
         }
     }
 
+    /** Finish importing a *Contacts file* that was already copied to the internal directory by [DavSyncRs.import_file_internal],
+     * and write its contacts to the Contacts *Content Provider*.
+     *
+     * The contacts are only written if the user has granted the `WRITE_CONTACTS` permission. */
+    internal fun importContactsFile(fileName: String) {
+        DavSyncRs.import_file_external(this.baseContext, null, fileName, this.syncDir.value)
+    }
+
     override fun onCreate(savedInstanceState: Bundle?) {
         // Must set navigationBarStyle to remove the scrim.
         enableEdgeToEdge(navigationBarStyle = SystemBarStyle.light(0, 0))
@@ -180,6 +186,11 @@ class MainActivity : ComponentActivity() {
         calendarWorkThread.execute {
             calendarWorkThreadId = Thread.currentThread().id
         }
+        // Finish or undo a file operation that was interrupted when the App was closed.
+        // Runs before anything else in the calendarWorkThread reads the files.
+        calendarWorkThread.launch {
+            DavSyncRs.recover_journal(this.baseContext, this.calendarPermission.usePermission())
+        }
 
         val fragmentCals = BooleanUserPreference(PreferenceKey.FRAGMENT_CALS)
 
@@ -354,8 +365,9 @@ class MainActivity : ComponentActivity() {
                     // Tell the user they will select the file to import
                     AsyncDialog.promptConfirm("Select file to import")
                 }
-                // The ACTION_OPEN_DOCUMENT Intent takes the MIME Types of files that can be opened
-                importFilesIntent.launch(CALENDAR_DOCUMENT_MIME_TYPE)
+                // The GetMultipleContents Intent only takes one MIME Type, so allow all files to also import Contacts files.
+                // The type of each file is detected when it is imported.
+                importFilesIntent.launch("*/*")
             }
         },
     )
@@ -482,9 +494,9 @@ class MutableCalendarsList(
                         tmpCal
 
                 this.perm.copyExternalCalendar(cal)?.let { newCal ->
-                    // Create the files
-                    DavSyncRs.create_calendar_files(activity.baseContext, "${newCal.name}.ics", newCal.color, activity.syncDir.value)
-                    DavSyncRs.write_calendar_data_to_file(newCal.name)
+                    // Create the files (the Calendar was already created in the Content Provider)
+                    DavSyncRs.create_calendar_files(activity.baseContext, null, "${newCal.name}.ics", newCal.color, activity.syncDir.value)
+                    DavSyncRs.write_calendar_data_to_file(this.perm, newCal.name, activity.syncDir.value)
                     // Add the Calendar to the list
                     this.list.add(newCal)
                 } ?: throw Exception("Error copying calendar ${cal.name}")
@@ -532,9 +544,9 @@ class MutableCalendarsList(
                     return@launch
                 }
             }
-            // Change color in files
+            // Change color in files (which were already renamed)
             if (old.color != new.color)
-                DavSyncRs.write_color_to_calendar_file(old.name, new.color)
+                DavSyncRs.write_color_to_calendar_file(activity, new.name, new.color, activity.syncDir.value)
 
             // Change data in the Content Provider
             if (!this.perm.editCalendar(old.id, new.name, new.color, new.sync))
@@ -553,15 +565,12 @@ class MutableCalendarsList(
             if (this.indexOfFirst { e -> e.name == name } != -1)
                 throw ElementExistsException(element.name)
 
-            // Create calendar files
-            DavSyncRs.create_calendar_files(activity.baseContext, "$name.ics", element.color, activity.syncDir.value)
-            // Create entry in Content Provider
-            this.perm.newCalendar(name, element.color)?.let { newCal ->
-                // Add Calendar to the list
-                this.list.add(newCal)
-            } ?: throw Exception("Error creating new calendar")
-            // Fill data in Content Provider
-            DavSyncRs.write_file_data_to_calendar(this.perm, name)
+            // Create calendar files and entry in Content Provider
+            DavSyncRs.create_calendar_files(activity.baseContext, this.perm, "$name.ics", element.color, activity.syncDir.value)
+            // Add Calendar to the list
+            this.list.add(this.perm.getData(name)
+                ?: throw Exception("Error getting data of newly created Calendar")
+            )
         }
     }
 
@@ -575,13 +584,27 @@ class MutableCalendarsList(
             // Import to internal file first
             val result = DavSyncRs.import_file_internal(activity.baseContext, fileUri)
 
+            // Contacts files are not added to the list of Calendars
+            if (result is ImportFileResult.Success && destinationDir(result.fileName) == "contacts") {
+                activity.importContactsFile(result.fileName)
+                return@launch
+            }
+
             val name = when (result) {
                 is ImportFileResult.Success -> result.calName
                 is ImportFileResult.Error -> {
                     showToast(importErrorToast)
                     return@launch
                 }
+                is ImportFileResult.Unsupported -> {
+                    showToast("\"${result.fileName}\" is not a calendar or contacts file")
+                    return@launch
+                }
                 is ImportFileResult.FileExists -> {
+                    if (destinationDir(result.fileName) == "contacts") {
+                        showToast("Contacts file \"${result.calName}\" was already imported")
+                        return@launch
+                    }
                     // In case of conflict, ask user whether to rename, overwrite, or don't import at all
                     var finalName: String = result.calName
                     var choice = 0
@@ -601,7 +624,7 @@ class MutableCalendarsList(
 
                     // Retry import under new conditions
                     when (DavSyncRs.import_file_internal(activity.baseContext, fileUri, "$finalName.ics")) {
-                        is ImportFileResult.Error -> {
+                        is ImportFileResult.Error, is ImportFileResult.Unsupported -> {
                             showToast(importErrorToast)
                             return@launch
                         }
@@ -619,14 +642,8 @@ class MutableCalendarsList(
             val fileName = "$name.ics"
 
             // TODO: check if file is in syncDir. if it's not, create external file, otherwise don't
-            // Create external file
-            activity.syncDir.value?.let { syncDir ->
-                DavSyncRs.import_file_external(activity.baseContext, fileName, syncDir)
-            } ?: Log.w("createFiles", "syncDir is NULL; Can't add external file; Will add it later")
-            // Create entry in Content Provider
-            // Color doesn't matter, as it will be assigned in writeFileDataToCalendar
-            this.perm.newCalendar(name, Color(0)) ?: throw Exception("Error creating Calendar from File")
-            DavSyncRs.write_file_data_to_calendar(this.perm, name)
+            // Create external file and entry in Content Provider
+            DavSyncRs.import_file_external(activity.baseContext, this.perm, fileName, activity.syncDir.value)
             // Add Calendar to the list
             this.list.add(this.perm.getData(name)
                 ?: throw Exception("Error getting data of newly added Calendar")
@@ -640,55 +657,19 @@ class MutableCalendarsList(
      *
      * @return The element that was removed
      * @throws NoSuchElementException if there is no calendar with this [name].
-     * @throws IOException if the element could not be removed because of some internal error.
-     * (e.g. deleting files, content provider, etc.) */
+     * @throws DavSyncException if the element could not be removed because of some internal error
+     * (e.g. deleting files, content provider, etc.), in which case nothing is removed. */
     @Suppress("RedundantSuspendModifier")
-    @Throws(NoSuchElementException::class, IOException::class)
+    @Throws(NoSuchElementException::class, DavSyncException::class)
     private suspend fun removeBlocking(name: String) {
         val index = this.indexOfFirst { cal -> cal.name == name }
         if (index == -1)
             throw NoSuchElementException("There is no calendar named \"$name\"")
-        val fileName = "$name.ics"
-        val dest = destinationDir(fileName)
-        val internalFile = Path("${activity.filesDir}/$dest/$fileName")
-        val deletedFile = Path("${activity.filesDir}/deleted/$dest/$fileName")
 
+        // Delete the Calendar from the Content Provider and the files, copying the internal file to the recycle bin
+        DavSyncRs.delete_calendar_files(this.perm, name, activity.syncDir.value)
         // Delete the Calendar from the list
         this.list.removeAt(index)
-
-        // Put this first in case there is a bug where the file doesn't exist, the entry will no longer be showed.
-        // Delete the Calendar from the Content Provider
-        if (!this.perm.deleteCalendarByName(name))
-            throw IOException("Calendar \"$name\" not removed from Content Provider.")
-
-        // -- Copy internal file to recycle bin before deleting everything
-        try {
-            internalFile.copyTo(deletedFile, true)
-        } catch (e: Exception) {
-            throw IOException("Error copying \"$fileName\" to \"deleted/$dest/\": $e")
-        }
-        // -- Delete the Calendar files
-        // Delete from App's internal storage
-        try {
-            if (!internalFile.delete())
-                throw Exception("Unknown Reason")
-        } catch (e: Exception) {
-            throw IOException("Error deleting file in Internal Directory: $e")
-        }
-        // Delete from syncDir in shared storage.
-        try {
-            val syncDir = activity.syncDir.value ?: run {
-                Log.w("deleteFiles", "syncDir is NULL; Can't delete external file")
-                return
-            }
-            DocumentFile.fromTreeUri(activity.baseContext, syncDir)!!
-                .findFile(dest)!!
-                .findFile(fileName)
-                ?.delete()
-                ?: Log.w("deleteFiles", "Tried deleting external file \"$fileName\", but it did not exist.")
-        } catch (e: Exception) {
-            throw IOException("Error deleting external file: $e")
-        }
     }
 
     /** Fully delete the Calendar, that is, delete the copy of the file that was moved to the "recycle bin". */
@@ -727,20 +708,14 @@ class MutableCalendarsList(
         } catch (e: Exception) {
             throw IOException("Error copying \"$fileName\" from \"deleted/$dest/\" to \"$dest/\": $e")
         }
-        // Copy file to external dir
-        DavSyncRs.import_file_external(activity.baseContext, fileName, activity.syncDir.value ?: run {
-            Log.w("MutableCalendarList.restore", "syncDir is NULL; can't create external file")
-            return
-        })
+        // Copy file to external dir, create entry in Content Provider and parse the file's content
+        DavSyncRs.import_file_external(activity.baseContext, this.perm, fileName, activity.syncDir.value)
         // File in recycle bin is no longer needed
         deletedFile.delete()
-        // Create entry in Content Provider ...
-        this.perm.newCalendar(name, Color(DEFAULT_CALENDAR_COLOR))?.let { newCal ->
-            // Add the Calendar to the list
-            this.list.add(newCal)
-        } ?: throw Exception("Error creating new calendar")
-        // ... and parse the file's content
-        DavSyncRs.write_file_data_to_calendar(this.perm, name)
+        // Add the Calendar to the list
+        this.list.add(this.perm.getData(name)
+            ?: throw Exception("Error getting data of restored Calendar")
+        )
     }
 
     // MutableMap and List overrides
@@ -6,6 +6,7 @@ import android.provider.DocumentsContract
 import android.util.Log
 import androidx.core.net.toUri
 import kotlinx.coroutines.runBlocking
+import me.marti.calprovexample.jni.DavSyncException
 import me.marti.calprovexample.ui.MainActivity
 import me.marti.calprovexample.ui.AsyncDialog
 import me.marti.calprovexample.ui.isOnWorkThread
@@ -155,6 +156,10 @@ fun ExecutorService.launch(msg: String? = null, command: suspend () -> Unit) {
             msg?.let { AsyncDialog.suspendMessage(it) }
             try {
                 command()
+            } catch (e: DavSyncException) {
+                // Errors from the native functions have a message that can be shown to the user
+                Log.e("WorkerThread", e.stackTraceToString())
+                showToast(e.message ?: "Error occurred")
             } catch (e: Throwable) {
                 Log.e("WorkerThread", e.stackTraceToString())
                 msg?.let {
@@ -177,7 +182,7 @@ fun fileNameWithoutExtension(fileName: String): String {
 /** Determines whether file should go to *`calendars`* or *`contacts`* based on the file's extension.
  * @throws Exception if the extension is for neither calendars or contacts. */
 fun destinationDir(fileName: String): String {
-    val errorMsg = "Invalid fileName \"$fileName\". Should have extension \".ics\" or \".vcard\"."
+    val errorMsg = "Invalid fileName \"$fileName\". Should have extension \".ics\", \".vcf\" or \".vcard\"."
     val extension = try {
         fileName.split('.').last()
     } catch (e: NoSuchElementException) {
@@ -186,7 +191,7 @@ fun destinationDir(fileName: String): String {
 
     return when (extension) {
         "ics" -> "calendars"
-        "vcard" -> "contacts"
+        "vcf", "vcard" -> "contacts"
         else -> throw Exception(errorMsg)
     }
 }
@@ -0,0 +1,349 @@
+//! Conversion between `VALARM` components and rows of the *Reminders* table.
+//!
+//! The Content Provider only stores the number of *minutes before the start* of the event and a *method*,
+//! so absolute and end-relative `TRIGGER`s are converted to minutes before the start when the alarms are written to the provider.
+use crate::provider::Row;
+use super::{
+    event::{date_property, Event},
+    ical::{Component, Property},
+    projections::{calendars, reminders},
+    time::{CalTime, Duration, TimeZoneResolver},
+};
+
+/// The `ACTION` of an alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Display,
+    Email,
+    Audio,
+}
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "DISPLAY" => Some(Self::Display),
+            "EMAIL" => Some(Self::Email),
+            "AUDIO" => Some(Self::Audio),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Display => "DISPLAY",
+            Self::Email => "EMAIL",
+            Self::Audio => "AUDIO",
+        }
+    }
+
+    /// The value of [`reminders::METHOD`] for this action.
+    pub fn method(&self) -> i64 {
+        match self {
+            Self::Display => reminders::METHOD_ALERT,
+            Self::Email => reminders::METHOD_EMAIL,
+            Self::Audio => reminders::METHOD_ALARM,
+        }
+    }
+
+    /// Inverse of [`Self::method()`].
+    ///
+    /// `METHOD_DEFAULT` and `METHOD_SMS` have no equivalent action, so they become [`Display`](Self::Display).
+    pub fn from_method(method: i64) -> Self {
+        match method {
+            reminders::METHOD_EMAIL => Self::Email,
+            reminders::METHOD_ALARM => Self::Audio,
+            _ => Self::Display,
+        }
+    }
+}
+
+/// The `TRIGGER` of an alarm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// A duration relative to the start of the event (or its end if **related_end**), which is negative if the alarm is before.
+    Relative { seconds: i64, related_end: bool },
+    /// A date-time in UTC (`VALUE=DATE-TIME`).
+    Absolute(CalTime),
+}
+impl Trigger {
+    /// Parse a `TRIGGER` property, using its `VALUE` and `RELATED` parameters.
+    pub fn parse(prop: &Property) -> Option<Self> {
+        let is_date_time = prop.param("VALUE").is_some_and(|value| value.eq_ignore_ascii_case("DATE-TIME"));
+        if is_date_time {
+            return date_property(prop).map(Self::Absolute);
+        }
+        Some(Self::Relative {
+            seconds: Duration::parse(&prop.value)?.as_seconds(),
+            related_end: prop.param("RELATED").is_some_and(|related| related.eq_ignore_ascii_case("END")),
+        })
+    }
+
+    pub fn to_property(&self) -> Property {
+        match self {
+            Self::Relative { seconds, related_end } => {
+                let prop = Property::new("TRIGGER", Duration::from_seconds(*seconds).to_string());
+                if *related_end { prop.with_param("RELATED", "END") } else { prop }
+            },
+            Self::Absolute(time) => Property::new("TRIGGER", time.value()).with_param("VALUE", "DATE-TIME"),
+        }
+    }
+}
+
+/// The data of a `VALARM` component that can be stored in the *Reminders* table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    pub action: Action,
+    pub trigger: Trigger,
+}
+impl Alarm {
+    /// Read the data of a `VALARM` component.
+    ///
+    /// Alarms with an `ACTION` the Content Provider doesn't support (e.g. `PROCEDURE`) become [`Action::Display`].
+    pub fn from_component(component: &Component) -> Result<Self, String> {
+        let trigger = component.property("TRIGGER")
+            .ok_or_else(|| "Alarm has no TRIGGER".to_string())?;
+        Ok(Self {
+            action: component.value("ACTION").and_then(Action::parse).unwrap_or(Action::Display),
+            trigger: Trigger::parse(trigger).ok_or_else(|| format!("Alarm has invalid TRIGGER {:?}", trigger.value))?,
+        })
+    }
+
+    /// Read a row of the *Reminders* table (queried with [`reminders::COPY_PROJECTION`]).
+    pub fn from_reminder(row: &Row) -> Self {
+        let minutes = row.get_long(reminders::MINUTES).unwrap_or_default();
+        Self {
+            action: Action::from_method(row.get_long(reminders::METHOD).unwrap_or(reminders::METHOD_DEFAULT)),
+            trigger: Trigger::Relative { seconds: -minutes * 60, related_end: false },
+        }
+    }
+
+    /// Convert to a `VALARM` component.
+    ///
+    /// **summary** is the title of the event, used for the `DESCRIPTION` (and `SUMMARY` of emails) that alarms require.
+    pub fn to_component(&self, summary: Option<&str>) -> Component {
+        let summary = summary.unwrap_or("Reminder");
+        let mut alarm = Component::new("VALARM");
+        alarm.push(self.trigger.to_property());
+        alarm.push(Property::new("ACTION", self.action.as_str()));
+        match self.action {
+            Action::Display => alarm.push(Property::text("DESCRIPTION", summary)),
+            Action::Email => {
+                alarm.push(Property::text("SUMMARY", summary));
+                alarm.push(Property::text("DESCRIPTION", summary));
+            },
+            Action::Audio => {},
+        }
+        alarm
+    }
+
+    /// The number of minutes before the start of the **event** that the alarm goes off, which is negative if the alarm is after the start.
+    ///
+    /// Parts of a minute are rounded so that the alarm goes off earlier.
+    /// Returns [`None`] if the **resolver** doesn't know the time zones of the event or the trigger.
+    pub fn minutes_before_start(&self, event: &Event, resolver: &mut dyn TimeZoneResolver) -> Option<i64> {
+        let seconds_before = match &self.trigger {
+            Trigger::Relative { seconds, related_end: false } => -seconds,
+            Trigger::Relative { seconds, related_end: true } => -(event.length(resolver)? + seconds),
+            Trigger::Absolute(time) => event.start.timestamp(resolver)? - time.timestamp(resolver)?,
+        };
+        Some((seconds_before + 59).div_euclid(60))
+    }
+}
+
+/// The number of reminders an event can have if the Calendar doesn't set [`calendars::MAX_REMINDERS`].
+const DEFAULT_MAX_REMINDERS: usize = 5;
+
+/// The limits that a Calendar sets on the Reminders of its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReminderLimits {
+    pub max: usize,
+    /// The values of [`reminders::METHOD`] that the Calendar accepts, in order of preference.
+    pub allowed: Vec<i64>,
+}
+impl ReminderLimits {
+    /// Read the limits from a row of the *Calendars* table with the [`calendars::MAX_REMINDERS`] and [`calendars::ALLOWED_REMINDERS`] columns.
+    pub fn from_row(row: &Row) -> Self {
+        let allowed = row.get_text(calendars::ALLOWED_REMINDERS)
+            .map(|allowed| allowed.split(',').filter_map(|method| method.trim().parse().ok()).collect::<Vec<_>>())
+            .filter(|allowed| !allowed.is_empty())
+            .unwrap_or_else(|| vec![
+                reminders::METHOD_DEFAULT,
+                reminders::METHOD_ALERT,
+                reminders::METHOD_EMAIL,
+                reminders::METHOD_SMS,
+                reminders::METHOD_ALARM,
+            ]);
+        Self {
+            max: row.get_long(calendars::MAX_REMINDERS)
+                .and_then(|max| usize::try_from(max).ok())
+                .unwrap_or(DEFAULT_MAX_REMINDERS),
+            allowed,
+        }
+    }
+
+    /// Convert the **alarms** of an **event** to rows of the *Reminders* table (without [`reminders::EVENT_ID`]).
+    ///
+    /// Alarms that don't fit in the limits are changed so that they do:
+    /// methods that are not allowed are folded into `METHOD_ALERT` (or the first allowed method),
+    /// alarms after the start of the event go off at the start,
+    /// and only the [`max`](Self::max) alarms closest to the start of the event are kept.
+    /// A message is returned for each of these changes so that they can be reported.
+    pub fn reminders(&self, alarms: &[Alarm], event: &Event, resolver: &mut dyn TimeZoneResolver) -> (Vec<Row>, Vec<String>) {
+        let id = event.uid.as_deref().unwrap_or("<no UID>");
+        let mut messages = Vec::new();
+        let mut reminders = Vec::<(i64, i64)>::new();
+
+        for alarm in alarms {
+            let Some(mut minutes) = alarm.minutes_before_start(event, resolver) else {
+                messages.push(format!("Alarm of event {id} has unknown time zone"));
+                continue;
+            };
+            if minutes < 0 {
+                messages.push(format!("Alarm of event {id} {} minutes after the start was moved to the start", -minutes));
+                minutes = 0;
+            }
+
+            let mut method = alarm.action.method();
+            if !self.allowed.contains(&method) {
+                let fallback = if self.allowed.contains(&reminders::METHOD_ALERT) {
+                    reminders::METHOD_ALERT
+                } else {
+                    self.allowed.first().copied().unwrap_or(reminders::METHOD_DEFAULT)
+                };
+                messages.push(format!("{} alarm of event {id} is not allowed by the calendar; using method {fallback} instead", alarm.action.as_str()));
+                method = fallback;
+            }
+
+            if !reminders.contains(&(method, minutes)) {
+                reminders.push((method, minutes));
+            }
+        }
+
+        reminders.sort_by_key(|&(_, minutes)| minutes);
+        if reminders.len() > self.max {
+            messages.push(format!("Event {id} has {} alarms, but the calendar only allows {}; the earliest ones were dropped", reminders.len(), self.max));
+            reminders.truncate(self.max);
+        }
+
+        let rows = reminders.into_iter()
+            .map(|(method, minutes)| {
+                let mut row = Row::new();
+                row.put(reminders::METHOD, method);
+                row.put(reminders::MINUTES, minutes);
+                row
+            })
+            .collect();
+        (rows, messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    /// Doesn't know any time zone.
+    struct NoTimeZones;
+    impl TimeZoneResolver for NoTimeZones {
+        fn utc_offset(&mut self, _tzid: &str, _local: i64) -> Option<i32> {
+            None
+        }
+        fn default_tzid(&mut self) -> String {
+            "UTC".to_string()
+        }
+    }
+
+    fn component(content: &str) -> Component {
+        ical::parse(content).unwrap().remove(0)
+    }
+    /// An event from 09:00 to 10:00 UTC.
+    fn event() -> Event {
+        Event::from_component(&component("BEGIN:VEVENT\r\nUID:1\r\nDTSTART:20240101T090000Z\r\nDTEND:20240101T100000Z\r\nEND:VEVENT\r\n")).unwrap()
+    }
+    fn alarm(action: &str, trigger: &str) -> Alarm {
+        Alarm::from_component(&component(&format!("BEGIN:VALARM\r\nACTION:{action}\r\n{trigger}\r\nEND:VALARM\r\n"))).unwrap()
+    }
+
+    #[test]
+    fn parse_triggers() {
+        assert_eq!(alarm("DISPLAY", "TRIGGER:-PT15M").trigger, Trigger::Relative { seconds: -900, related_end: false });
+        assert_eq!(alarm("DISPLAY", "TRIGGER;RELATED=END:PT5M").trigger, Trigger::Relative { seconds: 300, related_end: true });
+        assert!(matches!(alarm("DISPLAY", "TRIGGER;VALUE=DATE-TIME:20240101T083000Z").trigger, Trigger::Absolute(_)));
+        // Unsupported actions become DISPLAY
+        assert_eq!(alarm("PROCEDURE", "TRIGGER:-PT15M").action, Action::Display);
+        assert!(Alarm::from_component(&component("BEGIN:VALARM\r\nACTION:DISPLAY\r\nEND:VALARM\r\n")).is_err());
+        assert!(Alarm::from_component(&component("BEGIN:VALARM\r\nTRIGGER:soon\r\nEND:VALARM\r\n")).is_err());
+
+        for trigger in ["TRIGGER:-PT15M", "TRIGGER;RELATED=END:PT5M", "TRIGGER;VALUE=DATE-TIME:20240101T083000Z"] {
+            let alarm = alarm("DISPLAY", trigger);
+            assert_eq!(Trigger::parse(&alarm.trigger.to_property()), Some(alarm.trigger));
+        }
+    }
+
+    #[test]
+    fn minutes_before_start() {
+        let event = event();
+        let minutes = |trigger: &str| alarm("DISPLAY", trigger).minutes_before_start(&event, &mut NoTimeZones);
+        assert_eq!(minutes("TRIGGER:-PT15M"), Some(15));
+        assert_eq!(minutes("TRIGGER:PT0S"), Some(0));
+        assert_eq!(minutes("TRIGGER:PT10M"), Some(-10));
+        // Parts of a minute go off earlier
+        assert_eq!(minutes("TRIGGER:-PT30S"), Some(1));
+        assert_eq!(minutes("TRIGGER:PT30S"), Some(0));
+        // Relative to the end, which is an hour after the start
+        assert_eq!(minutes("TRIGGER;RELATED=END:-PT15M"), Some(-45));
+        assert_eq!(minutes("TRIGGER;RELATED=END:-PT1H15M"), Some(15));
+        assert_eq!(minutes("TRIGGER;VALUE=DATE-TIME:20240101T083000Z"), Some(30));
+
+        let zoned = Event::from_component(&component("BEGIN:VEVENT\r\nUID:2\r\nDTSTART;TZID=Mars/Olympus:20240101T090000\r\nEND:VEVENT\r\n")).unwrap();
+        assert_eq!(alarm("DISPLAY", "TRIGGER:-PT15M").minutes_before_start(&zoned, &mut NoTimeZones), Some(15));
+        assert_eq!(alarm("DISPLAY", "TRIGGER;VALUE=DATE-TIME:20240101T083000Z").minutes_before_start(&zoned, &mut NoTimeZones), None);
+    }
+
+    #[test]
+    fn reminders_round_trip() {
+        for (method, action) in [(reminders::METHOD_ALERT, Action::Display), (reminders::METHOD_EMAIL, Action::Email), (reminders::METHOD_ALARM, Action::Audio)] {
+            let mut row = Row::new();
+            row.put(reminders::METHOD, method);
+            row.put(reminders::MINUTES, 15);
+            let alarm = Alarm::from_reminder(&row);
+            assert_eq!(alarm, Alarm { action, trigger: Trigger::Relative { seconds: -900, related_end: false } });
+
+            let component = alarm.to_component(Some("Meeting"));
+            assert_eq!(component.value("DESCRIPTION"), Some("Meeting").filter(|_| action != Action::Audio));
+            assert_eq!(Alarm::from_component(&component).as_ref(), Ok(&alarm));
+            let (rows, messages) = ReminderLimits::from_row(&Row::new()).reminders(&[alarm], &event(), &mut NoTimeZones);
+            assert_eq!(rows, [row]);
+            assert!(messages.is_empty());
+        }
+        // Methods without an action
+        let mut row = Row::new();
+        row.put(reminders::METHOD, reminders::METHOD_SMS);
+        assert_eq!(Alarm::from_reminder(&row).action, Action::Display);
+    }
+
+    #[test]
+    fn reminder_limits() {
+        let mut calendar = Row::new();
+        calendar.put(calendars::MAX_REMINDERS, 2);
+        calendar.put(calendars::ALLOWED_REMINDERS, "1,2");
+        let limits = ReminderLimits::from_row(&calendar);
+        assert_eq!(limits, ReminderLimits { max: 2, allowed: vec![reminders::METHOD_ALERT, reminders::METHOD_EMAIL] });
+
+        let alarms = [
+            alarm("AUDIO", "TRIGGER:-PT10M"),
+            alarm("DISPLAY", "TRIGGER:-PT10M"),
+            alarm("EMAIL", "TRIGGER:PT30M"),
+            alarm("EMAIL", "TRIGGER:-P1D"),
+        ];
+        let (rows, messages) = limits.reminders(&alarms, &event(), &mut NoTimeZones);
+        let reminders = rows.iter()
+            .map(|row| (row.get_long(reminders::METHOD).unwrap(), row.get_long(reminders::MINUTES).unwrap()))
+            .collect::<Vec<_>>();
+        // The audio alarm became the same as the display one, and the one a day before was dropped
+        assert_eq!(reminders, [(reminders::METHOD_EMAIL, 0), (reminders::METHOD_ALERT, 10)]);
+        assert_eq!(messages, [
+            "AUDIO alarm of event 1 is not allowed by the calendar; using method 1 instead",
+            "Alarm of event 1 30 minutes after the start was moved to the start",
+            "Event 1 has 3 alarms, but the calendar only allows 2; the earliest ones were dropped",
+        ]);
+    }
+}
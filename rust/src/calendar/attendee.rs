@@ -0,0 +1,374 @@
+//! Conversion between the `ATTENDEE` and `ORGANIZER` properties of an event and rows of the *Attendees* table.
+//!
+//! The Attendees table has fewer values than the parameters of `ATTENDEE`, so some are combined:
+//! `ROLE` and `CUTYPE` are stored in [`attendees::TYPE`] and [`attendees::RELATIONSHIP`],
+//! and `RSVP=TRUE` is stored as [`attendees::STATUS_INVITED`].
+use crate::provider::Row;
+use super::{event::strip_mailto, ical::Property, projections::attendees};
+
+/// The `ROLE` of an attendee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Role {
+    Chair,
+    #[default]
+    Required,
+    Optional,
+    NonParticipant,
+}
+impl Role {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "CHAIR" => Some(Self::Chair),
+            "REQ-PARTICIPANT" => Some(Self::Required),
+            "OPT-PARTICIPANT" => Some(Self::Optional),
+            "NON-PARTICIPANT" => Some(Self::NonParticipant),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chair => "CHAIR",
+            Self::Required => "REQ-PARTICIPANT",
+            Self::Optional => "OPT-PARTICIPANT",
+            Self::NonParticipant => "NON-PARTICIPANT",
+        }
+    }
+}
+
+/// The participation status (`PARTSTAT`) of an attendee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartStat {
+    #[default]
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+    Delegated,
+}
+impl PartStat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "NEEDS-ACTION" => Some(Self::NeedsAction),
+            "ACCEPTED" => Some(Self::Accepted),
+            "DECLINED" => Some(Self::Declined),
+            "TENTATIVE" => Some(Self::Tentative),
+            "DELEGATED" => Some(Self::Delegated),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NeedsAction => "NEEDS-ACTION",
+            Self::Accepted => "ACCEPTED",
+            Self::Declined => "DECLINED",
+            Self::Tentative => "TENTATIVE",
+            Self::Delegated => "DELEGATED",
+        }
+    }
+}
+
+/// The kind of calendar user (`CUTYPE`) of an attendee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserType {
+    #[default]
+    Individual,
+    Group,
+    Resource,
+    Room,
+    Unknown,
+}
+impl UserType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "INDIVIDUAL" => Some(Self::Individual),
+            "GROUP" => Some(Self::Group),
+            "RESOURCE" => Some(Self::Resource),
+            "ROOM" => Some(Self::Room),
+            "UNKNOWN" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Individual => "INDIVIDUAL",
+            Self::Group => "GROUP",
+            Self::Resource => "RESOURCE",
+            Self::Room => "ROOM",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// A participant of an event: an `ATTENDEE`, the `ORGANIZER`, or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attendee {
+    /// The *CAL-ADDRESS* of the participant, without the `mailto:` if it is an email.
+    pub address: String,
+    /// Whether **address** is an email (i.e. it had `mailto:`).
+    pub is_email: bool,
+    /// The common name (`CN`).
+    pub name: Option<String>,
+    pub role: Role,
+    pub status: PartStat,
+    pub user_type: UserType,
+    pub rsvp: bool,
+    pub is_organizer: bool,
+    /// Whether the participant is in an `ATTENDEE` property (the organizer may only be in `ORGANIZER`).
+    pub is_attendee: bool,
+}
+impl Attendee {
+    /// Read an `ATTENDEE` or `ORGANIZER` property.
+    pub fn from_property(prop: &Property) -> Self {
+        let is_organizer = prop.name.eq_ignore_ascii_case("ORGANIZER");
+        let value = prop.value.trim();
+        let is_email = value.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("mailto:"));
+        Self {
+            address: if is_email { strip_mailto(value) } else { value.to_string() },
+            is_email,
+            name: prop.param("CN").map(str::to_string),
+            role: prop.param("ROLE").and_then(Role::parse).unwrap_or_default(),
+            status: prop.param("PARTSTAT").and_then(PartStat::parse).unwrap_or_default(),
+            user_type: prop.param("CUTYPE").and_then(UserType::parse).unwrap_or_default(),
+            rsvp: prop.param("RSVP").is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE")),
+            is_organizer,
+            is_attendee: !is_organizer,
+        }
+    }
+
+    /// Read the `ATTENDEE`s and the `ORGANIZER` of an event.
+    ///
+    /// The organizer is marked in the attendee with the same address, or is added at the end if it is not an attendee.
+    pub fn from_properties<'a>(props: impl IntoIterator<Item = &'a Property>) -> Vec<Self> {
+        let mut organizer = None;
+        let mut result = Vec::new();
+        for prop in props {
+            if prop.name.eq_ignore_ascii_case("ATTENDEE") {
+                result.push(Self::from_property(prop));
+            } else if prop.name.eq_ignore_ascii_case("ORGANIZER") {
+                organizer = Some(Self::from_property(prop));
+            }
+        }
+
+        if let Some(organizer) = organizer {
+            match result.iter_mut().find(|attendee| attendee.address.eq_ignore_ascii_case(&organizer.address)) {
+                Some(attendee) => {
+                    attendee.is_organizer = true;
+                    attendee.name = attendee.name.take().or(organizer.name);
+                },
+                None => result.push(organizer),
+            }
+        }
+        result
+    }
+
+    /// Convert to a row of the *Attendees* table (without [`attendees::EVENT_ID`]).
+    pub fn to_row(&self) -> Row {
+        let mut row = Row::new();
+        row.put(attendees::NAME, self.name.clone());
+        if self.is_email {
+            row.put(attendees::EMAIL, self.address.clone());
+        } else {
+            row.put(attendees::IDENTITY, self.address.clone());
+        }
+
+        row.put(attendees::RELATIONSHIP, match self.role {
+            _ if self.is_organizer => attendees::RELATIONSHIP_ORGANIZER,
+            _ if self.user_type == UserType::Room => attendees::RELATIONSHIP_PERFORMER,
+            Role::Chair => attendees::RELATIONSHIP_SPEAKER,
+            Role::NonParticipant => attendees::RELATIONSHIP_NONE,
+            Role::Required | Role::Optional => attendees::RELATIONSHIP_ATTENDEE,
+        });
+        row.put(attendees::TYPE, match self.role {
+            _ if !self.is_attendee => attendees::TYPE_NONE,
+            _ if matches!(self.user_type, UserType::Resource | UserType::Room) => attendees::TYPE_RESOURCE,
+            Role::Optional => attendees::TYPE_OPTIONAL,
+            Role::NonParticipant => attendees::TYPE_NONE,
+            Role::Chair | Role::Required => attendees::TYPE_REQUIRED,
+        });
+        row.put(attendees::STATUS, match self.status {
+            _ if !self.is_attendee => attendees::STATUS_NONE,
+            PartStat::Accepted => attendees::STATUS_ACCEPTED,
+            PartStat::Declined => attendees::STATUS_DECLINED,
+            PartStat::Tentative => attendees::STATUS_TENTATIVE,
+            PartStat::NeedsAction if self.rsvp => attendees::STATUS_INVITED,
+            PartStat::NeedsAction | PartStat::Delegated => attendees::STATUS_NONE,
+        });
+        row
+    }
+
+    /// Read a row of the *Attendees* table (queried with [`attendees::COPY_PROJECTION`]).
+    ///
+    /// Returns [`None`] if the attendee has neither email nor identity.
+    pub fn from_row(row: &Row) -> Option<Self> {
+        let (address, is_email) = match row.get_text(attendees::EMAIL) {
+            Some(email) => (email.to_string(), true),
+            None => (row.get_text(attendees::IDENTITY)?.to_string(), false),
+        };
+        let relationship = row.get_long(attendees::RELATIONSHIP).unwrap_or(attendees::RELATIONSHIP_ATTENDEE);
+        let attendee_type = row.get_long(attendees::TYPE).unwrap_or(attendees::TYPE_REQUIRED);
+        let status = row.get_long(attendees::STATUS).unwrap_or(attendees::STATUS_NONE);
+        let is_organizer = relationship == attendees::RELATIONSHIP_ORGANIZER;
+
+        Some(Self {
+            address,
+            is_email,
+            name: row.get_text(attendees::NAME).map(str::to_string),
+            role: match attendee_type {
+                attendees::TYPE_OPTIONAL => Role::Optional,
+                attendees::TYPE_NONE if !is_organizer => Role::NonParticipant,
+                _ if relationship == attendees::RELATIONSHIP_NONE => Role::NonParticipant,
+                _ if relationship == attendees::RELATIONSHIP_SPEAKER => Role::Chair,
+                _ => Role::Required,
+            },
+            status: match status {
+                attendees::STATUS_ACCEPTED => PartStat::Accepted,
+                attendees::STATUS_DECLINED => PartStat::Declined,
+                attendees::STATUS_TENTATIVE => PartStat::Tentative,
+                _ => PartStat::NeedsAction,
+            },
+            user_type: match (attendee_type, relationship) {
+                (_, attendees::RELATIONSHIP_PERFORMER) => UserType::Room,
+                (attendees::TYPE_RESOURCE, _) => UserType::Resource,
+                _ => UserType::Individual,
+            },
+            rsvp: status == attendees::STATUS_INVITED,
+            is_organizer,
+            // The organizer is only an attendee if it has a role in the event
+            is_attendee: !is_organizer || attendee_type != attendees::TYPE_NONE || status != attendees::STATUS_NONE,
+        })
+    }
+
+    fn value(&self) -> String {
+        if self.is_email {
+            format!("mailto:{}", self.address)
+        } else {
+            self.address.clone()
+        }
+    }
+
+    /// Convert to an `ATTENDEE` property.
+    ///
+    /// Returns [`None`] if the participant is only the organizer.
+    pub fn to_property(&self) -> Option<Property> {
+        if !self.is_attendee {
+            return None;
+        }
+        let mut property = Property::new("ATTENDEE", self.value());
+        if let Some(name) = &self.name {
+            property = property.with_param("CN", name);
+        }
+        if self.user_type != UserType::Individual {
+            property = property.with_param("CUTYPE", self.user_type.as_str());
+        }
+        if self.role != Role::Required {
+            property = property.with_param("ROLE", self.role.as_str());
+        }
+        if self.status != PartStat::NeedsAction {
+            property = property.with_param("PARTSTAT", self.status.as_str());
+        }
+        if self.rsvp {
+            property = property.with_param("RSVP", "TRUE");
+        }
+        Some(property)
+    }
+
+    /// Convert to an `ORGANIZER` property.
+    ///
+    /// Returns [`None`] if the participant is not the organizer.
+    pub fn to_organizer_property(&self) -> Option<Property> {
+        if !self.is_organizer {
+            return None;
+        }
+        let property = Property::new("ORGANIZER", self.value());
+        Some(match &self.name {
+            Some(name) => property.with_param("CN", name),
+            None => property,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    fn properties(lines: &[&str]) -> Vec<Property> {
+        let content = format!("BEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\n", lines.join("\r\n"));
+        ical::parse(&content).unwrap().remove(0).properties
+    }
+    /// Write the participants to rows and read them back, as the properties of the event.
+    fn round_trip(lines: &[&str]) -> Vec<String> {
+        Attendee::from_properties(&properties(lines)).iter()
+            .map(|attendee| Attendee::from_row(&attendee.to_row()).unwrap())
+            .flat_map(|attendee| [attendee.to_organizer_property(), attendee.to_property()])
+            .flatten()
+            .map(|property| property.to_string().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn status_and_type() {
+        let row = |line: &str| Attendee::from_property(&properties(&[line])[0]).to_row();
+        let columns = |row: Row| (
+            row.get_long(attendees::RELATIONSHIP).unwrap(),
+            row.get_long(attendees::TYPE).unwrap(),
+            row.get_long(attendees::STATUS).unwrap(),
+        );
+        assert_eq!(columns(row("ATTENDEE:mailto:a@example.com")),
+            (attendees::RELATIONSHIP_ATTENDEE, attendees::TYPE_REQUIRED, attendees::STATUS_NONE));
+        assert_eq!(columns(row("ATTENDEE;ROLE=OPT-PARTICIPANT;PARTSTAT=ACCEPTED:mailto:a@example.com")),
+            (attendees::RELATIONSHIP_ATTENDEE, attendees::TYPE_OPTIONAL, attendees::STATUS_ACCEPTED));
+        assert_eq!(columns(row("ATTENDEE;ROLE=CHAIR;PARTSTAT=TENTATIVE:mailto:a@example.com")),
+            (attendees::RELATIONSHIP_SPEAKER, attendees::TYPE_REQUIRED, attendees::STATUS_TENTATIVE));
+        assert_eq!(columns(row("ATTENDEE;ROLE=NON-PARTICIPANT;PARTSTAT=DECLINED:mailto:a@example.com")),
+            (attendees::RELATIONSHIP_NONE, attendees::TYPE_NONE, attendees::STATUS_DECLINED));
+        assert_eq!(columns(row("ATTENDEE;RSVP=TRUE:mailto:a@example.com")),
+            (attendees::RELATIONSHIP_ATTENDEE, attendees::TYPE_REQUIRED, attendees::STATUS_INVITED));
+        assert_eq!(columns(row("ATTENDEE;CUTYPE=ROOM:mailto:room@example.com")),
+            (attendees::RELATIONSHIP_PERFORMER, attendees::TYPE_RESOURCE, attendees::STATUS_NONE));
+        assert_eq!(columns(row("ATTENDEE;CUTYPE=RESOURCE;PARTSTAT=DELEGATED:mailto:projector@example.com")),
+            (attendees::RELATIONSHIP_ATTENDEE, attendees::TYPE_RESOURCE, attendees::STATUS_NONE));
+        assert_eq!(columns(row("ORGANIZER:mailto:boss@example.com")),
+            (attendees::RELATIONSHIP_ORGANIZER, attendees::TYPE_NONE, attendees::STATUS_NONE));
+
+        // Addresses that are not emails are stored as identities
+        let row = row("ATTENDEE:urn:uuid:1234");
+        assert_eq!(row.get_text(attendees::IDENTITY), Some("urn:uuid:1234"));
+        assert_eq!(row.get_text(attendees::EMAIL), None);
+    }
+
+    #[test]
+    fn attendees_round_trip() {
+        let lines = [
+            "ATTENDEE;CN=Ann:mailto:ann@example.com",
+            "ATTENDEE;ROLE=OPT-PARTICIPANT;PARTSTAT=ACCEPTED:mailto:bob@example.com",
+            "ATTENDEE;ROLE=CHAIR;PARTSTAT=TENTATIVE:mailto:carl@example.com",
+            "ATTENDEE;ROLE=NON-PARTICIPANT;PARTSTAT=DECLINED:mailto:dana@example.com",
+            "ATTENDEE;RSVP=TRUE:mailto:eve@example.com",
+            "ATTENDEE;CUTYPE=ROOM:mailto:room@example.com",
+            "ATTENDEE;CUTYPE=RESOURCE:mailto:projector@example.com",
+            "ATTENDEE:urn:uuid:1234",
+        ];
+        assert_eq!(round_trip(&lines), lines);
+
+        // The status of delegated attendees is not stored
+        assert_eq!(round_trip(&["ATTENDEE;PARTSTAT=DELEGATED:mailto:ann@example.com"]), ["ATTENDEE:mailto:ann@example.com"]);
+    }
+
+    #[test]
+    fn organizer_round_trip() {
+        // An organizer that is not an attendee
+        assert_eq!(round_trip(&["ORGANIZER;CN=Boss:mailto:boss@example.com", "ATTENDEE:mailto:ann@example.com"]),
+            ["ATTENDEE:mailto:ann@example.com", "ORGANIZER;CN=Boss:mailto:boss@example.com"]);
+        // An organizer that is also an attendee, which gets the name of either
+        let attendees = Attendee::from_properties(&properties(&[
+            "ORGANIZER;CN=Boss:mailto:boss@example.com",
+            "ATTENDEE;PARTSTAT=ACCEPTED:mailto:BOSS@example.com",
+        ]));
+        assert_eq!(attendees.len(), 1);
+        assert!(attendees[0].is_organizer && attendees[0].is_attendee);
+        assert_eq!(attendees[0].name.as_deref(), Some("Boss"));
+        assert_eq!(round_trip(&["ORGANIZER;CN=Boss:mailto:boss@example.com", "ATTENDEE;PARTSTAT=ACCEPTED:mailto:boss@example.com"]),
+            ["ORGANIZER;CN=Boss:mailto:boss@example.com", "ATTENDEE;CN=Boss;PARTSTAT=ACCEPTED:mailto:boss@example.com"]);
+    }
+}
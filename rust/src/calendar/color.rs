@@ -0,0 +1,256 @@
+//! The color of a Calendar, and how it is stored in the calendar file.
+//!
+//! The color is written as `X-APPLE-CALENDAR-COLOR` (an exact `#RRGGBB` value, understood by most clients)
+//! and as the RFC 7986 `COLOR` property, which can only hold a *CSS3 color name*, so it gets the closest named color.
+use ez_jni::{new, utils::get_field, FromObject, FromObjectError, ToObject};
+use jni::{JNIEnv, objects::JObject};
+use super::ical::{Component, Property};
+
+const APPLE_COLOR: &str = "X-APPLE-CALENDAR-COLOR";
+const COLOR: &str = "COLOR";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+impl Color {
+    /// The color of Calendars that were not given one (same as `DEFAULT_CALENDAR_COLOR` in `MainActivity.kt`).
+    pub const DEFAULT: Self = Self { r: 0x68, g: 0xac, b: 0xef };
+
+    /// Parse a color in the format `#RGB`, `#RRGGBB`, `#RRGGBBAA` (alpha is ignored), or a *CSS3 color name*.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let Some(hex) = s.strip_prefix('#') else {
+            return CSS_COLORS.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(s))
+                .map(|&(_, color)| Self::from_rgb(color));
+        };
+        if !hex.is_ascii() {
+            return None
+        }
+
+        let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).ok();
+        match hex.len() {
+            // Each digit is repeated (e.g. #0fa = #00ffaa)
+            3 => Some(Self { r: channel(0, 1)? * 0x11, g: channel(1, 1)? * 0x11, b: channel(2, 1)? * 0x11 }),
+            6 | 8 => Some(Self { r: channel(0, 2)?, g: channel(1, 2)?, b: channel(2, 2)? }),
+            _ => None,
+        }
+    }
+
+    fn from_rgb(rgb: u32) -> Self {
+        Self { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8 }
+    }
+
+    /// Get the color in the format `#RRGGBB`.
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+
+    /// Get the value of the color used in the `calendar_color` column of the Content Provider (opaque ARGB).
+    pub fn to_argb(self) -> i64 {
+        (0xFF000000u32 | ((self.r as u32) << 16) | ((self.g as u32) << 8) | self.b as u32) as i32 as i64
+    }
+
+    /// Find the *CSS3 color name* that is closest to this color.
+    pub fn css_name(self) -> &'static str {
+        let distance = |&&(_, rgb): &&(&str, u32)| {
+            let other = Self::from_rgb(rgb);
+            [(self.r, other.r), (self.g, other.g), (self.b, other.b)]
+                .into_iter()
+                .map(|(a, b)| (a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        };
+        CSS_COLORS.iter().min_by_key(distance).unwrap().0
+    }
+}
+// The channels are Kotlin `UByte`s, which are stored in a Java `byte` with the same bits.
+impl FromObject<'_> for Color {
+    fn from_object(object: &JObject, env: &mut JNIEnv) -> Result<Self, FromObjectError> {
+        if object.is_null() {
+            return Err(FromObjectError::Null);
+        }
+        let mut channel = |name: &str| get_field(object, name, "B", false, env)?
+            .b()
+            .map(|value| value as u8)
+            .map_err(|_| FromObjectError::FieldNotFound {
+                name: name.to_string(),
+                ty: "B".to_string(),
+                target_class: "me.marti.calprovexample.Color".to_string(),
+            });
+        Ok(Self { r: channel("r")?, g: channel("g")?, b: channel("b")? })
+    }
+}
+impl<'local> ToObject<'local> for Color {
+    fn to_object(&self, env: &mut JNIEnv<'local>) -> JObject<'local> {
+        new!(me.marti.calprovexample.Color(byte(self.r as i8), byte(self.g as i8), byte(self.b as i8)))
+    }
+}
+
+/// Read the color of a `VCALENDAR`.
+///
+/// `X-APPLE-CALENDAR-COLOR` is preferred because `COLOR` is usually an approximation.
+pub fn read_color(calendar: &Component) -> Option<Color> {
+    [APPLE_COLOR, COLOR].into_iter()
+        .filter_map(|name| calendar.value(name))
+        .find_map(Color::parse)
+}
+
+/// Set the color of a `VCALENDAR`, replacing the color it had before.
+pub fn write_color(calendar: &mut Component, color: Color) {
+    calendar.set(Property::new(APPLE_COLOR, color.to_hex()));
+    calendar.set(Property::new(COLOR, color.css_name()));
+}
+
+/// The *CSS3 color names* (<https://www.w3.org/TR/css-color-3/#svg-color>), which are the allowed values of `COLOR`.
+const CSS_COLORS: [(&str, u32); 147] = [
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
@@ -0,0 +1,502 @@
+//! Conversion between spreadsheets in CSV ([RFC 4180](https://datatracker.ietf.org/doc/html/rfc4180)) and `VEVENT`s.
+//!
+//! Each row of the spreadsheet is an event, and the [`Columns`] tell which column of the header has each of its fields.
+//! Dates are written as `YYYY-MM-DD` and times as `HH:MM[:SS]` (a `Z` at the end means UTC), so that spreadsheet apps recognize them.
+//! The end date of all-day events is inclusive (an event on a single day ends on the same day), unlike `DTEND`.
+use std::fmt::Display;
+use ez_jni::{call, FromObject, FromObjectError};
+use jni::{objects::JObject, JNIEnv};
+use super::{
+    event::Event,
+    ical::Component,
+    new_calendar,
+    time::{CalTime, Date, DateTime, Time, TimeZoneResolver},
+    timezone::{self, TimeZones},
+};
+
+/// The names of the columns of the CSV header that have the fields of the events.
+///
+/// Only **title** and **start** are required.
+/// The names are compared case insensitively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns {
+    pub title: String,
+    pub start: String,
+    pub end: Option<String>,
+    /// A column whose cells are `true`/`yes`/`1` for all-day events.
+    /// If there is no such column, events whose start has no time are all-day.
+    pub all_day: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    /// A column with the time zone of the start and end of each event (e.g. `Europe/Paris`).
+    /// Events without a time zone have *floating* times.
+    pub timezone: Option<String>,
+}
+impl FromObject<'_> for Columns {
+    /// Read the fields of a `me.marti.calprovexample.jni.CsvColumns`.
+    ///
+    /// Implemented by hand because the derive macro can't read nullable `String` fields.
+    fn from_object(object: &JObject, env: &mut JNIEnv) -> Result<Self, FromObjectError> {
+        if object.is_null() {
+            return Err(FromObjectError::Null);
+        }
+        Ok(Self {
+            title: call!(object.getTitle() -> String),
+            start: call!(object.getStart() -> String),
+            end: call!(object.getEnd() -> Option<String>),
+            all_day: call!(object.getAllDay() -> Option<String>),
+            location: call!(object.getLocation() -> Option<String>),
+            description: call!(object.getDescription() -> Option<String>),
+            timezone: call!(object.getTimezone() -> Option<String>),
+        })
+    }
+}
+impl Default for Columns {
+    /// The columns of the files written by [`export()`] (same as the defaults of `CsvColumns` in `DavSyncRs.kt`).
+    fn default() -> Self {
+        let [title, start, end, all_day, location, description, timezone] = HEADER.map(str::to_string);
+        Self {
+            title,
+            start,
+            end: Some(end),
+            all_day: Some(all_day),
+            location: Some(location),
+            description: Some(description),
+            timezone: Some(timezone),
+        }
+    }
+}
+
+/// The header of the files written by [`export()`].
+const HEADER: [&str; 7] = ["Title", "Start", "End", "All day", "Location", "Description", "Time zone"];
+
+/// A row of the spreadsheet that could not be converted to an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// The number of the row as shown by spreadsheet apps (the header is row `1`).
+    pub row: usize,
+    pub msg: String,
+}
+impl Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Row {}: {}", self.row, self.msg)
+    }
+}
+impl std::error::Error for RowError {}
+
+/// Convert the rows of a CSV file to the `VEVENT`s of a new Calendar with **name**.
+///
+/// The rows that can't be converted are skipped, and an error is returned for each of them.
+/// Returns an error if the file has no header or a column of **columns** is not in it.
+///
+/// **dtstamp** is the time of the import (usually [`now()`](super::time::now())), which is also used for the `UID`s of the events.
+pub fn import(
+    content: &str,
+    columns: &Columns,
+    name: &str,
+    dtstamp: DateTime,
+    resolver: &mut dyn TimeZoneResolver,
+) -> Result<(Component, Vec<RowError>), String> {
+    let mut rows = parse(content).into_iter();
+    let header = rows.next().ok_or("The CSV file is empty")?;
+    let column = |name: &Option<String>| -> Result<Option<usize>, String> {
+        match name.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(name) => header.iter()
+                .position(|cell| cell.trim().eq_ignore_ascii_case(name))
+                .map(Some)
+                .ok_or_else(|| format!("Column {name:?} is not in the CSV header")),
+        }
+    };
+    let title = column(&Some(columns.title.clone()))?.ok_or("The title column is required")?;
+    let start = column(&Some(columns.start.clone()))?.ok_or("The start column is required")?;
+    let end = column(&columns.end)?;
+    let all_day = column(&columns.all_day)?;
+    let location = column(&columns.location)?;
+    let description = column(&columns.description)?;
+    let timezone = column(&columns.timezone)?;
+
+    let mut calendar = new_calendar(name);
+    let mut errors = Vec::new();
+    let mut tzids = Vec::new();
+    let resolver = &mut TimeZones { system: resolver, embedded: &[] };
+    for (i, row) in rows.enumerate() {
+        let row_num = i + 2;
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let cell = |column: Option<usize>| column
+            .and_then(|column| row.get(column))
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty());
+
+        let result = (|| -> Result<Event, String> {
+            let summary = cell(Some(title)).ok_or("The title is empty")?;
+            let tzid = match cell(timezone) {
+                Some(tzid) => Some(resolver.resolve(tzid).ok_or_else(|| format!("Unknown time zone {tzid:?}"))?),
+                None => None,
+            };
+            let (start_date, start_time) = cell(Some(start))
+                .ok_or("The start is empty".to_string())
+                .and_then(|start| parse_date_time(start).ok_or_else(|| format!("Invalid start {start:?} (expected YYYY-MM-DD [HH:MM[:SS]])")))?;
+            let end = cell(end)
+                .map(|end| parse_date_time(end).ok_or_else(|| format!("Invalid end {end:?} (expected YYYY-MM-DD [HH:MM[:SS]])")))
+                .transpose()?;
+            let is_all_day = match cell(all_day) {
+                Some(value) => parse_bool(value).ok_or_else(|| format!("Invalid all-day value {value:?} (expected true or false)"))?,
+                None => start_time.is_none(),
+            };
+
+            let (start, end) = if is_all_day {
+                // The end date of the spreadsheet is inclusive
+                (CalTime::Date(start_date), end.map(|(end, _)| CalTime::Date(end.add_days(1))))
+            } else {
+                let time = |date: Date, time: Option<(Time, bool)>| match time.unwrap_or_default() {
+                    (time, true) => CalTime::Utc(DateTime { date, time }),
+                    (time, false) => match &tzid {
+                        Some(tzid) => CalTime::Zoned { time: DateTime { date, time }, tzid: tzid.clone() },
+                        None => CalTime::Floating(DateTime { date, time }),
+                    },
+                };
+                (time(start_date, start_time), end.map(|(date, end_time)| time(date, end_time)))
+            };
+            if let Some(end) = &end {
+                if end.timestamp(resolver) < start.timestamp(resolver) {
+                    return Err("The end is before the start".to_string());
+                }
+            }
+
+            Ok(Event {
+                uid: Some(format!("csv-{}-{row_num}@davsync", dtstamp.timestamp())),
+                summary: Some(summary.to_string()),
+                description: cell(description).map(str::to_string),
+                location: cell(location).map(str::to_string),
+                organizer: None,
+                start,
+                end,
+                duration: None,
+                rrule: None,
+                exrule: None,
+                rdate: None,
+                exdate: None,
+                recurrence_id: None,
+                status: None,
+                availability: Default::default(),
+                class: None,
+            })
+        })();
+
+        match result {
+            Ok(event) => {
+                if let Some(tzid) = event.start.tzid() {
+                    if !tzids.iter().any(|other| other == tzid) {
+                        tzids.push(tzid.to_string());
+                    }
+                }
+                calendar.components.push(event.to_component(dtstamp));
+            },
+            Err(msg) => errors.push(RowError { row: row_num, msg }),
+        }
+    }
+
+    // Other apps need the definitions of the time zones used by the events
+    let timezones = tzids.iter()
+        .filter_map(|tzid| timezone::to_vtimezone(tzid, dtstamp.date.year, &mut *resolver.system))
+        .collect::<Vec<_>>();
+    calendar.components.splice(0..0, timezones);
+
+    Ok((calendar, errors))
+}
+
+/// Write the `VEVENT`s of **calendar** as a CSV file with the [default](Columns::default()) columns.
+///
+/// Recurrence rules can't be represented in a spreadsheet, so recurring events only have a row for their first instance.
+/// Events that can't be read are skipped, and a message is returned for each of them so that they can be reported.
+pub fn export(calendar: &Component, resolver: &mut dyn TimeZoneResolver) -> (String, Vec<String>) {
+    let mut csv = String::new();
+    let mut messages = Vec::new();
+    write_row(&mut csv, HEADER);
+
+    for component in calendar.components("VEVENT") {
+        let event = match Event::from_component(component) {
+            Ok(event) => event,
+            Err(err) => {
+                messages.push(format!("Skipping event: {err}"));
+                continue;
+            },
+        };
+        let start = event.start.local();
+        // The end is written in the time zone of the start
+        let length = event.length(resolver).unwrap_or_else(|| {
+            messages.push(format!("Event {} has unknown time zone", event.uid.as_deref().unwrap_or("<no UID>")));
+            0
+        });
+        let end = DateTime::from_timestamp(start.timestamp() + length);
+
+        let (start, end) = match &event.start {
+            // The end date of the spreadsheet is inclusive
+            CalTime::Date(_) => (format_date(start.date), format_date(end.date.add_days(-1).max(start.date))),
+            CalTime::Utc(_) => (format!("{}Z", format_date_time(start)), format!("{}Z", format_date_time(end))),
+            CalTime::Floating(_) | CalTime::Zoned { .. } => (format_date_time(start), format_date_time(end)),
+        };
+        let tzid = match &event.start {
+            CalTime::Zoned { tzid, .. } => tzid.as_str(),
+            CalTime::Date(_) | CalTime::Utc(_) | CalTime::Floating(_) => "",
+        };
+
+        write_row(&mut csv, [
+            event.summary.as_deref().unwrap_or_default(),
+            &start,
+            &end,
+            if event.is_all_day() { "true" } else { "false" },
+            event.location.as_deref().unwrap_or_default(),
+            event.description.as_deref().unwrap_or_default(),
+            tzid,
+        ]);
+    }
+
+    (csv, messages)
+}
+
+/// Parse the records of a CSV file into their cells.
+///
+/// The separator is the first of `,`, `;` or tab that is in the first line (some spreadsheet apps use `;` in some languages).
+fn parse(content: &str) -> Vec<Vec<String>> {
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+    let first_line = content.lines().next().unwrap_or_default();
+    let separator = [',', ';', '\t'].into_iter()
+        .find(|separator| first_line.contains(*separator))
+        .unwrap_or(',');
+
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                // A quote in a quoted cell is escaped by another quote
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    cell.push('"');
+                } else {
+                    quoted = false;
+                }
+            },
+            '"' if cell.is_empty() => quoted = true,
+            _ if quoted => cell.push(c),
+            _ if c == separator => row.push(std::mem::take(&mut cell)),
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\r' | '\n' => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            },
+            c => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Write a record to a CSV file, quoting the cells that need it.
+fn write_row<'a>(csv: &mut String, cells: impl IntoIterator<Item = &'a str>) {
+    let cells = cells.into_iter()
+        .map(|cell| if cell.contains([',', ';', '"', '\n', '\r']) || cell.trim() != cell {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        })
+        .collect::<Vec<_>>();
+    csv.push_str(&cells.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Parse a date and optional time (e.g. `2024-01-15`, `2024-01-15 10:00`, `2024/01/15T10:00:00Z` or `20240115T100000`).
+///
+/// Returns the time (if any) and whether it is in UTC.
+fn parse_date_time(value: &str) -> Option<(Date, Option<(Time, bool)>)> {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.trim())),
+        None => (value, None),
+    };
+    let digits = date.replace(['-', '/', '.'], "");
+    let date = match date.len() - digits.len() {
+        // YYYY-MM-DD, which can have 1-digit months and days
+        2 => {
+            let mut parts = date.split(['-', '/', '.']);
+            let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+            if year.len() != 4 {
+                return None;
+            }
+            Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)?
+        },
+        0 => Date::parse(&digits)?,
+        _ => return None,
+    };
+    let Some(time) = time else {
+        return Some((date, None));
+    };
+
+    let (time, utc) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    let parts = if time.contains(':') {
+        time.split(':').map(str::to_string).collect::<Vec<_>>()
+    } else if time.len() == 6 || time.len() == 4 {
+        time.as_bytes().chunks(2).map(|part| String::from_utf8_lossy(part).into_owned()).collect()
+    } else {
+        return None;
+    };
+    let (hour, minute, second) = match parts.as_slice() {
+        [hour, minute] => (hour, minute, "0"),
+        [hour, minute, second] => (hour, minute, second.as_str()),
+        _ => return None,
+    };
+    let time = Time { hour: hour.parse().ok()?, minute: minute.parse().ok()?, second: second.parse().ok()? };
+    if time.hour > 23 || time.minute > 59 || time.second > 60 {
+        return None;
+    }
+    Some((date, Some((time, utc))))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" | "x" => Some(true),
+        "false" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_date(date: Date) -> String {
+    format!("{:04}-{:02}-{:02}", date.year, date.month, date.day)
+}
+fn format_date_time(time: DateTime) -> String {
+    format!("{} {:02}:{:02}:{:02}", format_date(time.date), time.time.hour, time.time.minute, time.time.second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    /// Knows only `America/New_York`, always at *-05:00*.
+    struct NewYork;
+    impl TimeZoneResolver for NewYork {
+        fn utc_offset(&mut self, tzid: &str, _local: i64) -> Option<i32> {
+            (tzid == "America/New_York").then_some(-5 * 3600)
+        }
+        fn default_tzid(&mut self) -> String {
+            "America/New_York".to_string()
+        }
+    }
+
+    fn dtstamp() -> DateTime {
+        DateTime::parse("20240101T000000").unwrap()
+    }
+    fn import_with(content: &str, columns: &Columns) -> (Component, Vec<RowError>) {
+        import(content, columns, "Test", dtstamp(), &mut NewYork).unwrap()
+    }
+    fn events(calendar: &Component) -> Vec<Event> {
+        calendar.components("VEVENT").map(|event| Event::from_component(event).unwrap()).collect()
+    }
+
+    #[test]
+    fn quoted_cells() {
+        let content = "Title,Notes\r\n\"Lunch, with Ann\",\"Bring \"\"the\"\" book\r\nand a pen\"\r\nPlain,  spaced  \n";
+        assert_eq!(parse(content), [
+            vec!["Title", "Notes"],
+            vec!["Lunch, with Ann", "Bring \"the\" book\r\nand a pen"],
+            vec!["Plain", "  spaced  "],
+        ]);
+        // Some spreadsheet apps use semicolons, and write a byte order mark
+        assert_eq!(parse("\u{FEFF}Title;Start\n\"a;b\";2024-01-01"), [vec!["Title", "Start"], vec!["a;b", "2024-01-01"]]);
+
+        let mut csv = String::new();
+        write_row(&mut csv, ["Lunch, with Ann", "Bring \"the\" book\nand a pen", " spaced", "plain"]);
+        assert_eq!(csv, "\"Lunch, with Ann\",\"Bring \"\"the\"\" book\nand a pen\",\" spaced\",plain\r\n");
+        assert_eq!(parse(&csv), [vec!["Lunch, with Ann", "Bring \"the\" book\nand a pen", " spaced", "plain"]]);
+    }
+
+    #[test]
+    fn inclusive_all_day_end() {
+        let columns = Columns { all_day: None, location: None, description: None, timezone: None, ..Columns::default() };
+        let (calendar, errors) = import_with("Title,Start,End\r\nTrip,2024-01-01,2024-01-03\r\nHoliday,2024-01-05,2024-01-05\r\nBirthday,2024-01-07,\r\n", &columns);
+        assert!(errors.is_empty());
+        let events = events(&calendar);
+        assert_eq!(events.iter().map(|event| (event.start.value(), event.end.as_ref().map(CalTime::value))).collect::<Vec<_>>(), [
+            ("20240101".to_string(), Some("20240104".to_string())),
+            ("20240105".to_string(), Some("20240106".to_string())),
+            ("20240107".to_string(), None),
+        ]);
+
+        let (csv, messages) = export(&calendar, &mut NewYork);
+        assert!(messages.is_empty());
+        assert_eq!(csv.lines().skip(1).map(|line| line.split(',').take(3).collect::<Vec<_>>().join(",")).collect::<Vec<_>>(), [
+            "Trip,2024-01-01,2024-01-03",
+            "Holiday,2024-01-05,2024-01-05",
+            "Birthday,2024-01-07,2024-01-07",
+        ]);
+    }
+
+    #[test]
+    fn row_errors() {
+        let columns = Columns { location: None, description: None, ..Columns::default() };
+        let (calendar, errors) = import_with("Title,Start,End,All day,Time zone\r\n\
+            ,2024-01-01,,,\r\n\
+            No start,,,,\r\n\
+            Bad start,tomorrow,,,\r\n\
+            Backwards,2024-01-02 10:00,2024-01-02 09:00,,\r\n\
+            Maybe,2024-01-02,,perhaps,\r\n\
+            Far away,2024-01-02 10:00,,,Mars/Olympus\r\n\
+            ,,,,\r\n\
+            Fine,2024-01-02 10:00,2024-01-02 11:00,,America/New_York\r\n", &columns);
+        assert_eq!(errors.iter().map(ToString::to_string).collect::<Vec<_>>(), [
+            "Row 2: The title is empty",
+            "Row 3: The start is empty",
+            "Row 4: Invalid start \"tomorrow\" (expected YYYY-MM-DD [HH:MM[:SS]])",
+            "Row 5: The end is before the start",
+            "Row 6: Invalid all-day value \"perhaps\" (expected true or false)",
+            "Row 7: Unknown time zone \"Mars/Olympus\"",
+        ]);
+        // The empty row is skipped without an error, and the row after the errors is imported
+        let events = events(&calendar);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].uid.as_deref(), Some(format!("csv-{}-9@davsync", dtstamp().timestamp()).as_str()));
+
+        let columns = Columns { location: Some("Room".to_string()), ..Columns::default() };
+        assert_eq!(import("Title,Start,End,All day,Description,Time zone\r\n", &columns, "Test", dtstamp(), &mut NewYork).unwrap_err(), "Column \"Room\" is not in the CSV header");
+        assert_eq!(import("", &Columns::default(), "Test", dtstamp(), &mut NewYork).unwrap_err(), "The CSV file is empty");
+    }
+
+    #[test]
+    fn export_and_import() {
+        let calendar = &ical::parse("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n\
+            BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Trip\r\nDTSTART;VALUE=DATE:20240101\r\nDTEND;VALUE=DATE:20240104\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Lunch\\, with Ann\r\nLOCATION:Café\r\nDESCRIPTION:Bring \"the\" book\\nand a pen\r\n\
+            DTSTART;TZID=America/New_York:20240102T120000\r\nDTEND;TZID=America/New_York:20240102T133000\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:3\r\nSUMMARY:Call\r\nDTSTART:20240103T150000Z\r\nDURATION:PT30M\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:4\r\nSUMMARY:Floating\r\nDTSTART:20240104T090000\r\nDTEND:20240104T100000\r\nEND:VEVENT\r\n\
+            END:VCALENDAR\r\n").unwrap()[0];
+
+        let (csv, messages) = export(calendar, &mut NewYork);
+        assert!(messages.is_empty());
+        let (imported, errors) = import_with(&csv, &Columns::default());
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let fields = |event: &Event| (
+            event.summary.clone(),
+            event.location.clone(),
+            event.description.clone(),
+            event.start.clone(),
+            event.start.timestamp(&mut NewYork).zip(event.length(&mut NewYork)).map(|(start, length)| start + length),
+        );
+        assert_eq!(events(&imported).iter().map(fields).collect::<Vec<_>>(), events(calendar).iter().map(fields).collect::<Vec<_>>());
+        // The time zone of the events is defined in the file
+        assert_eq!(imported.components("VTIMEZONE").next().and_then(|timezone| timezone.value("TZID")), Some("America/New_York"));
+    }
+}
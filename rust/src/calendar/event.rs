@@ -0,0 +1,464 @@
+//! Typed representation of a `VEVENT` and its conversion to and from a row of the *Events* table.
+use crate::provider::Row;
+use super::{
+    ical::{split_list, Component, Property},
+    projections::events,
+    recurrence::RRule,
+    time::{CalTime, DateTime, Duration, TimeZoneResolver},
+    timezone::TimeZones,
+};
+
+/// The data of a `VEVENT` component that can be stored in the Calendar Content Provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    /// The email address of the organizer (without the `mailto:`).
+    pub organizer: Option<String>,
+    pub start: CalTime,
+    /// Mutually exclusive with [`Self::duration`].
+    pub end: Option<CalTime>,
+    pub duration: Option<Duration>,
+    pub rrule: Option<String>,
+    pub exrule: Option<String>,
+    /// In the format used by the Content Provider (see [`date_list()`]).
+    pub rdate: Option<String>,
+    /// In the format used by the Content Provider (see [`date_list()`]).
+    pub exdate: Option<String>,
+    /// If the event is an *exception* (`RECURRENCE-ID`), the start of the instance of the recurring event with the same UID that it replaces.
+    pub recurrence_id: Option<CalTime>,
+    pub status: Option<EventStatus>,
+    /// Whether the event blocks time (see [`Availability`]).
+    pub availability: Availability,
+    pub class: Option<Class>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStatus {
+    Tentative,
+    Confirmed,
+    Cancelled,
+}
+impl EventStatus {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "TENTATIVE" => Some(Self::Tentative),
+            "CONFIRMED" => Some(Self::Confirmed),
+            "CANCELLED" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tentative => "TENTATIVE",
+            Self::Confirmed => "CONFIRMED",
+            Self::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+/// The property that Outlook (and other apps) use for the availability of an event, which `TRANSP` only has 2 values for.
+const BUSY_STATUS: &str = "X-MICROSOFT-CDO-BUSYSTATUS";
+
+/// Whether an event blocks time in the schedule of the user (the [`events::AVAILABILITY`] column).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Availability {
+    #[default]
+    Busy,
+    /// `TRANSP:TRANSPARENT`
+    Free,
+    /// `X-MICROSOFT-CDO-BUSYSTATUS:TENTATIVE`
+    Tentative,
+}
+impl Availability {
+    /// Read the availability from the `TRANSP` and `X-MICROSOFT-CDO-BUSYSTATUS` properties of an event.
+    pub fn from_component(component: &Component) -> Self {
+        let transparent = component.value("TRANSP").is_some_and(|transp| transp.trim().eq_ignore_ascii_case("TRANSPARENT"));
+        let tentative = component.value(BUSY_STATUS).is_some_and(|status| status.trim().eq_ignore_ascii_case("TENTATIVE"));
+        match (transparent, tentative) {
+            (true, _) => Self::Free,
+            (false, true) => Self::Tentative,
+            (false, false) => Self::Busy,
+        }
+    }
+}
+
+/// The *access classification* of an event (`CLASS` property).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Public,
+    Private,
+    Confidential,
+}
+impl Class {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "PUBLIC" => Some(Self::Public),
+            "PRIVATE" => Some(Self::Private),
+            "CONFIDENTIAL" => Some(Self::Confidential),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "PUBLIC",
+            Self::Private => "PRIVATE",
+            Self::Confidential => "CONFIDENTIAL",
+        }
+    }
+}
+
+impl Event {
+    /// Read the data of a `VEVENT` component.
+    ///
+    /// Returns an error if the component is missing `DTSTART` or has invalid date values.
+    pub fn from_component(component: &Component) -> Result<Self, String> {
+        if !component.name.eq_ignore_ascii_case("VEVENT") {
+            return Err(format!("Expected VEVENT component, found {}", component.name));
+        }
+        let uid = component.value("UID").map(str::to_string);
+        // Used to identify the event in error messages
+        let id = uid.as_deref().unwrap_or("<no UID>");
+
+        let start = component
+            .property("DTSTART")
+            .ok_or_else(|| format!("Event {id} has no DTSTART"))
+            .and_then(|prop| date_property(prop).ok_or_else(|| format!("Event {id} has invalid DTSTART {:?}", prop.value)))?;
+        let end = component
+            .property("DTEND")
+            .map(|prop| date_property(prop).ok_or_else(|| format!("Event {id} has invalid DTEND {:?}", prop.value)))
+            .transpose()?;
+        let duration = component
+            .value("DURATION")
+            .map(|value| Duration::parse(value).ok_or_else(|| format!("Event {id} has invalid DURATION {value:?}")))
+            .transpose()?;
+        let recurrence_id = component
+            .property("RECURRENCE-ID")
+            .map(|prop| date_property(prop).ok_or_else(|| format!("Event {id} has invalid RECURRENCE-ID {:?}", prop.value)))
+            .transpose()?;
+        for name in ["RRULE", "EXRULE"] {
+            if let Some(value) = component.value(name) {
+                RRule::parse(value).map_err(|err| format!("Event {id} has invalid {name}: {err}"))?;
+            }
+        }
+
+        Ok(Self {
+            summary: component.property("SUMMARY").map(Property::text_value),
+            description: component.property("DESCRIPTION").map(Property::text_value),
+            location: component.property("LOCATION").map(Property::text_value),
+            organizer: component.value("ORGANIZER").map(strip_mailto),
+            start,
+            end,
+            duration,
+            rrule: component.value("RRULE").map(str::to_string),
+            exrule: component.value("EXRULE").map(str::to_string),
+            rdate: date_list(component.properties("RDATE")),
+            exdate: date_list(component.properties("EXDATE")),
+            recurrence_id,
+            status: component.value("STATUS").and_then(EventStatus::parse),
+            availability: Availability::from_component(component),
+            class: component.value("CLASS").and_then(Class::parse),
+            uid,
+        })
+    }
+
+    pub fn is_all_day(&self) -> bool {
+        self.start.is_date()
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        self.rrule.is_some() || self.rdate.is_some()
+    }
+
+    /// Whether the event replaces an instance of a recurring event (i.e. it has a `RECURRENCE-ID`).
+    pub fn is_exception(&self) -> bool {
+        self.recurrence_id.is_some()
+    }
+
+    /// The start of the instance of this recurring event at **timestamp** (in seconds), in the same form as `DTSTART`.
+    ///
+    /// This is the `RECURRENCE-ID` of the exceptions of this event.
+    pub fn instance_time(&self, timestamp: i64, resolver: &mut dyn TimeZoneResolver) -> CalTime {
+        match &self.start {
+            CalTime::Floating(_) => {
+                let tzid = resolver.default_tzid();
+                CalTime::Floating(CalTime::from_timestamp(timestamp, Some(&tzid), false, resolver).local())
+            },
+            start => CalTime::from_timestamp(timestamp, start.tzid(), start.is_date(), resolver),
+        }
+    }
+
+    /// Replace the time zones of the dates of the event with ones the Content Provider can use (see [`TimeZones::normalize()`]).
+    pub fn normalize_time_zones(&mut self, time_zones: &mut TimeZones) {
+        self.start = time_zones.normalize(&self.start);
+        self.end = self.end.as_ref().map(|end| time_zones.normalize(end));
+        self.recurrence_id = self.recurrence_id.as_ref().map(|recurrence_id| time_zones.normalize(recurrence_id));
+        self.rdate = self.rdate.as_deref().and_then(|rdate| time_zones.normalize_date_list(rdate));
+        self.exdate = self.exdate.as_deref().and_then(|exdate| time_zones.normalize_date_list(exdate));
+    }
+
+    /// The length of the event in seconds.
+    ///
+    /// If the event has neither `DTEND` nor `DURATION`, it lasts *1 day* if it is all-day, or *0 seconds* otherwise (RFC 5545 section 3.6.1).
+    pub fn length(&self, resolver: &mut dyn TimeZoneResolver) -> Option<i64> {
+        Some(match (&self.end, &self.duration) {
+            (_, Some(duration)) => duration.as_seconds(),
+            (Some(end), None) => end.timestamp(resolver)? - self.start.timestamp(resolver)?,
+            (None, None) if self.is_all_day() => 86400,
+            (None, None) => 0,
+        })
+    }
+
+    /// Convert to a row of the *Events* table.
+    ///
+    /// The **calendar_id** column is not included.
+    /// Neither is **original_id** for exceptions, which is the ID of the row of the recurring event.
+    pub fn to_row(&self, resolver: &mut dyn TimeZoneResolver) -> Result<Row, String> {
+        let id = self.uid.as_deref().unwrap_or("<no UID>");
+        let unknown_tz = |time: &CalTime| format!("Event {id} has unknown time zone {:?}", time.tzid().unwrap_or_default());
+
+        let mut row = Row::new();
+        row.put(events::UID, self.uid.clone());
+        row.put(events::TITLE, self.summary.clone());
+        row.put(events::DESCRIPTION, self.description.clone());
+        row.put(events::LOCATION, self.location.clone());
+        row.put(events::ORGANIZER, self.organizer.clone());
+
+        let start = self.start.timestamp(resolver).ok_or_else(|| unknown_tz(&self.start))?;
+        row.put(events::DATE_START, start * 1000);
+        // All-day events must have UTC time zone
+        let timezone = match &self.start {
+            CalTime::Date(_) | CalTime::Utc(_) => "UTC".to_string(),
+            CalTime::Floating(_) => resolver.default_tzid(),
+            CalTime::Zoned { tzid, .. } => tzid.clone(),
+        };
+        row.put(events::TIMEZONE, timezone);
+        row.put(events::ALL_DAY, self.is_all_day());
+
+        let length = self.length(resolver).ok_or_else(|| unknown_tz(self.end.as_ref().unwrap_or(&self.start)))?;
+        // The Content Provider requires recurring events to have DURATION instead of DTEND
+        if self.is_recurring() {
+            row.put(events::DURATION, Duration::from_seconds(length).to_string());
+        } else {
+            row.put(events::DATE_END, (start + length) * 1000);
+            if let Some(tzid) = self.end.as_ref().and_then(CalTime::tzid) {
+                row.put(events::END_TIMEZONE, tzid);
+            }
+        }
+
+        row.put(events::R_RULE, self.rrule.clone());
+        row.put(events::R_DATE, self.rdate.clone());
+        row.put(events::EX_RULE, self.exrule.clone());
+        row.put(events::EX_DATE, self.exdate.clone());
+        if let Some(recurrence_id) = &self.recurrence_id {
+            let instance = recurrence_id.timestamp(resolver).ok_or_else(|| unknown_tz(recurrence_id))?;
+            row.put(events::ORIGINAL_INSTANCE_TIME, instance * 1000);
+            row.put(events::ORIGINAL_ALL_DAY, recurrence_id.is_date());
+        }
+
+        row.put(events::AVAILABILITY, match self.availability {
+            Availability::Busy => events::AVAILABILITY_BUSY,
+            Availability::Free => events::AVAILABILITY_FREE,
+            Availability::Tentative => events::AVAILABILITY_TENTATIVE,
+        });
+        row.put(events::ACCESS_LEVEL, match self.class {
+            None => events::ACCESS_DEFAULT,
+            Some(Class::Public) => events::ACCESS_PUBLIC,
+            Some(Class::Private) => events::ACCESS_PRIVATE,
+            Some(Class::Confidential) => events::ACCESS_CONFIDENTIAL,
+        });
+        if let Some(status) = self.status {
+            row.put(events::STATUS, match status {
+                EventStatus::Tentative => events::STATUS_TENTATIVE,
+                EventStatus::Confirmed => events::STATUS_CONFIRMED,
+                EventStatus::Cancelled => events::STATUS_CANCELED,
+            });
+        }
+
+        Ok(row)
+    }
+
+    /// Read the data of a row of the *Events* table that was queried with [`events::COPY_PROJECTION`].
+    ///
+    /// The [`recurrence_id`](Self::recurrence_id) of exceptions is in the time zone of the row,
+    /// which may be different from the one of the recurring event (see [`Self::instance_time()`]).
+    pub fn from_row(row: &Row, resolver: &mut dyn TimeZoneResolver) -> Result<Self, String> {
+        let all_day = row.get_long(events::ALL_DAY).is_some_and(|all_day| all_day != 0);
+        let timezone = row.get_text(events::TIMEZONE);
+        let start = row
+            .get_long(events::DATE_START)
+            .ok_or_else(|| format!("Event with ID {:?} has no {}", row.get_long(events::ID), events::DATE_START))?;
+
+        Ok(Self {
+            uid: row.get_text(events::UID).map(str::to_string),
+            summary: row.get_text(events::TITLE).map(str::to_string),
+            description: row.get_text(events::DESCRIPTION).map(str::to_string),
+            location: row.get_text(events::LOCATION).map(str::to_string),
+            organizer: row.get_text(events::ORGANIZER).map(str::to_string),
+            start: CalTime::from_timestamp(start / 1000, timezone, all_day, resolver),
+            end: row.get_long(events::DATE_END).map(|end| CalTime::from_timestamp(
+                end / 1000,
+                row.get_text(events::END_TIMEZONE).or(timezone),
+                all_day,
+                resolver,
+            )),
+            duration: row.get_text(events::DURATION).and_then(Duration::parse),
+            rrule: row.get_text(events::R_RULE).map(str::to_string),
+            exrule: row.get_text(events::EX_RULE).map(str::to_string),
+            rdate: row.get_text(events::R_DATE).map(str::to_string),
+            exdate: row.get_text(events::EX_DATE).map(str::to_string),
+            recurrence_id: row.get_long(events::ORIGINAL_INSTANCE_TIME).map(|instance| CalTime::from_timestamp(
+                instance / 1000,
+                timezone,
+                row.get_long(events::ORIGINAL_ALL_DAY).is_some_and(|all_day| all_day != 0),
+                resolver,
+            )),
+            status: row.get_long(events::STATUS).and_then(|status| match status {
+                events::STATUS_TENTATIVE => Some(EventStatus::Tentative),
+                events::STATUS_CONFIRMED => Some(EventStatus::Confirmed),
+                events::STATUS_CANCELED => Some(EventStatus::Cancelled),
+                _ => None,
+            }),
+            availability: match row.get_long(events::AVAILABILITY) {
+                Some(events::AVAILABILITY_FREE) => Availability::Free,
+                Some(events::AVAILABILITY_TENTATIVE) => Availability::Tentative,
+                _ => Availability::Busy,
+            },
+            class: row.get_long(events::ACCESS_LEVEL).and_then(|access| match access {
+                events::ACCESS_PUBLIC => Some(Class::Public),
+                events::ACCESS_PRIVATE => Some(Class::Private),
+                events::ACCESS_CONFIDENTIAL => Some(Class::Confidential),
+                _ => None,
+            }),
+        })
+    }
+
+    /// Convert to a `VEVENT` component.
+    ///
+    /// **dtstamp** is the time the component was created (usually [`now()`](super::time::now())).
+    pub fn to_component(&self, dtstamp: DateTime) -> Component {
+        let mut component = Component::new("VEVENT");
+        if let Some(uid) = &self.uid {
+            component.push(Property::new("UID", uid));
+        }
+        component.push(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+        if let Some(recurrence_id) = &self.recurrence_id {
+            component.push(new_date_property("RECURRENCE-ID", recurrence_id));
+        }
+        component.push(new_date_property("DTSTART", &self.start));
+        if let Some(end) = &self.end {
+            component.push(new_date_property("DTEND", end));
+        } else if let Some(duration) = &self.duration {
+            component.push(Property::new("DURATION", duration.to_string()));
+        }
+        if let Some(summary) = &self.summary {
+            component.push(Property::text("SUMMARY", summary));
+        }
+        if let Some(description) = &self.description {
+            component.push(Property::text("DESCRIPTION", description));
+        }
+        if let Some(location) = &self.location {
+            component.push(Property::text("LOCATION", location));
+        }
+        if let Some(organizer) = &self.organizer {
+            component.push(Property::new("ORGANIZER", format!("mailto:{organizer}")));
+        }
+        if let Some(rrule) = &self.rrule {
+            component.push(Property::new("RRULE", rrule));
+        }
+        if let Some(exrule) = &self.exrule {
+            component.push(Property::new("EXRULE", exrule));
+        }
+        if let Some(rdate) = &self.rdate {
+            component.properties.extend(date_list_properties("RDATE", rdate));
+        }
+        if let Some(exdate) = &self.exdate {
+            component.properties.extend(date_list_properties("EXDATE", exdate));
+        }
+        if let Some(status) = self.status {
+            component.push(Property::new("STATUS", status.as_str()));
+        }
+        match self.availability {
+            Availability::Busy => {},
+            Availability::Free => component.push(Property::new("TRANSP", "TRANSPARENT")),
+            Availability::Tentative => component.push(Property::new(BUSY_STATUS, "TENTATIVE")),
+        }
+        if let Some(class) = self.class {
+            component.push(Property::new("CLASS", class.as_str()));
+        }
+
+        component
+    }
+}
+
+/// Create a property whose value is a `DATE` or `DATE-TIME` (e.g. `DTSTART`), adding the `VALUE` or `TZID` parameters as necessary.
+pub fn new_date_property(name: &str, time: &CalTime) -> Property {
+    let property = Property::new(name, time.value());
+    match time {
+        CalTime::Date(_) => property.with_param("VALUE", "DATE"),
+        CalTime::Zoned { tzid, .. } => property.with_param("TZID", tzid),
+        CalTime::Floating(_) | CalTime::Utc(_) => property,
+    }
+}
+
+/// Parse a property whose value is a `DATE` or `DATE-TIME` (e.g. `DTSTART`).
+pub fn date_property(prop: &Property) -> Option<CalTime> {
+    CalTime::parse(&prop.value, prop.param("TZID"))
+}
+
+/// Remove the `mailto:` scheme from a *CAL-ADDRESS* value.
+pub fn strip_mailto(value: &str) -> String {
+    let value = value.trim();
+    match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => value[7..].to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Combine the values of multiple `RDATE` or `EXDATE` properties into the format the Content Provider uses.
+///
+/// Each property becomes a line of comma-separated values, prefixed by `<TZID>;` if the property has a time zone.
+/// For example: `"America/New_York;19970714T133000,19970715T133000"`.
+pub fn date_list<'a>(props: impl Iterator<Item = &'a Property>) -> Option<String> {
+    let lines = props
+        .map(|prop| {
+            let values = split_list(&prop.value)
+                .into_iter()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join(",");
+            match prop.param("TZID") {
+                Some(tzid) => format!("{tzid};{values}"),
+                None => values,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Inverse of [`date_list()`]: create one `RDATE` or `EXDATE` property for each line of the Content Provider's format.
+pub fn date_list_properties(name: &str, list: &str) -> Vec<Property> {
+    list.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (tzid, values) = match line.split_once(';') {
+                Some((tzid, values)) => (Some(tzid), values),
+                None => (None, line),
+            };
+            let property = Property::new(name, values.trim());
+            match tzid {
+                Some(tzid) => property.with_param("TZID", tzid),
+                // Date values have no time part
+                None if !values.contains('T') => property.with_param("VALUE", "DATE"),
+                None => property,
+            }
+        })
+        .collect()
+}
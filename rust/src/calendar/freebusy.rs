@@ -0,0 +1,265 @@
+//! Generation of `VFREEBUSY` components (RFC 5545 section 3.6.4), which tell when the user is busy without showing the events.
+use super::{
+    event::{Availability, Event, EventStatus},
+    ical::{Component, Property},
+    recurrence::Recurrence,
+    time::{DateTime, TimeZoneResolver},
+    timezone::{TimeZones, VTimeZone},
+};
+
+/// The `FBTYPE` of a [`Period`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FreeBusyType {
+    Busy,
+    BusyTentative,
+}
+impl FreeBusyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Busy => "BUSY",
+            Self::BusyTentative => "BUSY-TENTATIVE",
+        }
+    }
+}
+
+/// A period of time when the user is busy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Period {
+    /// In seconds since the *Unix epoch*.
+    pub start: i64,
+    /// In seconds since the *Unix epoch* (exclusive).
+    pub end: i64,
+    pub fb_type: FreeBusyType,
+}
+
+/// Find the periods between **from** and **to** (in seconds since the *Unix epoch*) when the user is busy because of the events in **calendars**.
+///
+/// Events that are [free](Availability::Free) or cancelled don't block time,
+/// and events that are [tentative](Availability::Tentative) (or have `STATUS:TENTATIVE`) are `BUSY-TENTATIVE`.
+/// Recurring events are expanded, and their instances are replaced by their exceptions (`RECURRENCE-ID`).
+/// The periods of each type are merged when they overlap, and are sorted by their start.
+///
+/// Events that can't be read are skipped, and a message is returned for each of them so that they can be reported.
+pub fn busy_periods(calendars: &[Component], from: i64, to: i64, resolver: &mut dyn TimeZoneResolver) -> (Vec<Period>, Vec<String>) {
+    let embedded = VTimeZone::from_calendars(calendars);
+    let resolver = &mut TimeZones { system: resolver, embedded: &embedded };
+    let mut messages = Vec::new();
+
+    let mut events = Vec::new();
+    for component in calendars.iter().flat_map(|calendar| calendar.components("VEVENT")) {
+        match Event::from_component(component) {
+            Ok(event) => events.push(event),
+            Err(err) => messages.push(format!("Skipping event: {err}")),
+        }
+    }
+    // The instances that were replaced by exceptions
+    let mut replaced = Vec::new();
+    for event in &events {
+        if let (Some(uid), Some(recurrence_id)) = (&event.uid, &event.recurrence_id) {
+            match recurrence_id.timestamp(resolver) {
+                Some(timestamp) => replaced.push((uid.as_str(), timestamp)),
+                None => messages.push(format!("Exception of event {uid} has unknown time zone")),
+            }
+        }
+    }
+
+    let mut periods = Vec::new();
+    for event in &events {
+        let id = event.uid.as_deref().unwrap_or("<no UID>");
+        let fb_type = match (event.availability, event.status) {
+            (Availability::Free, _) | (_, Some(EventStatus::Cancelled)) => continue,
+            (Availability::Tentative, _) | (_, Some(EventStatus::Tentative)) => FreeBusyType::BusyTentative,
+            (Availability::Busy, _) => FreeBusyType::Busy,
+        };
+        let Some(length) = event.length(resolver) else {
+            messages.push(format!("Event {id} has unknown time zone"));
+            continue;
+        };
+
+        let starts = if event.is_recurring() && !event.is_exception() {
+            // Include the instances that started before the range but end in it
+            match Recurrence::from_event(event).and_then(|recurrence| recurrence.occurrences(from - length, to, resolver)) {
+                Ok(occurrences) => occurrences.into_iter()
+                    .map(|occurrence| occurrence.timestamp)
+                    .filter(|timestamp| !replaced.contains(&(id, *timestamp)))
+                    .collect(),
+                Err(err) => {
+                    messages.push(format!("Skipping event {id}: {err}"));
+                    continue;
+                },
+            }
+        } else {
+            match event.start.timestamp(resolver) {
+                Some(start) => vec![start],
+                None => {
+                    messages.push(format!("Event {id} has unknown time zone"));
+                    continue;
+                },
+            }
+        };
+
+        for start in starts {
+            let (start, end) = (start.max(from), (start + length).min(to));
+            if start < end {
+                periods.push(Period { start, end, fb_type });
+            }
+        }
+    }
+
+    (merge(periods), messages)
+}
+
+/// Merge the periods of the same type that overlap (or are next to each other).
+fn merge(mut periods: Vec<Period>) -> Vec<Period> {
+    periods.sort_by_key(|period| (period.fb_type, period.start));
+    let mut merged = Vec::<Period>::new();
+    for period in periods {
+        match merged.last_mut() {
+            Some(last) if last.fb_type == period.fb_type && period.start <= last.end => last.end = last.end.max(period.end),
+            _ => merged.push(period),
+        }
+    }
+    merged.sort_by_key(|period| period.start);
+    merged
+}
+
+/// Build a `VFREEBUSY` with the busy **periods** between **from** and **to** (in seconds since the *Unix epoch*).
+///
+/// **dtstamp** is the time the component was created (usually [`now()`](super::time::now())).
+pub fn to_component(periods: &[Period], from: i64, to: i64, dtstamp: DateTime) -> Component {
+    let utc = |timestamp: i64| format!("{}Z", DateTime::from_timestamp(timestamp));
+
+    let mut component = Component::new("VFREEBUSY");
+    component.push(Property::new("UID", format!("freebusy-{from}-{to}-{}@davsync", dtstamp.timestamp())));
+    component.push(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+    component.push(Property::new("DTSTART", utc(from)));
+    component.push(Property::new("DTEND", utc(to)));
+    for period in periods {
+        component.push(
+            Property::new("FREEBUSY", format!("{}/{}", utc(period.start), utc(period.end)))
+                .with_param("FBTYPE", period.fb_type.as_str())
+        );
+    }
+    component
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    /// Doesn't know any time zone, so the events must be in UTC.
+    struct NoTimeZones;
+    impl TimeZoneResolver for NoTimeZones {
+        fn utc_offset(&mut self, _tzid: &str, _local: i64) -> Option<i32> {
+            None
+        }
+        fn default_tzid(&mut self) -> String {
+            "UTC".to_string()
+        }
+    }
+
+    fn utc(value: &str) -> i64 {
+        DateTime::parse(value).unwrap().timestamp()
+    }
+    fn period(start: &str, end: &str, fb_type: FreeBusyType) -> Period {
+        Period { start: utc(start), end: utc(end), fb_type }
+    }
+    /// A calendar with an event for each of the **events**, which are the properties of the event separated by spaces.
+    ///
+    /// Events without a `UID` get their index.
+    fn calendar(events: &[&str]) -> Vec<Component> {
+        let events = events.iter()
+            .enumerate()
+            .map(|(i, properties)| {
+                let uid = if properties.contains("UID:") { String::new() } else { format!("UID:{i}\r\n") };
+                format!("BEGIN:VEVENT\r\n{uid}{}\r\nEND:VEVENT\r\n", properties.replace(' ', "\r\n"))
+            })
+            .collect::<String>();
+        ical::parse(&format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{events}END:VCALENDAR\r\n")).unwrap()
+    }
+    fn busy(events: &[&str]) -> (Vec<Period>, Vec<String>) {
+        busy_periods(&calendar(events), utc("20240101T000000"), utc("20240104T000000"), &mut NoTimeZones)
+    }
+
+    #[test]
+    fn expand_recurring_events() {
+        let (periods, messages) = busy(&[
+            "DTSTART:20240101T090000Z DTEND:20240101T100000Z RRULE:FREQ=DAILY",
+            // Moves the instance of the 2nd
+            "UID:0 RECURRENCE-ID:20240102T090000Z DTSTART:20240102T150000Z DTEND:20240102T160000Z",
+        ]);
+        assert!(messages.is_empty());
+        assert_eq!(periods, [
+            period("20240101T090000", "20240101T100000", FreeBusyType::Busy),
+            period("20240102T150000", "20240102T160000", FreeBusyType::Busy),
+            period("20240103T090000", "20240103T100000", FreeBusyType::Busy),
+        ]);
+
+        // A cancelled instance doesn't block time
+        let (periods, _) = busy(&[
+            "DTSTART:20240101T090000Z DTEND:20240101T100000Z RRULE:FREQ=DAILY;COUNT=2",
+            "UID:0 RECURRENCE-ID:20240102T090000Z DTSTART:20240102T090000Z DTEND:20240102T100000Z STATUS:CANCELLED",
+        ]);
+        assert_eq!(periods, [period("20240101T090000", "20240101T100000", FreeBusyType::Busy)]);
+    }
+
+    #[test]
+    fn skip_free_and_cancelled_events() {
+        let (periods, messages) = busy(&[
+            "DTSTART:20240101T090000Z DTEND:20240101T100000Z TRANSP:TRANSPARENT",
+            "DTSTART:20240101T110000Z DTEND:20240101T120000Z STATUS:CANCELLED",
+            "DTSTART:20240101T130000Z DTEND:20240101T140000Z TRANSP:OPAQUE",
+            "DTSTART:20240101T150000Z DTEND:20240101T160000Z STATUS:TENTATIVE",
+            "SUMMARY:Unscheduled",
+        ]);
+        assert_eq!(periods, [
+            period("20240101T130000", "20240101T140000", FreeBusyType::Busy),
+            period("20240101T150000", "20240101T160000", FreeBusyType::BusyTentative),
+        ]);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Skipping event: "), "{messages:?}");
+    }
+
+    #[test]
+    fn merge_overlapping_periods() {
+        let (periods, _) = busy(&[
+            "DTSTART:20240101T090000Z DTEND:20240101T100000Z",
+            "DTSTART:20240101T093000Z DTEND:20240101T110000Z",
+            // Right after the others
+            "DTSTART:20240101T110000Z DTEND:20240101T113000Z",
+            "DTSTART:20240101T120000Z DTEND:20240101T130000Z",
+            // Periods of different types are not merged
+            "DTSTART:20240101T123000Z DTEND:20240101T140000Z STATUS:TENTATIVE",
+        ]);
+        assert_eq!(periods, [
+            period("20240101T090000", "20240101T113000", FreeBusyType::Busy),
+            period("20240101T120000", "20240101T130000", FreeBusyType::Busy),
+            period("20240101T123000", "20240101T140000", FreeBusyType::BusyTentative),
+        ]);
+    }
+
+    #[test]
+    fn clip_to_range() {
+        let (periods, _) = busy(&[
+            "DTSTART:20231231T220000Z DTEND:20240101T020000Z",
+            "DTSTART:20240103T230000Z DTEND:20240104T010000Z",
+            "DTSTART:20231230T090000Z DTEND:20231230T100000Z",
+            "DTSTART:20240105T090000Z DTEND:20240105T100000Z",
+        ]);
+        assert_eq!(periods, [
+            period("20240101T000000", "20240101T020000", FreeBusyType::Busy),
+            period("20240103T230000", "20240104T000000", FreeBusyType::Busy),
+        ]);
+        // An instance that started before the range but ends in it
+        let (instances, _) = busy(&["DTSTART:20231231T230000Z DURATION:PT2H RRULE:FREQ=WEEKLY"]);
+        assert_eq!(instances, [period("20240101T000000", "20240101T010000", FreeBusyType::Busy)]);
+
+        let component = to_component(&periods, utc("20240101T000000"), utc("20240104T000000"), DateTime::parse("20240101T000000").unwrap());
+        assert_eq!(component.value("DTSTART"), Some("20240101T000000Z"));
+        assert_eq!(component.properties("FREEBUSY").map(|property| property.value.as_str()).collect::<Vec<_>>(), [
+            "20240101T000000Z/20240101T020000Z",
+            "20240103T230000Z/20240104T000000Z",
+        ]);
+    }
+}
@@ -0,0 +1,550 @@
+//! Parser and serializer for the *iCalendar* format ([RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545)).
+//!
+//! A file is parsed into a tree of [`Component`]s (`VCALENDAR`, `VEVENT`, `VALARM`, ...) which hold [`Property`]s.
+//! A [`Component`] is written back to the file format with its [`Display`] implementation.
+//! This module does not interpret the *values* of the properties;
+//! that is done by the typed representations (e.g. [`Event`](super::event::Event)).
+//!
+//! *vCard* files ([RFC 6350](https://datatracker.ietf.org/doc/html/rfc6350)) use the same *content lines*, so they are also parsed by this module.
+use std::fmt::{self, Display};
+
+/// A *component* (the part between `BEGIN:<name>` and `END:<name>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    /// Always uppercase.
+    pub name: String,
+    pub properties: Vec<Property>,
+    /// Components nested in this one (e.g. a `VALARM` in a `VEVENT`).
+    pub components: Vec<Component>,
+}
+impl Component {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into().to_ascii_uppercase(),
+            properties: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+
+    /// Get the first property with this **name** (case insensitive).
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|prop| prop.name.eq_ignore_ascii_case(name))
+    }
+    /// Get all the properties with this **name** (case insensitive).
+    pub fn properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> + 'a {
+        self.properties.iter().filter(move |prop| prop.name.eq_ignore_ascii_case(name))
+    }
+    /// Shortcut to get the *raw* value of the first property with this **name**.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.property(name).map(|prop| prop.value.as_str())
+    }
+
+    /// Get all the *sub-components* with this **name** (case insensitive).
+    pub fn components<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Component> + 'a {
+        self.components.iter().filter(move |comp| comp.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Add a property at the end of the property list.
+    pub fn push(&mut self, property: Property) {
+        self.properties.push(property)
+    }
+    /// Remove all the properties with this **name** and add a new one with the **value**.
+    pub fn set(&mut self, property: Property) {
+        self.remove(&property.name);
+        self.properties.push(property)
+    }
+    /// Remove all the properties with this **name** (case insensitive).
+    pub fn remove(&mut self, name: &str) {
+        self.properties.retain(|prop| !prop.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A *content line* of a [`Component`], e.g. `DTSTART;TZID=America/New_York:19970714T133000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// Always uppercase.
+    pub name: String,
+    pub params: Vec<Param>,
+    /// The *raw* value, as it appears in the file.
+    /// Text values still have their escape sequences (see [`unescape_text()`]).
+    pub value: String,
+}
+impl Property {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into().to_ascii_uppercase(),
+            params: Vec::new(),
+            value: value.into(),
+        }
+    }
+    /// Create a property with a *TEXT* value, which will be escaped.
+    pub fn text(name: impl Into<String>, value: &str) -> Self {
+        Self::new(name, escape_text(value))
+    }
+
+    /// Builder-like method to add a parameter.
+    pub fn with_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push(Param {
+            name: name.into().to_ascii_uppercase(),
+            values: vec![value.into()],
+        });
+        self
+    }
+
+    /// Get the first value of the parameter with this **name** (case insensitive).
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+            .and_then(|param| param.values.first())
+            .map(String::as_str)
+    }
+
+    /// Set the parameter with this **name** (case insensitive) to a single **value**, replacing its previous values.
+    pub fn set_param(&mut self, name: &str, value: impl Into<String>) {
+        match self.params.iter_mut().find(|param| param.name.eq_ignore_ascii_case(name)) {
+            Some(param) => param.values = vec![value.into()],
+            None => self.params.push(Param {
+                name: name.to_ascii_uppercase(),
+                values: vec![value.into()],
+            }),
+        }
+    }
+    /// Remove the parameter with this **name** (case insensitive).
+    pub fn remove_param(&mut self, name: &str) {
+        self.params.retain(|param| !param.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The value interpreted as *TEXT*, with escape sequences resolved.
+    pub fn text_value(&self) -> String {
+        unescape_text(&self.value)
+    }
+}
+
+impl Display for Component {
+    /// Write the component as *content lines* ending in `CRLF`, including the `BEGIN` and `END` lines.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BEGIN:{}\r\n", self.name)?;
+        for property in &self.properties {
+            write!(f, "{property}")?;
+        }
+        for component in &self.components {
+            write!(f, "{component}")?;
+        }
+        write!(f, "END:{}\r\n", self.name)
+    }
+}
+impl Display for Property {
+    /// Write the property as a *content line* ending in `CRLF`, folded so that no line is longer than 75 octets.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = self.name.clone();
+        for param in &self.params {
+            line.push(';');
+            line.push_str(&param.name);
+            line.push('=');
+            for (i, value) in param.values.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                // Values with these characters must be quoted
+                if value.contains([';', ':', ',']) {
+                    line.push('"');
+                    line.push_str(&value.replace('"', "'"));
+                    line.push('"');
+                } else {
+                    line.push_str(value);
+                }
+            }
+        }
+        line.push(':');
+        line.push_str(&self.value);
+
+        write_folded(f, &line)
+    }
+}
+
+/// Write a *content line* splitting it into multiple lines of at most 75 octets,
+/// where each subsequent line starts with a space.
+fn write_folded(f: &mut fmt::Formatter<'_>, line: &str) -> fmt::Result {
+    const MAX_LEN: usize = 75;
+    let mut rest = line;
+    // The first line has no leading space, so it can be 1 octet longer
+    let mut max_len = MAX_LEN;
+    while rest.len() > max_len {
+        // Don't split a multi-byte character
+        let mut split = max_len;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        f.write_str(&rest[..split])?;
+        f.write_str("\r\n ")?;
+        rest = &rest[split..];
+        max_len = MAX_LEN - 1;
+    }
+    f.write_str(rest)?;
+    f.write_str("\r\n")
+}
+
+/// A *property parameter*, e.g. `TZID=America/New_York` or `MEMBER="mailto:a@b.com","mailto:c@d.com"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    /// Always uppercase.
+    pub name: String,
+    /// The values without the quotes.
+    pub values: Vec<String>,
+}
+
+/// An error that occurred while parsing an *iCalendar* file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line number (1-based, *before* unfolding) where the error occurred.
+    pub line: usize,
+    pub msg: String,
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error parsing iCalendar at line {}: {}", self.line, self.msg)
+    }
+}
+impl std::error::Error for ParseError {}
+
+/// Parse the contents of an *iCalendar* file.
+///
+/// Returns the top-level components, which should be `VCALENDAR`s.
+/// There is usually only one, but a file is allowed to have more.
+pub fn parse(content: &str) -> Result<Vec<Component>, ParseError> {
+    // Components that have been opened with BEGIN but not yet closed
+    let mut stack = Vec::<Component>::new();
+    let mut result = Vec::new();
+
+    for (line_num, line) in unfold(content) {
+        let err = |msg: String| ParseError { line: line_num, msg };
+        let property = parse_line(&line).map_err(err)?;
+
+        if property.name == "BEGIN" {
+            stack.push(Component::new(property.value.trim()));
+        } else if property.name == "END" {
+            let component = stack
+                .pop()
+                .ok_or_else(|| err(format!("Found END:{} without a BEGIN", property.value)))?;
+            if !component.name.eq_ignore_ascii_case(property.value.trim()) {
+                return Err(err(format!(
+                    "Expected END:{}, found END:{}",
+                    component.name, property.value
+                )));
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.components.push(component),
+                None => result.push(component),
+            }
+        } else {
+            stack
+                .last_mut()
+                .ok_or_else(|| err(format!("Property {} is outside of a component", property.name)))?
+                .properties
+                .push(property);
+        }
+    }
+
+    if let Some(component) = stack.last() {
+        return Err(ParseError {
+            line: content.lines().count(),
+            msg: format!("Reached end of file without END:{}", component.name),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Join lines that were *folded* (split into multiple lines that start with a space or tab).
+///
+/// Returns the *logical* lines along with the line number where they start.
+/// Accepts both `CRLF` and `LF` line endings, and skips empty lines.
+fn unfold(content: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::<(usize, String)>::new();
+
+    for (i, line) in content.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match line.strip_prefix([' ', '\t']) {
+            Some(continuation) if !lines.is_empty() => lines.last_mut().unwrap().1.push_str(continuation),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push((i + 1, line.to_string())),
+        }
+    }
+
+    lines
+}
+
+/// Parse an (unfolded) *content line* in the form of `name *(";" param) ":" value`.
+fn parse_line(line: &str) -> Result<Property, String> {
+    // Find where the name ends
+    let name_end = line
+        .find([';', ':'])
+        .ok_or_else(|| format!("Line {line:?} is missing ':'"))?;
+    let name = &line[..name_end];
+    // vCard properties can have a group prefix (e.g. `item1.EMAIL`)
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+        return Err(format!("Invalid property name {name:?}"));
+    }
+
+    let mut params = Vec::new();
+    let mut rest = &line[name_end..];
+    // Parse params until reaching the ':' that separates the value
+    while let Some(param) = rest.strip_prefix(';') {
+        let (param_name, after_name) = param
+            .split_once('=')
+            .ok_or_else(|| format!("Parameter of {name} is missing '='"))?;
+        if param_name.is_empty() || !param_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!("Invalid parameter name {param_name:?} in property {name}"));
+        }
+
+        let mut values = Vec::new();
+        rest = after_name;
+        loop {
+            // Values can be quoted, in which case they can contain ';', ':' and ','
+            let (value, after_value) = if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| format!("Unclosed quote in parameter {param_name} of {name}"))?;
+                (&quoted[..end], &quoted[end + 1..])
+            } else {
+                let end = rest
+                    .find([',', ';', ':'])
+                    .ok_or_else(|| format!("Line of property {name} is missing ':'"))?;
+                (&rest[..end], &rest[end..])
+            };
+            values.push(value.to_string());
+
+            match after_value.strip_prefix(',') {
+                Some(next) => rest = next,
+                None => {
+                    rest = after_value;
+                    break;
+                }
+            }
+        }
+
+        params.push(Param {
+            name: param_name.to_ascii_uppercase(),
+            values,
+        });
+    }
+
+    let value = rest
+        .strip_prefix(':')
+        .ok_or_else(|| format!("Expected ':' after parameters of {name}"))?;
+
+    Ok(Property {
+        name: name.to_ascii_uppercase(),
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Resolve the escape sequences of a *TEXT* value (`\\`, `\;`, `\,`, `\n` or `\N`).
+pub fn unescape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Inverse of [`unescape_text()`].
+pub fn escape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Split a value that is a *list* (e.g. `EXDATE` or `CATEGORIES`) at the commas that are not escaped.
+pub fn split_list(value: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                result.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&value[start..]);
+    result
+}
+
+/// The calendars of the examples in RFC 7265 and RFC 6321 (appendix B of both), which are also written there as *jCal* and *xCal*.
+#[cfg(test)]
+pub(crate) mod examples {
+    /// Example 1: a single event.
+    pub const SIMPLE: &str = "BEGIN:VCALENDAR\r\n\
+        CALSCALE:GREGORIAN\r\n\
+        PRODID:-//Example Inc.//Example Calendar//EN\r\n\
+        VERSION:2.0\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTAMP:20080205T191224Z\r\n\
+        DTSTART;VALUE=DATE:20081006\r\n\
+        SUMMARY:Planning meeting\r\n\
+        UID:4088E990AD89CB3DBB484909\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    /// Example 2: a recurring event with an exception, and its time zone.
+    pub const RECURRING: &str = "BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        PRODID:-//Example Corp.//Example Client//EN\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        LAST-MODIFIED:20040110T032845Z\r\n\
+        TZID:US/Eastern\r\n\
+        BEGIN:DAYLIGHT\r\n\
+        DTSTART:20000404T020000\r\n\
+        RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=4\r\n\
+        TZNAME:EDT\r\n\
+        TZOFFSETFROM:-0500\r\n\
+        TZOFFSETTO:-0400\r\n\
+        END:DAYLIGHT\r\n\
+        BEGIN:STANDARD\r\n\
+        DTSTART:20001026T020000\r\n\
+        RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r\n\
+        TZNAME:EST\r\n\
+        TZOFFSETFROM:-0400\r\n\
+        TZOFFSETTO:-0500\r\n\
+        END:STANDARD\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTAMP:20060206T001121Z\r\n\
+        DTSTART;TZID=US/Eastern:20060102T120000\r\n\
+        DURATION:PT1H\r\n\
+        RRULE:FREQ=DAILY;COUNT=5\r\n\
+        RDATE;TZID=US/Eastern;VALUE=PERIOD:20060102T150000/PT2H\r\n\
+        SUMMARY:Event #2\r\n\
+        DESCRIPTION:We are having a meeting all this week at 12pm for one hour\\, wi\r\n \
+        th an additional meeting on the first day 2 hours long.\\nPlease bring your\r\n  \
+        own lunch for the 12 pm meetings.\r\n\
+        UID:00959BC664CA650E933C892C@example.com\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTAMP:20060206T001121Z\r\n\
+        DTSTART;TZID=US/Eastern:20060104T140000\r\n\
+        DURATION:PT1H\r\n\
+        RECURRENCE-ID;TZID=US/Eastern:20060104T120000\r\n\
+        SUMMARY:Event #2 bis\r\n\
+        UID:00959BC664CA650E933C892C@example.com\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nested_components() {
+        let content = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:1\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nEND:VALARM\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let calendars = parse(content).unwrap();
+        assert_eq!(calendars.len(), 1);
+        let event = calendars[0].components("vevent").next().unwrap();
+        assert_eq!(event.value("uid"), Some("1"));
+        assert_eq!(event.components("VALARM").count(), 1);
+        // Writing it back gives the same content
+        assert_eq!(calendars[0].to_string(), content);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("BEGIN:VCALENDAR\nEND:VEVENT\n").unwrap_err().line, 2);
+        assert_eq!(parse("UID:1\n").unwrap_err().line, 1);
+        assert!(parse("BEGIN:VCALENDAR\nUID:1\n").is_err());
+        assert!(parse("BEGIN:VCALENDAR\nNO VALUE\nEND:VCALENDAR\n").is_err());
+    }
+
+    #[test]
+    fn unfold_lines() {
+        // RFC 5545 section 3.1: a CRLF followed by a single space or tab is removed
+        let content = "BEGIN:VEVENT\r\nDESCRIPTION:This is a lo\r\n ng description\r\n\tthat exists on a long line.\nEND:VEVENT\n";
+        let event = &parse(content).unwrap()[0];
+        assert_eq!(event.value("DESCRIPTION"), Some("This is a long descriptionthat exists on a long line."));
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let property = Property::text("DESCRIPTION", &"0123456789".repeat(20));
+        let written = property.to_string();
+        let lines = written.strip_suffix("\r\n").unwrap().split("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(unfold(&written), vec![(1, format!("DESCRIPTION:{}", "0123456789".repeat(20)))]);
+    }
+
+    #[test]
+    fn fold_multibyte_characters() {
+        let property = Property::text("SUMMARY", &"ñ".repeat(60));
+        let written = property.to_string();
+        assert!(written.split("\r\n").all(|line| line.len() <= 75));
+        let event = &parse(&format!("BEGIN:VEVENT\r\n{written}END:VEVENT\r\n")).unwrap()[0];
+        assert_eq!(event.property("SUMMARY").unwrap().text_value(), "ñ".repeat(60));
+    }
+
+    #[test]
+    fn escape_text_values() {
+        let text = "Meeting; room 3, floor 2\\B\nBring notes";
+        let escaped = escape_text(text);
+        assert_eq!(escaped, r"Meeting\; room 3\, floor 2\\B\nBring notes");
+        assert_eq!(unescape_text(&escaped), text);
+        assert_eq!(unescape_text("Line 1\\NLine 2"), "Line 1\nLine 2");
+        assert_eq!(split_list("a\\,b,c,,d"), vec!["a\\,b", "c", "", "d"]);
+    }
+
+    #[test]
+    fn parse_params() {
+        let property = parse_line(
+            r#"ATTENDEE;ROLE=REQ-PARTICIPANT;DELEGATED-FROM="mailto:a@example.com","mailto:b@example.com";CN="Doe, John":mailto:john@example.com"#
+        ).unwrap();
+        assert_eq!(property.name, "ATTENDEE");
+        assert_eq!(property.value, "mailto:john@example.com");
+        assert_eq!(property.param("role"), Some("REQ-PARTICIPANT"));
+        assert_eq!(property.params[1].values, vec!["mailto:a@example.com", "mailto:b@example.com"]);
+        assert_eq!(property.param("CN"), Some("Doe, John"));
+
+        assert!(parse_line(r#"ATTENDEE;CN="Doe:mailto:john@example.com"#).is_err());
+        assert!(parse_line("ATTENDEE;CN:mailto:john@example.com").is_err());
+    }
+
+    #[test]
+    fn quote_params() {
+        let property = Property::new("ATTENDEE", "mailto:john@example.com")
+            .with_param("CN", "Doe, John")
+            .with_param("ROLE", "CHAIR");
+        let written = property.to_string();
+        assert_eq!(written, "ATTENDEE;CN=\"Doe, John\";ROLE=CHAIR:mailto:john@example.com\r\n");
+        assert_eq!(parse_line(written.trim_end()).unwrap(), property);
+
+        // Quotes can't be escaped in a parameter value
+        let property = Property::new("X-TEST", "").with_param("X-NAME", "say \"hi\"; bye");
+        assert_eq!(property.to_string(), "X-TEST;X-NAME=\"say 'hi'; bye\":\r\n");
+    }
+}
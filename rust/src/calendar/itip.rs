@@ -0,0 +1,537 @@
+//! Scheduling messages of *iTIP* ([RFC 5546](https://datatracker.ietf.org/doc/html/rfc5546)).
+//!
+//! A message is a `VCALENDAR` with a `METHOD`, which has the events the message is about.
+//! The organizer of an event sends a [`request()`] to the attendees when the event changes, and a [`cancel()`] when it is removed,
+//! and updates the event with the `REPLY`s of the attendees with [`process()`].
+//! Attendees can also propose changes to the event (`COUNTER`), which the organizer accepts with [`accept_counter()`] or rejects with [`decline_counter()`].
+//!
+//! The messages are only built and read by this module; it is up to the user to send them (e.g. as an email attachment).
+use super::{
+    attendee::{Attendee, PartStat},
+    ical::{Component, Property},
+    time::DateTime,
+    PRODID,
+};
+
+/// The `METHOD` of an iTIP message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Publish,
+    Request,
+    Reply,
+    Add,
+    Cancel,
+    Refresh,
+    Counter,
+    DeclineCounter,
+}
+impl Method {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "PUBLISH" => Some(Self::Publish),
+            "REQUEST" => Some(Self::Request),
+            "REPLY" => Some(Self::Reply),
+            "ADD" => Some(Self::Add),
+            "CANCEL" => Some(Self::Cancel),
+            "REFRESH" => Some(Self::Refresh),
+            "COUNTER" => Some(Self::Counter),
+            "DECLINECOUNTER" => Some(Self::DeclineCounter),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Publish => "PUBLISH",
+            Self::Request => "REQUEST",
+            Self::Reply => "REPLY",
+            Self::Add => "ADD",
+            Self::Cancel => "CANCEL",
+            Self::Refresh => "REFRESH",
+            Self::Counter => "COUNTER",
+            Self::DeclineCounter => "DECLINECOUNTER",
+        }
+    }
+
+    /// Get the method of a **message**.
+    pub fn of(message: &Component) -> Option<Self> {
+        message.value("METHOD").and_then(Self::parse)
+    }
+}
+
+/// The properties of an event that an attendee can propose to change in a `COUNTER`.
+const COUNTER_PROPERTIES: [&str; 6] = ["DTSTART", "DTEND", "DURATION", "SUMMARY", "LOCATION", "DESCRIPTION"];
+
+/// Build a `REQUEST` with the event with **uid** in **calendar** (and its exceptions), to send to its attendees after the organizer changed it.
+///
+/// The `SEQUENCE` of the events in **calendar** is incremented,
+/// because that is how the attendees know that this request is newer than the one they have.
+/// Returns an error if there is no such event, or it has no `ORGANIZER` or `ATTENDEE`s.
+pub fn request(calendar: &mut Component, uid: &str, dtstamp: DateTime) -> Result<Component, String> {
+    let mut events = Vec::new();
+    for event in calendar.components.iter_mut().filter(|comp| is_event(comp, uid)) {
+        if event.property("ORGANIZER").is_none() {
+            return Err(format!("Event {uid} has no ORGANIZER"));
+        }
+        increment_sequence(event);
+
+        let mut event = event.clone();
+        event.set(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+        // Alarms are personal, so they are not sent to the attendees
+        event.components.retain(|comp| !comp.name.eq_ignore_ascii_case("VALARM"));
+        // Ask the attendees that haven't answered to do so
+        for attendee in event.properties.iter_mut().filter(|prop| prop.name.eq_ignore_ascii_case("ATTENDEE")) {
+            if Attendee::from_property(attendee).status == PartStat::NeedsAction {
+                attendee.set_param("RSVP", "TRUE");
+            }
+        }
+        events.push(event);
+    }
+
+    if events.is_empty() {
+        return Err(format!("Event {uid} not found"));
+    }
+    if !events.iter().any(|event| event.property("ATTENDEE").is_some()) {
+        return Err(format!("Event {uid} has no attendees"));
+    }
+    Ok(message(Method::Request, calendar, events))
+}
+
+/// Build a `CANCEL` for the event with **uid** in **calendar**, to send to its attendees when the organizer removes it,
+/// and mark the event as cancelled in **calendar** (`STATUS:CANCELLED`).
+///
+/// If **recurrence_id** is [`Some`], only that instance of the recurring event is cancelled.
+/// The instance is excluded from the recurring event (`EXDATE`) if it is not an exception.
+/// Returns an error if there is no such event, or it has no `ORGANIZER`.
+pub fn cancel(calendar: &mut Component, uid: &str, recurrence_id: Option<&str>, dtstamp: DateTime) -> Result<Component, String> {
+    let master = calendar.components.iter()
+        .position(|comp| is_event(comp, uid) && comp.property("RECURRENCE-ID").is_none());
+    let exception = recurrence_id.and_then(|recurrence_id| calendar.components.iter()
+        .position(|comp| is_event(comp, uid) && comp.value("RECURRENCE-ID").is_some_and(|value| value.trim() == recurrence_id.trim())));
+    let index = exception.or(master).ok_or_else(|| format!("Event {uid} not found"))?;
+    if calendar.components[index].property("ORGANIZER").is_none() {
+        return Err(format!("Event {uid} has no ORGANIZER"));
+    }
+
+    let mut cancelled = Vec::new();
+    match (recurrence_id, exception) {
+        // An instance that is not an exception: exclude it from the recurring event
+        (Some(recurrence_id), None) => {
+            let event = &mut calendar.components[index];
+            increment_sequence(event);
+            let mut instance = event.clone();
+            let start = event.property("DTSTART").ok_or_else(|| format!("Event {uid} has no DTSTART"))?;
+            let mut recurrence_id = Property::new("RECURRENCE-ID", recurrence_id.trim());
+            recurrence_id.params = start.params.clone();
+            let mut exdate = recurrence_id.clone();
+            exdate.name = "EXDATE".to_string();
+            event.push(exdate);
+            let mut instance_start = recurrence_id.clone();
+            instance_start.name = "DTSTART".to_string();
+            instance.set(instance_start);
+            instance.set(recurrence_id);
+            cancelled.push(cancellation(&instance, dtstamp));
+        },
+        // A single exception
+        (Some(_), Some(_)) => {
+            let event = &mut calendar.components[index];
+            increment_sequence(event);
+            event.set(Property::new("STATUS", "CANCELLED"));
+            cancelled.push(cancellation(event, dtstamp));
+        },
+        // The whole event, including its exceptions
+        (None, _) => for event in calendar.components.iter_mut().filter(|comp| is_event(comp, uid)) {
+            increment_sequence(event);
+            event.set(Property::new("STATUS", "CANCELLED"));
+            if event.property("RECURRENCE-ID").is_none() {
+                cancelled.push(cancellation(event, dtstamp));
+            }
+        },
+    }
+
+    Ok(message(Method::Cancel, calendar, cancelled))
+}
+
+/// Apply an iTIP **message** that the organizer of an event received to the **calendar** with that event.
+///
+/// * `REPLY`: Sets the `PARTSTAT` of the attendee that replied (or adds the attendee if it was not invited).
+///   Replies to an older `SEQUENCE` of the event are ignored.
+/// * `COUNTER`: Does not change the calendar. The changes proposed by the attendee are described so the organizer can decide
+///   whether to [accept](accept_counter()) or [decline](decline_counter()) them.
+/// * `DECLINECOUNTER`: Does not change the calendar.
+///
+/// Returns a description of each event in the message and what was done with it, which can be shown to the user.
+/// Returns an error if the message has another `METHOD`, or is about an event that is not in **calendar**.
+pub fn process(calendar: &mut Component, message: &Component) -> Result<Vec<String>, String> {
+    let method = Method::of(message).ok_or_else(|| "Message has no valid METHOD".to_string())?;
+    let mut descriptions = Vec::new();
+    for event in message.components("VEVENT") {
+        let index = find_target(calendar, event)?;
+        let target = &mut calendar.components[index];
+        let summary = summary(target);
+        let attendee = event.property("ATTENDEE").map(Attendee::from_property);
+        let attendee_name = attendee.as_ref()
+            .map(|attendee| attendee.name.clone().unwrap_or_else(|| attendee.address.clone()))
+            .unwrap_or_else(|| "An attendee".to_string());
+
+        descriptions.push(match method {
+            Method::Reply => {
+                let reply = event.property("ATTENDEE").ok_or_else(|| format!("Reply to {summary} has no ATTENDEE"))?;
+                let attendee = Attendee::from_property(reply);
+                if sequence(event) < sequence(target) {
+                    format!("Ignored outdated reply of {attendee_name} to {summary}")
+                } else {
+                    let existing = target.properties.iter_mut()
+                        .filter(|prop| prop.name.eq_ignore_ascii_case("ATTENDEE"))
+                        .find(|prop| Attendee::from_property(prop).address.eq_ignore_ascii_case(&attendee.address));
+                    match existing {
+                        Some(existing) => {
+                            existing.set_param("PARTSTAT", attendee.status.as_str());
+                            existing.remove_param("RSVP");
+                        },
+                        None => {
+                            let mut reply = reply.clone();
+                            reply.remove_param("RSVP");
+                            target.push(reply);
+                        },
+                    }
+                    format!("{attendee_name} replied {} to {summary}", attendee.status.as_str())
+                }
+            },
+            Method::Counter => {
+                let changes = proposed_changes(target, event)
+                    .into_iter()
+                    .map(|prop| format!("{} {}", prop.name, prop.text_value()))
+                    .collect::<Vec<_>>();
+                let comment = event.property("COMMENT").map(|comment| format!(" ({})", comment.text_value())).unwrap_or_default();
+                format!("{attendee_name} proposed changes to {summary}: {}{comment}", changes.join(", "))
+            },
+            Method::DeclineCounter => format!("The organizer declined the changes proposed to {summary}"),
+            method => return Err(format!("{} messages are not supported", method.as_str())),
+        });
+    }
+    Ok(descriptions)
+}
+
+/// Apply the changes proposed in a `COUNTER` **message** to the events in **calendar**,
+/// and build a `REQUEST` with the changed events to send to the attendees (see [`request()`]).
+pub fn accept_counter(calendar: &mut Component, message: &Component, dtstamp: DateTime) -> Result<Component, String> {
+    if Method::of(message) != Some(Method::Counter) {
+        return Err("Message is not a COUNTER".to_string());
+    }
+    let mut uids = Vec::new();
+    for event in message.components("VEVENT") {
+        let index = find_target(calendar, event)?;
+        let target = &mut calendar.components[index];
+        let changes = proposed_changes(target, event);
+        // The end is either DTEND or DURATION
+        if changes.iter().any(|prop| prop.name == "DTEND" || prop.name == "DURATION") {
+            target.remove("DTEND");
+            target.remove("DURATION");
+        }
+        for change in changes {
+            target.set(change.clone());
+        }
+        let uid = target.value("UID").unwrap_or_default().to_string();
+        if !uids.contains(&uid) {
+            uids.push(uid);
+        }
+    }
+
+    let mut request = None::<Component>;
+    for uid in uids {
+        let message = self::request(calendar, &uid, dtstamp)?;
+        match &mut request {
+            Some(request) => for component in message.components {
+                if !request.components.contains(&component) {
+                    request.components.push(component);
+                }
+            },
+            None => request = Some(message),
+        }
+    }
+    request.ok_or_else(|| "COUNTER has no events".to_string())
+}
+
+/// Build a `DECLINECOUNTER` to send to the attendee that sent the `COUNTER` **message** about an event in **calendar**.
+pub fn decline_counter(calendar: &Component, message: &Component, dtstamp: DateTime) -> Result<Component, String> {
+    if Method::of(message) != Some(Method::Counter) {
+        return Err("Message is not a COUNTER".to_string());
+    }
+    let mut declined = Vec::new();
+    for event in message.components("VEVENT") {
+        let target = &calendar.components[find_target(calendar, event)?];
+        let mut decline = Component::new("VEVENT");
+        for name in ["UID", "RECURRENCE-ID", "SEQUENCE", "ORGANIZER"] {
+            decline.properties.extend(target.property(name).cloned());
+        }
+        decline.properties.extend(event.property("ATTENDEE").cloned());
+        decline.push(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+        declined.push(decline);
+    }
+    Ok(message_with(Method::DeclineCounter, Vec::new(), declined))
+}
+
+/// Whether **component** is the event with **uid** (or one of its exceptions).
+fn is_event(component: &Component, uid: &str) -> bool {
+    component.name.eq_ignore_ascii_case("VEVENT") && component.value("UID").is_some_and(|value| value.trim() == uid.trim())
+}
+
+/// Find the index of the event in **calendar** that an **event** of a message refers to.
+///
+/// A message about an instance of a recurring event that is not an exception refers to the recurring event.
+fn find_target(calendar: &Component, event: &Component) -> Result<usize, String> {
+    let uid = event.value("UID").ok_or_else(|| "Event in message has no UID".to_string())?;
+    let recurrence_id = event.value("RECURRENCE-ID").map(str::trim);
+    let find = |recurrence_id: Option<&str>| calendar.components.iter()
+        .position(|comp| is_event(comp, uid) && comp.value("RECURRENCE-ID").map(str::trim) == recurrence_id);
+    find(recurrence_id)
+        .or_else(|| find(None))
+        .ok_or_else(|| format!("Event {uid} not found"))
+}
+
+/// The properties of a `COUNTER` **event** that are different in the **target** event.
+fn proposed_changes<'a>(target: &Component, event: &'a Component) -> Vec<&'a Property> {
+    COUNTER_PROPERTIES.into_iter()
+        .filter_map(|name| event.property(name))
+        .filter(|prop| target.property(&prop.name) != Some(*prop))
+        .collect()
+}
+
+/// The minimal version of an **event** sent in a `CANCEL`.
+fn cancellation(event: &Component, dtstamp: DateTime) -> Component {
+    let mut cancelled = Component::new("VEVENT");
+    for name in ["UID", "RECURRENCE-ID", "SEQUENCE", "DTSTART", "SUMMARY", "ORGANIZER"] {
+        cancelled.properties.extend(event.property(name).cloned());
+    }
+    cancelled.properties.extend(event.properties("ATTENDEE").cloned());
+    cancelled.push(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+    cancelled.push(Property::new("STATUS", "CANCELLED"));
+    cancelled
+}
+
+/// Build a message with **events**, along with the `VTIMEZONE`s of **calendar** that they use.
+fn message(method: Method, calendar: &Component, events: Vec<Component>) -> Component {
+    let timezones = calendar.components("VTIMEZONE")
+        .filter(|timezone| timezone.value("TZID").is_some_and(|tzid| events.iter()
+            .flat_map(|event| &event.properties)
+            .any(|prop| prop.param("TZID") == Some(tzid))))
+        .cloned()
+        .collect();
+    message_with(method, timezones, events)
+}
+
+fn message_with(method: Method, timezones: Vec<Component>, events: Vec<Component>) -> Component {
+    let mut message = Component::new("VCALENDAR");
+    message.push(Property::new("VERSION", "2.0"));
+    message.push(Property::new("PRODID", PRODID));
+    message.push(Property::new("METHOD", method.as_str()));
+    message.components = timezones;
+    message.components.extend(events);
+    message
+}
+
+/// The `SEQUENCE` of a component, which is `0` if it has none.
+fn sequence(component: &Component) -> i64 {
+    component.value("SEQUENCE").and_then(|seq| seq.trim().parse().ok()).unwrap_or(0)
+}
+
+fn increment_sequence(component: &mut Component) {
+    let sequence = sequence(component) + 1;
+    component.set(Property::new("SEQUENCE", sequence.to_string()));
+}
+
+/// The `SUMMARY` of an event (or its `UID` if it has none), which can be shown to the user.
+fn summary(event: &Component) -> String {
+    event.property("SUMMARY")
+        .map(Property::text_value)
+        .or_else(|| event.value("UID").map(str::to_string))
+        .unwrap_or_else(|| "<no UID>".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTIMEZONE\r
+TZID:Europe/Paris\r
+BEGIN:STANDARD\r
+DTSTART:19701025T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VTIMEZONE\r
+TZID:Unused\r
+BEGIN:STANDARD\r
+DTSTART:19700101T000000\r
+TZOFFSETFROM:+0000\r
+TZOFFSETTO:+0000\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:meeting\r
+DTSTART;TZID=Europe/Paris:20240108T100000\r
+DTEND;TZID=Europe/Paris:20240108T110000\r
+RRULE:FREQ=WEEKLY\r
+SUMMARY:Weekly meeting\r
+ORGANIZER;CN=Alice:mailto:alice@example.com\r
+ATTENDEE;CN=Bob;PARTSTAT=NEEDS-ACTION:mailto:bob@example.com\r
+ATTENDEE;CN=Carol;PARTSTAT=ACCEPTED:mailto:carol@example.com\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:lunch\r
+DTSTART:20240109T120000Z\r
+SUMMARY:Lunch\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn calendar() -> Component {
+        ical::parse(CALENDAR).unwrap().remove(0)
+    }
+
+    fn dtstamp() -> DateTime {
+        DateTime::parse("20240101T120000").unwrap()
+    }
+
+    /// Send a **message** the way the App does: As the text of an `.ics` file, which the other side parses.
+    fn send(message: &Component) -> Component {
+        let mut messages = ical::parse(&message.to_string()).unwrap();
+        assert_eq!(messages.len(), 1);
+        messages.remove(0)
+    }
+
+    fn attendee<'a>(event: &'a Component, address: &str) -> Option<&'a Property> {
+        event.properties("ATTENDEE").find(|prop| prop.value.ends_with(address))
+    }
+
+    /// The `REPLY` that an attendee sends to the organizer after receiving a `REQUEST`.
+    fn reply(request: &Component, address: &str, status: &str) -> Component {
+        let event = request.components("VEVENT").next().unwrap();
+        let mut reply = Component::new("VEVENT");
+        for name in ["UID", "SEQUENCE", "ORGANIZER"] {
+            reply.properties.extend(event.property(name).cloned());
+        }
+        reply.push(Property::new("ATTENDEE", format!("mailto:{address}")).with_param("PARTSTAT", status));
+        message_with(Method::Reply, Vec::new(), vec![reply])
+    }
+
+    #[test]
+    fn request_and_replies() {
+        let mut calendar = calendar();
+        let request = send(&request(&mut calendar, "meeting", dtstamp()).unwrap());
+        assert_eq!(Method::of(&request), Some(Method::Request));
+        // Only the time zone that the event uses is sent
+        let timezones = request.components("VTIMEZONE").map(|tz| tz.value("TZID").unwrap()).collect::<Vec<_>>();
+        assert_eq!(timezones, ["Europe/Paris"]);
+        let event = request.components("VEVENT").next().unwrap();
+        assert_eq!(event.value("SEQUENCE"), Some("1"));
+        assert_eq!(event.value("DTSTAMP"), Some("20240101T120000Z"));
+        assert_eq!(event.components("VALARM").count(), 0);
+        assert_eq!(attendee(event, "bob@example.com").unwrap().param("RSVP"), Some("TRUE"));
+        assert_eq!(attendee(event, "carol@example.com").unwrap().param("RSVP"), None);
+        // The organizer's copy has the new SEQUENCE, and keeps its alarm
+        let meeting = &calendar.components[2];
+        assert_eq!(meeting.value("SEQUENCE"), Some("1"));
+        assert_eq!(meeting.components("VALARM").count(), 1);
+
+        let descriptions = process(&mut calendar, &send(&reply(&request, "bob@example.com", "ACCEPTED"))).unwrap();
+        assert_eq!(descriptions, ["bob@example.com replied ACCEPTED to Weekly meeting"]);
+        let bob = attendee(&calendar.components[2], "bob@example.com").unwrap();
+        assert_eq!(bob.param("PARTSTAT"), Some("ACCEPTED"));
+        assert_eq!(bob.param("CN"), Some("Bob"));
+
+        // A reply to an older version of the event is ignored
+        let mut old_reply = reply(&request, "carol@example.com", "DECLINED");
+        old_reply.components[0].set(Property::new("SEQUENCE", "0"));
+        let descriptions = process(&mut calendar, &send(&old_reply)).unwrap();
+        assert_eq!(descriptions, ["Ignored outdated reply of carol@example.com to Weekly meeting"]);
+        assert_eq!(attendee(&calendar.components[2], "carol@example.com").unwrap().param("PARTSTAT"), Some("ACCEPTED"));
+
+        // Someone the invitation was forwarded to is added
+        process(&mut calendar, &send(&reply(&request, "dave@example.com", "TENTATIVE"))).unwrap();
+        assert_eq!(attendee(&calendar.components[2], "dave@example.com").unwrap().param("PARTSTAT"), Some("TENTATIVE"));
+    }
+
+    #[test]
+    fn request_errors() {
+        let mut calendar = calendar();
+        assert_eq!(request(&mut calendar, "lunch", dtstamp()).unwrap_err(), "Event lunch has no ORGANIZER");
+        assert_eq!(request(&mut calendar, "unknown", dtstamp()).unwrap_err(), "Event unknown not found");
+        assert_eq!(cancel(&mut calendar, "unknown", None, dtstamp()).unwrap_err(), "Event unknown not found");
+
+        let mut publish = reply(&request(&mut calendar, "meeting", dtstamp()).unwrap(), "bob@example.com", "ACCEPTED");
+        publish.set(Property::new("METHOD", "PUBLISH"));
+        assert_eq!(process(&mut calendar, &send(&publish)).unwrap_err(), "PUBLISH messages are not supported");
+    }
+
+    #[test]
+    fn cancel_events() {
+        // One instance of the recurring event
+        let mut calendar = calendar();
+        let message = send(&cancel(&mut calendar, "meeting", Some("20240115T100000"), dtstamp()).unwrap());
+        assert_eq!(Method::of(&message), Some(Method::Cancel));
+        let cancelled = message.components("VEVENT").next().unwrap();
+        assert_eq!(cancelled.value("RECURRENCE-ID"), Some("20240115T100000"));
+        assert_eq!(cancelled.property("RECURRENCE-ID").unwrap().param("TZID"), Some("Europe/Paris"));
+        assert_eq!(cancelled.value("STATUS"), Some("CANCELLED"));
+        assert_eq!(cancelled.properties("ATTENDEE").count(), 2);
+        let meeting = &calendar.components[2];
+        assert_eq!(meeting.value("EXDATE"), Some("20240115T100000"));
+        assert_eq!(meeting.value("STATUS"), None);
+
+        // The whole event
+        let mut calendar = self::calendar();
+        let message = send(&cancel(&mut calendar, "meeting", None, dtstamp()).unwrap());
+        let cancelled = message.components("VEVENT").next().unwrap();
+        assert_eq!(cancelled.value("RECURRENCE-ID"), None);
+        assert_eq!(cancelled.value("SEQUENCE"), Some("1"));
+        assert_eq!(calendar.components[2].value("STATUS"), Some("CANCELLED"));
+    }
+
+    #[test]
+    fn counter_proposals() {
+        let mut calendar = calendar();
+        let request = send(&request(&mut calendar, "meeting", dtstamp()).unwrap());
+        // Bob proposes to move the meeting one hour later
+        let mut counter = request.components("VEVENT").next().unwrap().clone();
+        counter.set(Property::new("DTSTART", "20240108T110000").with_param("TZID", "Europe/Paris"));
+        counter.set(Property::new("DTEND", "20240108T120000").with_param("TZID", "Europe/Paris"));
+        counter.push(Property::text("COMMENT", "I have another meeting at 10"));
+        counter.properties.retain(|prop| !prop.name.eq_ignore_ascii_case("ATTENDEE") || prop.value.ends_with("bob@example.com"));
+        let counter = send(&message_with(Method::Counter, Vec::new(), vec![counter]));
+
+        let descriptions = process(&mut calendar, &counter).unwrap();
+        assert_eq!(descriptions, [
+            "Bob proposed changes to Weekly meeting: DTSTART 20240108T110000, DTEND 20240108T120000 (I have another meeting at 10)",
+        ]);
+        // Processing a COUNTER doesn't change the event
+        assert_eq!(calendar.components[2].value("DTSTART"), Some("20240108T100000"));
+
+        // Declining only goes to Bob
+        let decline = send(&decline_counter(&calendar, &counter, dtstamp()).unwrap());
+        assert_eq!(Method::of(&decline), Some(Method::DeclineCounter));
+        let declined = decline.components("VEVENT").next().unwrap();
+        assert_eq!(declined.properties("ATTENDEE").map(|prop| prop.value.as_str()).collect::<Vec<_>>(), ["mailto:bob@example.com"]);
+        assert_eq!(process(&mut calendar, &decline).unwrap(), ["The organizer declined the changes proposed to Weekly meeting"]);
+
+        // Accepting changes the event, and sends a new REQUEST to all the attendees
+        let new_request = send(&accept_counter(&mut calendar, &counter, dtstamp()).unwrap());
+        assert_eq!(Method::of(&new_request), Some(Method::Request));
+        let event = new_request.components("VEVENT").next().unwrap();
+        assert_eq!(event.value("DTSTART"), Some("20240108T110000"));
+        assert_eq!(event.value("SEQUENCE"), Some("2"));
+        assert_eq!(event.properties("ATTENDEE").count(), 2);
+        assert_eq!(calendar.components[2].value("DTEND"), Some("20240108T120000"));
+
+        assert_eq!(accept_counter(&mut calendar, &request, dtstamp()).unwrap_err(), "Message is not a COUNTER");
+    }
+}
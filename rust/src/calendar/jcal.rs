@@ -0,0 +1,256 @@
+//! Conversion between [`Component`]s and *jCal*, the JSON format for *iCalendar* ([RFC 7265](https://datatracker.ietf.org/doc/html/rfc7265)).
+//!
+//! A component is an array `[name, [properties], [components]]`,
+//! and a property is an array `[name, {parameters}, type, values...]`.
+use serde_json::{json, Map, Value as Json};
+use super::{
+    ical::{Component, Param},
+    value::{self, Value, ValueType},
+};
+
+/// Parse the contents of a *jCal* file, which is a single `vcalendar` or an array of them.
+pub fn parse(content: &str) -> Result<Vec<Component>, String> {
+    let json = serde_json::from_str::<Json>(content)
+        .map_err(|err| format!("Invalid JSON: {err}"))?;
+    match &json {
+        // A single component starts with its name
+        Json::Array(array) if matches!(array.first(), Some(Json::String(_))) => Ok(vec![component_from_json(&json)?]),
+        Json::Array(array) if !array.is_empty() => array.iter().map(component_from_json).collect(),
+        _ => Err("jCal must be a non-empty array".to_string()),
+    }
+}
+
+/// Write **calendars** as *jCal*.
+///
+/// A single calendar is written as is, and multiple calendars are written in an array.
+pub fn write(calendars: &[Component]) -> String {
+    let json = match calendars {
+        [calendar] => component_to_json(calendar),
+        calendars => Json::Array(calendars.iter().map(component_to_json).collect()),
+    };
+    serde_json::to_string_pretty(&json).expect("JSON values can always be serialized")
+}
+
+fn component_to_json(component: &Component) -> Json {
+    let properties = component.properties.iter()
+        .map(|prop| {
+            let mut params = Map::new();
+            for param in prop.params.iter().filter(|param| !param.name.eq_ignore_ascii_case("VALUE")) {
+                params.insert(param.name.to_ascii_lowercase(), match param.values.as_slice() {
+                    [value] => json!(value),
+                    values => json!(values),
+                });
+            }
+            let (value_type, values) = value::values(prop);
+
+            let mut array = vec![json!(prop.name.to_ascii_lowercase()), Json::Object(params), json!(value_type.as_str())];
+            array.extend(values.iter().map(value_to_json));
+            Json::Array(array)
+        })
+        .collect();
+    let components = component.components.iter().map(component_to_json).collect();
+
+    json!([component.name.to_ascii_lowercase(), Json::Array(properties), Json::Array(components)])
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::Text(text) => json!(text),
+        Value::Integer(integer) => json!(integer),
+        Value::Float(float) => json!(float),
+        Value::Boolean(boolean) => json!(boolean),
+        Value::Period(start, end) => json!([start, end]),
+        Value::Recur(parts) => Json::Object(parts.iter()
+            .map(|(name, values)| (name.clone(), match values.as_slice() {
+                [value] => value_to_json(value),
+                values => Json::Array(values.iter().map(value_to_json).collect()),
+            }))
+            .collect()),
+        Value::Structured(parts) => Json::Array(parts.iter().map(value_to_json).collect()),
+    }
+}
+
+fn component_from_json(json: &Json) -> Result<Component, String> {
+    let (name, properties, components) = match json.as_array().map(Vec::as_slice) {
+        Some([Json::String(name), Json::Array(properties), Json::Array(components)]) => (name, properties, components),
+        _ => return Err(format!("Expected a component ([name, [properties], [components]]), found {json}")),
+    };
+
+    let mut component = Component::new(name);
+    for property in properties {
+        let (name, params, value_type, values) = match property.as_array().map(Vec::as_slice) {
+            Some([Json::String(name), Json::Object(params), Json::String(value_type), values @ ..]) => (name, params, value_type, values),
+            _ => return Err(format!("Expected a property ([name, {{parameters}}, type, values...]) in {}, found {property}", component.name)),
+        };
+        let params = params.iter()
+            .map(|(name, values)| Param {
+                name: name.to_ascii_uppercase(),
+                values: match values {
+                    Json::Array(values) => values.iter().map(param_value).collect(),
+                    value => vec![param_value(value)],
+                },
+            })
+            .collect();
+        let value_type = ValueType::parse(value_type).unwrap_or(ValueType::Unknown);
+        let values = values.iter().map(|value| value_from_json(value, value_type)).collect::<Vec<_>>();
+
+        component.push(value::to_property(name, params, value_type, &values));
+    }
+    for subcomponent in components {
+        component.components.push(component_from_json(subcomponent)?);
+    }
+    Ok(component)
+}
+
+fn param_value(value: &Json) -> String {
+    match value {
+        Json::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn value_from_json(json: &Json, value_type: ValueType) -> Value {
+    match json {
+        Json::String(text) => Value::Text(text.clone()),
+        Json::Number(number) => match number.as_i64() {
+            Some(integer) => Value::Integer(integer),
+            None => Value::Float(number.as_f64().unwrap_or_default()),
+        },
+        Json::Bool(boolean) => Value::Boolean(*boolean),
+        Json::Array(array) => match array.as_slice() {
+            [Json::String(start), Json::String(end)] if value_type == ValueType::Period => Value::Period(start.clone(), end.clone()),
+            parts => Value::Structured(parts.iter().map(|part| value_from_json(part, value_type)).collect()),
+        },
+        Json::Object(parts) => {
+            let mut parts = parts.iter()
+                .map(|(name, values)| (name.to_ascii_lowercase(), match values {
+                    Json::Array(values) => values.iter().map(|value| value_from_json(value, ValueType::Unknown)).collect(),
+                    value => vec![value_from_json(value, ValueType::Unknown)],
+                }))
+                .collect::<Vec<_>>();
+            // Other tools may write the parts in any order, but FREQ should be the first one for compatibility
+            parts.sort_by_key(|(name, _)| name != "freq");
+            Value::Recur(parts)
+        },
+        Json::Null => Value::Text(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical::{self, examples};
+
+    /// [`examples::SIMPLE`] in RFC 7265.
+    const SIMPLE: &str = r#"["vcalendar",
+      [
+        ["calscale", {}, "text", "GREGORIAN"],
+        ["prodid", {}, "text", "-//Example Inc.//Example Calendar//EN"],
+        ["version", {}, "text", "2.0"]
+      ],
+      [
+        ["vevent",
+          [
+            ["dtstamp", {}, "date-time", "2008-02-05T19:12:24Z"],
+            ["dtstart", {}, "date", "2008-10-06"],
+            ["summary", {}, "text", "Planning meeting"],
+            ["uid", {}, "text", "4088E990AD89CB3DBB484909"]
+          ],
+          []
+        ]
+      ]
+    ]"#;
+
+    /// [`examples::RECURRING`] in RFC 7265.
+    const RECURRING: &str = r#"["vcalendar",
+      [
+        ["version", {}, "text", "2.0"],
+        ["prodid", {}, "text", "-//Example Corp.//Example Client//EN"]
+      ],
+      [
+        ["vtimezone",
+          [
+            ["last-modified", {}, "date-time", "2004-01-10T03:28:45Z"],
+            ["tzid", {}, "text", "US/Eastern"]
+          ],
+          [
+            ["daylight",
+              [
+                ["dtstart", {}, "date-time", "2000-04-04T02:00:00"],
+                ["rrule", {}, "recur", {"freq": "YEARLY", "byday": "1SU", "bymonth": 4}],
+                ["tzname", {}, "text", "EDT"],
+                ["tzoffsetfrom", {}, "utc-offset", "-05:00"],
+                ["tzoffsetto", {}, "utc-offset", "-04:00"]
+              ],
+              []
+            ],
+            ["standard",
+              [
+                ["dtstart", {}, "date-time", "2000-10-26T02:00:00"],
+                ["rrule", {}, "recur", {"freq": "YEARLY", "byday": "-1SU", "bymonth": 10}],
+                ["tzname", {}, "text", "EST"],
+                ["tzoffsetfrom", {}, "utc-offset", "-04:00"],
+                ["tzoffsetto", {}, "utc-offset", "-05:00"]
+              ],
+              []
+            ]
+          ]
+        ],
+        ["vevent",
+          [
+            ["dtstamp", {}, "date-time", "2006-02-06T00:11:21Z"],
+            ["dtstart", { "tzid": "US/Eastern" }, "date-time", "2006-01-02T12:00:00"],
+            ["duration", {}, "duration", "PT1H"],
+            ["rrule", {}, "recur", {"freq": "DAILY", "count": 5}],
+            ["rdate", { "tzid": "US/Eastern" }, "period", ["2006-01-02T15:00:00", "PT2H"]],
+            ["summary", {}, "text", "Event #2"],
+            ["description", {}, "text", "We are having a meeting all this week at 12pm for one hour, with an additional meeting on the first day 2 hours long.\nPlease bring your own lunch for the 12 pm meetings."],
+            ["uid", {}, "text", "00959BC664CA650E933C892C@example.com"]
+          ],
+          []
+        ],
+        ["vevent",
+          [
+            ["dtstamp", {}, "date-time", "2006-02-06T00:11:21Z"],
+            ["dtstart", { "tzid": "US/Eastern" }, "date-time", "2006-01-04T14:00:00"],
+            ["duration", {}, "duration", "PT1H"],
+            ["recurrence-id", { "tzid": "US/Eastern" }, "date-time", "2006-01-04T12:00:00"],
+            ["summary", {}, "text", "Event #2 bis"],
+            ["uid", {}, "text", "00959BC664CA650E933C892C@example.com"]
+          ],
+          []
+        ]
+      ]
+    ]"#;
+
+    #[test]
+    fn rfc_examples() {
+        for (jcal, ics) in [(SIMPLE, examples::SIMPLE), (RECURRING, examples::RECURRING)] {
+            let calendars = ical::parse(ics).unwrap();
+            assert_eq!(parse(jcal).unwrap(), calendars);
+            assert_eq!(serde_json::from_str::<Json>(&write(&calendars)).unwrap(), serde_json::from_str::<Json>(jcal).unwrap());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for ics in [examples::SIMPLE, examples::RECURRING] {
+            let calendars = ical::parse(ics).unwrap();
+            let written = parse(&write(&calendars)).unwrap();
+            assert_eq!(written, calendars);
+            assert_eq!(written.iter().map(ToString::to_string).collect::<String>(), ics);
+        }
+        // Multiple calendars are written in an array
+        let calendars = ical::parse(&[examples::SIMPLE, examples::RECURRING].concat()).unwrap();
+        assert!(write(&calendars).starts_with("[\n  [\n    \"vcalendar\""));
+        assert_eq!(parse(&write(&calendars)).unwrap(), calendars);
+    }
+
+    #[test]
+    fn invalid_jcal() {
+        assert!(parse("{}").is_err());
+        assert!(parse("[]").is_err());
+        assert!(parse(r#"["vcalendar", [["version"]], []]"#).is_err());
+        assert!(parse("not json").is_err());
+    }
+}
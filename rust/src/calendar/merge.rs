@@ -0,0 +1,253 @@
+//! Three-way merge of two versions of a calendar file using the version they had when they were last in sync (the *base*).
+//!
+//! The components of the calendars are matched by their `UID` and `RECURRENCE-ID` (`VTIMEZONE`s by their `TZID`),
+//! so that a change to an event on one side doesn't overwrite a change to another event on the other side.
+use super::ical::{self, Component, Property};
+
+/// Which version of a component to keep when it changed on both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The version in the **internal** directory.
+    Local,
+    /// The version in the **external** directory.
+    Remote,
+}
+
+/// A component that changed in both versions, and whose `SEQUENCE` and `LAST-MODIFIED` don't tell which change is newer.
+///
+/// One of the versions is [`None`] if the component was deleted on that side.
+#[derive(Debug, Clone, Copy)]
+pub struct Conflict<'a> {
+    pub local: Option<&'a Component>,
+    pub remote: Option<&'a Component>,
+}
+impl Conflict<'_> {
+    /// The `SUMMARY` of the component (the `FN` of a `VCARD`, or its `UID` if it has none), which can be shown to the user.
+    pub fn summary(&self) -> String {
+        let Some(comp) = self.local.or(self.remote) else { return String::new() };
+        comp.property("SUMMARY")
+            .or_else(|| comp.property("FN"))
+            .map(|prop| prop.text_value())
+            .or_else(|| comp.value("UID").map(str::to_string))
+            .unwrap_or_else(|| comp.name.clone())
+    }
+}
+
+/// Merge the **local** and **remote** versions of a `VCALENDAR`.
+///
+/// Components that changed only on one side take that side's version (including deletions),
+/// and components that changed on both sides take the version with the higher `SEQUENCE` (or the later `LAST-MODIFIED`).
+/// **resolve** is called for the rest of the conflicts, and can return [`None`] to cancel the merge.
+/// The properties of the calendar itself are merged in the same way by their name, but the local version is kept if both changed.
+///
+/// If there is no **base** (the files were never in sync), the components of both versions are kept.
+pub fn merge(
+    base: Option<&Component>,
+    local: &Component,
+    remote: &Component,
+    mut resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Option<Component> {
+    let mut merged = Component::new(local.name.clone());
+    // The properties of the calendar itself (e.g. its name and color)
+    merged.properties = merge_properties(base.map(|base| base.properties.as_slice()), &local.properties, &remote.properties);
+
+    // The keys of all the components, in the order they appear in local, and then in remote
+    let mut keys = Vec::new();
+    for component in local.components.iter().chain(&remote.components) {
+        let key = Key::of(component);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    for key in keys {
+        let base = base.and_then(|base| key.find(base));
+        let local = key.find(local);
+        let remote = key.find(remote);
+
+        let component = if same(local, remote) || same(base, remote) {
+            local
+        } else if same(base, local) {
+            remote
+        } else {
+            match (local, remote) {
+                (Some(local_comp), Some(remote_comp)) if version(local_comp) > version(remote_comp) => local,
+                (Some(local_comp), Some(remote_comp)) if version(local_comp) < version(remote_comp) => remote,
+                _ => match resolve(Conflict { local, remote })? {
+                    Side::Local => local,
+                    Side::Remote => remote,
+                },
+            }
+        };
+        merged.components.extend(component.cloned());
+    }
+
+    Some(merged)
+}
+
+/// Merge the **local** and **remote** versions of the properties of a component, property by property.
+///
+/// The properties with the same name are taken from the side where they changed, or from **local** if they changed on both sides.
+/// If there is no **base**, the properties that are only in **remote** are added to **local**.
+fn merge_properties(base: Option<&[Property]>, local: &[Property], remote: &[Property]) -> Vec<Property> {
+    let with_name = |properties: Option<&[Property]>, name: &str| properties.unwrap_or_default()
+        .iter()
+        .filter(|prop| prop.name == name)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut names = Vec::new();
+    for prop in local.iter().chain(remote) {
+        if !names.contains(&prop.name.as_str()) {
+            names.push(&prop.name);
+        }
+    }
+
+    let mut merged = Vec::new();
+    for name in names {
+        let (base, local, remote) = (with_name(base, name), with_name(Some(local), name), with_name(Some(remote), name));
+        merged.extend(if local != remote && base == local { remote } else { local });
+    }
+    merged
+}
+
+/// [`merge()`] the content of the **local** and **remote** versions of a *calendar file*.
+///
+/// Returns [`None`] if the merge was canceled in **resolve**.
+/// Returns an error if either version is not a file with one `VCALENDAR`, in which case the files can only be compared as a whole.
+/// A **base** that can't be parsed is ignored.
+pub fn merge_files(
+    base: Option<&str>,
+    local: &str,
+    remote: &str,
+    resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Result<Option<String>, String> {
+    fn single_calendar(content: &str) -> Result<Component, String> {
+        let mut calendars = ical::parse(content).map_err(|err| err.to_string())?;
+        match calendars.len() {
+            1 => Ok(calendars.remove(0)),
+            len => Err(format!("Expected 1 VCALENDAR in file, found {len}")),
+        }
+    }
+
+    let base = base.and_then(|base| single_calendar(base).ok());
+    let local = single_calendar(local)?;
+    let remote = single_calendar(remote)?;
+
+    Ok(merge(base.as_ref(), &local, &remote, resolve).map(|merged| merged.to_string()))
+}
+
+/// What identifies a component across versions of a calendar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    /// An instance of an event (or other component with `UID`). The `RECURRENCE-ID` is [`None`] for the main event.
+    Uid { name: String, uid: String, recurrence_id: Option<String> },
+    Timezone(String),
+    /// Components that can't be identified are only matched if they are equal.
+    Other(Component),
+}
+impl Key {
+    fn of(component: &Component) -> Self {
+        if let Some(uid) = component.value("UID") {
+            Self::Uid {
+                name: component.name.clone(),
+                uid: uid.to_string(),
+                recurrence_id: component.value("RECURRENCE-ID").map(str::to_string),
+            }
+        } else if let (true, Some(tzid)) = (component.name == "VTIMEZONE", component.value("TZID")) {
+            Self::Timezone(tzid.to_string())
+        } else {
+            Self::Other(component.clone())
+        }
+    }
+
+    fn find<'a>(&self, calendar: &'a Component) -> Option<&'a Component> {
+        calendar.components.iter().find(|comp| Self::of(comp) == *self)
+    }
+}
+
+/// Whether two versions of a component are the same, ignoring `DTSTAMP` (which changes every time the file is written).
+fn same(a: Option<&Component>, b: Option<&Component>) -> bool {
+    let without_dtstamp = |comp: &Component| {
+        let mut comp = comp.clone();
+        comp.remove("DTSTAMP");
+        comp
+    };
+    match (a, b) {
+        (Some(a), Some(b)) => without_dtstamp(a) == without_dtstamp(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The `SEQUENCE` and `LAST-MODIFIED` of a component, which can be compared to know which version is newer.
+///
+/// `LAST-MODIFIED` is always in UTC, so it can be compared as text.
+fn version(component: &Component) -> (i64, String) {
+    (
+        component.value("SEQUENCE").and_then(|seq| seq.trim().parse().ok()).unwrap_or(0),
+        component.value("LAST-MODIFIED").unwrap_or_default().trim().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(properties: &[(&str, &str)], events: &[(&str, &str)]) -> Component {
+        let mut calendar = Component::new("VCALENDAR");
+        for (name, value) in properties {
+            calendar.push(Property::new(*name, *value));
+        }
+        for (uid, summary) in events {
+            let mut event = Component::new("VEVENT");
+            event.push(Property::new("UID", *uid));
+            event.push(Property::text("SUMMARY", summary));
+            calendar.components.push(event);
+        }
+        calendar
+    }
+
+    #[test]
+    fn merge_calendar_properties() {
+        let base = calendar(&[("X-WR-CALNAME", "Work"), ("COLOR", "red")], &[]);
+        // The name changed in local, and the color in remote
+        let local = calendar(&[("X-WR-CALNAME", "Office"), ("COLOR", "red")], &[]);
+        let remote = calendar(&[("X-WR-CALNAME", "Work"), ("COLOR", "blue"), ("X-WR-TIMEZONE", "Europe/Paris")], &[]);
+
+        let merged = merge(Some(&base), &local, &remote, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(merged.value("X-WR-CALNAME"), Some("Office"));
+        assert_eq!(merged.value("COLOR"), Some("blue"));
+        assert_eq!(merged.value("X-WR-TIMEZONE"), Some("Europe/Paris"));
+
+        // Both changed the color
+        let local = calendar(&[("X-WR-CALNAME", "Work"), ("COLOR", "green")], &[]);
+        let merged = merge(Some(&base), &local, &remote, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(merged.value("COLOR"), Some("green"));
+
+        // Deleted in remote
+        let remote = calendar(&[("COLOR", "red")], &[]);
+        let merged = merge(Some(&base), &base, &remote, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(merged.value("X-WR-CALNAME"), None);
+    }
+
+    #[test]
+    fn merge_components() {
+        let base = calendar(&[], &[("a", "A"), ("b", "B"), ("c", "C")]);
+        let local = calendar(&[], &[("a", "A local"), ("b", "B"), ("c", "C local")]);
+        let remote = calendar(&[], &[("a", "A"), ("c", "C remote"), ("d", "D")]);
+
+        let mut asked = Vec::new();
+        let merged = merge(Some(&base), &local, &remote, |conflict| {
+            asked.push(conflict.summary());
+            Some(Side::Remote)
+        }).unwrap();
+        assert_eq!(asked, ["C local"]);
+        let summaries = merged.components.iter()
+            .map(|comp| comp.property("SUMMARY").unwrap().text_value())
+            .collect::<Vec<_>>();
+        assert_eq!(summaries, ["A local", "C remote", "D"]);
+
+        assert_eq!(merge(Some(&base), &local, &remote, |_| None), None);
+    }
+}
@@ -1,19 +1,106 @@
-use std::path::PathBuf;
-use ez_jni::{call, jni_fn, new, FromException, FromObject, ToObject};
+mod alarm;
+mod attendee;
+pub mod color;
+pub mod csv;
+mod event;
+mod freebusy;
+pub mod ical;
+pub mod itip;
+pub mod jcal;
+pub mod merge;
+mod projections;
+mod provider;
+mod recurrence;
+mod task;
+mod time;
+mod timezone;
+mod value;
+pub mod xcal;
+
+use std::{io::{self, Read as _, Write as _}, path::PathBuf};
+use ez_jni::{call, jni_fn, println, FromException, FromObject, ToObject};
 use jni::{JNIEnv, objects::JObject};
-use classes::fs::file_stem;
-use crate::{get_app_dir, DocUri, ExternalDir, ILLEGAL_FILE_CHARACTERS, SUFFIX_DIR};
+use classes::fs::{file_stem, DocUri, ExtraMode, OpenOptions};
+use crate::{error::{Error, OrThrow as _}, file_type::FileType, get_app_dir, journal::{Journal, Step}, utils::{open_external_dir, write_base_snapshot}, ExternalDir, ILLEGAL_FILE_CHARACTERS};
+use alarm::{Alarm, ReminderLimits};
+use attendee::Attendee;
+use color::Color;
+use event::Event;
+use ical::{Component, Property};
+use projections::{attendees, calendars, events, reminders, task_lists, tasks};
+use recurrence::Recurrence;
+use task::Task;
+use time::TimeZoneResolver;
+use timezone::{TimeZones, VTimeZone};
+use crate::provider::{account_name, Operation, Row, ACCOUNT_TYPE_LOCAL};
+use provider::JavaTimeZones;
+
+/// The `PRODID` property of the calendar files created by this App.
+pub const PRODID: &str = "-//Megadash452//DavSync//EN";
 
-#[derive(Debug, FromObject, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-#[class(me.marti.calprovexample.Color)]
-struct Color {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8
+/// The formats that calendar files can be imported from and exported to.
+///
+/// Calendar files are always stored as *iCalendar*, so files in the other formats are converted when they are imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarFormat {
+    /// `.ics` ([RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545)).
+    ICalendar,
+    /// `.json` (see [`jcal`]).
+    JCal,
+    /// `.xml` (see [`xcal`]).
+    XCal,
 }
-impl<'local> ToObject<'local> for Color {
-    fn to_object(&self, env: &mut JNIEnv<'local>) -> JObject<'local> {
-        new!(me.marti.calprovexample.Color(u8(self.r), u8(self.g), u8(self.b)))
+impl CalendarFormat {
+    /// Get the format with an **extension** (case insensitive).
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "ics" => Some(Self::ICalendar),
+            "json" => Some(Self::JCal),
+            "xml" => Some(Self::XCal),
+            _ => None,
+        }
+    }
+
+    /// Detect the format of a calendar file from its **content**.
+    ///
+    /// Returns [`None`] if the content is not a calendar in any of the formats.
+    pub fn sniff(content: &[u8]) -> Option<Self> {
+        let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+        let start = content.iter().position(|byte| !byte.is_ascii_whitespace())?;
+        let content = &content[start..];
+        let contains = |needle: &[u8]| content.windows(needle.len()).any(|window| window.eq_ignore_ascii_case(needle));
+
+        match content[0] {
+            b'[' if contains(br#""vcalendar""#) => Some(Self::JCal),
+            b'<' if contains(xcal::XCAL.as_bytes()) => Some(Self::XCal),
+            _ if content.get(..15).is_some_and(|line| line.eq_ignore_ascii_case(b"BEGIN:VCALENDAR")) => Some(Self::ICalendar),
+            _ => None,
+        }
+    }
+
+    /// Parse a calendar file in this format.
+    pub fn parse(self, content: &str) -> Result<Vec<Component>, Error> {
+        match self {
+            Self::ICalendar => ical::parse(content).map_err(|err| Error::Parse(err.to_string())),
+            Self::JCal => jcal::parse(content).map_err(|err| Error::Parse(format!("Invalid jCal: {err}"))),
+            Self::XCal => xcal::parse(content).map_err(|err| Error::Parse(format!("Invalid xCal: {err}"))),
+        }
+    }
+
+    /// Write **calendars** in this format.
+    pub fn write(self, calendars: &[Component]) -> String {
+        match self {
+            Self::ICalendar => calendars.iter().map(Component::to_string).collect(),
+            Self::JCal => jcal::write(calendars),
+            Self::XCal => xcal::write(calendars),
+        }
+    }
+
+    /// Convert the **content** of a calendar file in this format to *iCalendar*.
+    pub fn to_icalendar(self, content: &[u8]) -> Result<String, Error> {
+        let content = std::str::from_utf8(content)
+            .map_err(|err| Error::Parse(format!("Calendar file is not valid UTF-8: {err}")))?;
+        Ok(Self::ICalendar.write(&self.parse(content)?))
     }
 }
 
@@ -22,79 +109,1201 @@ jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
     /// 
     /// If **external_dir_uri** is **`NULL`**, only the file in app storage will be created.
     /// **file_name** is the name of the file that will be created in each directory (e.g. `"name.ics"`). */
+    /// If **perm** is not `NULL`, the Calendar is also created in the *Content Provider*.
+    ///
+    /// If any of these steps fails, the files and the Calendar that were created are deleted.
     pub fn create_calendar_files<'local>(
         context: android.content.Context,
+        perm: Option<me.marti.calprovexample.ui.CalendarPermissionScope>,
         file_name: String,
         color: me.marti.calprovexample.Color,
         external_dir_uri: Option<android.net.Uri>,
     ) {
-        let davsyncrs = env.get_static_field("me/marti/calprovexample/jni/DavSyncRs", "INSTANCE", "Lme/marti/calprovexample/jni/DavSyncRs;")
-            .unwrap().l().unwrap();
-        call!(davsyncrs.initialize_dirs(
-            android.content.Context(context),
-            android.net.Uri(external_dir_uri.to_object(env))
-        ) -> void);
-        let external_dir_uri = external_dir_uri.map(|uri| DocUri::from_tree_uri(env, uri).unwrap());
-        
-        // Check for illegal characters
-        if file_name.contains(ILLEGAL_FILE_CHARACTERS) {
-            panic!("File name can't contain the following characters: {ILLEGAL_FILE_CHARACTERS:?}")
-        }
-
-        // Create file in App's internal storage
-        std::fs::File::create_new(get_app_dir(env, &context).join(SUFFIX_DIR).join(&file_name))
-            .unwrap_or_else(|err| panic!("Error creating file in internal directory: {err}"));
-        // Create file in external directory in shared storage
-        if let Some(external_dir_uri) = external_dir_uri {
-            ExternalDir::new(context, external_dir_uri, env)
-                .unwrap_or_else(|| panic!("external_dir_uri does not point to a directory"))
-                .create_file_at(env, PathBuf::from(SUFFIX_DIR).join(&file_name))
-                .unwrap_or_else(|err| panic!("Error creating external file: {err}"));
-        }
+        create_calendar_files(env, &context, perm.as_ref(), &file_name, &color, external_dir_uri)
+            .or_throw(env)
+    }
 
-        write_color_to_calendar_file(
-            file_stem(&file_name),
-            Color::from_object(&color, env)
-                .unwrap_or_else(|err| panic!("Error getting color: {err}"))
-        );
+    /// Delete the Calendar with **name** from the *Content Provider*, and its files from the **internal** directory,
+    /// and from the **external** directory if **external_dir_uri** is not `NULL`.
+    ///
+    /// A copy of the internal file is put in the *recycle bin* (the `deleted` directory), so that the Calendar can be restored.
+    /// If any of these steps fails, everything that was deleted is restored.
+    pub fn delete_calendar_files<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) {
+        delete_calendar_files(env, &perm, &name, external_dir_uri)
+            .or_throw(env)
     }
 
-    /// Read a *Calendar file* and write the data to the Calendar *Content Provider*.
+    /// Read a *Calendar file* and write the data to the Calendar *Content Provider* (and its tasks to the *OpenTasks* provider).
     /// 
     /// Creates a new Calendar in the Content Provider if one with **name** does not exist.
+    /// If the file has a color, it is used instead of **color**.
     pub fn write_file_data_to_calendar<'local>(
-        perm: me.marti.calprovexample.ui.CalendarPermission,
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
         name: String,
         color: Option<me.marti.calprovexample.Color>
     ) {
-        #[derive(FromException)]
-        #[class(me.marti.calprovexample.ElementExistsException)]
-        struct ElementExists;
-        
-        // let context = call!(perm.getContext() -> android.content.Context);
-
-        // Create the calendar if it does not exist. Ignore the result
-        let _ = call!(static me.marti.calprovexample.calendar.ActionsKt.newCalendar(
-            me.marti.calprovexample.ui.CalendarPermissionScope(perm),
-            String(name),
-            me.marti.calprovexample.Color(color.to_object(env)),
-            kotlin.coroutines.Continuation(JObject::null())
-        ) -> Result<Option<java.lang.Object>, ElementExists>)
-            .inspect(|r| if r.is_none() { panic!("Failed creating calendar") });
-        
-        // TODO: parse file contents and add them to the Content Provider
+        let color = color.to_object(env);
+        write_file_data_to_calendar(env, &perm, &name, &color)
+            .or_throw(env);
         // TODO: add to list without adding to provider
     }
 
-    pub fn write_calendar_data_to_file<'local>(name: String) {
-        // TODO:
+    /// Read the data of the Calendar with **name** from the *Content Provider* (its Events, Reminders and Attendees, and the Tasks of its Task List)
+    /// and write it to the Calendar's file in the **internal** directory, and in the **external** directory if **external_dir_uri** is not `NULL`.
+    pub fn write_calendar_data_to_file<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) {
+        write_calendar_data_to_file(env, &perm, &name, external_dir_uri)
+            .or_throw(env)
+    }
+
+    /// Build an *iTIP* `REQUEST` for the event with **uid** in the Calendar with **name**, to send to its attendees after it changed.
+    ///
+    /// The `SEQUENCE` of the event is incremented in the Calendar's file (see [`update_calendar_file()`] for **external_dir_uri**).
+    /// Returns the message as the content of a `.ics` file.
+    pub fn itip_request<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        uid: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) -> String {
+        update_calendar_file(env, &perm, &name, external_dir_uri, |calendar| itip::request(calendar, &uid, time::now()).map_err(Error::Parse))
+            .map(|message| message.to_string())
+            .or_throw(env)
     }
 
-    pub fn write_color_to_calendar_file<'local>(name: String, color: me.marti.calprovexample.Color) {
-        // TODO:
+    /// Build an *iTIP* `CANCEL` for the event with **uid** in the Calendar with **name** (or only its instance with **recurrence_id**),
+    /// to send to its attendees, and mark the event as cancelled (see [`update_calendar_file()`] for **external_dir_uri**).
+    ///
+    /// Returns the message as the content of a `.ics` file.
+    pub fn itip_cancel<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        uid: String,
+        recurrence_id: Option<String>,
+        external_dir_uri: Option<android.net.Uri>,
+    ) -> String {
+        update_calendar_file(env, &perm, &name, external_dir_uri, |calendar| itip::cancel(calendar, &uid, recurrence_id.as_deref(), time::now()).map_err(Error::Parse))
+            .map(|message| message.to_string())
+            .or_throw(env)
+    }
+
+    /// Apply the *iTIP* **message** (a `REPLY`, `COUNTER` or `DECLINECOUNTER`) to the Calendar with **name**
+    /// (see [`update_calendar_file()`] for **external_dir_uri**).
+    ///
+    /// Returns a description of what the message contained, which can be shown to the user.
+    pub fn process_itip_message<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        message: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) -> String {
+        let result = (|| -> Result<String, Error> {
+            let message = parse_itip_message(&message)?;
+            update_calendar_file(env, &perm, &name, external_dir_uri, |calendar| itip::process(calendar, &message).map_err(Error::Parse))
+                .map(|descriptions| descriptions.join("\n"))
+        })();
+        result.or_throw(env)
+    }
+
+    /// Apply the changes proposed in the *iTIP* `COUNTER` **message** to the Calendar with **name**
+    /// (see [`update_calendar_file()`] for **external_dir_uri**).
+    ///
+    /// Returns the `REQUEST` with the changed event to send to its attendees, as the content of a `.ics` file.
+    pub fn itip_accept_counter<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        message: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) -> String {
+        let result = (|| -> Result<String, Error> {
+            let message = parse_itip_message(&message)?;
+            update_calendar_file(env, &perm, &name, external_dir_uri, |calendar| itip::accept_counter(calendar, &message, time::now()).map_err(Error::Parse))
+                .map(|request| request.to_string())
+        })();
+        result.or_throw(env)
+    }
+
+    /// Build the *iTIP* `DECLINECOUNTER` to reply to the `COUNTER` **message** about an event in the Calendar with **name**.
+    ///
+    /// Returns the message as the content of a `.ics` file.
+    pub fn itip_decline_counter<'local>(
+        context: android.content.Context,
+        name: String,
+        message: String,
+    ) -> String {
+        let result = (|| -> Result<String, Error> {
+            let message = parse_itip_message(&message)?;
+            let calendar = read_calendar_file(env, &context, &name)?;
+            itip::decline_counter(&calendar, &message, time::now())
+                .map(|decline| decline.to_string())
+                .map_err(Error::Parse)
+        })();
+        result.or_throw(env)
+    }
+
+    /// Build a `VFREEBUSY` with the times between **from** and **to** (in milliseconds since the *Unix epoch*)
+    /// when the user is busy because of the events of the Calendars with **names**.
+    ///
+    /// Only the busy times are included (not the events), so that the user can publish their availability.
+    /// Returns the content of a `.ics` file with the `VFREEBUSY`.
+    pub fn free_busy_from_calendars<'local>(
+        context: android.content.Context,
+        names: [String],
+        from: long,
+        to: long,
+    ) -> String {
+        free_busy_from_calendars(env, &context, &names, from / 1000, to / 1000)
+            .or_throw(env)
+    }
+
+    /// Write the Calendar with **name** to the file at **file_uri** (e.g. one the user picked to export to) in a **format**,
+    /// which is the extension of one of the [`CalendarFormat`]s: `"ics"`, `"json"` (*jCal*) or `"xml"` (*xCal*).
+    pub fn export_calendar<'local>(
+        context: android.content.Context,
+        name: String,
+        format: String,
+        file_uri: android.net.Uri,
+    ) {
+        let result = (|| -> Result<(), Error> {
+            let format = CalendarFormat::from_extension(&format)
+                .ok_or_else(|| Error::Parse(format!("Unknown calendar format {format:?}")))?;
+            let file_uri = DocUri::from_doc_uri(env, file_uri).map_err(Error::InvalidUri)?;
+            export_calendar(env, &context, &name, format, &file_uri)
+        })();
+        result.or_throw(env)
+    }
+
+    /// Write the events of the Calendar with **name** to the file at **file_uri** as a spreadsheet in CSV,
+    /// with the [default columns](csv::Columns::default()), so that it can be imported back with them.
+    pub fn export_calendar_csv<'local>(
+        context: android.content.Context,
+        name: String,
+        file_uri: android.net.Uri,
+    ) {
+        let result = (|| -> Result<(), Error> {
+            let file_uri = DocUri::from_doc_uri(env, file_uri).map_err(Error::InvalidUri)?;
+            export_calendar_csv(env, &context, &name, &file_uri)
+        })();
+        result.or_throw(env)
+    }
+
+    /// Set the color of the Calendar with **name** in its file in the **internal** directory,
+    /// and in the **external** directory if **external_dir_uri** is not `NULL`.
+    pub fn write_color_to_calendar_file<'local>(
+        context: android.content.Context,
+        name: String,
+        color: me.marti.calprovexample.Color,
+        external_dir_uri: Option<android.net.Uri>,
+    ) {
+        let result = (|| -> Result<(), Error> {
+            let external_dir = match external_dir_uri {
+                Some(external_dir_uri) => Some(open_external_dir(env, &context, external_dir_uri)?),
+                None => None,
+            };
+            let color = Color::from_object(&color, env)
+                .map_err(|err| Error::Parse(format!("Error getting color: {err}")))?;
+            write_color_to_calendar_file(env, &context, &name, color, external_dir.as_ref())
+        })();
+        result.or_throw(env)
     }
 }
 
-fn write_color_to_calendar_file(name: &str, color: Color) {
-    // TODO:
+/// Create the (empty) file of a new Calendar with **file_name** in the **internal** directory,
+/// and in the **external** directory if **external_dir_uri** is [`Some`], and write its **color** to them.
+///
+/// The Calendar is created in the *Content Provider* if there is **perm**ission.
+/// The steps are done in a [`Journal`], so they are undone if one fails.
+fn create_calendar_files<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    perm: Option<&JObject<'local>>,
+    file_name: &str,
+    color: &JObject<'local>,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    crate::initialize_dirs(env, context, external_dir_uri.as_ref())?;
+
+    // Check for illegal characters
+    if file_name.contains(ILLEGAL_FILE_CHARACTERS) {
+        return Err(Error::InvalidUri(format!("File name can't contain the following characters: {ILLEGAL_FILE_CHARACTERS:?}")));
+    }
+    let name = file_stem(file_name);
+    let path = PathBuf::from(FileType::Calendar.dir()).join(file_name);
+    let app_dir = get_app_dir(env, context);
+
+    let mut journal = Journal::begin(env, context, &format!("create {file_name}"))?;
+    let result = (|| -> Result<(), Error> {
+        // Create file in App's internal storage
+        std::fs::File::create_new(app_dir.join(&path))
+            .map_err(|err| Error::io("Error creating file in internal directory", err))?;
+        journal.record(Step::CreateInternal(path.clone()))?;
+        // Create file in external directory in shared storage
+        let external_dir = match external_dir_uri {
+            Some(external_dir_uri) => {
+                let dir_uri = call!(external_dir_uri.toString() -> String);
+                let external_dir = open_external_dir(env, context, external_dir_uri)?;
+                external_dir.create_file_at(env, &path)
+                    .map_err(|err| Error::io("Error creating external file", err))?;
+                journal.record(Step::CreateExternal { dir_uri, path: path.clone() })?;
+                Some(external_dir)
+            },
+            None => None,
+        };
+
+        let color_value = Color::from_object(color, env)
+            .map_err(|err| Error::Parse(format!("Error getting color: {err}")))?;
+        write_color_to_calendar_file(env, context, name, color_value, external_dir.as_ref())?;
+
+        if let Some(perm) = perm {
+            journal.record(Step::CreateCalendar(name.to_string()))?;
+            write_file_data_to_calendar(env, perm, name, color)?;
+        }
+        Ok(())
+    })();
+
+    journal.finish(env, context, perm, result)
+}
+
+/// Delete the Calendar with **name** from the *Content Provider* and its files,
+/// and put a copy of its internal file in the *recycle bin* (the `deleted` directory).
+///
+/// The steps are done in a [`Journal`], so they are undone if one fails.
+fn delete_calendar_files<'local>(
+    env: &mut JNIEnv<'local>,
+    perm: &JObject<'local>,
+    name: &str,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    let context = call!(perm.getContext() -> android.content.Context);
+    let file_name = format!("{name}.ics");
+    let path = PathBuf::from(FileType::Calendar.dir()).join(&file_name);
+    let deleted_path = PathBuf::from("deleted").join(&path);
+    let app_dir = get_app_dir(env, &context);
+
+    let mut journal = Journal::begin(env, &context, &format!("delete {file_name}"))?;
+    let result = (|| -> Result<(), Error> {
+        let content = std::fs::read(app_dir.join(&path))
+            .map_err(|err| Error::io(format!("Error reading calendar file {path:?}"), err))?;
+
+        // Copy internal file to recycle bin
+        std::fs::create_dir_all(app_dir.join("deleted").join(FileType::Calendar.dir()))
+            .and_then(|()| std::fs::write(app_dir.join(&deleted_path), &content))
+            .map_err(|err| Error::io(format!("Error copying {file_name:?} to the recycle bin"), err))?;
+        journal.record(Step::CreateInternal(deleted_path.clone()))?;
+
+        // Delete the Calendar from the Content Provider
+        journal.record(Step::DeleteCalendar(name.to_string()))?;
+        delete_calendar(env, &context, name)?;
+
+        // Delete from App's internal storage
+        let backup = journal.backup(&content)?;
+        std::fs::remove_file(app_dir.join(&path))
+            .map_err(|err| Error::io("Error deleting file in internal directory", err))?;
+        journal.record(Step::RemoveInternal { path: path.clone(), backup })?;
+
+        // Unsubscribe from the feed the Calendar mirrors, if any
+        let subscription_path = crate::dav::Subscription::path(name);
+        if let Ok(subscription) = std::fs::read(app_dir.join(&subscription_path)) {
+            let backup = journal.backup(&subscription)?;
+            std::fs::remove_file(app_dir.join(&subscription_path))
+                .map_err(|err| Error::io("Error deleting subscription of calendar", err))?;
+            journal.record(Step::RemoveInternal { path: subscription_path, backup })?;
+        }
+        // The changes that were not uploaded to the server can't be uploaded without the file,
+        // and with the state of the last sync but no file, the next sync would delete the events from the server
+        for (dav_path, what) in [(crate::dav::Outbox::path(name), "outbox"), (crate::dav::SyncState::path(FileType::Calendar, name), "sync state")] {
+            if let Ok(content) = std::fs::read(app_dir.join(&dav_path)) {
+                let backup = journal.backup(&content)?;
+                std::fs::remove_file(app_dir.join(&dav_path))
+                    .map_err(|err| Error::io(format!("Error deleting {what} of calendar"), err))?;
+                journal.record(Step::RemoveInternal { path: dav_path, backup })?;
+            }
+        }
+
+        // Delete from external directory in shared storage
+        if let Some(external_dir_uri) = external_dir_uri {
+            let dir_uri = call!(external_dir_uri.toString() -> String);
+            let external_dir = open_external_dir(env, &context, external_dir_uri)?;
+            let mut external_content = Vec::new();
+            match external_dir.open_file(env, &path, OpenOptions::ReadOnly) {
+                Ok(mut file) => {
+                    file.read_to_end(&mut external_content)
+                        .map_err(|err| Error::io("Error reading file in external directory", err))?;
+                    let backup = journal.backup(&external_content)?;
+                    external_dir.remove_file_at(env, &path)
+                        .map_err(|err| Error::io("Error deleting file in external directory", err))?;
+                    journal.record(Step::RemoveExternal { dir_uri, path: path.clone(), backup })?;
+                },
+                Err(err) if err.kind() == io::ErrorKind::NotFound => { println!("Tried deleting external file {file_name:?}, but it did not exist."); },
+                Err(err) => return Err(Error::io("Error opening file in external directory", err)),
+            }
+        }
+        Ok(())
+    })();
+
+    journal.finish(env, &context, Some(perm), result)
+}
+
+/// Build the file of the Calendar with **name** from the data in the *Content Provider*
+/// and write it to the **internal** directory, and to the **external** directory if **external_dir_uri** is [`Some`].
+fn write_calendar_data_to_file<'local>(
+    env: &mut JNIEnv<'local>,
+    perm: &JObject<'local>,
+    name: &str,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    let context = call!(perm.getContext() -> android.content.Context);
+    let file_path = get_app_dir(env, &context).join(FileType::Calendar.dir()).join(format!("{name}.ics"));
+
+    let calendar_id = provider::calendar_id(env, &context, name)?
+        .ok_or_else(|| Error::Provider(format!("Calendar {name:?} was not found in the Content Provider")))?;
+    // Keep the properties of the calendar that are only stored in the file (e.g. its color)
+    let old_calendar = std::fs::read_to_string(&file_path)
+        .ok()
+        .and_then(|content| ical::parse(&content).ok())
+        .and_then(|calendars| calendars.into_iter().next());
+
+    let calendar = read_calendar_from_provider(env, &context, calendar_id, name, old_calendar)?;
+    write_calendar_file(env, &context, name, &calendar, external_dir_uri)
+}
+
+/// Write the **calendar** that was changed in the App to the file of the Calendar with **name**
+/// in the **internal** directory, and in the **external** directory if **external_dir_uri** is [`Some`].
+///
+/// The changes are also queued to be uploaded the next time the Calendar is synced with its server (if it is synced).
+fn write_calendar_file<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    calendar: &Component,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    let file_name = format!("{name}.ics");
+    let app_dir = get_app_dir(env, context);
+    let file_path = app_dir.join(FileType::Calendar.dir()).join(&file_name);
+    let content = calendar.to_string();
+
+    std::fs::write(&file_path, &content)
+        .map_err(|err| Error::io("Error writing to file in internal directory", err))?;
+    crate::dav::record_changes(&app_dir, name, calendar)?;
+    if let Some(external_dir_uri) = external_dir_uri {
+        open_external_dir(env, context, external_dir_uri)?
+            .write_file_at(env, PathBuf::from(FileType::Calendar.dir()).join(&file_name), content.as_bytes())
+            .map_err(|err| Error::io("Error writing to file in external directory", err))?;
+        write_base_snapshot(&app_dir, FileType::Calendar.dir(), &file_name, content.as_bytes())
+            .map_err(|err| Error::io(format!("Error writing base snapshot of {file_name:?}"), err))?;
+    }
+
+    Ok(())
+}
+
+/// Read the file of the Calendar with **name** and write its data to the Calendar *Content Provider*.
+///
+/// Creates a new Calendar (with **color**, or [`Color::DEFAULT`] if it's `NULL`) in the Content Provider if one with **name** does not exist.
+/// If the file has a color, it is used instead of **color**.
+pub fn write_file_data_to_calendar<'local>(
+    env: &mut JNIEnv<'local>,
+    perm: &JObject<'local>,
+    name: &str,
+    color: &JObject<'local>,
+) -> Result<(), Error> {
+    #[derive(FromException)]
+    #[class(me.marti.calprovexample.ElementExistsException)]
+    struct ElementExists;
+
+    let context = call!(perm.getContext() -> android.content.Context);
+    // newCalendar() needs a color, even if it is replaced by the one in the file
+    let default_color;
+    let color = if color.is_null() {
+        default_color = Color::DEFAULT.to_object(env);
+        &default_color
+    } else {
+        color
+    };
+
+    // Create the calendar if it does not exist. Ignore the result
+    let created = call!(static me.marti.calprovexample.calendar.ActionsKt.newCalendar(
+        me.marti.calprovexample.ui.CalendarPermissionScope(perm),
+        String(name),
+        me.marti.calprovexample.Color(color),
+        kotlin.coroutines.Continuation(JObject::null())
+    ) -> Result<Option<java.lang.Object>, ElementExists>);
+    if let Ok(None) = created {
+        return Err(Error::Provider(format!("Failed creating calendar {name:?}")));
+    }
+
+    let file_path = get_app_dir(env, &context).join(FileType::Calendar.dir()).join(format!("{name}.ics"));
+    let content = std::fs::read_to_string(&file_path)
+        .map_err(|err| Error::io(format!("Error reading calendar file {file_path:?}"), err))?;
+    let calendar_id = provider::calendar_id(env, &context, name)?
+        .ok_or_else(|| Error::Provider(format!("Calendar {name:?} was not found in the Content Provider")))?;
+
+    // The color stored in the file takes precedence over the one the calendar was created with
+    if let Some(color) = ical::parse(&content).ok()
+        .and_then(|calendars| calendars.into_iter().next())
+        .and_then(|calendar| color::read_color(&calendar))
+    {
+        let mut row = Row::new();
+        row.put(calendars::COLOR, color.to_argb());
+        provider::update(env, &context, calendars::CONTENT_URI, calendar_id, &row)?;
+    }
+
+    write_events_to_provider(env, &context, calendar_id, &content)?;
+    write_tasks_to_provider(env, &context, name, calendar_id, &content)
+}
+
+/// Delete the Calendar with **name** (and all its Events) from the Calendar *Content Provider*,
+/// and its Task List from the *OpenTasks* provider.
+///
+/// Does nothing if there is no such Calendar.
+pub fn delete_calendar(env: &mut JNIEnv, context: &JObject, name: &str) -> Result<(), Error> {
+    if let Some(calendar_id) = provider::calendar_id(env, context, name)? {
+        provider::delete(env, context, calendars::CONTENT_URI, &format!("{} = ?", calendars::ID), &[&calendar_id.to_string()])?;
+    }
+    // The OpenTasks provider might not be installed, in which case there are no tasks to delete
+    if let Ok(Some(list_id)) = provider::task_list_id(env, context, name) {
+        provider::delete(env, context, task_lists::CONTENT_URI, &format!("{} = ?", task_lists::ID), &[&list_id.to_string()])?;
+    }
+    Ok(())
+}
+
+/// Set whether the user can change the events of the Calendar with **name** in the Calendar *Content Provider*
+/// (e.g. a Calendar that is subscribed to a feed is **read_only**).
+pub fn set_read_only(env: &mut JNIEnv, context: &JObject, name: &str, read_only: bool) -> Result<(), Error> {
+    let calendar_id = provider::calendar_id(env, context, name)?
+        .ok_or_else(|| Error::Provider(format!("Calendar {name:?} was not found in the Content Provider")))?;
+    let mut row = Row::new();
+    row.put(calendars::ACCESS_LEVEL, if read_only { calendars::ACCESS_READ } else { calendars::ACCESS_OWNER });
+    provider::update(env, context, calendars::CONTENT_URI, calendar_id, &row)?;
+    Ok(())
+}
+
+/// Parse the **content** of a calendar file and insert its events into the Calendar with **calendar_id**,
+/// replacing all the events the Calendar had before.
+///
+/// The old events are deleted and the new ones inserted in a single batch (see [`event_operations()`]),
+/// so the Calendar is never left half written if one of the inserts fails.
+fn write_events_to_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    calendar_id: i64,
+    content: &str,
+) -> Result<(), Error> {
+    let calendars = ical::parse(content).map_err(|err| Error::Parse(err.to_string()))?;
+
+    let limits = provider::query(env, context,
+        calendars::CONTENT_URI,
+        &[calendars::MAX_REMINDERS, calendars::ALLOWED_REMINDERS],
+        &format!("({} = ?)", calendars::ID),
+        &[&calendar_id.to_string()]
+    )?
+        .first()
+        .map(ReminderLimits::from_row)
+        .unwrap_or_else(|| ReminderLimits::from_row(&Row::new()));
+
+    let (operations, messages) = event_operations(&calendars, calendar_id, &limits, &mut JavaTimeZones { env: &mut *env })?;
+    for message in messages {
+        println!("{message}");
+    }
+    provider::apply_batch(env, context, projections::AUTHORITY, &operations)
+}
+
+/// Build the [`Operation`]s that replace all the events of the Calendar with **calendar_id**
+/// with the events of the parsed calendar file (**calendars**).
+///
+/// Events that can't be read (e.g. are missing `DTSTART`) are skipped.
+/// The time zones of the events are resolved to IANA time zones, or converted to UTC with the `VTIMEZONE`s of the file (see [`TimeZones`]).
+/// Exceptions (events with `RECURRENCE-ID`) are linked to the recurring event with the same UID,
+/// or inserted as normal events if the calendar doesn't have that recurring event.
+/// The Reminders and Attendees of each event are inserted right after it.
+///
+/// Returns the operations and the messages about what was skipped or changed.
+fn event_operations(
+    calendars: &[Component],
+    calendar_id: i64,
+    limits: &ReminderLimits,
+    resolver: &mut dyn TimeZoneResolver,
+) -> Result<(Vec<Operation>, Vec<String>), Error> {
+    let mut messages = Vec::new();
+    let mut operations = vec![Operation::Delete {
+        table_uri: events::CONTENT_URI,
+        selection: format!("({} = ?)", events::CALENDAR_ID),
+        selection_args: vec![calendar_id.to_string()],
+    }];
+
+    let mut exceptions = Vec::new();
+    // The indices of the operations that insert the recurring events, to link the exceptions with
+    let mut recurring_indices = Vec::new();
+    let timezones = VTimeZone::from_calendars(calendars);
+    for component in calendars.iter().flat_map(|calendar| calendar.components("VEVENT")) {
+        let mut event = match Event::from_component(component) {
+            Ok(event) => event,
+            Err(err) => {
+                messages.push(format!("Skipping event: {err}"));
+                continue;
+            }
+        };
+        event.normalize_time_zones(&mut TimeZones { system: &mut *resolver, embedded: &timezones });
+        let mut alarms = Vec::new();
+        for alarm in component.components("VALARM") {
+            match Alarm::from_component(alarm) {
+                Ok(alarm) => alarms.push(alarm),
+                Err(err) => messages.push(format!("Skipping alarm of event {}: {err}", event.uid.as_deref().unwrap_or("<no UID>"))),
+            }
+        }
+        let attendees = Attendee::from_properties(&component.properties);
+        // The recurring events must be inserted first
+        if event.is_exception() {
+            exceptions.push((event, alarms, attendees));
+            continue;
+        }
+        if event.is_recurring() {
+            let recurrence = Recurrence::from_event(&event).map_err(Error::Parse)?;
+            if recurrence.is_empty(&mut *resolver).map_err(Error::Parse)? {
+                messages.push(format!("Skipping event {}: all of its instances are excluded", event.uid.as_deref().unwrap_or("<no UID>")));
+                continue;
+            }
+        }
+        let mut row = event.to_row(&mut *resolver).map_err(Error::Parse)?;
+        row.put(events::CALENDAR_ID, calendar_id);
+        if event.is_recurring() {
+            recurring_indices.push((event.uid.clone(), operations.len()));
+        }
+        operations.push(Operation::Insert { table_uri: events::CONTENT_URI, row, back_references: Vec::new() });
+        event_children_operations(&mut operations, &event, &alarms, &attendees, limits, &mut messages, &mut *resolver);
+    }
+
+    for (mut event, alarms, attendees) in exceptions {
+        let original_index = recurring_indices.iter()
+            .find(|(uid, _)| uid.is_some() && *uid == event.uid)
+            .map(|(_, index)| *index);
+        if original_index.is_none() {
+            messages.push(format!("Recurring event of exception {} not found", event.uid.as_deref().unwrap_or("<no UID>")));
+            event.recurrence_id = None;
+        }
+        let mut row = event.to_row(&mut *resolver).map_err(Error::Parse)?;
+        row.put(events::CALENDAR_ID, calendar_id);
+        operations.push(Operation::Insert {
+            table_uri: events::CONTENT_URI,
+            row,
+            back_references: original_index.map(|index| (events::ORIGINAL_ID, index)).into_iter().collect(),
+        });
+        event_children_operations(&mut operations, &event, &alarms, &attendees, limits, &mut messages, &mut *resolver);
+    }
+
+    Ok((operations, messages))
+}
+
+/// Add the operations that insert the **alarms** (within the **limits** of its Calendar) and **attendees** of an **event**,
+/// whose insert must be the last of the **operations**.
+///
+/// Alarms that had to be changed or dropped to fit the limits are reported in the **messages**.
+fn event_children_operations(
+    operations: &mut Vec<Operation>,
+    event: &Event,
+    alarms: &[Alarm],
+    attendees: &[Attendee],
+    limits: &ReminderLimits,
+    messages: &mut Vec<String>,
+    resolver: &mut dyn TimeZoneResolver,
+) {
+    let event_index = operations.len() - 1;
+    let (rows, reminder_messages) = limits.reminders(alarms, event, resolver);
+    messages.extend(reminder_messages);
+    for row in rows {
+        operations.push(Operation::Insert {
+            table_uri: reminders::CONTENT_URI,
+            row,
+            back_references: vec![(reminders::EVENT_ID, event_index)],
+        });
+    }
+    for attendee in attendees {
+        operations.push(Operation::Insert {
+            table_uri: attendees::CONTENT_URI,
+            row: attendee.to_row(),
+            back_references: vec![(attendees::EVENT_ID, event_index)],
+        });
+    }
+}
+
+/// Parse the **content** of a calendar file and insert its tasks into the Task List with **name** in the *OpenTasks* provider,
+/// replacing all the tasks the Task List had before.
+///
+/// The Task List is created (with the color of the Calendar with **calendar_id**) if it does not exist and the file has tasks.
+/// If the OpenTasks provider is not available, the tasks are skipped.
+/// Subtasks are linked to the task with the UID in their `RELATED-TO`, or the task that lists them as its `CHILD`.
+fn write_tasks_to_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    calendar_id: i64,
+    content: &str,
+) -> Result<(), Error> {
+    let calendars = ical::parse(content).map_err(|err| Error::Parse(err.to_string()))?;
+    let timezones = VTimeZone::from_calendars(&calendars);
+    let mut todos = Vec::new();
+    for component in calendars.iter().flat_map(|calendar| calendar.components("VTODO")) {
+        match Task::from_component(component) {
+            Ok(mut task) => {
+                task.normalize_time_zones(&mut TimeZones { system: &mut JavaTimeZones { env: &mut *env }, embedded: &timezones });
+                todos.push(task);
+            },
+            Err(err) => { println!("Skipping task: {err}"); },
+        }
+    }
+
+    let list_id = match provider::task_list_id(env, context, name) {
+        Ok(Some(list_id)) => list_id,
+        Ok(None) if todos.is_empty() => return Ok(()),
+        Ok(None) => {
+            let color = provider::query(env, context,
+                calendars::CONTENT_URI,
+                &[calendars::COLOR],
+                &format!("({} = ?)", calendars::ID),
+                &[&calendar_id.to_string()]
+            )?
+                .first()
+                .and_then(|row| row.get_long(calendars::COLOR));
+            let mut row = Row::new();
+            row.put(task_lists::NAME, name);
+            row.put(task_lists::COLOR, color);
+            row.put(task_lists::ACCOUNT_NAME, account_name(env, context));
+            row.put(task_lists::ACCOUNT_TYPE, ACCOUNT_TYPE_LOCAL);
+            row.put(task_lists::SYNC_ENABLED, true);
+            row.put(task_lists::VISIBLE, true);
+            provider::insert(env, context, task_lists::CONTENT_URI, &row)?
+        },
+        Err(err) => {
+            if !todos.is_empty() {
+                println!("Skipping {} tasks of {name:?}: {err}", todos.len());
+            }
+            return Ok(());
+        },
+    };
+
+    // Remove the old tasks
+    provider::delete(env, context,
+        tasks::CONTENT_URI,
+        &format!("({} = ?)", tasks::LIST_ID),
+        &[&list_id.to_string()]
+    )?;
+
+    let mut ids = Vec::with_capacity(todos.len());
+    for task in &todos {
+        let mut row = task.to_row(&mut JavaTimeZones { env: &mut *env }).map_err(Error::Parse)?;
+        row.put(tasks::LIST_ID, list_id);
+        ids.push(provider::insert(env, context, tasks::CONTENT_URI, &row)?);
+    }
+
+    // The parents of the subtasks can only be set once all the tasks have an ID
+    let id_of = |uid: &str| todos.iter()
+        .zip(&ids)
+        .find(|(task, _)| task.uid.as_deref() == Some(uid))
+        .map(|(_, id)| *id);
+    for (task, id) in todos.iter().zip(&ids) {
+        let listed_by = |parent: &&Task| parent.children.iter().any(|child| Some(child.as_str()) == task.uid.as_deref());
+        let parent = task.parent.as_deref()
+            .or_else(|| todos.iter().find(listed_by).and_then(|parent| parent.uid.as_deref()));
+        let Some(parent) = parent else { continue };
+        match id_of(parent) {
+            Some(parent_id) => {
+                let mut row = Row::new();
+                row.put(tasks::PARENT_ID, parent_id);
+                provider::update(env, context, tasks::CONTENT_URI, *id, &row)?;
+            },
+            None => { println!("Parent task {parent} of task {} not found", task.uid.as_deref().unwrap_or("<no UID>")); },
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a `VCALENDAR` with all the Events (and their Reminders and Attendees) of the Calendar with **calendar_id**,
+/// and the Tasks of the Task List with the same **name**.
+///
+/// The properties of **old_calendar** (the calendar previously stored in the file, if any) are kept,
+/// and so are its components that are not in the Content Provider (e.g. `VTIMEZONE`s, `VJOURNAL`s or unknown components).
+/// Its `VEVENT`s are replaced (keeping only their `SEQUENCE`), and so are its `VTODO`s if the Task List could be read.
+/// A `VTIMEZONE` is added for each time zone the events use that is not already defined.
+fn read_calendar_from_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    calendar_id: i64,
+    name: &str,
+    old_calendar: Option<Component>,
+) -> Result<Component, Error> {
+    let (mut calendar, old_components) = match old_calendar {
+        Some(mut calendar) => {
+            let old_components = std::mem::take(&mut calendar.components);
+            (calendar, old_components)
+        },
+        None => (new_calendar(name), Vec::new()),
+    };
+    let dtstamp = time::now();
+
+    let event_rows = provider::query(env, context,
+        events::CONTENT_URI,
+        &events::COPY_PROJECTION,
+        &format!("({} = ?)", events::CALENDAR_ID),
+        &[&calendar_id.to_string()]
+    )?;
+    let mut rows = Vec::new();
+    for row in event_rows {
+        let event_id = row
+            .get_long(events::ID)
+            .ok_or_else(|| Error::Parse("Event row has no ID".to_string()))?;
+        let event = Event::from_row(&row, &mut JavaTimeZones { env: &mut *env }).map_err(Error::Parse)?;
+        rows.push((event_id, row, event));
+    }
+
+    for (event_id, event) in link_exceptions(rows, calendar_id, &mut JavaTimeZones { env: &mut *env }) {
+        calendar.components.push(event_to_component(env, context, event_id, &event, dtstamp)?);
+    }
+
+    let todos = read_tasks_from_provider(env, context, name, dtstamp)?;
+    let has_todos = todos.is_some();
+    calendar.components.extend(todos.into_iter().flatten());
+
+    // The Content Provider doesn't store the SEQUENCE, which iTIP needs to tell which version of an event is newer
+    for component in &mut calendar.components {
+        let sequence = old_components.iter()
+            .find(|old| old.name == component.name
+                && old.value("UID") == component.value("UID")
+                && old.value("RECURRENCE-ID") == component.value("RECURRENCE-ID"))
+            .and_then(|old| old.property("SEQUENCE"));
+        if let Some(sequence) = sequence {
+            component.set(sequence.clone());
+        }
+    }
+
+    // Keep the components that are not in the Content Provider (time zones, journals, unknown components),
+    // and the tasks if they could not be read from the OpenTasks provider
+    let (mut timezones, others) = old_components.into_iter()
+        .filter(|component| component.name != "VEVENT" && !(has_todos && component.name == "VTODO"))
+        .partition::<Vec<_>, _>(|component| component.name == "VTIMEZONE");
+    calendar.components.extend(others);
+
+    // Other apps need the definitions of the time zones used by the events and tasks
+    let mut tzids = Vec::new();
+    for tzid in calendar.components.iter().flat_map(|component| &component.properties).filter_map(|prop| prop.param("TZID")) {
+        let defined = timezones.iter().any(|timezone| timezone.value("TZID") == Some(tzid));
+        if !defined && !tzids.contains(&tzid) {
+            tzids.push(tzid);
+        }
+    }
+    timezones.extend(tzids.into_iter()
+        .filter_map(|tzid| timezone::to_vtimezone(tzid, dtstamp.date.year, &mut JavaTimeZones { env: &mut *env })));
+    calendar.components.splice(0..0, timezones);
+
+    Ok(calendar)
+}
+
+/// Read all the Tasks of the Task List with **name** from the *OpenTasks* provider, and convert them to `VTODO`s.
+///
+/// Returns [`None`] if there is no such Task List or the provider is not available.
+fn read_tasks_from_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    dtstamp: time::DateTime,
+) -> Result<Option<Vec<Component>>, Error> {
+    let list_id = match provider::task_list_id(env, context, name) {
+        Ok(Some(list_id)) => list_id,
+        Ok(None) => return Ok(None),
+        Err(err) => {
+            println!("Skipping tasks of {name:?}: {err}");
+            return Ok(None);
+        },
+    };
+
+    let mut todos = Vec::new();
+    for row in provider::query(env, context,
+        tasks::CONTENT_URI,
+        &tasks::COPY_PROJECTION,
+        &format!("({} = ?)", tasks::LIST_ID),
+        &[&list_id.to_string()]
+    )? {
+        let task_id = row
+            .get_long(tasks::ID)
+            .ok_or_else(|| Error::Parse("Task row has no ID".to_string()))?;
+        let mut task = Task::from_row(&row, &mut JavaTimeZones { env: &mut *env });
+        // Tasks created by other apps might not have a UID, but it is required in the file
+        if task.uid.is_none() {
+            task.uid = Some(format!("task-{task_id}-{list_id}@davsync"));
+        }
+        todos.push((task_id, row.get_long(tasks::PARENT_ID), task));
+    }
+
+    let uids = todos.iter()
+        .map(|(task_id, _, task)| (*task_id, task.uid.clone()))
+        .collect::<Vec<_>>();
+    Ok(Some(todos.into_iter()
+        .map(|(_, parent_id, mut task)| {
+            task.parent = parent_id.and_then(|parent_id| uids.iter().find(|(task_id, _)| *task_id == parent_id))
+                .and_then(|(_, uid)| uid.clone());
+            task.to_component(dtstamp)
+        })
+        .collect()))
+}
+
+/// Order the events read from the rows of a Calendar (with their **ID**) the way they are written in the calendar file:
+/// each recurring event followed by its exceptions, which get the UID of the recurring event
+/// and a `RECURRENCE-ID` in the same form as its `DTSTART`.
+///
+/// Rows with an **original_id** of an event that is not in the Calendar are written as normal events.
+fn link_exceptions(rows: Vec<(i64, Row, Event)>, calendar_id: i64, resolver: &mut dyn TimeZoneResolver) -> Vec<(i64, Event)> {
+    let is_exception = |row: &Row| row.get_long(events::ORIGINAL_ID)
+        .is_some_and(|original_id| rows.iter().any(|(event_id, _, _)| *event_id == original_id));
+    let (exceptions, recurring) = rows.iter().partition::<Vec<_>, _>(|(_, row, _)| is_exception(row));
+    let mut events = Vec::with_capacity(rows.len());
+    for (event_id, row, event) in recurring {
+        let mut event = event.clone();
+        // Events created by other apps don't have a UID, but it is required in the file
+        if event.uid.is_none() {
+            event.uid = Some(format!("{event_id}-{calendar_id}@davsync"));
+        }
+        // The recurring event of this exception is not in the calendar
+        if row.get_long(events::ORIGINAL_ID).is_some() {
+            event.recurrence_id = None;
+        }
+
+        let mut event_exceptions = Vec::new();
+        for (exception_id, exception_row, exception) in &exceptions {
+            if exception_row.get_long(events::ORIGINAL_ID) != Some(*event_id) {
+                continue;
+            }
+            let mut exception = exception.clone();
+            exception.uid = event.uid.clone();
+            exception.recurrence_id = exception_row.get_long(events::ORIGINAL_INSTANCE_TIME)
+                .map(|instance| event.instance_time(instance / 1000, resolver));
+            event_exceptions.push((*exception_id, exception));
+        }
+        events.push((*event_id, event));
+        events.extend(event_exceptions);
+    }
+    events
+}
+
+/// Convert an **event** to a `VEVENT`, along with the Reminders and Attendees of the row with **event_id**.
+fn event_to_component<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    event_id: i64,
+    event: &Event,
+    dtstamp: time::DateTime,
+) -> Result<Component, Error> {
+    let event_id = event_id.to_string();
+    let mut component = event.to_component(dtstamp);
+
+    for row in provider::query(env, context,
+        attendees::CONTENT_URI,
+        &attendees::COPY_PROJECTION,
+        &format!("({} = ?)", attendees::EVENT_ID),
+        &[&event_id]
+    )? {
+        let Some(attendee) = Attendee::from_row(&row) else { continue };
+        // The name of the organizer is only stored in its Attendee row
+        let is_event_organizer = event.organizer.as_ref().is_none_or(|organizer| organizer.eq_ignore_ascii_case(&attendee.address));
+        if let Some(organizer) = attendee.to_organizer_property().filter(|_| is_event_organizer) {
+            component.set(organizer);
+        }
+        component.properties.extend(attendee.to_property());
+    }
+    for row in provider::query(env, context,
+        reminders::CONTENT_URI,
+        &reminders::COPY_PROJECTION,
+        &format!("({} = ?)", reminders::EVENT_ID),
+        &[&event_id]
+    )? {
+        component.components.push(Alarm::from_reminder(&row).to_component(event.summary.as_deref()));
+    }
+
+    Ok(component)
+}
+
+/// Build a calendar file with a `VFREEBUSY` of the events in the internal files of the Calendars with **names**,
+/// between **from** and **to** (in seconds since the *Unix epoch*).
+///
+/// The file has `METHOD:PUBLISH`, as it is meant to be shared.
+fn free_busy_from_calendars(
+    env: &mut JNIEnv,
+    context: &JObject,
+    names: &[String],
+    from: i64,
+    to: i64,
+) -> Result<String, Error> {
+    if from >= to {
+        return Err(Error::Parse(format!("Invalid time range: {from} is not before {to}")));
+    }
+    let mut calendars = Vec::with_capacity(names.len());
+    for name in names {
+        calendars.push(read_calendar_file(env, context, name)?);
+    }
+
+    let (periods, messages) = freebusy::busy_periods(&calendars, from, to, &mut JavaTimeZones { env: &mut *env });
+    for message in messages {
+        println!("{message}");
+    }
+
+    let mut calendar = Component::new("VCALENDAR");
+    calendar.push(Property::new("VERSION", "2.0"));
+    calendar.push(Property::new("PRODID", PRODID));
+    calendar.push(Property::new("METHOD", "PUBLISH"));
+    calendar.components.push(freebusy::to_component(&periods, from, to, time::now()));
+    Ok(calendar.to_string())
+}
+
+/// Write the internal file of the Calendar with **name** to the file at **file_uri** in **format**.
+fn export_calendar<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    format: CalendarFormat,
+    file_uri: &DocUri<'local>,
+) -> Result<(), Error> {
+    let calendar = read_calendar_file(env, context, name)?;
+    file_uri.open_file(env, context, OpenOptions::WriteOnly { extra: Some(ExtraMode::Truncate) })
+        .and_then(|mut file| file.write_all(format.write(&[calendar]).as_bytes()))
+        .map_err(|err| Error::io(format!("Error exporting calendar {name:?}"), err))
+}
+
+/// Write the events of the internal file of the Calendar with **name** to the file at **file_uri** as a spreadsheet in CSV.
+fn export_calendar_csv<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    file_uri: &DocUri<'local>,
+) -> Result<(), Error> {
+    let calendar = read_calendar_file(env, context, name)?;
+    let (content, messages) = csv::export(&calendar, &mut JavaTimeZones { env: &mut *env });
+    for message in messages {
+        println!("{message}");
+    }
+    file_uri.open_file(env, context, OpenOptions::WriteOnly { extra: Some(ExtraMode::Truncate) })
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|err| Error::io(format!("Error exporting calendar {name:?} to CSV"), err))
+}
+
+/// Convert the **content** of a spreadsheet in CSV to the content of the file of a new Calendar with **name** (see [`csv::import()`]).
+///
+/// Returns the errors of the rows that were skipped along with the content.
+pub fn csv_to_calendar(env: &mut JNIEnv, content: &[u8], columns: &csv::Columns, name: &str) -> Result<(String, Vec<csv::RowError>), Error> {
+    let content = std::str::from_utf8(content)
+        .map_err(|err| Error::Parse(format!("CSV file is not valid UTF-8: {err}")))?;
+    let (calendar, row_errors) = csv::import(content, columns, name, time::now(), &mut JavaTimeZones { env: &mut *env })
+        .map_err(Error::Parse)?;
+    Ok((calendar.to_string(), row_errors))
+}
+
+/// Read the `VCALENDAR` in the internal file of the Calendar with **name**.
+fn read_calendar_file(env: &mut JNIEnv, context: &JObject, name: &str) -> Result<Component, Error> {
+    let file_path = get_app_dir(env, context).join(FileType::Calendar.dir()).join(format!("{name}.ics"));
+    let content = std::fs::read_to_string(&file_path)
+        .map_err(|err| Error::io(format!("Error reading calendar file {file_path:?}"), err))?;
+    ical::parse(&content)
+        .map_err(|err| Error::Parse(err.to_string()))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Parse(format!("Calendar file {file_path:?} has no VCALENDAR")))
+}
+
+/// Make a **change** to the `VCALENDAR` in the internal file of the Calendar with **name**,
+/// and write the changed file to the Calendar *Content Provider*.
+///
+/// Like [`write_calendar_data_to_file()`], the file is also written to the **external** directory if **external_dir_uri** is [`Some`],
+/// and the changes are queued to be uploaded to the Calendar's server.
+fn update_calendar_file<'local, T>(
+    env: &mut JNIEnv<'local>,
+    perm: &JObject<'local>,
+    name: &str,
+    external_dir_uri: Option<JObject<'local>>,
+    change: impl FnOnce(&mut Component) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let context = call!(perm.getContext() -> android.content.Context);
+    let mut calendar = read_calendar_file(env, &context, name)?;
+    let result = change(&mut calendar)?;
+
+    write_calendar_file(env, &context, name, &calendar, external_dir_uri)?;
+    write_file_data_to_calendar(env, perm, name, &JObject::null())?;
+
+    Ok(result)
+}
+
+/// Parse the content of an *iTIP* message, which is a `VCALENDAR` with a `METHOD`.
+fn parse_itip_message(content: &str) -> Result<Component, Error> {
+    ical::parse(content)
+        .map_err(|err| Error::Parse(err.to_string()))?
+        .into_iter()
+        .find(|calendar| itip::Method::of(calendar).is_some())
+        .ok_or_else(|| Error::Parse("iTIP message has no METHOD".to_string()))
+}
+
+/// Set the color of the `VCALENDAR` in the file of the Calendar with **name**, keeping the rest of the file as it was.
+///
+/// The file is written to the internal directory, and to **external_dir** if it is [`Some`].
+/// If the file is empty (e.g. it was just created), a new `VCALENDAR` is written to it.
+fn write_color_to_calendar_file<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    color: Color,
+    external_dir: Option<&ExternalDir<'local>>,
+) -> Result<(), Error> {
+    let file_name = format!("{name}.ics");
+    let app_dir = get_app_dir(env, context);
+    let file_path = app_dir.join(FileType::Calendar.dir()).join(&file_name);
+    let content = std::fs::read_to_string(&file_path)
+        .map_err(|err| Error::io(format!("Error reading file {file_path:?}"), err))?;
+
+    let mut calendars = ical::parse(&content).map_err(|err| Error::Parse(err.to_string()))?;
+    if calendars.is_empty() {
+        calendars.push(new_calendar(name));
+    }
+    // Only the first VCALENDAR is used by the App
+    color::write_color(&mut calendars[0], color);
+    let content = calendars.iter()
+        .map(ToString::to_string)
+        .collect::<String>();
+
+    std::fs::write(&file_path, &content)
+        .map_err(|err| Error::io("Error writing to file in internal directory", err))?;
+    if let Some(external_dir) = external_dir {
+        external_dir.write_file_at(env, PathBuf::from(FileType::Calendar.dir()).join(&file_name), content.as_bytes())
+            .map_err(|err| Error::io("Error writing to file in external directory", err))?;
+        write_base_snapshot(&app_dir, FileType::Calendar.dir(), &file_name, content.as_bytes())
+            .map_err(|err| Error::io(format!("Error writing base snapshot of {file_name:?}"), err))?;
+    }
+
+    Ok(())
+}
+
+/// Create an empty `VCALENDAR` for a Calendar with **name**.
+pub fn new_calendar(name: &str) -> Component {
+    let mut calendar = Component::new("VCALENDAR");
+    calendar.push(Property::new("VERSION", "2.0"));
+    calendar.push(Property::new("PRODID", PRODID));
+    calendar.push(Property::text("X-WR-CALNAME", name));
+    calendar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Knows only `America/New_York`, always at *-05:00*.
+    struct NewYork;
+    impl TimeZoneResolver for NewYork {
+        fn utc_offset(&mut self, tzid: &str, _local: i64) -> Option<i32> {
+            (tzid == "America/New_York").then_some(-5 * 3600)
+        }
+        fn default_tzid(&mut self) -> String {
+            "America/New_York".to_string()
+        }
+    }
+
+    /// A daily event with an instance that was moved and one that was cancelled.
+    const EXCEPTIONS: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n\
+        BEGIN:VEVENT\r\nUID:daily\r\nSUMMARY:Standup\r\nDTSTART;TZID=America/New_York:20240101T090000\r\nDURATION:PT15M\r\nRRULE:FREQ=DAILY;COUNT=5\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:daily\r\nSUMMARY:Standup (moved)\r\nRECURRENCE-ID;TZID=America/New_York:20240102T090000\r\nDTSTART;TZID=America/New_York:20240102T140000\r\nDURATION:PT15M\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:daily\r\nSUMMARY:Standup\r\nRECURRENCE-ID;TZID=America/New_York:20240103T090000\r\nDTSTART;TZID=America/New_York:20240103T090000\r\nDURATION:PT15M\r\nSTATUS:CANCELLED\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:orphan\r\nSUMMARY:Lost\r\nRECURRENCE-ID;TZID=America/New_York:20240105T090000\r\nDTSTART;TZID=America/New_York:20240105T090000\r\nDURATION:PT15M\r\nEND:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    /// Apply the inserts of events in the **operations** like the Content Provider would,
+    /// giving each row the index of its operation as **ID** and resolving the back references.
+    fn insert_events(operations: &[Operation]) -> Vec<Row> {
+        operations.iter().enumerate()
+            .filter_map(|(index, operation)| match operation {
+                Operation::Insert { table_uri: events::CONTENT_URI, row, back_references } => {
+                    let mut row = row.clone();
+                    row.put(events::ID, index as i64);
+                    for &(column, reference) in back_references {
+                        row.put(column, reference as i64);
+                    }
+                    Some(row)
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn write_exceptions() {
+        let calendars = ical::parse(EXCEPTIONS).unwrap();
+        let (operations, messages) = event_operations(&calendars, 7, &ReminderLimits::from_row(&Row::new()), &mut NewYork).unwrap();
+        assert_eq!(messages, ["Recurring event of exception orphan not found"]);
+        assert_eq!(operations[0], Operation::Delete {
+            table_uri: events::CONTENT_URI,
+            selection: format!("({} = ?)", events::CALENDAR_ID),
+            selection_args: vec!["7".to_string()],
+        });
+
+        let rows = insert_events(&operations);
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row.get_long(events::CALENDAR_ID) == Some(7)));
+        // The recurring event is inserted first, so that the exceptions can refer to its ID
+        let recurring = &rows[0];
+        let recurring_id = recurring.get_long(events::ID);
+        assert_eq!(recurring.get_text(events::TITLE), Some("Standup"));
+        assert_eq!(recurring.get_long(events::ORIGINAL_ID), None);
+
+        let moved = rows.iter().find(|row| row.get_text(events::TITLE) == Some("Standup (moved)")).unwrap();
+        assert_eq!(moved.get_long(events::ORIGINAL_ID), recurring_id);
+        // 2024-01-02 09:00 -05:00
+        assert_eq!(moved.get_long(events::ORIGINAL_INSTANCE_TIME), Some(1704204000 * 1000));
+        assert_eq!(moved.get_long(events::DATE_START), Some(1704222000 * 1000));
+        assert_eq!(moved.get_long(events::STATUS), None);
+
+        let cancelled = rows.iter().find(|row| row.get_long(events::STATUS) == Some(events::STATUS_CANCELED)).unwrap();
+        assert_eq!(cancelled.get_long(events::ORIGINAL_ID), recurring_id);
+        assert_eq!(cancelled.get_long(events::ORIGINAL_INSTANCE_TIME), Some(1704290400 * 1000));
+
+        // The exception without its recurring event becomes a normal event
+        let orphan = rows.iter().find(|row| row.get_text(events::UID) == Some("orphan")).unwrap();
+        assert_eq!(orphan.get_long(events::ORIGINAL_ID), None);
+        assert_eq!(orphan.get_long(events::ORIGINAL_INSTANCE_TIME), None);
+    }
+
+    #[test]
+    fn exceptions_round_trip() {
+        let calendars = ical::parse(EXCEPTIONS).unwrap();
+        let (operations, _) = event_operations(&calendars, 7, &ReminderLimits::from_row(&Row::new()), &mut NewYork).unwrap();
+        // The Content Provider does not return the UID of exceptions created by other apps
+        let rows = insert_events(&operations).into_iter()
+            .map(|mut row| {
+                if row.get_long(events::ORIGINAL_ID).is_some() {
+                    row.put(events::UID, None::<String>);
+                }
+                let event = Event::from_row(&row, &mut NewYork).unwrap();
+                (row.get_long(events::ID).unwrap(), row, event)
+            })
+            .collect();
+
+        let components = link_exceptions(rows, 7, &mut NewYork).into_iter()
+            .map(|(_, event)| event.to_component(time::now()))
+            .collect::<Vec<_>>();
+        // The RECURRENCE-ID is compared with its parameters, which must have the same TZID as the DTSTART
+        let summary = |component: &Component| [
+            component.value("UID").map(str::to_string),
+            component.value("SUMMARY").map(str::to_string),
+            component.property("RECURRENCE-ID").map(ToString::to_string),
+            component.value("STATUS").map(str::to_string),
+        ];
+        let expected = calendars[0].components("VEVENT").map(summary).collect::<Vec<_>>();
+        // Each recurring event is followed by its exceptions
+        assert_eq!(components.iter().map(summary).collect::<Vec<_>>(), [
+            expected[0].clone(),
+            expected[1].clone(),
+            expected[2].clone(),
+            // The orphan lost its RECURRENCE-ID when it was written
+            [Some("orphan".to_string()), Some("Lost".to_string()), None, None],
+        ]);
+    }
 }
@@ -0,0 +1,208 @@
+//! Names of the columns of the Calendar *Content Provider* tables, and the constant values stored in them.
+//!
+//! These mirror the constants of `android.provider.CalendarContract` and the projections in `calendar/Projections.kt`.
+//! The [`task_lists`] and [`tasks`] tables are in the *OpenTasks* provider, and mirror `org.dmfs.tasks.contract.TaskContract`.
+
+/// The authority of the Calendar provider, which all the tables except [`task_lists`] and [`tasks`] belong to.
+pub const AUTHORITY: &str = "com.android.calendar";
+
+pub mod calendars {
+    pub const CONTENT_URI: &str = "content://com.android.calendar/calendars";
+    pub const ID: &str = "_id";
+    pub const DISPLAY_NAME: &str = "calendar_displayName";
+    pub const ACCOUNT_NAME: &str = "account_name";
+    pub const ACCOUNT_TYPE: &str = "account_type";
+    pub const COLOR: &str = "calendar_color";
+    pub const MAX_REMINDERS: &str = "maxReminders";
+    pub const ALLOWED_REMINDERS: &str = "allowedReminders";
+    pub const ACCESS_LEVEL: &str = "calendar_access_level";
+
+    pub const ACCESS_READ: i64 = 200;
+    pub const ACCESS_OWNER: i64 = 700;
+}
+
+pub mod events {
+    pub const CONTENT_URI: &str = "content://com.android.calendar/events";
+    pub const ID: &str = "_id";
+    pub const CALENDAR_ID: &str = "calendar_id";
+    pub const UID: &str = "uid2445";
+    pub const COLOR: &str = "eventColor";
+    pub const TITLE: &str = "title";
+    pub const ORGANIZER: &str = "organizer";
+    pub const LOCATION: &str = "eventLocation";
+    pub const DESCRIPTION: &str = "description";
+    pub const TIMEZONE: &str = "eventTimezone";
+    pub const END_TIMEZONE: &str = "eventEndTimezone";
+    pub const DATE_START: &str = "dtstart";
+    pub const DATE_END: &str = "dtend";
+    pub const DURATION: &str = "duration";
+    pub const ALL_DAY: &str = "allDay";
+    pub const R_RULE: &str = "rrule";
+    pub const R_DATE: &str = "rdate";
+    pub const EX_RULE: &str = "exrule";
+    pub const EX_DATE: &str = "exdate";
+    pub const ORIGINAL_ID: &str = "original_id";
+    pub const ORIGINAL_INSTANCE_TIME: &str = "originalInstanceTime";
+    pub const ORIGINAL_ALL_DAY: &str = "originalAllDay";
+    pub const ACCESS_LEVEL: &str = "accessLevel";
+    pub const AVAILABILITY: &str = "availability";
+    pub const STATUS: &str = "eventStatus";
+    pub const GUESTS_CAN_MODIFY: &str = "guestsCanModify";
+    pub const GUESTS_CAN_INVITE_OTHERS: &str = "guestsCanInviteOthers";
+    pub const GUESTS_CAN_SEE_GUESTS: &str = "guestsCanSeeGuests";
+
+    pub const ACCESS_DEFAULT: i64 = 0;
+    pub const ACCESS_CONFIDENTIAL: i64 = 1;
+    pub const ACCESS_PRIVATE: i64 = 2;
+    pub const ACCESS_PUBLIC: i64 = 3;
+
+    pub const AVAILABILITY_BUSY: i64 = 0;
+    pub const AVAILABILITY_FREE: i64 = 1;
+    pub const AVAILABILITY_TENTATIVE: i64 = 2;
+
+    pub const STATUS_TENTATIVE: i64 = 0;
+    pub const STATUS_CONFIRMED: i64 = 1;
+    pub const STATUS_CANCELED: i64 = 2;
+
+    /// Same columns as `CopyEventsProjection`.
+    pub const COPY_PROJECTION: [&str; 26] = [
+        ID,
+        COLOR,
+        TITLE,
+        ORGANIZER,
+        LOCATION,
+        DESCRIPTION,
+        TIMEZONE,
+        END_TIMEZONE,
+        DATE_START,
+        DATE_END,
+        DURATION,
+        ALL_DAY,
+        R_RULE,
+        R_DATE,
+        EX_RULE,
+        EX_DATE,
+        ORIGINAL_ID,
+        ORIGINAL_INSTANCE_TIME,
+        ORIGINAL_ALL_DAY,
+        ACCESS_LEVEL,
+        AVAILABILITY,
+        STATUS,
+        GUESTS_CAN_MODIFY,
+        GUESTS_CAN_INVITE_OTHERS,
+        GUESTS_CAN_SEE_GUESTS,
+        UID,
+    ];
+}
+
+pub mod reminders {
+    pub const CONTENT_URI: &str = "content://com.android.calendar/reminders";
+    pub const EVENT_ID: &str = "event_id";
+    pub const METHOD: &str = "method";
+    pub const MINUTES: &str = "minutes";
+
+    pub const METHOD_DEFAULT: i64 = 0;
+    pub const METHOD_ALERT: i64 = 1;
+    pub const METHOD_EMAIL: i64 = 2;
+    pub const METHOD_SMS: i64 = 3;
+    pub const METHOD_ALARM: i64 = 4;
+
+    /// Same columns as `CopyRemindersProjection`.
+    pub const COPY_PROJECTION: [&str; 2] = [METHOD, MINUTES];
+}
+
+pub mod attendees {
+    pub const CONTENT_URI: &str = "content://com.android.calendar/attendees";
+    pub const EVENT_ID: &str = "event_id";
+    pub const NAME: &str = "attendeeName";
+    pub const EMAIL: &str = "attendeeEmail";
+    pub const RELATIONSHIP: &str = "attendeeRelationship";
+    pub const TYPE: &str = "attendeeType";
+    pub const STATUS: &str = "attendeeStatus";
+    pub const IDENTITY: &str = "attendeeIdentity";
+    pub const ID_NAMESPACE: &str = "attendeeIdNamespace";
+
+    pub const RELATIONSHIP_NONE: i64 = 0;
+    pub const RELATIONSHIP_ATTENDEE: i64 = 1;
+    pub const RELATIONSHIP_ORGANIZER: i64 = 2;
+    pub const RELATIONSHIP_PERFORMER: i64 = 3;
+    pub const RELATIONSHIP_SPEAKER: i64 = 4;
+
+    pub const TYPE_NONE: i64 = 0;
+    pub const TYPE_REQUIRED: i64 = 1;
+    pub const TYPE_OPTIONAL: i64 = 2;
+    pub const TYPE_RESOURCE: i64 = 3;
+
+    pub const STATUS_NONE: i64 = 0;
+    pub const STATUS_ACCEPTED: i64 = 1;
+    pub const STATUS_DECLINED: i64 = 2;
+    pub const STATUS_INVITED: i64 = 3;
+    pub const STATUS_TENTATIVE: i64 = 4;
+
+    /// Same columns as `CopyAttendeesProjection`.
+    pub const COPY_PROJECTION: [&str; 7] = [NAME, EMAIL, RELATIONSHIP, TYPE, STATUS, IDENTITY, ID_NAMESPACE];
+}
+
+pub mod task_lists {
+    pub const CONTENT_URI: &str = "content://org.dmfs.tasks/tasklists";
+    pub const ID: &str = "_id";
+    pub const NAME: &str = "list_name";
+    pub const COLOR: &str = "list_color";
+    pub const ACCOUNT_NAME: &str = "account_name";
+    pub const ACCOUNT_TYPE: &str = "account_type";
+    pub const SYNC_ENABLED: &str = "sync_enabled";
+    pub const VISIBLE: &str = "visible";
+}
+
+pub mod tasks {
+    pub const CONTENT_URI: &str = "content://org.dmfs.tasks/tasks";
+    pub const ID: &str = "_id";
+    pub const LIST_ID: &str = "list_id";
+    pub const UID: &str = "_uid";
+    pub const TITLE: &str = "title";
+    pub const DESCRIPTION: &str = "description";
+    pub const LOCATION: &str = "location";
+    pub const DATE_START: &str = "dtstart";
+    pub const DUE: &str = "due";
+    pub const DURATION: &str = "duration";
+    pub const TIMEZONE: &str = "tz";
+    pub const ALL_DAY: &str = "is_allday";
+    pub const COMPLETED: &str = "completed";
+    pub const COMPLETED_ALL_DAY: &str = "completed_is_allday";
+    pub const PERCENT_COMPLETE: &str = "percent_complete";
+    pub const PRIORITY: &str = "priority";
+    pub const STATUS: &str = "status";
+    pub const CLASSIFICATION: &str = "class";
+    pub const R_RULE: &str = "rrule";
+    pub const PARENT_ID: &str = "parent_id";
+
+    pub const STATUS_NEEDS_ACTION: i64 = 0;
+    pub const STATUS_IN_PROCESS: i64 = 1;
+    pub const STATUS_COMPLETED: i64 = 2;
+    pub const STATUS_CANCELLED: i64 = 3;
+
+    pub const CLASSIFICATION_PUBLIC: i64 = 0;
+    pub const CLASSIFICATION_PRIVATE: i64 = 1;
+    pub const CLASSIFICATION_CONFIDENTIAL: i64 = 2;
+
+    pub const COPY_PROJECTION: [&str; 18] = [
+        ID,
+        UID,
+        TITLE,
+        DESCRIPTION,
+        LOCATION,
+        DATE_START,
+        DUE,
+        DURATION,
+        TIMEZONE,
+        ALL_DAY,
+        COMPLETED,
+        PERCENT_COMPLETE,
+        PRIORITY,
+        STATUS,
+        CLASSIFICATION,
+        R_RULE,
+        PARENT_ID,
+        COMPLETED_ALL_DAY,
+    ];
+}
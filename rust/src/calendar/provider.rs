@@ -0,0 +1,78 @@
+//! Access to the Calendar *Content Provider* from Rust.
+//!
+//! The functions to read and write rows are in the [crate's provider module](crate::provider).
+use jni::{JNIEnv, objects::JObject};
+use ez_jni::call;
+use classes::Cursor;
+use crate::{error::Error, provider::{account_name, ACCOUNT_TYPE_LOCAL}};
+use super::{projections::{calendars, task_lists}, time::TimeZoneResolver};
+pub use crate::provider::{apply_batch, delete, insert, query, update};
+
+/// Find the **ID** of the Calendar owned by this App with a **name**.
+///
+/// Returns [`None`] if there is no such Calendar.
+pub fn calendar_id(env: &mut JNIEnv, context: &JObject, name: &str) -> Result<Option<i64>, Error> {
+    let account_name = account_name(env, context);
+    let cursor = Cursor::query_str(
+        env,
+        context,
+        calendars::CONTENT_URI,
+        &[calendars::ID],
+        &format!(
+            "({} = ?) AND ({} = ?) AND ({} = ?)",
+            calendars::DISPLAY_NAME, calendars::ACCOUNT_NAME, calendars::ACCOUNT_TYPE
+        ),
+        &[name, account_name.as_str(), ACCOUNT_TYPE_LOCAL],
+        "",
+    )
+        .map_err(|err| Error::Provider(format!("Error querying calendar {name:?}: {err}")))?;
+
+    let id = if cursor.next(env) {
+        Some(cursor.get_long(env, 0))
+    } else {
+        None
+    };
+    cursor.close(env);
+
+    Ok(id)
+}
+
+/// Find the **ID** of the Task List owned by this App with a **name** in the *OpenTasks* provider.
+///
+/// Returns [`None`] if there is no such Task List,
+/// and an error if the provider is not installed or the App doesn't have permission to use it.
+pub fn task_list_id(env: &mut JNIEnv, context: &JObject, name: &str) -> Result<Option<i64>, Error> {
+    let account_name = account_name(env, context);
+    let rows = query(env, context,
+        task_lists::CONTENT_URI,
+        &[task_lists::ID],
+        &format!("({} = ?) AND ({} = ?) AND ({} = ?)", task_lists::NAME, task_lists::ACCOUNT_NAME, task_lists::ACCOUNT_TYPE),
+        &[name, account_name.as_str(), ACCOUNT_TYPE_LOCAL]
+    )?;
+    Ok(rows.first().and_then(|row| row.get_long(task_lists::ID)))
+}
+
+/// Resolves time zones using `java.util.TimeZone`, which has the same time zone database the Content Provider uses.
+pub struct JavaTimeZones<'a, 'local> {
+    pub env: &'a mut JNIEnv<'local>,
+}
+impl TimeZoneResolver for JavaTimeZones<'_, '_> {
+    fn utc_offset(&mut self, tzid: &str, local: i64) -> Option<i32> {
+        let env = &mut *self.env;
+        let tz = call!(static java.util.TimeZone.getTimeZone(String(tzid)) -> java.util.TimeZone);
+        // getTimeZone() returns GMT if it doesn't know the time zone
+        if call!(tz.getID() -> String) != tzid {
+            return None;
+        }
+        // getOffset() takes a UTC time, so first get an approximate UTC time by using the offset at the local time.
+        let local = local * 1000;
+        let guess = local - call!(tz.getOffset(long(local)) -> int) as i64;
+        Some(call!(tz.getOffset(long(guess)) -> int) / 1000)
+    }
+
+    fn default_tzid(&mut self) -> String {
+        let env = &mut *self.env;
+        let tz = call!(static java.util.TimeZone.getDefault() -> java.util.TimeZone);
+        call!(tz.getID() -> String)
+    }
+}
@@ -0,0 +1,838 @@
+//! Expansion of the recurrence of an event (`RRULE`, `RDATE`, `EXRULE` and `EXDATE`) into the start times of its instances.
+//!
+//! The rules are expanded in the *local time* of `DTSTART` (RFC 5545 section 3.3.10), so that the instances keep their wall-clock time
+//! across daylight saving time changes, and the local times are then converted to *Unix timestamps* with a [`TimeZoneResolver`].
+use std::fmt::{self, Display};
+use super::{
+    event::{date_list_properties, Event},
+    ical::{split_list, Property},
+    time::{days_in_month, is_leap_year, CalTime, Date, DateTime, Time, TimeZoneResolver},
+};
+
+/// Rules are not expanded past this year (or past the end set with [`Instances::stop_at()`]),
+/// so that rules that never match (e.g. `BYMONTH=2;BYMONTHDAY=30`) don't loop forever.
+const MAX_YEAR: i32 = 9999;
+/// The rules are expanded in local time, which can be this many seconds away from the UTC times of the window.
+const WINDOW_MARGIN: i64 = 2 * 86400;
+
+/// The `FREQ` of a rule, ordered from the shortest to the longest period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Frequency {
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+impl Frequency {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "SECONDLY" => Some(Self::Secondly),
+            "MINUTELY" => Some(Self::Minutely),
+            "HOURLY" => Some(Self::Hourly),
+            "DAILY" => Some(Self::Daily),
+            "WEEKLY" => Some(Self::Weekly),
+            "MONTHLY" => Some(Self::Monthly),
+            "YEARLY" => Some(Self::Yearly),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Secondly => "SECONDLY",
+            Self::Minutely => "MINUTELY",
+            Self::Hourly => "HOURLY",
+            Self::Daily => "DAILY",
+            Self::Weekly => "WEEKLY",
+            Self::Monthly => "MONTHLY",
+            Self::Yearly => "YEARLY",
+        }
+    }
+}
+
+/// The names of the days of the week, in the order of [`Date::weekday()`] (`0` is *Monday*).
+const WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+fn parse_weekday(s: &str) -> Option<u8> {
+    WEEKDAYS.iter()
+        .position(|day| day.eq_ignore_ascii_case(s.trim()))
+        .map(|day| day as u8)
+}
+
+/// A day of the week in `BYDAY`, with an optional ordinal (e.g. `-1SU` is the last Sunday of the month or year).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i32>,
+    /// `0` is *Monday* (see [`Date::weekday()`]).
+    pub weekday: u8,
+}
+impl WeekdayNum {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let split = s.len().checked_sub(2)?;
+        let weekday = parse_weekday(s.get(split..)?)?;
+        let ordinal = match &s[..split] {
+            "" => None,
+            ordinal => Some(ordinal.parse::<i32>().ok().filter(|n| *n != 0 && n.abs() <= 53)?),
+        };
+        Some(Self { ordinal, weekday })
+    }
+}
+impl Display for WeekdayNum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ordinal) = self.ordinal {
+            write!(f, "{ordinal}")?;
+        }
+        f.write_str(WEEKDAYS[self.weekday as usize])
+    }
+}
+
+/// A *recurrence rule* (the value of `RRULE` or `EXRULE`), as defined in RFC 5545 section 3.3.10.
+///
+/// The lists of the `BYxxx` parts are empty if the part is not in the rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    /// Mutually exclusive with [`Self::count`].
+    pub until: Option<CalTime>,
+    pub count: Option<u32>,
+    pub by_second: Vec<u8>,
+    pub by_minute: Vec<u8>,
+    pub by_hour: Vec<u8>,
+    pub by_day: Vec<WeekdayNum>,
+    /// `1..=31` or `-31..=-1` (counting from the end of the month).
+    pub by_month_day: Vec<i32>,
+    /// `1..=366` or `-366..=-1` (counting from the end of the year).
+    pub by_year_day: Vec<i32>,
+    /// `1..=53` or `-53..=-1` (counting from the end of the year).
+    pub by_week_no: Vec<i32>,
+    pub by_month: Vec<u8>,
+    pub by_set_pos: Vec<i32>,
+    /// The day that weeks start on (`WKST`), where `0` is *Monday*.
+    pub week_start: u8,
+}
+impl RRule {
+    /// A rule with only a `FREQ`, which repeats forever.
+    pub fn new(freq: Frequency) -> Self {
+        Self {
+            freq,
+            interval: 1,
+            until: None,
+            count: None,
+            by_second: Vec::new(),
+            by_minute: Vec::new(),
+            by_hour: Vec::new(),
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_year_day: Vec::new(),
+            by_week_no: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            week_start: 0,
+        }
+    }
+
+    /// Parse the value of a `RRULE` (e.g. `"FREQ=MONTHLY;BYDAY=-1FR;COUNT=10"`).
+    ///
+    /// Unknown parts (e.g. `X-` extensions) are ignored.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        fn list<T: std::str::FromStr>(value: &str, valid: impl Fn(&T) -> bool) -> Option<Vec<T>> {
+            value.split(',')
+                .map(|item| item.trim().parse().ok().filter(&valid))
+                .collect()
+        }
+
+        let mut freq = None;
+        let mut rule = Self::new(Frequency::Yearly);
+
+        for part in s.trim().split(';').filter(|part| !part.trim().is_empty()) {
+            let (name, value) = part.split_once('=')
+                .ok_or_else(|| format!("Invalid part {part:?} in RRULE {s:?}"))?;
+            let name = name.trim().to_ascii_uppercase();
+            let invalid = || format!("Invalid value of {name} in RRULE {s:?}");
+            match name.as_str() {
+                "FREQ" => freq = Some(Frequency::parse(value).ok_or_else(invalid)?),
+                "INTERVAL" => rule.interval = value.trim().parse().ok().filter(|&interval| interval > 0).ok_or_else(invalid)?,
+                "UNTIL" => rule.until = Some(CalTime::parse(value, None).ok_or_else(invalid)?),
+                "COUNT" => rule.count = Some(value.trim().parse().ok().filter(|&count| count > 0).ok_or_else(invalid)?),
+                "BYSECOND" => rule.by_second = list(value, |&n: &u8| n <= 60).ok_or_else(invalid)?,
+                "BYMINUTE" => rule.by_minute = list(value, |&n: &u8| n <= 59).ok_or_else(invalid)?,
+                "BYHOUR" => rule.by_hour = list(value, |&n: &u8| n <= 23).ok_or_else(invalid)?,
+                "BYDAY" => rule.by_day = value.split(',').map(WeekdayNum::parse).collect::<Option<_>>().ok_or_else(invalid)?,
+                "BYMONTHDAY" => rule.by_month_day = list(value, |&n: &i32| n != 0 && n.abs() <= 31).ok_or_else(invalid)?,
+                "BYYEARDAY" => rule.by_year_day = list(value, |&n: &i32| n != 0 && n.abs() <= 366).ok_or_else(invalid)?,
+                "BYWEEKNO" => rule.by_week_no = list(value, |&n: &i32| n != 0 && n.abs() <= 53).ok_or_else(invalid)?,
+                "BYMONTH" => rule.by_month = list(value, |&n: &u8| (1..=12).contains(&n)).ok_or_else(invalid)?,
+                "BYSETPOS" => rule.by_set_pos = list(value, |&n: &i32| n != 0 && n.abs() <= 366).ok_or_else(invalid)?,
+                "WKST" => rule.week_start = parse_weekday(value).ok_or_else(invalid)?,
+                _ => {},
+            }
+        }
+
+        rule.freq = freq.ok_or_else(|| format!("RRULE {s:?} has no FREQ"))?;
+        if rule.until.is_some() && rule.count.is_some() {
+            return Err(format!("RRULE {s:?} can't have both UNTIL and COUNT"));
+        }
+        Ok(rule)
+    }
+
+    /// Iterate over the local start times of the instances that this rule generates for an event that starts at **start**,
+    /// in chronological order.
+    ///
+    /// **start** is always the first instance (RFC 5545 section 3.8.5.3), even if it doesn't match the rule, and counts for `COUNT`.
+    /// The **resolver** is needed to compare the instances with an `UNTIL` in UTC when **start** is in a time zone.
+    pub fn instances(&self, start: &CalTime, resolver: &mut dyn TimeZoneResolver) -> Result<Instances<'_>, String> {
+        let local = start.local();
+        let until = match (&self.until, start) {
+            (None, _) => None,
+            // An all-day event can only end on a date
+            (Some(until), CalTime::Date(_)) => Some(DateTime { date: until.local().date, time: Time::default() }),
+            // A date includes the whole day
+            (Some(CalTime::Date(date)), _) => Some(DateTime { date: *date, time: Time { hour: 23, minute: 59, second: 59 } }),
+            (Some(until @ (CalTime::Utc(_) | CalTime::Zoned { .. })), CalTime::Zoned { .. } | CalTime::Floating(_)) => {
+                let timestamp = until.timestamp(resolver)
+                    .ok_or_else(|| format!("Unknown time zone {:?}", until.tzid().unwrap_or_default()))?;
+                let tzid = start.tzid().map(str::to_string).unwrap_or_else(|| resolver.default_tzid());
+                Some(CalTime::from_timestamp(timestamp, Some(&tzid), false, resolver).local())
+            },
+            (Some(until), _) => Some(until.local()),
+        };
+
+        Ok(Instances {
+            rule: self,
+            start: local,
+            until,
+            period: self.first_period(local),
+            end: None,
+            pending: Vec::new(),
+            emitted: 0,
+            done: false,
+        })
+    }
+
+    /// The start of the period (of length [`Self::freq`]) that contains **time**.
+    fn first_period(&self, time: DateTime) -> DateTime {
+        let date = time.date;
+        let (date, time) = match self.freq {
+            Frequency::Yearly => (Date { year: date.year, month: 1, day: 1 }, Time::default()),
+            Frequency::Monthly => (Date { day: 1, ..date }, Time::default()),
+            Frequency::Weekly => (date.add_days(-((date.weekday() as i64 - self.week_start as i64).rem_euclid(7))), Time::default()),
+            Frequency::Daily => (date, Time::default()),
+            Frequency::Hourly => (date, Time { hour: time.time.hour, minute: 0, second: 0 }),
+            Frequency::Minutely => (date, Time { second: 0, ..time.time }),
+            Frequency::Secondly => (date, time.time),
+        };
+        DateTime { date, time }
+    }
+
+    /// The start of the period that is **n** intervals after **period**.
+    fn next_period(&self, period: DateTime, n: i64) -> DateTime {
+        let steps = n * self.interval as i64;
+        match self.freq {
+            Frequency::Yearly => DateTime {
+                date: Date { year: period.date.year.saturating_add(steps.clamp(i32::MIN as i64, i32::MAX as i64) as i32), ..period.date },
+                ..period
+            },
+            Frequency::Monthly => {
+                let months = period.date.year as i64 * 12 + period.date.month as i64 - 1 + steps;
+                DateTime {
+                    date: Date { year: months.div_euclid(12) as i32, month: months.rem_euclid(12) as u8 + 1, day: 1 },
+                    ..period
+                }
+            },
+            Frequency::Weekly => DateTime::from_timestamp(period.timestamp() + steps * 7 * 86400),
+            Frequency::Daily => DateTime::from_timestamp(period.timestamp() + steps * 86400),
+            Frequency::Hourly => DateTime::from_timestamp(period.timestamp() + steps * 3600),
+            Frequency::Minutely => DateTime::from_timestamp(period.timestamp() + steps * 60),
+            Frequency::Secondly => DateTime::from_timestamp(period.timestamp() + steps),
+        }
+    }
+
+    /// The candidates of the instances in a **period**, in chronological order, before applying `BYSETPOS`.
+    fn period_candidates(&self, period: DateTime, start: DateTime) -> Vec<DateTime> {
+        let days = match self.freq {
+            // Only the days of the months in BYMONTH can match
+            Frequency::Yearly if !self.by_month.is_empty() => (1..=12u8)
+                .filter(|month| self.by_month.contains(month))
+                .flat_map(|month| (1..=days_in_month(period.date.year, month)).map(move |day| Date { year: period.date.year, month, day }))
+                .collect::<Vec<_>>(),
+            Frequency::Yearly => {
+                let first = Date { month: 1, day: 1, ..period.date };
+                let len = if is_leap_year(period.date.year) { 366 } else { 365 };
+                (0..len).map(|day| first.add_days(day)).collect()
+            },
+            Frequency::Monthly => (1..=days_in_month(period.date.year, period.date.month))
+                .map(|day| Date { day, ..period.date })
+                .collect(),
+            Frequency::Weekly => (0..7).map(|day| period.date.add_days(day)).collect(),
+            _ => vec![period.date],
+        };
+        let days = days.into_iter().filter(|&date| self.day_matches(date, start.date));
+
+        // The times of day are in the default order of the BYxxx values, so sort them
+        let in_period = |values: &[u8], value: u8| values.is_empty() || values.contains(&value);
+        let hours = match self.freq {
+            Frequency::Hourly | Frequency::Minutely | Frequency::Secondly
+                => if in_period(&self.by_hour, period.time.hour) { vec![period.time.hour] } else { Vec::new() },
+            _ if self.by_hour.is_empty() => vec![start.time.hour],
+            _ => self.by_hour.clone(),
+        };
+        let minutes = match self.freq {
+            Frequency::Minutely | Frequency::Secondly
+                => if in_period(&self.by_minute, period.time.minute) { vec![period.time.minute] } else { Vec::new() },
+            _ if self.by_minute.is_empty() => vec![start.time.minute],
+            _ => self.by_minute.clone(),
+        };
+        let seconds = match self.freq {
+            Frequency::Secondly
+                => if in_period(&self.by_second, period.time.second) { vec![period.time.second] } else { Vec::new() },
+            _ if self.by_second.is_empty() => vec![start.time.second],
+            _ => self.by_second.clone(),
+        };
+        let mut times = Vec::new();
+        for &hour in &hours {
+            for &minute in &minutes {
+                times.extend(seconds.iter().map(|&second| Time { hour, minute, second }));
+            }
+        }
+        times.sort();
+        times.dedup();
+
+        days.flat_map(|date| times.iter().map(move |&time| DateTime { date, time }))
+            .collect()
+    }
+
+    /// Whether the instances of the rule can fall on **date**.
+    ///
+    /// When the rule has none of `BYWEEKNO`, `BYYEARDAY`, `BYMONTHDAY` and `BYDAY`,
+    /// the day (and month) of **start** are used according to the frequency (e.g. `FREQ=MONTHLY` repeats on the day of the month of **start**).
+    fn day_matches(&self, date: Date, start: Date) -> bool {
+        let days_in_year = if is_leap_year(date.year) { 366 } else { 365 };
+        let day_of_year = (date.days_since_epoch() - Date { month: 1, day: 1, ..date }.days_since_epoch()) as i32 + 1;
+        let days_in_month = days_in_month(date.year, date.month) as i32;
+        let no_day_rules = self.by_week_no.is_empty() && self.by_year_day.is_empty() && self.by_month_day.is_empty() && self.by_day.is_empty();
+
+        if !self.by_month.is_empty() && !self.by_month.contains(&date.month) {
+            return false;
+        }
+        if no_day_rules {
+            return match self.freq {
+                Frequency::Yearly => (!self.by_month.is_empty() || date.month == start.month) && date.day == start.day,
+                Frequency::Monthly => date.day == start.day,
+                Frequency::Weekly => date.weekday() == start.weekday(),
+                _ => true,
+            };
+        }
+
+        if !self.by_week_no.is_empty() {
+            let (week, weeks_in_year) = week_number(date, self.week_start);
+            if !self.by_week_no.iter().any(|&n| n == week || n == week - weeks_in_year - 1) {
+                return false;
+            }
+        }
+        if !self.by_year_day.is_empty()
+            && !self.by_year_day.iter().any(|&n| n == day_of_year || n == day_of_year - days_in_year - 1) {
+            return false;
+        }
+        if !self.by_month_day.is_empty()
+            && !self.by_month_day.iter().any(|&n| n == date.day as i32 || n == date.day as i32 - days_in_month - 1) {
+            return false;
+        }
+        if !self.by_day.is_empty() {
+            // The ordinals count the weekdays in the month or in the year, and are ignored for other frequencies (and with BYWEEKNO)
+            let in_month = self.freq == Frequency::Monthly || (self.freq == Frequency::Yearly && !self.by_month.is_empty());
+            let use_ordinals = self.freq >= Frequency::Monthly && self.by_week_no.is_empty();
+            let (day, len) = if in_month {
+                (date.day as i32, days_in_month)
+            } else {
+                (day_of_year, days_in_year)
+            };
+            let nth = (day - 1) / 7 + 1;
+            let nth_from_end = -((len - day) / 7 + 1);
+            if !self.by_day.iter().any(|weekday| weekday.weekday == date.weekday() && match weekday.ordinal {
+                Some(ordinal) if use_ordinals => ordinal == nth || ordinal == nth_from_end,
+                _ => true,
+            }) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+impl Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: Display>(f: &mut fmt::Formatter<'_>, name: &str, values: &[T]) -> fmt::Result {
+            if values.is_empty() {
+                return Ok(());
+            }
+            write!(f, ";{name}=")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{value}")?;
+            }
+            Ok(())
+        }
+
+        write!(f, "FREQ={}", self.freq.as_str())?;
+        if let Some(until) = &self.until {
+            write!(f, ";UNTIL={}", until.value())?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        list(f, "BYSECOND", &self.by_second)?;
+        list(f, "BYMINUTE", &self.by_minute)?;
+        list(f, "BYHOUR", &self.by_hour)?;
+        list(f, "BYDAY", &self.by_day)?;
+        list(f, "BYMONTHDAY", &self.by_month_day)?;
+        list(f, "BYYEARDAY", &self.by_year_day)?;
+        list(f, "BYWEEKNO", &self.by_week_no)?;
+        list(f, "BYMONTH", &self.by_month)?;
+        list(f, "BYSETPOS", &self.by_set_pos)?;
+        if self.week_start != 0 {
+            write!(f, ";WKST={}", WEEKDAYS[self.week_start as usize])?;
+        }
+        Ok(())
+    }
+}
+
+/// The week number of **date** in its year (and the number of weeks in that year), where weeks start on **week_start**.
+///
+/// As in ISO 8601, the first week of the year is the first week with at least 4 days in that year,
+/// so the first days of January can be in the last week of the previous year, and the last days of December in the first week of the next year.
+fn week_number(date: Date, week_start: u8) -> (i32, i32) {
+    // The first day of the first week of a year
+    let first_week = |year: i32| {
+        let jan_1 = Date { year, month: 1, day: 1 };
+        let offset = (jan_1.weekday() as i64 - week_start as i64).rem_euclid(7);
+        // Week 1 starts on the week_start that is on or before January 4th
+        if offset <= 3 { jan_1.add_days(-offset) } else { jan_1.add_days(7 - offset) }
+    };
+    let weeks_in_year = |year: i32| ((first_week(year + 1).days_since_epoch() - first_week(year).days_since_epoch()) / 7) as i32;
+
+    let days = date.days_since_epoch();
+    let year = if days < first_week(date.year).days_since_epoch() {
+        date.year - 1
+    } else if days >= first_week(date.year + 1).days_since_epoch() {
+        date.year + 1
+    } else {
+        date.year
+    };
+    ((days - first_week(year).days_since_epoch()) as i32 / 7 + 1, weeks_in_year(year))
+}
+
+/// Iterator over the local start times of the instances of an [`RRule`], returned by [`RRule::instances()`].
+pub struct Instances<'a> {
+    rule: &'a RRule,
+    start: DateTime,
+    /// `UNTIL` in the local time of `DTSTART`.
+    until: Option<DateTime>,
+    /// The start of the next period to expand.
+    period: DateTime,
+    /// No periods that start after this are expanded.
+    end: Option<DateTime>,
+    /// The instances of the last period that was expanded that were not returned yet, in reverse order.
+    pending: Vec<DateTime>,
+    emitted: u32,
+    done: bool,
+}
+impl Instances<'_> {
+    /// Skip the periods that end before **time**, so that the instances don't have to be expanded from the start.
+    ///
+    /// Does nothing if the rule has a `COUNT`, because the skipped instances would have to be counted.
+    pub fn skip_to(&mut self, time: DateTime) {
+        if self.rule.count.is_some() || self.emitted == 0 || time <= self.period {
+            return;
+        }
+        let periods = self.periods_until(time);
+        if periods > 0 {
+            self.period = self.rule.next_period(self.period, periods);
+            self.pending.clear();
+        }
+    }
+
+    /// Stop expanding the rule at **end**: The instances after it are not returned,
+    /// and the periods after it are not searched for instances (which would take until [`MAX_YEAR`] if the rule never matches).
+    pub fn stop_at(&mut self, end: DateTime) {
+        self.end = Some(end);
+    }
+
+    /// The number of whole periods from the next period to expand to the period that contains **time**.
+    fn periods_until(&self, time: DateTime) -> i64 {
+        let target = self.rule.first_period(time);
+        let period_length = match self.rule.freq {
+            Frequency::Yearly | Frequency::Monthly => None,
+            Frequency::Weekly => Some(7 * 86400),
+            Frequency::Daily => Some(86400),
+            Frequency::Hourly => Some(3600),
+            Frequency::Minutely => Some(60),
+            Frequency::Secondly => Some(1),
+        };
+        match period_length {
+            Some(length) => (target.timestamp() - self.period.timestamp()) / (length * self.rule.interval as i64),
+            None => {
+                let months = |time: DateTime| time.date.year as i64 * 12 + time.date.month as i64;
+                let months_per_period = if self.rule.freq == Frequency::Yearly { 12 } else { 1 } * self.rule.interval as i64;
+                (months(target) - months(self.period)) / months_per_period
+            },
+        }
+    }
+}
+impl Iterator for Instances<'_> {
+    type Item = DateTime;
+
+    fn next(&mut self) -> Option<DateTime> {
+        if self.done || self.rule.count.is_some_and(|count| self.emitted >= count) {
+            return None;
+        }
+        // DTSTART is always the first instance
+        if self.emitted == 0 {
+            self.emitted = 1;
+            return Some(self.start);
+        }
+
+        while self.pending.is_empty() {
+            if self.period.date.year > MAX_YEAR || self.end.is_some_and(|end| self.period > end) {
+                self.done = true;
+                return None;
+            }
+            // Periods shorter than a day are skipped up to the next day when the rule can't fall on their day,
+            // so that a rule that never matches is not expanded minute by minute
+            if self.rule.freq < Frequency::Daily && !self.rule.day_matches(self.period.date, self.start.date) {
+                let next_day = DateTime { date: self.period.date.add_days(1), time: Time::default() };
+                self.period = self.rule.next_period(self.period, self.periods_until(next_day));
+                if self.period < next_day {
+                    self.period = self.rule.next_period(self.period, 1);
+                }
+                continue;
+            }
+            let mut candidates = self.rule.period_candidates(self.period, self.start);
+            self.period = self.rule.next_period(self.period, 1);
+
+            if !self.rule.by_set_pos.is_empty() {
+                let len = candidates.len() as i32;
+                let mut selected = self.rule.by_set_pos.iter()
+                    .filter_map(|&pos| {
+                        let index = if pos > 0 { pos - 1 } else { len + pos };
+                        (0..len).contains(&index).then(|| candidates[index as usize])
+                    })
+                    .collect::<Vec<_>>();
+                selected.sort();
+                selected.dedup();
+                candidates = selected;
+            }
+
+            // DTSTART was already returned
+            self.pending = candidates.into_iter()
+                .filter(|&candidate| candidate > self.start)
+                .rev()
+                .collect();
+        }
+
+        let instance = self.pending.pop()?;
+        if self.until.is_some_and(|until| instance > until) || self.end.is_some_and(|end| instance > end) {
+            self.done = true;
+            return None;
+        }
+        self.emitted += 1;
+        Some(instance)
+    }
+}
+
+/// An instance of a recurring event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    /// The start of the instance, in the same form as `DTSTART` (or the `RDATE` it came from).
+    /// This is the value of the `RECURRENCE-ID` of the instance.
+    pub start: CalTime,
+    /// The start of the instance in seconds since the *Unix epoch*.
+    pub timestamp: i64,
+}
+
+/// The *recurrence set* of an event: `DTSTART`, and the instances of its `RRULE`s and `RDATE`s
+/// minus the instances of its `EXRULE`s and `EXDATE`s (RFC 5545 section 3.8.5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub start: CalTime,
+    pub rrules: Vec<RRule>,
+    pub exrules: Vec<RRule>,
+    pub rdates: Vec<CalTime>,
+    pub exdates: Vec<CalTime>,
+}
+impl Recurrence {
+    /// Read the recurrence of an [`Event`], whose `RDATE` and `EXDATE` are in the format of the Content Provider.
+    pub fn from_event(event: &Event) -> Result<Self, String> {
+        let dates = |list: &Option<String>, name: &str| list.iter()
+            .flat_map(|list| date_list_properties(name, list))
+            .flat_map(|prop| date_values(&prop))
+            .collect();
+        Ok(Self {
+            start: event.start.clone(),
+            rrules: event.rrule.iter().map(|rrule| RRule::parse(rrule)).collect::<Result<_, _>>()?,
+            exrules: event.exrule.iter().map(|exrule| RRule::parse(exrule)).collect::<Result<_, _>>()?,
+            rdates: dates(&event.rdate, "RDATE"),
+            exdates: dates(&event.exdate, "EXDATE"),
+        })
+    }
+
+    /// Whether all the rules end (with `COUNT` or `UNTIL`), so that the recurrence set has a finite number of instances.
+    pub fn is_finite(&self) -> bool {
+        self.rrules.iter().all(|rule| rule.count.is_some() || rule.until.is_some())
+    }
+
+    /// The instances that start between **from** (inclusive) and **to** (exclusive), in chronological order.
+    ///
+    /// **from** and **to** are in seconds since the *Unix epoch*.
+    /// Returns an error if the **resolver** doesn't know the time zone of one of the instances.
+    pub fn occurrences(&self, from: i64, to: i64, resolver: &mut dyn TimeZoneResolver) -> Result<Vec<Occurrence>, String> {
+        let mut occurrences = Vec::new();
+        if self.rrules.is_empty() {
+            occurrences.push(occurrence(self.start.clone(), resolver)?);
+        }
+        for rule in &self.rrules {
+            for local in expand(rule, &self.start, from, to, resolver)? {
+                occurrences.push(occurrence(self.start.with_local(local), resolver)?);
+            }
+        }
+        for rdate in &self.rdates {
+            occurrences.push(occurrence(rdate.clone(), resolver)?);
+        }
+
+        let mut excluded = Vec::new();
+        for exdate in &self.exdates {
+            excluded.push(occurrence(exdate.clone(), resolver)?);
+        }
+        for rule in &self.exrules {
+            for local in expand(rule, &self.start, from, to, resolver)? {
+                excluded.push(occurrence(self.start.with_local(local), resolver)?);
+            }
+        }
+
+        occurrences.retain(|occurrence| {
+            (from..to).contains(&occurrence.timestamp)
+                && !excluded.iter().any(|excluded| match excluded.start {
+                    // An EXDATE with only a date excludes the instances on that day
+                    CalTime::Date(date) => occurrence.start.local().date == date,
+                    _ => excluded.timestamp == occurrence.timestamp,
+                })
+        });
+        occurrences.sort_by_key(|occurrence| occurrence.timestamp);
+        occurrences.dedup_by_key(|occurrence| occurrence.timestamp);
+        Ok(occurrences)
+    }
+
+    /// Whether the recurrence set has no instances at all (e.g. all of them were excluded by `EXDATE`).
+    ///
+    /// Recurrence sets that are not [finite][Self::is_finite()] are never considered empty.
+    pub fn is_empty(&self, resolver: &mut dyn TimeZoneResolver) -> Result<bool, String> {
+        if !self.is_finite() {
+            return Ok(false);
+        }
+        let min = Date { year: 1, month: 1, day: 1 }.days_since_epoch() * 86400;
+        let max = Date { year: MAX_YEAR + 1, month: 1, day: 1 }.days_since_epoch() * 86400;
+        Ok(self.occurrences(min, max, resolver)?.is_empty())
+    }
+}
+
+/// The local times of the instances of a **rule** that can start between **from** and **to** (in UTC).
+fn expand(rule: &RRule, start: &CalTime, from: i64, to: i64, resolver: &mut dyn TimeZoneResolver) -> Result<Vec<DateTime>, String> {
+    let mut instances = rule.instances(start, resolver)?;
+    let mut times = Vec::new();
+    // The start is always the first instance, even when it is before the window
+    times.extend(instances.next());
+    instances.skip_to(DateTime::from_timestamp(from.saturating_sub(WINDOW_MARGIN)));
+    let end = DateTime::from_timestamp(to.saturating_add(WINDOW_MARGIN));
+    instances.stop_at(end);
+    times.extend(instances.take_while(|&local| local < end));
+    Ok(times)
+}
+
+fn occurrence(start: CalTime, resolver: &mut dyn TimeZoneResolver) -> Result<Occurrence, String> {
+    let timestamp = start.timestamp(resolver)
+        .ok_or_else(|| format!("Unknown time zone {:?}", start.tzid().unwrap_or_default()))?;
+    Ok(Occurrence { start, timestamp })
+}
+
+/// The dates of an `RDATE` or `EXDATE` property, which can have a list of values.
+///
+/// Only the start of `PERIOD` values is used.
+fn date_values(prop: &Property) -> Vec<CalTime> {
+    split_list(&prop.value)
+        .into_iter()
+        .filter_map(|value| {
+            let start = value.split('/').next().unwrap_or_default();
+            CalTime::parse(start, prop.param("TZID"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::{event::date_property, ical::{self, Component}, timezone::{TimeZones, VTimeZone}};
+
+    /// The rules of `America/New_York` from 1987 to 2006, which the examples of RFC 5545 use.
+    const NEW_YORK: &str = "BEGIN:VTIMEZONE\r
+TZID:America/New_York\r
+BEGIN:DAYLIGHT\r
+DTSTART:19870405T020000\r
+TZOFFSETFROM:-0500\r
+TZOFFSETTO:-0400\r
+RRULE:FREQ=YEARLY;BYMONTH=4;BYDAY=1SU\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:19671029T020000\r
+TZOFFSETFROM:-0400\r
+TZOFFSETTO:-0500\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+END:STANDARD\r
+END:VTIMEZONE\r
+";
+
+    /// A system that knows no time zones, so that only the embedded `VTIMEZONE`s are used.
+    struct NoTimeZones;
+    impl TimeZoneResolver for NoTimeZones {
+        fn utc_offset(&mut self, _tzid: &str, _local: i64) -> Option<i32> {
+            None
+        }
+        fn default_tzid(&mut self) -> String {
+            "UTC".to_string()
+        }
+    }
+
+    /// Read the recurrence of a component such as `VEVENT` (the App reads it from an [`Event`] instead).
+    fn recurrence(component: &Component) -> Recurrence {
+        let start = component.property("DTSTART").and_then(date_property).unwrap();
+        Recurrence {
+            start,
+            rrules: component.properties("RRULE").map(|prop| RRule::parse(&prop.value).unwrap()).collect(),
+            exrules: component.properties("EXRULE").map(|prop| RRule::parse(&prop.value).unwrap()).collect(),
+            rdates: component.properties("RDATE").flat_map(date_values).collect(),
+            exdates: component.properties("EXDATE").flat_map(date_values).collect(),
+        }
+    }
+
+    /// The local start times of the instances of the event with **properties** (e.g. `DTSTART` and `RRULE`)
+    /// that start between the UTC times **from** and **to**.
+    fn occurrences(properties: &str, from: &str, to: &str) -> Vec<String> {
+        let content = format!("BEGIN:VCALENDAR\r\n{NEW_YORK}BEGIN:VEVENT\r\n{properties}END:VEVENT\r\nEND:VCALENDAR\r\n");
+        let calendar = ical::parse(&content).unwrap().remove(0);
+        let timezones = VTimeZone::from_calendars([&calendar]);
+        let mut resolver = TimeZones { system: &mut NoTimeZones, embedded: &timezones };
+
+        let recurrence = recurrence(calendar.components("VEVENT").next().unwrap());
+        let timestamp = |time: &str| DateTime::parse(time).unwrap().timestamp();
+        recurrence.occurrences(timestamp(from), timestamp(to), &mut resolver).unwrap()
+            .into_iter()
+            .map(|occurrence| occurrence.start.value())
+            .collect()
+    }
+
+    #[test]
+    fn last_weekday_of_month() {
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970926T090000\r\nRRULE:FREQ=MONTHLY;COUNT=6;BYDAY=-1FR\r\n", "19970101T000000", "19990101T000000"),
+            ["19970926T090000", "19971031T090000", "19971128T090000", "19971226T090000", "19980130T090000", "19980227T090000"],
+        );
+        // The first Friday, until a time in UTC
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970905T090000\r\nRRULE:FREQ=MONTHLY;UNTIL=19971224T000000Z;BYDAY=1FR\r\n", "19970101T000000", "19990101T000000"),
+            ["19970905T090000", "19971003T090000", "19971107T090000", "19971205T090000"],
+        );
+    }
+
+    #[test]
+    fn set_positions() {
+        // The third instance into the month of one of Tuesday, Wednesday, or Thursday
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970904T090000\r\nRRULE:FREQ=MONTHLY;COUNT=3;BYDAY=TU,WE,TH;BYSETPOS=3\r\n", "19970101T000000", "19990101T000000"),
+            ["19970904T090000", "19971007T090000", "19971106T090000"],
+        );
+        // The second-to-last weekday of the month
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970929T090000\r\nRRULE:FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-2\r\n", "19970101T000000", "19980401T000000"),
+            ["19970929T090000", "19971030T090000", "19971127T090000", "19971230T090000", "19980129T090000", "19980226T090000", "19980330T090000"],
+        );
+    }
+
+    #[test]
+    fn week_numbers_and_week_start() {
+        // Monday of week number 20
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970512T090000\r\nRRULE:FREQ=YEARLY;BYWEEKNO=20;BYDAY=MO\r\n", "19970101T000000", "20000101T000000"),
+            ["19970512T090000", "19980511T090000", "19990517T090000"],
+        );
+        // Week 20 of 1997 starts on Monday May 12 with WKST=MO, and on Sunday May 11 with WKST=SU
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970101T090000\r\nRRULE:FREQ=YEARLY;COUNT=2;BYWEEKNO=20;BYDAY=SU\r\n", "19970101T000000", "19980101T000000"),
+            ["19970101T090000", "19970518T090000"],
+        );
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970101T090000\r\nRRULE:FREQ=YEARLY;COUNT=2;BYWEEKNO=20;BYDAY=SU;WKST=SU\r\n", "19970101T000000", "19980101T000000"),
+            ["19970101T090000", "19970511T090000"],
+        );
+        // Every other week on Tuesday and Sunday, where the weeks start on Monday or on Sunday
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970805T090000\r\nRRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=MO\r\n", "19970101T000000", "19980101T000000"),
+            ["19970805T090000", "19970810T090000", "19970819T090000", "19970824T090000"],
+        );
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970805T090000\r\nRRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=SU\r\n", "19970101T000000", "19980101T000000"),
+            ["19970805T090000", "19970817T090000", "19970819T090000", "19970831T090000"],
+        );
+    }
+
+    #[test]
+    fn until_in_utc_with_time_zone() {
+        let daily = |until: &str| occurrences(
+            &format!("DTSTART;TZID=America/New_York:19970902T090000\r\nRRULE:FREQ=DAILY;UNTIL={until}\r\n"),
+            "19970101T000000", "19990101T000000",
+        );
+        let instances = daily("19971224T000000Z");
+        assert_eq!(instances.len(), 113);
+        assert_eq!(instances.last().unwrap(), "19971223T090000");
+        // 9:00 EST is 14:00 UTC, and UNTIL is inclusive
+        assert_eq!(daily("19971223T140000Z").last().unwrap(), "19971223T090000");
+        assert_eq!(daily("19971223T135959Z").last().unwrap(), "19971222T090000");
+        // 9:00 EDT is 13:00 UTC before the change to EST on October 26
+        assert_eq!(daily("19971025T130000Z").last().unwrap(), "19971025T090000");
+    }
+
+    #[test]
+    fn exclude_dates() {
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970902T090000\r\nRRULE:FREQ=DAILY;COUNT=5\r\nEXDATE;VALUE=DATE:19970903\r\n", "19970101T000000", "19980101T000000"),
+            ["19970902T090000", "19970904T090000", "19970905T090000", "19970906T090000"],
+        );
+        assert_eq!(
+            occurrences("DTSTART;TZID=America/New_York:19970902T090000\r\nRRULE:FREQ=DAILY;COUNT=5\r\nEXDATE;TZID=America/New_York:19970902T090000,19970904T090000\r\n", "19970101T000000", "19980101T000000"),
+            ["19970903T090000", "19970905T090000", "19970906T090000"],
+        );
+    }
+
+    #[test]
+    fn rules_that_never_match() {
+        // Only DTSTART, without expanding all the minutes until the end of the window
+        assert_eq!(
+            occurrences("DTSTART:20240101T090000Z\r\nRRULE:FREQ=MINUTELY;BYMONTH=2;BYMONTHDAY=30\r\n", "20240101T000000", "20340101T000000"),
+            ["20240101T090000Z"],
+        );
+
+        let mut event = Component::new("VEVENT");
+        event.push(Property::new("DTSTART", "20240101T090000Z"));
+        event.push(Property::new("RRULE", "FREQ=MINUTELY;COUNT=2;BYMONTH=2;BYMONTHDAY=30"));
+        event.push(Property::new("EXDATE", "20240101T090000Z"));
+        let recurrence = recurrence(&event);
+        assert!(recurrence.is_finite());
+        assert!(recurrence.is_empty(&mut NoTimeZones).unwrap());
+    }
+}
@@ -0,0 +1,389 @@
+//! Typed representation of a `VTODO` and its conversion to and from a row of the *Tasks* table of OpenTasks.
+use crate::provider::Row;
+use super::{
+    event::{date_property, new_date_property, Class},
+    ical::{Component, Property},
+    projections::tasks,
+    time::{CalTime, DateTime, Duration, TimeZoneResolver},
+    timezone::TimeZones,
+};
+
+/// The data of a `VTODO` component that can be stored in the Tasks Content Provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub start: Option<CalTime>,
+    /// Mutually exclusive with [`Self::duration`].
+    pub due: Option<CalTime>,
+    /// Only used if the task has a [`start`](Self::start).
+    pub duration: Option<Duration>,
+    /// The time the task was completed, in UTC.
+    pub completed: Option<CalTime>,
+    /// From `0` to `100`.
+    pub percent_complete: Option<i64>,
+    /// From `1` (highest) to `9` (lowest), or `0` if undefined.
+    pub priority: Option<i64>,
+    pub status: Option<TaskStatus>,
+    pub class: Option<Class>,
+    pub rrule: Option<String>,
+    /// The UID of the parent task (`RELATED-TO;RELTYPE=PARENT`).
+    pub parent: Option<String>,
+    /// The UIDs of the subtasks that are listed in this task (`RELATED-TO;RELTYPE=CHILD`).
+    ///
+    /// Only used to find the [`parent`](Self::parent) of the subtasks, so it is not stored in the provider.
+    pub children: Vec<String>,
+}
+
+/// The `STATUS` of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    NeedsAction,
+    InProcess,
+    Completed,
+    Cancelled,
+}
+impl TaskStatus {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "NEEDS-ACTION" => Some(Self::NeedsAction),
+            "IN-PROCESS" => Some(Self::InProcess),
+            "COMPLETED" => Some(Self::Completed),
+            "CANCELLED" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NeedsAction => "NEEDS-ACTION",
+            Self::InProcess => "IN-PROCESS",
+            Self::Completed => "COMPLETED",
+            Self::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+impl Task {
+    /// Read the data of a `VTODO` component.
+    ///
+    /// Returns an error if the component has invalid date values, or a `PERCENT-COMPLETE` or `PRIORITY` out of range.
+    pub fn from_component(component: &Component) -> Result<Self, String> {
+        if !component.name.eq_ignore_ascii_case("VTODO") {
+            return Err(format!("Expected VTODO component, found {}", component.name));
+        }
+        let uid = component.value("UID").map(str::to_string);
+        // Used to identify the task in error messages
+        let id = uid.as_deref().unwrap_or("<no UID>");
+
+        let date = |name: &str| component
+            .property(name)
+            .map(|prop| date_property(prop).ok_or_else(|| format!("Task {id} has invalid {name} {:?}", prop.value)))
+            .transpose();
+        let integer = |name: &str, range: std::ops::RangeInclusive<i64>| component
+            .value(name)
+            .map(|value| value.trim().parse().ok()
+                .filter(|value| range.contains(value))
+                .ok_or_else(|| format!("Task {id} has invalid {name} {value:?}")))
+            .transpose();
+
+        let start = date("DTSTART")?;
+        let due = date("DUE")?;
+        let duration = component
+            .value("DURATION")
+            .map(|value| Duration::parse(value).ok_or_else(|| format!("Task {id} has invalid DURATION {value:?}")))
+            .transpose()?
+            // DURATION is relative to DTSTART, and can't be used with DUE
+            .filter(|_| start.is_some() && due.is_none());
+
+        let mut parent = None;
+        let mut children = Vec::new();
+        for prop in component.properties("RELATED-TO") {
+            match prop.param("RELTYPE").map(str::to_ascii_uppercase).as_deref() {
+                None | Some("PARENT") => parent = Some(prop.value.trim().to_string()),
+                Some("CHILD") => children.push(prop.value.trim().to_string()),
+                // Siblings can't be stored in the provider
+                Some(_) => {},
+            }
+        }
+
+        Ok(Self {
+            summary: component.property("SUMMARY").map(Property::text_value),
+            description: component.property("DESCRIPTION").map(Property::text_value),
+            location: component.property("LOCATION").map(Property::text_value),
+            start,
+            due,
+            duration,
+            completed: date("COMPLETED")?,
+            percent_complete: integer("PERCENT-COMPLETE", 0..=100)?,
+            priority: integer("PRIORITY", 0..=9)?,
+            status: component.value("STATUS").and_then(TaskStatus::parse),
+            class: component.value("CLASS").and_then(Class::parse),
+            rrule: component.value("RRULE").map(str::to_string),
+            parent,
+            children,
+            uid,
+        })
+    }
+
+    pub fn is_all_day(&self) -> bool {
+        self.start.as_ref().or(self.due.as_ref()).is_some_and(CalTime::is_date)
+    }
+
+    /// Replace the time zones of the dates of the task with ones the Content Provider can use (see [`TimeZones::normalize()`]).
+    pub fn normalize_time_zones(&mut self, time_zones: &mut TimeZones) {
+        self.start = self.start.as_ref().map(|start| time_zones.normalize(start));
+        self.due = self.due.as_ref().map(|due| time_zones.normalize(due));
+    }
+
+    /// Convert to a row of the *Tasks* table.
+    ///
+    /// The **list_id** and **parent_id** columns are not included.
+    /// The provider has a single time zone for `DTSTART` and `DUE`, so it is taken from `DTSTART` (or `DUE` if there is no start).
+    pub fn to_row(&self, resolver: &mut dyn TimeZoneResolver) -> Result<Row, String> {
+        let id = self.uid.as_deref().unwrap_or("<no UID>");
+        let unknown_tz = |time: &CalTime| format!("Task {id} has unknown time zone {:?}", time.tzid().unwrap_or_default());
+
+        let mut row = Row::new();
+        row.put(tasks::UID, self.uid.clone());
+        row.put(tasks::TITLE, self.summary.clone());
+        row.put(tasks::DESCRIPTION, self.description.clone());
+        row.put(tasks::LOCATION, self.location.clone());
+
+        if let Some(time) = self.start.as_ref().or(self.due.as_ref()) {
+            // All-day tasks have no time zone
+            let timezone = match time {
+                CalTime::Date(_) => None,
+                CalTime::Utc(_) => Some("UTC".to_string()),
+                CalTime::Floating(_) => Some(resolver.default_tzid()),
+                CalTime::Zoned { tzid, .. } => Some(tzid.clone()),
+            };
+            row.put(tasks::TIMEZONE, timezone);
+            row.put(tasks::ALL_DAY, self.is_all_day());
+        }
+        if let Some(start) = &self.start {
+            row.put(tasks::DATE_START, start.timestamp(resolver).ok_or_else(|| unknown_tz(start))? * 1000);
+        }
+        if let Some(due) = &self.due {
+            row.put(tasks::DUE, due.timestamp(resolver).ok_or_else(|| unknown_tz(due))? * 1000);
+        } else if let Some(duration) = &self.duration {
+            row.put(tasks::DURATION, duration.to_string());
+        }
+        if let Some(completed) = &self.completed {
+            row.put(tasks::COMPLETED, completed.timestamp(resolver).ok_or_else(|| unknown_tz(completed))? * 1000);
+            row.put(tasks::COMPLETED_ALL_DAY, completed.is_date());
+        }
+
+        row.put(tasks::PERCENT_COMPLETE, self.percent_complete);
+        row.put(tasks::PRIORITY, self.priority);
+        row.put(tasks::STATUS, self.status.map(|status| match status {
+            TaskStatus::NeedsAction => tasks::STATUS_NEEDS_ACTION,
+            TaskStatus::InProcess => tasks::STATUS_IN_PROCESS,
+            TaskStatus::Completed => tasks::STATUS_COMPLETED,
+            TaskStatus::Cancelled => tasks::STATUS_CANCELLED,
+        }));
+        row.put(tasks::CLASSIFICATION, self.class.map(|class| match class {
+            Class::Public => tasks::CLASSIFICATION_PUBLIC,
+            Class::Private => tasks::CLASSIFICATION_PRIVATE,
+            Class::Confidential => tasks::CLASSIFICATION_CONFIDENTIAL,
+        }));
+        row.put(tasks::R_RULE, self.rrule.clone());
+
+        Ok(row)
+    }
+
+    /// Read the data of a row of the *Tasks* table that was queried with [`tasks::COPY_PROJECTION`].
+    ///
+    /// The [`parent`](Self::parent) is not set, because the row only has the ID of the parent (see [`tasks::PARENT_ID`]).
+    pub fn from_row(row: &Row, resolver: &mut dyn TimeZoneResolver) -> Self {
+        let all_day = row.get_long(tasks::ALL_DAY).is_some_and(|all_day| all_day != 0);
+        let timezone = row.get_text(tasks::TIMEZONE);
+        let mut time = |column: &str| row.get_long(column)
+            .map(|millis| CalTime::from_timestamp(millis / 1000, timezone, all_day, resolver));
+        let start = time(tasks::DATE_START);
+        let due = time(tasks::DUE);
+        let completed = row.get_long(tasks::COMPLETED).map(|completed| CalTime::from_timestamp(
+            completed / 1000,
+            None,
+            row.get_long(tasks::COMPLETED_ALL_DAY).is_some_and(|all_day| all_day != 0),
+            resolver,
+        ));
+
+        Self {
+            uid: row.get_text(tasks::UID).map(str::to_string),
+            summary: row.get_text(tasks::TITLE).map(str::to_string),
+            description: row.get_text(tasks::DESCRIPTION).map(str::to_string),
+            location: row.get_text(tasks::LOCATION).map(str::to_string),
+            start,
+            due,
+            duration: row.get_text(tasks::DURATION).and_then(Duration::parse),
+            completed,
+            percent_complete: row.get_long(tasks::PERCENT_COMPLETE),
+            priority: row.get_long(tasks::PRIORITY),
+            status: row.get_long(tasks::STATUS).and_then(|status| match status {
+                tasks::STATUS_NEEDS_ACTION => Some(TaskStatus::NeedsAction),
+                tasks::STATUS_IN_PROCESS => Some(TaskStatus::InProcess),
+                tasks::STATUS_COMPLETED => Some(TaskStatus::Completed),
+                tasks::STATUS_CANCELLED => Some(TaskStatus::Cancelled),
+                _ => None,
+            }),
+            class: row.get_long(tasks::CLASSIFICATION).and_then(|class| match class {
+                tasks::CLASSIFICATION_PUBLIC => Some(Class::Public),
+                tasks::CLASSIFICATION_PRIVATE => Some(Class::Private),
+                tasks::CLASSIFICATION_CONFIDENTIAL => Some(Class::Confidential),
+                _ => None,
+            }),
+            rrule: row.get_text(tasks::R_RULE).map(str::to_string),
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Convert to a `VTODO` component.
+    ///
+    /// **dtstamp** is the time the component was created (usually [`now()`](super::time::now())).
+    pub fn to_component(&self, dtstamp: DateTime) -> Component {
+        let mut component = Component::new("VTODO");
+        if let Some(uid) = &self.uid {
+            component.push(Property::new("UID", uid));
+        }
+        component.push(Property::new("DTSTAMP", format!("{dtstamp}Z")));
+        if let Some(start) = &self.start {
+            component.push(new_date_property("DTSTART", start));
+        }
+        if let Some(due) = &self.due {
+            component.push(new_date_property("DUE", due));
+        } else if let (Some(duration), Some(_)) = (&self.duration, &self.start) {
+            component.push(Property::new("DURATION", duration.to_string()));
+        }
+        if let Some(summary) = &self.summary {
+            component.push(Property::text("SUMMARY", summary));
+        }
+        if let Some(description) = &self.description {
+            component.push(Property::text("DESCRIPTION", description));
+        }
+        if let Some(location) = &self.location {
+            component.push(Property::text("LOCATION", location));
+        }
+        if let Some(completed) = &self.completed {
+            component.push(new_date_property("COMPLETED", completed));
+        }
+        if let Some(percent_complete) = self.percent_complete {
+            component.push(Property::new("PERCENT-COMPLETE", percent_complete.to_string()));
+        }
+        if let Some(priority) = self.priority {
+            component.push(Property::new("PRIORITY", priority.to_string()));
+        }
+        if let Some(status) = self.status {
+            component.push(Property::new("STATUS", status.as_str()));
+        }
+        if let Some(class) = self.class {
+            component.push(Property::new("CLASS", class.as_str()));
+        }
+        if let Some(rrule) = &self.rrule {
+            component.push(Property::new("RRULE", rrule));
+        }
+        if let Some(parent) = &self.parent {
+            component.push(Property::new("RELATED-TO", parent));
+        }
+        for child in &self.children {
+            component.push(Property::new("RELATED-TO", child).with_param("RELTYPE", "CHILD"));
+        }
+
+        component
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::{ical, time::now};
+
+    /// Knows only `America/New_York`, always at *-05:00*.
+    struct NewYork;
+    impl TimeZoneResolver for NewYork {
+        fn utc_offset(&mut self, tzid: &str, _local: i64) -> Option<i32> {
+            (tzid == "America/New_York").then_some(-5 * 3600)
+        }
+        fn default_tzid(&mut self) -> String {
+            "America/New_York".to_string()
+        }
+    }
+
+    fn task(lines: &[&str]) -> Result<Task, String> {
+        let content = format!("BEGIN:VTODO\r\nUID:1\r\n{}\r\nEND:VTODO\r\n", lines.join("\r\n"));
+        Task::from_component(&ical::parse(&content).unwrap()[0])
+    }
+
+    #[test]
+    fn task_columns() {
+        let row = task(&["STATUS:IN-PROCESS", "PRIORITY:1", "PERCENT-COMPLETE:40", "CLASS:PRIVATE", "DUE;TZID=America/New_York:20240101T170000"])
+            .unwrap().to_row(&mut NewYork).unwrap();
+        assert_eq!(row.get_long(tasks::STATUS), Some(tasks::STATUS_IN_PROCESS));
+        assert_eq!(row.get_long(tasks::PRIORITY), Some(1));
+        assert_eq!(row.get_long(tasks::PERCENT_COMPLETE), Some(40));
+        assert_eq!(row.get_long(tasks::CLASSIFICATION), Some(tasks::CLASSIFICATION_PRIVATE));
+        // 2024-01-01 17:00 -05:00, without a start the time zone is the one of the due date
+        assert_eq!(row.get_long(tasks::DUE), Some(1704146400 * 1000));
+        assert_eq!(row.get_long(tasks::DATE_START), None);
+        assert_eq!(row.get_text(tasks::TIMEZONE), Some("America/New_York"));
+        assert_eq!(row.get_long(tasks::ALL_DAY), Some(0));
+
+        for (status, column) in [
+            ("NEEDS-ACTION", tasks::STATUS_NEEDS_ACTION),
+            ("IN-PROCESS", tasks::STATUS_IN_PROCESS),
+            ("COMPLETED", tasks::STATUS_COMPLETED),
+            ("CANCELLED", tasks::STATUS_CANCELLED),
+        ] {
+            let row = task(&[&format!("STATUS:{status}")]).unwrap().to_row(&mut NewYork).unwrap();
+            assert_eq!(row.get_long(tasks::STATUS), Some(column));
+            assert_eq!(Task::from_row(&row, &mut NewYork).status.map(|status| status.as_str()), Some(status));
+        }
+
+        // All-day tasks have no time zone
+        let row = task(&["DTSTART;VALUE=DATE:20240101", "DUE;VALUE=DATE:20240103"]).unwrap().to_row(&mut NewYork).unwrap();
+        assert_eq!(row.get_long(tasks::ALL_DAY), Some(1));
+        assert_eq!(row.get(tasks::TIMEZONE), Some(&crate::provider::Value::Null));
+        assert_eq!(row.get_long(tasks::DUE), Some(1704240000 * 1000));
+        // DURATION is only stored without a DUE
+        let row = task(&["DTSTART:20240101T090000Z", "DURATION:PT1H"]).unwrap().to_row(&mut NewYork).unwrap();
+        assert_eq!(row.get_text(tasks::DURATION), Some("PT1H"));
+        assert_eq!(task(&["DTSTART:20240101T090000Z", "DUE:20240101T100000Z", "DURATION:PT1H"]).unwrap().duration, None);
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(task(&["PRIORITY:10"]).unwrap_err(), "Task 1 has invalid PRIORITY \"10\"");
+        assert_eq!(task(&["PERCENT-COMPLETE:-1"]).unwrap_err(), "Task 1 has invalid PERCENT-COMPLETE \"-1\"");
+        assert_eq!(task(&["DUE:tomorrow"]).unwrap_err(), "Task 1 has invalid DUE \"tomorrow\"");
+        assert_eq!(task(&["DUE;TZID=Mars/Olympus:20240101T170000"]).unwrap().to_row(&mut NewYork).unwrap_err(),
+            "Task 1 has unknown time zone \"Mars/Olympus\"");
+        assert_eq!(task(&["STATUS:DONE"]).unwrap().status, None);
+    }
+
+    #[test]
+    fn tasks_round_trip() {
+        for lines in [
+            &[
+                "DTSTART;TZID=America/New_York:20240101T090000",
+                "DUE;TZID=America/New_York:20240101T170000",
+                "SUMMARY:Write report",
+                "PERCENT-COMPLETE:40",
+                "PRIORITY:1",
+                "STATUS:IN-PROCESS",
+                "CLASS:CONFIDENTIAL",
+                "RRULE:FREQ=WEEKLY",
+            ][..],
+            &["DTSTART;VALUE=DATE:20240101", "DURATION:P2D", "STATUS:NEEDS-ACTION"],
+            &["DUE;VALUE=DATE:20240103", "COMPLETED:20240102T120000Z", "STATUS:COMPLETED"],
+        ] {
+            let task = task(lines).unwrap();
+            let row = task.to_row(&mut NewYork).unwrap();
+            let read = Task::from_row(&row, &mut NewYork);
+            assert_eq!(read, task);
+            let component = read.to_component(now());
+            assert_eq!(Task::from_component(&component).unwrap(), task);
+        }
+    }
+}
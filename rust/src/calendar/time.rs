@@ -0,0 +1,388 @@
+//! Date and time values as they appear in *iCalendar* properties (e.g. `DTSTART`, `DURATION`).
+//!
+//! Only the calendar arithmetic needed to convert these values to and from the *Unix timestamps* used by the Content Provider lives here;
+//! resolving the offset of a named time zone is left to a [`TimeZoneResolver`].
+use std::fmt::{self, Display};
+
+/// A calendar date (in the *proleptic Gregorian* calendar).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    /// `1..=12`
+    pub month: u8,
+    /// `1..=31`
+    pub day: u8,
+}
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> Option<Self> {
+        if month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Self { year, month, day })
+    }
+
+    /// Parse a `DATE` value (e.g. `"19970714"`).
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Self::new(
+            s[0..4].parse().ok()?,
+            s[4..6].parse().ok()?,
+            s[6..8].parse().ok()?,
+        )
+    }
+
+    /// Number of days since `1970-01-01`.
+    pub fn days_since_epoch(&self) -> i64 {
+        // Algorithm from http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let y = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = if y >= 0 { y } else { y - 399 } / 400;
+        let yoe = y - era * 400;
+        let m = self.month as i64;
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// Inverse of [`Self::days_since_epoch()`].
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        // Algorithm from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = if z >= 0 { z } else { z - 146096 } / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        Self { year, month, day }
+    }
+
+    /// Day of the week, where `0` is *Monday* and `6` is *Sunday*.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        (self.days_since_epoch() + 3).rem_euclid(7) as u8
+    }
+
+    /// Returns the date **days** after (or before, if negative) this one.
+    pub fn add_days(&self, days: i64) -> Self {
+        Self::from_days_since_epoch(self.days_since_epoch() + days)
+    }
+}
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// A time of day, without any time zone information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    /// Can be `60` for leap seconds, as allowed by *RFC 5545*.
+    pub second: u8,
+}
+impl Time {
+    pub fn seconds_since_midnight(&self) -> i64 {
+        self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+}
+
+/// A date with a time of day, without any time zone information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub date: Date,
+    pub time: Time,
+}
+impl DateTime {
+    /// Parse a `DATE-TIME` value **without** the trailing `Z` (e.g. `"19970714T133000"`).
+    pub fn parse(s: &str) -> Option<Self> {
+        let (date, time) = s.split_once('T')?;
+        if time.len() != 6 || !time.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let time = Time {
+            hour: time[0..2].parse().ok()?,
+            minute: time[2..4].parse().ok()?,
+            second: time[4..6].parse().ok()?,
+        };
+        if time.hour > 23 || time.minute > 59 || time.second > 60 {
+            return None;
+        }
+        Some(Self { date: Date::parse(date)?, time })
+    }
+
+    /// The number of seconds since the *Unix epoch* **as if** this time was in UTC.
+    pub fn timestamp(&self) -> i64 {
+        self.date.days_since_epoch() * 86400 + self.time.seconds_since_midnight()
+    }
+
+    /// Inverse of [`Self::timestamp()`].
+    pub fn from_timestamp(secs: i64) -> Self {
+        let secs_of_day = secs.rem_euclid(86400);
+        Self {
+            date: Date::from_days_since_epoch(secs.div_euclid(86400)),
+            time: Time {
+                hour: (secs_of_day / 3600) as u8,
+                minute: (secs_of_day % 3600 / 60) as u8,
+                second: (secs_of_day % 60) as u8,
+            },
+        }
+    }
+}
+impl Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}T{:02}{:02}{:02}", self.date, self.time.hour, self.time.minute, self.time.second)
+    }
+}
+
+/// The value of a date property such as `DTSTART`, which can be in one of the forms allowed by *RFC 5545*.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CalTime {
+    /// A `VALUE=DATE` (e.g. for *all-day* events).
+    Date(Date),
+    /// A *floating* time, which is the same local time regardless of the time zone (`DTSTART:19980118T230000`).
+    Floating(DateTime),
+    /// A time in UTC (`DTSTART:19980119T070000Z`).
+    Utc(DateTime),
+    /// A local time in some time zone (`DTSTART;TZID=America/New_York:19980119T020000`).
+    Zoned { time: DateTime, tzid: String },
+}
+impl CalTime {
+    /// Parse the value of a date property given its `VALUE` and `TZID` parameters.
+    pub fn parse(value: &str, tzid: Option<&str>) -> Option<Self> {
+        let value = value.trim();
+        if !value.contains('T') {
+            return Date::parse(value).map(Self::Date);
+        }
+        if let Some(utc) = value.strip_suffix('Z') {
+            return DateTime::parse(utc).map(Self::Utc);
+        }
+        let time = DateTime::parse(value)?;
+        Some(match tzid {
+            Some(tzid) => Self::Zoned { time, tzid: tzid.to_string() },
+            None => Self::Floating(time),
+        })
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, Self::Date(_))
+    }
+
+    pub fn tzid(&self) -> Option<&str> {
+        match self {
+            Self::Zoned { tzid, .. } => Some(tzid),
+            _ => None,
+        }
+    }
+
+    /// The date and time as written in the file, ignoring its time zone.
+    /// A [`Date`] is at midnight.
+    pub fn local(&self) -> DateTime {
+        match self {
+            Self::Date(date) => DateTime { date: *date, time: Time::default() },
+            Self::Floating(time) | Self::Utc(time) | Self::Zoned { time, .. } => *time,
+        }
+    }
+
+    /// A time of the same kind (e.g. in the same time zone) at a different **local** time.
+    /// [`Date`](Self::Date)s ignore the time of **local**.
+    pub fn with_local(&self, local: DateTime) -> Self {
+        match self {
+            Self::Date(_) => Self::Date(local.date),
+            Self::Floating(_) => Self::Floating(local),
+            Self::Utc(_) => Self::Utc(local),
+            Self::Zoned { tzid, .. } => Self::Zoned { time: local, tzid: tzid.clone() },
+        }
+    }
+
+    /// Convert to seconds since the *Unix epoch*.
+    ///
+    /// [`Date`](Self::Date)s are taken at *midnight UTC*, as that is how the Content Provider stores all-day events.
+    /// [`Floating`](Self::Floating) times use the **default** time zone of the **resolver**.
+    ///
+    /// Returns [`None`] if **resolver** doesn't know the time zone.
+    pub fn timestamp(&self, resolver: &mut dyn TimeZoneResolver) -> Option<i64> {
+        match self {
+            Self::Date(_) | Self::Utc(_) => Some(self.local().timestamp()),
+            Self::Floating(time) => {
+                let tzid = resolver.default_tzid();
+                let local = time.timestamp();
+                Some(local - resolver.utc_offset(&tzid, local)? as i64)
+            }
+            Self::Zoned { time, tzid } => {
+                let local = time.timestamp();
+                Some(local - resolver.utc_offset(tzid, local)? as i64)
+            }
+        }
+    }
+
+    /// Inverse of [`Self::timestamp()`]: create a time from seconds since the *Unix epoch*
+    /// as it is stored in the Content Provider along with its time zone.
+    ///
+    /// If **all_day**, the result is a [`Date`](Self::Date).
+    /// If the time zone is `UTC` or unknown to the **resolver**, the result is in [`Utc`](Self::Utc).
+    pub fn from_timestamp(secs: i64, tzid: Option<&str>, all_day: bool, resolver: &mut dyn TimeZoneResolver) -> Self {
+        let utc = DateTime::from_timestamp(secs);
+        if all_day {
+            return Self::Date(utc.date);
+        }
+        let tzid = match tzid {
+            Some(tzid) if !tzid.eq_ignore_ascii_case("UTC") => tzid,
+            _ => return Self::Utc(utc),
+        };
+        // The resolver takes a local time, so use the offset at the UTC time as a first approximation
+        match resolver.utc_offset(tzid, secs) {
+            Some(approx) => {
+                let offset = resolver.utc_offset(tzid, secs + approx as i64).unwrap_or(approx);
+                Self::Zoned {
+                    time: DateTime::from_timestamp(secs + offset as i64),
+                    tzid: tzid.to_string(),
+                }
+            }
+            None => Self::Utc(utc),
+        }
+    }
+
+    /// Get the value in the format it is written in a *property* (without the `TZID` parameter).
+    pub fn value(&self) -> String {
+        match self {
+            Self::Date(date) => date.to_string(),
+            Self::Floating(time) | Self::Zoned { time, .. } => time.to_string(),
+            Self::Utc(time) => format!("{time}Z"),
+        }
+    }
+}
+
+/// The current time in UTC (e.g. for `DTSTAMP`).
+pub fn now() -> DateTime {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    DateTime::from_timestamp(secs)
+}
+
+/// Gives the *UTC offset* of named time zones (usually an IANA ID such as `"America/New_York"`).
+pub trait TimeZoneResolver {
+    /// The offset (in seconds) from UTC that the time zone **tzid** has at the **local** time,
+    /// where **local** is the [timestamp](DateTime::timestamp()) of the local time *as if* it was in UTC.
+    ///
+    /// Returns [`None`] if the time zone is not known.
+    fn utc_offset(&mut self, tzid: &str, local: i64) -> Option<i32>;
+    /// The ID of the time zone used for *floating* times.
+    fn default_tzid(&mut self) -> String;
+}
+
+/// A `DURATION` value (e.g. `"P15DT5H0M20S"` or `"-PT15M"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Duration {
+    pub negative: bool,
+    pub weeks: u32,
+    pub days: u32,
+    pub hours: u32,
+    pub minutes: u32,
+    pub seconds: u32,
+}
+impl Duration {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (negative, s) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let chars = s.strip_prefix('P')?.chars();
+        let mut duration = Self { negative, ..Default::default() };
+        let mut in_time = false;
+        let mut num = None::<u32>;
+        let mut any = false;
+
+        for c in chars {
+            match c {
+                '0'..='9' => num = Some(num.unwrap_or(0).checked_mul(10)?.checked_add(c as u32 - '0' as u32)?),
+                'T' if !in_time && num.is_none() => in_time = true,
+                'W' if !in_time => duration.weeks = num.take()?,
+                'D' if !in_time => duration.days = num.take()?,
+                'H' if in_time => duration.hours = num.take()?,
+                'M' if in_time => duration.minutes = num.take()?,
+                'S' if in_time => duration.seconds = num.take()?,
+                _ => return None,
+            }
+            // Only count designators that are preceded by a number
+            any |= c != 'T' && !c.is_ascii_digit();
+        }
+
+        if num.is_some() || !any {
+            return None;
+        }
+        Some(duration)
+    }
+
+    /// Create a duration from a number of seconds, using the largest units possible (except weeks).
+    pub fn from_seconds(secs: i64) -> Self {
+        let abs = secs.unsigned_abs();
+        Self {
+            negative: secs < 0,
+            weeks: 0,
+            days: (abs / 86400) as u32,
+            hours: (abs % 86400 / 3600) as u32,
+            minutes: (abs % 3600 / 60) as u32,
+            seconds: (abs % 60) as u32,
+        }
+    }
+
+    /// The total length of the duration in seconds, where a day is always 24 hours.
+    pub fn as_seconds(&self) -> i64 {
+        let secs = (self.weeks as i64 * 7 + self.days as i64) * 86400
+            + self.hours as i64 * 3600
+            + self.minutes as i64 * 60
+            + self.seconds as i64;
+        if self.negative { -secs } else { secs }
+    }
+}
+impl Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            f.write_str("-")?;
+        }
+        f.write_str("P")?;
+        if self.weeks > 0 {
+            write!(f, "{}W", self.weeks)?;
+        }
+        if self.days > 0 {
+            write!(f, "{}D", self.days)?;
+        }
+        if self.hours > 0 || self.minutes > 0 || self.seconds > 0 {
+            f.write_str("T")?;
+            if self.hours > 0 {
+                write!(f, "{}H", self.hours)?;
+            }
+            if self.minutes > 0 {
+                write!(f, "{}M", self.minutes)?;
+            }
+            if self.seconds > 0 {
+                write!(f, "{}S", self.seconds)?;
+            }
+        } else if self.weeks == 0 && self.days == 0 {
+            f.write_str("T0S")?;
+        }
+        Ok(())
+    }
+}
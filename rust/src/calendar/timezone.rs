@@ -0,0 +1,676 @@
+//! Resolution of the `TZID`s used in calendar files to the IANA time zones the Content Provider uses,
+//! and conversion between `VTIMEZONE` components and UTC offsets.
+//!
+//! Files exported by *Outlook* and *Exchange* use Windows time zone names (e.g. `"Eastern Standard Time"`),
+//! and other apps prefix the IANA ID with their own path (e.g. `"/mozilla.org/20050126_1/America/New_York"`).
+//! When a `TZID` can't be resolved to an IANA time zone, the rules of the `VTIMEZONE` with that `TZID` embedded in the file are used.
+use super::{
+    event::{date_list, date_list_properties},
+    ical::{Component, Property},
+    recurrence::{Frequency, RRule, WeekdayNum},
+    time::{days_in_month, CalTime, Date, DateTime, Time, TimeZoneResolver},
+};
+
+/// Mapping of Windows time zone names to IANA time zone IDs, from the CLDR `windowsZones.xml` (territory `001`).
+const WINDOWS_ZONES: &[(&str, &str)] = &[
+    ("Dateline Standard Time", "Etc/GMT+12"),
+    ("UTC-11", "Etc/GMT+11"),
+    ("Aleutian Standard Time", "America/Adak"),
+    ("Hawaiian Standard Time", "Pacific/Honolulu"),
+    ("Marquesas Standard Time", "Pacific/Marquesas"),
+    ("Alaskan Standard Time", "America/Anchorage"),
+    ("UTC-09", "Etc/GMT+9"),
+    ("Pacific Standard Time (Mexico)", "America/Tijuana"),
+    ("UTC-08", "Etc/GMT+8"),
+    ("Pacific Standard Time", "America/Los_Angeles"),
+    ("US Mountain Standard Time", "America/Phoenix"),
+    ("Mountain Standard Time (Mexico)", "America/Mazatlan"),
+    ("Mexico Standard Time 2", "America/Chihuahua"),
+    ("Mountain Standard Time", "America/Denver"),
+    ("Yukon Standard Time", "America/Whitehorse"),
+    ("Central America Standard Time", "America/Guatemala"),
+    ("Central Standard Time", "America/Chicago"),
+    ("Easter Island Standard Time", "Pacific/Easter"),
+    ("Central Standard Time (Mexico)", "America/Mexico_City"),
+    ("Mexico Standard Time", "America/Mexico_City"),
+    ("Canada Central Standard Time", "America/Regina"),
+    ("SA Pacific Standard Time", "America/Bogota"),
+    ("Eastern Standard Time (Mexico)", "America/Cancun"),
+    ("Eastern Standard Time", "America/New_York"),
+    ("Haiti Standard Time", "America/Port-au-Prince"),
+    ("Cuba Standard Time", "America/Havana"),
+    ("US Eastern Standard Time", "America/Indiana/Indianapolis"),
+    ("Turks And Caicos Standard Time", "America/Grand_Turk"),
+    ("Paraguay Standard Time", "America/Asuncion"),
+    ("Atlantic Standard Time", "America/Halifax"),
+    ("Venezuela Standard Time", "America/Caracas"),
+    ("Central Brazilian Standard Time", "America/Cuiaba"),
+    ("SA Western Standard Time", "America/La_Paz"),
+    ("Pacific SA Standard Time", "America/Santiago"),
+    ("Newfoundland Standard Time", "America/St_Johns"),
+    ("Tocantins Standard Time", "America/Araguaina"),
+    ("E. South America Standard Time", "America/Sao_Paulo"),
+    ("SA Eastern Standard Time", "America/Cayenne"),
+    ("Argentina Standard Time", "America/Argentina/Buenos_Aires"),
+    ("Greenland Standard Time", "America/Godthab"),
+    ("Montevideo Standard Time", "America/Montevideo"),
+    ("Magallanes Standard Time", "America/Punta_Arenas"),
+    ("Saint Pierre Standard Time", "America/Miquelon"),
+    ("Bahia Standard Time", "America/Bahia"),
+    ("UTC-02", "Etc/GMT+2"),
+    ("Mid-Atlantic Standard Time", "Etc/GMT+2"),
+    ("Azores Standard Time", "Atlantic/Azores"),
+    ("Cape Verde Standard Time", "Atlantic/Cape_Verde"),
+    ("UTC", "Etc/UTC"),
+    ("GMT Standard Time", "Europe/London"),
+    ("Greenwich Standard Time", "Atlantic/Reykjavik"),
+    ("Sao Tome Standard Time", "Africa/Sao_Tome"),
+    ("Morocco Standard Time", "Africa/Casablanca"),
+    ("W. Europe Standard Time", "Europe/Berlin"),
+    ("Central Europe Standard Time", "Europe/Budapest"),
+    ("Romance Standard Time", "Europe/Paris"),
+    ("Central European Standard Time", "Europe/Warsaw"),
+    ("W. Central Africa Standard Time", "Africa/Lagos"),
+    ("Jordan Standard Time", "Asia/Amman"),
+    ("GTB Standard Time", "Europe/Bucharest"),
+    ("Middle East Standard Time", "Asia/Beirut"),
+    ("Egypt Standard Time", "Africa/Cairo"),
+    ("E. Europe Standard Time", "Europe/Chisinau"),
+    ("Syria Standard Time", "Asia/Damascus"),
+    ("West Bank Standard Time", "Asia/Hebron"),
+    ("South Africa Standard Time", "Africa/Johannesburg"),
+    ("FLE Standard Time", "Europe/Kiev"),
+    ("Israel Standard Time", "Asia/Jerusalem"),
+    ("South Sudan Standard Time", "Africa/Juba"),
+    ("Kaliningrad Standard Time", "Europe/Kaliningrad"),
+    ("Sudan Standard Time", "Africa/Khartoum"),
+    ("Libya Standard Time", "Africa/Tripoli"),
+    ("Namibia Standard Time", "Africa/Windhoek"),
+    ("Arabic Standard Time", "Asia/Baghdad"),
+    ("Turkey Standard Time", "Europe/Istanbul"),
+    ("Arab Standard Time", "Asia/Riyadh"),
+    ("Belarus Standard Time", "Europe/Minsk"),
+    ("Russian Standard Time", "Europe/Moscow"),
+    ("E. Africa Standard Time", "Africa/Nairobi"),
+    ("Volgograd Standard Time", "Europe/Volgograd"),
+    ("Iran Standard Time", "Asia/Tehran"),
+    ("Arabian Standard Time", "Asia/Dubai"),
+    ("Astrakhan Standard Time", "Europe/Astrakhan"),
+    ("Azerbaijan Standard Time", "Asia/Baku"),
+    ("Russia Time Zone 3", "Europe/Samara"),
+    ("Mauritius Standard Time", "Indian/Mauritius"),
+    ("Saratov Standard Time", "Europe/Saratov"),
+    ("Georgian Standard Time", "Asia/Tbilisi"),
+    ("Caucasus Standard Time", "Asia/Yerevan"),
+    ("Armenian Standard Time", "Asia/Yerevan"),
+    ("Afghanistan Standard Time", "Asia/Kabul"),
+    ("West Asia Standard Time", "Asia/Tashkent"),
+    ("Ekaterinburg Standard Time", "Asia/Yekaterinburg"),
+    ("Pakistan Standard Time", "Asia/Karachi"),
+    ("Qyzylorda Standard Time", "Asia/Qyzylorda"),
+    ("India Standard Time", "Asia/Kolkata"),
+    ("Sri Lanka Standard Time", "Asia/Colombo"),
+    ("Nepal Standard Time", "Asia/Kathmandu"),
+    ("Central Asia Standard Time", "Asia/Almaty"),
+    ("Bangladesh Standard Time", "Asia/Dhaka"),
+    ("Omsk Standard Time", "Asia/Omsk"),
+    ("Myanmar Standard Time", "Asia/Yangon"),
+    ("SE Asia Standard Time", "Asia/Bangkok"),
+    ("Altai Standard Time", "Asia/Barnaul"),
+    ("W. Mongolia Standard Time", "Asia/Hovd"),
+    ("North Asia Standard Time", "Asia/Krasnoyarsk"),
+    ("N. Central Asia Standard Time", "Asia/Novosibirsk"),
+    ("Tomsk Standard Time", "Asia/Tomsk"),
+    ("China Standard Time", "Asia/Shanghai"),
+    ("North Asia East Standard Time", "Asia/Irkutsk"),
+    ("Singapore Standard Time", "Asia/Singapore"),
+    ("W. Australia Standard Time", "Australia/Perth"),
+    ("Taipei Standard Time", "Asia/Taipei"),
+    ("Ulaanbaatar Standard Time", "Asia/Ulaanbaatar"),
+    ("Aus Central W. Standard Time", "Australia/Eucla"),
+    ("Transbaikal Standard Time", "Asia/Chita"),
+    ("Tokyo Standard Time", "Asia/Tokyo"),
+    ("North Korea Standard Time", "Asia/Pyongyang"),
+    ("Korea Standard Time", "Asia/Seoul"),
+    ("Yakutsk Standard Time", "Asia/Yakutsk"),
+    ("Cen. Australia Standard Time", "Australia/Adelaide"),
+    ("AUS Central Standard Time", "Australia/Darwin"),
+    ("E. Australia Standard Time", "Australia/Brisbane"),
+    ("AUS Eastern Standard Time", "Australia/Sydney"),
+    ("West Pacific Standard Time", "Pacific/Port_Moresby"),
+    ("Tasmania Standard Time", "Australia/Hobart"),
+    ("Vladivostok Standard Time", "Asia/Vladivostok"),
+    ("Lord Howe Standard Time", "Australia/Lord_Howe"),
+    ("Bougainville Standard Time", "Pacific/Bougainville"),
+    ("Russia Time Zone 10", "Asia/Srednekolymsk"),
+    ("Magadan Standard Time", "Asia/Magadan"),
+    ("Norfolk Standard Time", "Pacific/Norfolk"),
+    ("Sakhalin Standard Time", "Asia/Sakhalin"),
+    ("Central Pacific Standard Time", "Pacific/Guadalcanal"),
+    ("Russia Time Zone 11", "Asia/Kamchatka"),
+    ("Kamchatka Standard Time", "Asia/Kamchatka"),
+    ("New Zealand Standard Time", "Pacific/Auckland"),
+    ("UTC+12", "Etc/GMT-12"),
+    ("Fiji Standard Time", "Pacific/Fiji"),
+    ("Chatham Islands Standard Time", "Pacific/Chatham"),
+    ("UTC+13", "Etc/GMT-13"),
+    ("Tonga Standard Time", "Pacific/Tongatapu"),
+    ("Samoa Standard Time", "Pacific/Apia"),
+    ("Line Islands Standard Time", "Pacific/Kiritimati"),
+];
+
+/// Get the IANA ID of a Windows time zone name (e.g. `"Eastern Standard Time"` -> `"America/New_York"`).
+pub fn windows_to_iana(name: &str) -> Option<&'static str> {
+    let name = name.trim().trim_matches('"');
+    WINDOWS_ZONES.iter()
+        .find(|(windows, _)| windows.eq_ignore_ascii_case(name))
+        .map(|(_, iana)| *iana)
+}
+
+/// Parse a `UTC-OFFSET` value (e.g. `"-0500"` or `"+053000"`) into seconds.
+pub fn parse_utc_offset(s: &str) -> Option<i32> {
+    let s = s.trim();
+    let (sign, digits) = match s.get(..1)? {
+        "+" => (1, &s[1..]),
+        "-" => (-1, &s[1..]),
+        _ => return None,
+    };
+    if !matches!(digits.len(), 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours = digits[0..2].parse::<i32>().ok()?;
+    let minutes = digits[2..4].parse::<i32>().ok()?;
+    let seconds = digits.get(4..6).map_or(Some(0), |seconds| seconds.parse::<i32>().ok())?;
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+/// Inverse of [`parse_utc_offset()`].
+pub fn format_utc_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    let (hours, minutes, seconds) = (offset / 3600, offset % 3600 / 60, offset % 60);
+    if seconds == 0 {
+        format!("{sign}{hours:02}{minutes:02}")
+    } else {
+        format!("{sign}{hours:02}{minutes:02}{seconds:02}")
+    }
+}
+
+/// A resolver that gives the same offset for all time zones, used to expand the rules of a `VTIMEZONE`.
+struct FixedOffset(i32);
+impl TimeZoneResolver for FixedOffset {
+    fn utc_offset(&mut self, _tzid: &str, _local: i64) -> Option<i32> {
+        Some(self.0)
+    }
+    fn default_tzid(&mut self) -> String {
+        String::new()
+    }
+}
+
+/// A `STANDARD` or `DAYLIGHT` sub-component of a `VTIMEZONE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Observance {
+    /// The first onset of the observance, in the local time before the onset (i.e. with **offset_from**).
+    start: DateTime,
+    offset_from: i32,
+    offset_to: i32,
+    rrule: Option<RRule>,
+    rdates: Vec<DateTime>,
+}
+impl Observance {
+    /// The last onset of the observance at or before the local time **time**.
+    fn last_onset(&self, time: DateTime) -> Option<DateTime> {
+        let mut last = self.rdates.iter()
+            .copied()
+            .filter(|&onset| onset <= time)
+            .max();
+        if self.start > time {
+            return last;
+        }
+        last = last.max(Some(self.start));
+
+        if let Some(rrule) = &self.rrule {
+            let start = CalTime::Floating(self.start);
+            let mut instances = rrule.instances(&start, &mut FixedOffset(self.offset_from)).ok()?;
+            instances.next();
+            // The rules of time zones are yearly, so there is always an onset in the year before
+            instances.skip_to(DateTime { date: Date { year: time.date.year - 1, month: 1, day: 1 }, time: Time::default() });
+            instances.stop_at(time);
+            last = last.max(instances.last());
+        }
+        last
+    }
+}
+
+/// The rules of a `VTIMEZONE` component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VTimeZone {
+    pub tzid: String,
+    observances: Vec<Observance>,
+}
+impl VTimeZone {
+    /// Read a `VTIMEZONE` component.
+    ///
+    /// Returns [`None`] if it has no `TZID` or no valid observances.
+    pub fn from_component(component: &Component) -> Option<Self> {
+        let observances = component.components
+            .iter()
+            .filter(|comp| comp.name.eq_ignore_ascii_case("STANDARD") || comp.name.eq_ignore_ascii_case("DAYLIGHT"))
+            .filter_map(|comp| Some(Observance {
+                start: comp.value("DTSTART").and_then(|value| CalTime::parse(value, None))?.local(),
+                offset_from: comp.value("TZOFFSETFROM").and_then(parse_utc_offset)?,
+                offset_to: comp.value("TZOFFSETTO").and_then(parse_utc_offset)?,
+                rrule: comp.value("RRULE").and_then(|rrule| RRule::parse(rrule).ok()),
+                rdates: comp.properties("RDATE")
+                    .flat_map(|prop| prop.value.split(','))
+                    .filter_map(|value| CalTime::parse(value, None))
+                    .map(|time| time.local())
+                    .collect(),
+            }))
+            .collect::<Vec<_>>();
+
+        if observances.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tzid: component.value("TZID")?.trim().to_string(),
+            observances,
+        })
+    }
+
+    /// Read all the `VTIMEZONE`s of some `VCALENDAR`s.
+    pub fn from_calendars<'a>(calendars: impl IntoIterator<Item = &'a Component>) -> Vec<Self> {
+        calendars.into_iter()
+            .flat_map(|calendar| calendar.components("VTIMEZONE"))
+            .filter_map(Self::from_component)
+            .collect()
+    }
+
+    /// The offset from UTC (in seconds) at the **local** time (see [`TimeZoneResolver::utc_offset()`]).
+    pub fn utc_offset(&self, local: i64) -> i32 {
+        let time = DateTime::from_timestamp(local);
+        self.observances.iter()
+            .filter_map(|observance| Some((observance.last_onset(time)?, observance.offset_to)))
+            .max_by_key(|(onset, _)| *onset)
+            .map(|(_, offset)| offset)
+            // Before the first onset, use the offset the time zone had before it
+            .unwrap_or_else(|| self.observances.iter()
+                .min_by_key(|observance| observance.start)
+                .map_or(0, |observance| observance.offset_from))
+    }
+}
+
+/// Resolves the `TZID`s of a calendar file, using the **system** time zones (which have IANA IDs),
+/// and the `VTIMEZONE`s **embedded** in the file for those that are not known by the system.
+pub struct TimeZones<'a> {
+    pub system: &'a mut dyn TimeZoneResolver,
+    pub embedded: &'a [VTimeZone],
+}
+impl TimeZones<'_> {
+    /// Find the IANA time zone that **tzid** refers to.
+    ///
+    /// Returns [`None`] if the **system** doesn't know the time zone by any of its names.
+    pub fn resolve(&mut self, tzid: &str) -> Option<String> {
+        let tzid = tzid.trim().trim_matches('"');
+        if self.system.utc_offset(tzid, 0).is_some() {
+            return Some(tzid.to_string());
+        }
+        if let Some(iana) = windows_to_iana(tzid) {
+            if self.system.utc_offset(iana, 0).is_some() {
+                return Some(iana.to_string());
+            }
+        }
+        // Remove the prefix some apps add to the IANA ID (e.g. "/mozilla.org/20050126_1/America/New_York")
+        let mut rest = tzid.trim_start_matches('/');
+        while let Some((_, suffix)) = rest.split_once('/') {
+            if self.system.utc_offset(suffix, 0).is_some() {
+                return Some(suffix.to_string());
+            }
+            rest = suffix;
+        }
+        None
+    }
+
+    /// Convert a **time** so that the Content Provider can use its time zone:
+    /// a `TZID` is replaced with the IANA time zone it refers to,
+    /// or the time is converted to UTC with the rules of the embedded `VTIMEZONE` if the system doesn't know the time zone.
+    ///
+    /// The time is unchanged if the time zone can't be found at all.
+    pub fn normalize(&mut self, time: &CalTime) -> CalTime {
+        let CalTime::Zoned { time: local, tzid } = time else {
+            return time.clone();
+        };
+        if let Some(iana) = self.resolve(tzid) {
+            return CalTime::Zoned { time: *local, tzid: iana };
+        }
+        match self.embedded.iter().find(|timezone| timezone.tzid == tzid.trim()) {
+            Some(timezone) => CalTime::Utc(DateTime::from_timestamp(local.timestamp() - timezone.utc_offset(local.timestamp()) as i64)),
+            None => time.clone(),
+        }
+    }
+
+    /// Same as [`Self::normalize()`], but for the values of a list of dates in the format of the Content Provider (see [`date_list()`]).
+    pub fn normalize_date_list(&mut self, list: &str) -> Option<String> {
+        let props = date_list_properties("RDATE", list)
+            .into_iter()
+            .map(|prop| {
+                let tzid = match prop.param("TZID") {
+                    Some(tzid) => tzid.to_string(),
+                    None => return prop,
+                };
+                let values = prop.value.split(',')
+                    .filter_map(|value| CalTime::parse(value, Some(&tzid)))
+                    .map(|time| self.normalize(&time))
+                    .collect::<Vec<_>>();
+                let mut normalized = Property::new("RDATE", values.iter().map(CalTime::value).collect::<Vec<_>>().join(","));
+                if let Some(tzid) = values.first().and_then(CalTime::tzid) {
+                    normalized = normalized.with_param("TZID", tzid);
+                }
+                normalized
+            })
+            .collect::<Vec<_>>();
+        date_list(props.iter())
+    }
+}
+impl TimeZoneResolver for TimeZones<'_> {
+    fn utc_offset(&mut self, tzid: &str, local: i64) -> Option<i32> {
+        if let Some(iana) = self.resolve(tzid) {
+            return self.system.utc_offset(&iana, local);
+        }
+        self.embedded.iter()
+            .find(|timezone| timezone.tzid == tzid.trim())
+            .map(|timezone| timezone.utc_offset(local))
+    }
+
+    fn default_tzid(&mut self) -> String {
+        self.system.default_tzid()
+    }
+}
+
+/// A transition of a time zone from one UTC offset to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transition {
+    /// The local time of the transition, before the transition happens (i.e. with **offset_from**).
+    time: DateTime,
+    offset_from: i32,
+    offset_to: i32,
+}
+impl Transition {
+    /// The ordinal of the weekday of the transition in its month, as used in `BYDAY` (`-1` if it is the last one of the month).
+    fn weekday_num(&self) -> WeekdayNum {
+        let date = self.time.date;
+        let ordinal = if date.day + 7 > days_in_month(date.year, date.month) {
+            -1
+        } else {
+            (date.day as i32 - 1) / 7 + 1
+        };
+        WeekdayNum { ordinal: Some(ordinal), weekday: date.weekday() }
+    }
+
+    /// Whether the transition happens on the same weekday of the month and at the same time as **other**.
+    fn same_rule(&self, other: &Self) -> bool {
+        self.time.date.month == other.time.date.month
+            && self.time.time == other.time.time
+            && self.weekday_num() == other.weekday_num()
+            && self.offset_from == other.offset_from
+            && self.offset_to == other.offset_to
+    }
+}
+
+/// Find the transitions of the time zone **tzid** during **year**.
+fn transitions(tzid: &str, year: i32, resolver: &mut dyn TimeZoneResolver) -> Option<Vec<Transition>> {
+    let first_day = Date { year, month: 1, day: 1 }.days_since_epoch();
+    let last_day = Date { year: year + 1, month: 1, day: 1 }.days_since_epoch();
+    let mut offset = resolver.utc_offset(tzid, first_day * 86400)?;
+    let mut transitions = Vec::new();
+
+    for day in first_day..last_day {
+        let end = (day + 1) * 86400;
+        let next_offset = resolver.utc_offset(tzid, end)?;
+        if next_offset == offset {
+            continue;
+        }
+        // Find the first local time with the new offset
+        let (mut low, mut high) = (day * 86400, end);
+        while high - low > 1 {
+            let middle = (low + high) / 2;
+            if resolver.utc_offset(tzid, middle)? == offset {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let utc = high - next_offset as i64;
+        transitions.push(Transition {
+            time: DateTime::from_timestamp(utc + offset as i64),
+            offset_from: offset,
+            offset_to: next_offset,
+        });
+        offset = next_offset;
+    }
+
+    Some(transitions)
+}
+
+/// Create a `VTIMEZONE` component for the IANA time zone **tzid** with the offsets the **resolver** gives during **year**.
+///
+/// If the transitions of the time zone happen on the same weekday of the month every year (e.g. the *second Sunday of March*),
+/// the observances have an `RRULE` that starts in 1970. Otherwise only the transitions of **year** are included.
+///
+/// Returns [`None`] if the **resolver** doesn't know the time zone.
+pub fn to_vtimezone(tzid: &str, year: i32, resolver: &mut dyn TimeZoneResolver) -> Option<Component> {
+    let mut component = Component::new("VTIMEZONE");
+    component.push(Property::new("TZID", tzid));
+
+    let this_year = transitions(tzid, year, resolver)?;
+    if this_year.is_empty() {
+        let offset = format_utc_offset(resolver.utc_offset(tzid, Date { year, month: 1, day: 1 }.days_since_epoch() * 86400)?);
+        let mut standard = Component::new("STANDARD");
+        standard.push(Property::new("DTSTART", "19700101T000000"));
+        standard.push(Property::new("TZOFFSETFROM", &offset));
+        standard.push(Property::new("TZOFFSETTO", offset));
+        component.components.push(standard);
+        return Some(component);
+    }
+
+    let next_year = transitions(tzid, year + 1, resolver)?;
+    let yearly = this_year.len() == next_year.len()
+        && this_year.iter().zip(&next_year).all(|(this, next)| this.same_rule(next));
+    // The observance with the largest offset is the daylight saving time
+    let max_offset = this_year.iter().map(|transition| transition.offset_to).max().unwrap_or_default();
+
+    for transition in &this_year {
+        let is_daylight = this_year.len() > 1 && transition.offset_to == max_offset;
+        let mut observance = Component::new(if is_daylight { "DAYLIGHT" } else { "STANDARD" });
+        let start = if yearly {
+            let weekday = transition.weekday_num();
+            let rule = RRule {
+                by_day: vec![weekday],
+                by_month: vec![transition.time.date.month],
+                ..RRule::new(Frequency::Yearly)
+            };
+            observance.push(Property::new("RRULE", rule.to_string()));
+            DateTime { date: nth_weekday(1970, transition.time.date.month, weekday), time: transition.time.time }
+        } else {
+            transition.time
+        };
+        observance.push(Property::new("DTSTART", start.to_string()));
+        observance.push(Property::new("TZOFFSETFROM", format_utc_offset(transition.offset_from)));
+        observance.push(Property::new("TZOFFSETTO", format_utc_offset(transition.offset_to)));
+        component.components.push(observance);
+    }
+
+    Some(component)
+}
+
+/// The date of the day of the week **weekday** in **month** of **year** (e.g. `2SU` is the second Sunday).
+fn nth_weekday(year: i32, month: u8, weekday: WeekdayNum) -> Date {
+    let first = Date { year, month, day: 1 };
+    let first_match = first.add_days((weekday.weekday as i64 - first.weekday() as i64).rem_euclid(7));
+    match weekday.ordinal.unwrap_or(1) {
+        ordinal if ordinal > 0 => first_match.add_days((ordinal as i64 - 1) * 7),
+        ordinal => {
+            let weeks = (days_in_month(year, month) - first_match.day) as i64 / 7;
+            first_match.add_days((weeks + 1 + ordinal as i64) * 7)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    /// A system that knows a few IANA time zones, with their current rules.
+    struct System;
+    impl TimeZoneResolver for System {
+        fn utc_offset(&mut self, tzid: &str, local: i64) -> Option<i32> {
+            let time = DateTime::from_timestamp(local);
+            let at = |month: u8, weekday: WeekdayNum, hour: u8| DateTime {
+                date: nth_weekday(time.date.year, month, weekday),
+                time: Time { hour, minute: 0, second: 0 },
+            };
+            let sunday = |ordinal: i32| WeekdayNum { ordinal: Some(ordinal), weekday: 6 };
+            // The local times that don't exist have the offset before the change, and those that happen twice have the offset after it
+            match tzid {
+                "America/New_York" => Some(if (at(3, sunday(2), 3)..at(11, sunday(1), 1)).contains(&time) { -4 * 3600 } else { -5 * 3600 }),
+                "Europe/Paris" => Some(if (at(3, sunday(-1), 3)..at(10, sunday(-1), 2)).contains(&time) { 2 * 3600 } else { 3600 }),
+                "Asia/Tokyo" => Some(9 * 3600),
+                _ => None,
+            }
+        }
+        fn default_tzid(&mut self) -> String {
+            "Europe/Paris".to_string()
+        }
+    }
+
+    fn local(time: &str) -> DateTime {
+        DateTime::parse(time).unwrap()
+    }
+
+    #[test]
+    fn resolve_tzids() {
+        let mut timezones = TimeZones { system: &mut System, embedded: &[] };
+        assert_eq!(timezones.resolve("America/New_York").as_deref(), Some("America/New_York"));
+        assert_eq!(timezones.resolve("\"Europe/Paris\"").as_deref(), Some("Europe/Paris"));
+        assert_eq!(timezones.resolve("Eastern Standard Time").as_deref(), Some("America/New_York"));
+        assert_eq!(timezones.resolve("romance standard time").as_deref(), Some("Europe/Paris"));
+        assert_eq!(timezones.resolve("/mozilla.org/20050126_1/America/New_York").as_deref(), Some("America/New_York"));
+        assert_eq!(timezones.resolve("/citadel.org/20190914_1/Asia/Tokyo").as_deref(), Some("Asia/Tokyo"));
+        // Known by Windows, but not by the system
+        assert_eq!(timezones.resolve("W. Europe Standard Time"), None);
+        assert_eq!(timezones.resolve("Custom"), None);
+    }
+
+    #[test]
+    fn utc_offsets() {
+        for (offset, s) in [(-5 * 3600, "-0500"), (5 * 3600 + 30 * 60, "+0530"), (0, "+0000"), (-(3600 + 30 * 60 + 15), "-013015")] {
+            assert_eq!(parse_utc_offset(s), Some(offset));
+            assert_eq!(format_utc_offset(offset), s);
+        }
+        assert_eq!(parse_utc_offset("0500"), None);
+        assert_eq!(parse_utc_offset("+05"), None);
+    }
+
+    /// A `VTIMEZONE` exported by *Outlook*, with a Windows name that the system doesn't know.
+    const OUTLOOK_VTIMEZONE: &str = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:W. Europe Standard Time\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+BEGIN:VTIMEZONE\r
+TZID:Custom\r
+BEGIN:STANDARD\r
+DTSTART:20200101T000000\r
+TZOFFSETFROM:+0300\r
+TZOFFSETTO:+0300\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:20240601T000000\r
+RDATE:20250601T000000\r
+TZOFFSETFROM:+0300\r
+TZOFFSETTO:+0400\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+DTSTART:20240901T000000\r
+RDATE:20250901T000000\r
+TZOFFSETFROM:+0400\r
+TZOFFSETTO:+0300\r
+END:STANDARD\r
+END:VTIMEZONE\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn embedded_vtimezones() {
+        let embedded = VTimeZone::from_calendars(&ical::parse(OUTLOOK_VTIMEZONE).unwrap());
+        assert_eq!(embedded.len(), 2);
+        let mut timezones = TimeZones { system: &mut System, embedded: &embedded };
+
+        let zoned = |time: &str, tzid: &str| CalTime::Zoned { time: local(time), tzid: tzid.to_string() };
+        // Converted to UTC with the rules of the VTIMEZONE
+        assert_eq!(timezones.normalize(&zoned("20240115T120000", "W. Europe Standard Time")), CalTime::Utc(local("20240115T110000")));
+        assert_eq!(timezones.normalize(&zoned("20240715T120000", "W. Europe Standard Time")), CalTime::Utc(local("20240715T100000")));
+        // The changes happen on the last Sunday of March and October
+        assert_eq!(timezones.utc_offset("W. Europe Standard Time", local("20240331T015959").timestamp()), Some(3600));
+        assert_eq!(timezones.utc_offset("W. Europe Standard Time", local("20240331T030000").timestamp()), Some(2 * 3600));
+        assert_eq!(timezones.utc_offset("W. Europe Standard Time", local("20241027T030000").timestamp()), Some(3600));
+        // Observances with RDATEs
+        assert_eq!(timezones.utc_offset("Custom", local("20230701T000000").timestamp()), Some(3 * 3600));
+        assert_eq!(timezones.utc_offset("Custom", local("20240701T000000").timestamp()), Some(4 * 3600));
+        assert_eq!(timezones.utc_offset("Custom", local("20241001T000000").timestamp()), Some(3 * 3600));
+        assert_eq!(timezones.utc_offset("Custom", local("20250701T000000").timestamp()), Some(4 * 3600));
+        // Before the first observance
+        assert_eq!(timezones.utc_offset("Custom", local("20000101T000000").timestamp()), Some(3 * 3600));
+
+        // Time zones that the system knows are kept, and those that are not known at all are left as they are
+        assert_eq!(timezones.normalize(&zoned("20240115T120000", "Eastern Standard Time")), zoned("20240115T120000", "America/New_York"));
+        assert_eq!(timezones.normalize(&zoned("20240115T120000", "Unknown")), zoned("20240115T120000", "Unknown"));
+        assert_eq!(timezones.utc_offset("Unknown", 0), None);
+    }
+
+    #[test]
+    fn vtimezone_round_trip() {
+        let component = to_vtimezone("Europe/Paris", 2024, &mut System).unwrap();
+        let observances = component.components.iter()
+            .map(|observance| (
+                observance.name.as_str(),
+                observance.value("DTSTART").unwrap(),
+                observance.value("RRULE").unwrap_or_default(),
+                observance.value("TZOFFSETTO").unwrap(),
+            ))
+            .collect::<Vec<_>>();
+        assert_eq!(observances, [
+            ("DAYLIGHT", "19700329T020000", "FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3", "+0200"),
+            ("STANDARD", "19701025T030000", "FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10", "+0100"),
+        ]);
+
+        // The VTIMEZONE gives the same offsets as the system (at noon, away from the ambiguous times of the changes)
+        for tzid in ["Europe/Paris", "America/New_York", "Asia/Tokyo"] {
+            let vtimezone = VTimeZone::from_component(&to_vtimezone(tzid, 2024, &mut System).unwrap()).unwrap();
+            assert_eq!(vtimezone.tzid, tzid);
+            let first_day = Date { year: 2023, month: 1, day: 1 }.days_since_epoch();
+            for day in first_day..first_day + 3 * 365 {
+                let noon = day * 86400 + 12 * 3600;
+                assert_eq!(Some(vtimezone.utc_offset(noon)), System.utc_offset(tzid, noon), "{tzid} on {}", DateTime::from_timestamp(noon));
+            }
+        }
+
+        // The time zone without changes only has a STANDARD observance
+        let tokyo = to_vtimezone("Asia/Tokyo", 2024, &mut System).unwrap();
+        assert_eq!(tokyo.components.len(), 1);
+        assert_eq!(tokyo.components[0].value("TZOFFSETTO"), Some("+0900"));
+        assert!(to_vtimezone("Unknown", 2024, &mut System).is_none());
+    }
+}
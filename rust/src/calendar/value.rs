@@ -0,0 +1,316 @@
+//! Typed *values* of [`Property`]s, which is how the *jCal* ([RFC 7265](https://datatracker.ietf.org/doc/html/rfc7265))
+//! and *xCal* ([RFC 6321](https://datatracker.ietf.org/doc/html/rfc6321)) formats represent them.
+//!
+//! Both formats write dates and times in the *ISO 8601* extended format (e.g. `2024-01-15T10:00:00Z` instead of `20240115T100000Z`),
+//! split lists into separate values, and give each part of a structured value (e.g. `RRULE`) its own name.
+use super::ical::{escape_text, split_list, unescape_text, Param, Property};
+
+/// The *value data types* of RFC 5545 section 3.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Binary,
+    Boolean,
+    CalAddress,
+    Date,
+    DateTime,
+    Duration,
+    Float,
+    Integer,
+    Period,
+    Recur,
+    Text,
+    Time,
+    Uri,
+    UtcOffset,
+    /// The type of properties that are not known (e.g. `X-` properties), whose value is kept as is.
+    Unknown,
+}
+impl ValueType {
+    /// Parse the name of a type, as used in the `VALUE` parameter (case insensitive).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "BINARY" => Some(Self::Binary),
+            "BOOLEAN" => Some(Self::Boolean),
+            "CAL-ADDRESS" => Some(Self::CalAddress),
+            "DATE" => Some(Self::Date),
+            "DATE-TIME" => Some(Self::DateTime),
+            "DURATION" => Some(Self::Duration),
+            "FLOAT" => Some(Self::Float),
+            "INTEGER" => Some(Self::Integer),
+            "PERIOD" => Some(Self::Period),
+            "RECUR" => Some(Self::Recur),
+            "TEXT" => Some(Self::Text),
+            "TIME" => Some(Self::Time),
+            "URI" => Some(Self::Uri),
+            "UTC-OFFSET" => Some(Self::UtcOffset),
+            "UNKNOWN" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+    /// The name of the type in lowercase, as used in *jCal* and *xCal*.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::Boolean => "boolean",
+            Self::CalAddress => "cal-address",
+            Self::Date => "date",
+            Self::DateTime => "date-time",
+            Self::Duration => "duration",
+            Self::Float => "float",
+            Self::Integer => "integer",
+            Self::Period => "period",
+            Self::Recur => "recur",
+            Self::Text => "text",
+            Self::Time => "time",
+            Self::Uri => "uri",
+            Self::UtcOffset => "utc-offset",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// The type of the property with **name** when it has no `VALUE` parameter.
+    pub fn default_of(name: &str) -> Self {
+        match name.to_ascii_uppercase().as_str() {
+            "DTSTART" | "DTEND" | "DUE" | "RECURRENCE-ID" | "EXDATE" | "RDATE"
+            | "DTSTAMP" | "CREATED" | "LAST-MODIFIED" | "COMPLETED" => Self::DateTime,
+            "DURATION" | "TRIGGER" => Self::Duration,
+            "RRULE" | "EXRULE" => Self::Recur,
+            "TZOFFSETFROM" | "TZOFFSETTO" => Self::UtcOffset,
+            "PRIORITY" | "SEQUENCE" | "PERCENT-COMPLETE" | "REPEAT" => Self::Integer,
+            "GEO" => Self::Float,
+            "FREEBUSY" => Self::Period,
+            "ATTENDEE" | "ORGANIZER" => Self::CalAddress,
+            "URL" | "TZURL" | "ATTACH" | "SOURCE" => Self::Uri,
+            "CALSCALE" | "METHOD" | "PRODID" | "VERSION" | "CATEGORIES" | "CLASS" | "COMMENT" | "DESCRIPTION"
+            | "LOCATION" | "RESOURCES" | "STATUS" | "SUMMARY" | "TRANSP" | "TZID" | "TZNAME" | "CONTACT"
+            | "RELATED-TO" | "UID" | "ACTION" | "REQUEST-STATUS" | "NAME" | "COLOR" | "REFRESH-INTERVAL"
+            | "X-WR-CALNAME" | "X-WR-CALDESC" | "X-WR-TIMEZONE" => Self::Text,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The type of the value of **prop**, which is in its `VALUE` parameter, or is the [default](Self::default_of()).
+    pub fn of(prop: &Property) -> Self {
+        prop.param("VALUE")
+            .and_then(Self::parse)
+            .unwrap_or_else(|| Self::default_of(&prop.name))
+    }
+}
+
+/// The type of the values of a parameter in *xCal*, which (unlike *jCal*) writes the type of parameters.
+pub fn param_type(name: &str) -> ValueType {
+    match name.to_ascii_uppercase().as_str() {
+        "DELEGATED-FROM" | "DELEGATED-TO" | "MEMBER" | "SENT-BY" => ValueType::CalAddress,
+        "ALTREP" | "DIR" => ValueType::Uri,
+        _ => ValueType::Text,
+    }
+}
+
+/// A single value of a property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A value of any type that is written as a string.
+    /// Dates and times are in the *ISO 8601* extended format, and text has no escape sequences.
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// The start and end (or duration) of a *PERIOD*.
+    Period(String, String),
+    /// The parts of a *RECUR* value, with their names in lowercase (e.g. `("byday", [Text("MO"), Text("WE")])`).
+    Recur(Vec<(String, Vec<Value>)>),
+    /// The components of a structured value (`GEO` or `REQUEST-STATUS`), which are separated by `;` in *iCalendar*.
+    Structured(Vec<Value>),
+}
+
+/// Properties whose *TEXT* value is a list.
+fn is_text_list(name: &str) -> bool {
+    name.eq_ignore_ascii_case("CATEGORIES") || name.eq_ignore_ascii_case("RESOURCES")
+}
+/// Parts of a *RECUR* value whose values are integers.
+fn is_integer_part(name: &str) -> bool {
+    matches!(name, "count" | "interval" | "bysecond" | "byminute" | "byhour" | "bymonthday" | "byyearday" | "byweekno" | "bymonth" | "bysetpos")
+}
+
+/// Split the value of **prop** into typed values.
+///
+/// Values that don't match their type are returned as a single [`Text`](Value::Text) of [`ValueType::Unknown`],
+/// so that they are kept as they were.
+pub fn values(prop: &Property) -> (ValueType, Vec<Value>) {
+    let value_type = ValueType::of(prop);
+    let unknown = || (ValueType::Unknown, vec![Value::Text(prop.value.clone())]);
+    let values = match value_type {
+        ValueType::Text if prop.name.eq_ignore_ascii_case("REQUEST-STATUS") => vec![Value::Structured(
+            split_structured(&prop.value).into_iter().map(|part| Value::Text(unescape_text(part))).collect()
+        )],
+        ValueType::Text if is_text_list(&prop.name) => split_list(&prop.value).into_iter()
+            .map(|value| Value::Text(unescape_text(value)))
+            .collect(),
+        ValueType::Text => vec![Value::Text(unescape_text(&prop.value))],
+        ValueType::Integer => match prop.value.trim().parse() {
+            Ok(value) => vec![Value::Integer(value)],
+            Err(_) => return unknown(),
+        },
+        ValueType::Float if prop.name.eq_ignore_ascii_case("GEO") => {
+            match prop.value.split_once(';').and_then(|(lat, lon)| Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?))) {
+                Some((lat, lon)) => vec![Value::Structured(vec![Value::Float(lat), Value::Float(lon)])],
+                None => return unknown(),
+            }
+        },
+        ValueType::Float => match prop.value.trim().parse() {
+            Ok(value) => vec![Value::Float(value)],
+            Err(_) => return unknown(),
+        },
+        ValueType::Boolean => match prop.value.trim().to_ascii_uppercase().as_str() {
+            "TRUE" => vec![Value::Boolean(true)],
+            "FALSE" => vec![Value::Boolean(false)],
+            _ => return unknown(),
+        },
+        ValueType::Date | ValueType::DateTime | ValueType::Time | ValueType::UtcOffset => prop.value.split(',')
+            .map(|value| Value::Text(to_iso(value.trim(), value_type)))
+            .collect(),
+        ValueType::Period => {
+            let mut periods = Vec::new();
+            for period in prop.value.split(',') {
+                let Some((start, end)) = period.trim().split_once('/') else { return unknown() };
+                periods.push(Value::Period(to_iso(start, ValueType::DateTime), to_iso(end, ValueType::DateTime)));
+            }
+            periods
+        },
+        ValueType::Recur => vec![Value::Recur(
+            prop.value.trim().split(';')
+                .filter(|part| !part.is_empty())
+                .map(|part| {
+                    let (name, value) = part.split_once('=').unwrap_or((part, ""));
+                    let name = name.trim().to_ascii_lowercase();
+                    let values = value.split(',')
+                        .map(|value| match value.trim().parse() {
+                            Ok(value) if is_integer_part(&name) => Value::Integer(value),
+                            _ if name == "until" => Value::Text(to_iso(value.trim(), ValueType::DateTime)),
+                            _ => Value::Text(value.trim().to_string()),
+                        })
+                        .collect();
+                    (name, values)
+                })
+                .collect()
+        )],
+        ValueType::Binary | ValueType::CalAddress | ValueType::Duration | ValueType::Uri | ValueType::Unknown =>
+            vec![Value::Text(prop.value.clone())],
+    };
+    (value_type, values)
+}
+
+/// Build a property with **name** and **params** from **values** of **value_type** (the inverse of [`values()`]).
+///
+/// The `VALUE` parameter is added if **value_type** is not the [default](ValueType::default_of()) type of the property.
+pub fn to_property(name: &str, params: Vec<Param>, value_type: ValueType, values: &[Value]) -> Property {
+    let separator = match value_type {
+        ValueType::Recur => ";",
+        _ => ",",
+    };
+    let mut property = Property::new(name, values.iter()
+        .map(|value| to_ical(value, value_type))
+        .collect::<Vec<_>>()
+        .join(separator));
+    property.params = params;
+    property.remove_param("VALUE");
+    if value_type != ValueType::default_of(name) && value_type != ValueType::Unknown {
+        property.set_param("VALUE", value_type.as_str().to_ascii_uppercase());
+    }
+    property
+}
+
+/// Convert a single typed **value** to the *iCalendar* format.
+fn to_ical(value: &Value, value_type: ValueType) -> String {
+    match value {
+        Value::Text(text) => match value_type {
+            ValueType::Text => escape_text(text),
+            ValueType::Date | ValueType::DateTime | ValueType::Time | ValueType::UtcOffset => from_iso(text, value_type),
+            _ => text.clone(),
+        },
+        Value::Integer(integer) => integer.to_string(),
+        Value::Float(float) => float.to_string(),
+        Value::Boolean(boolean) => if *boolean { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Period(start, end) => format!("{}/{}", from_iso(start, ValueType::DateTime), from_iso(end, ValueType::DateTime)),
+        Value::Recur(parts) => parts.iter()
+            .map(|(name, values)| {
+                let values = values.iter()
+                    .map(|value| match value {
+                        Value::Text(text) if name == "until" => from_iso(text, ValueType::DateTime),
+                        value => to_ical(value, ValueType::Unknown),
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}={}", name.to_ascii_uppercase(), values.join(","))
+            })
+            .collect::<Vec<_>>()
+            .join(";"),
+        Value::Structured(parts) => parts.iter()
+            .map(|part| to_ical(part, value_type))
+            .collect::<Vec<_>>()
+            .join(";"),
+    }
+}
+
+/// Split a structured value at the semicolons that are not escaped.
+fn split_structured(value: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ';' => {
+                result.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&value[start..]);
+    result
+}
+
+/// Convert a date, time or UTC offset to the *ISO 8601* extended format (e.g. `19970714T133000Z` to `1997-07-14T13:30:00Z`).
+///
+/// Values that are not in the expected format (e.g. the *DURATION* at the end of a period) are returned as they are.
+fn to_iso(value: &str, value_type: ValueType) -> String {
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|byte| byte.is_ascii_digit());
+    let time = |time: &str| -> Option<String> {
+        let (digits, utc) = time.strip_suffix(['Z', 'z']).map_or((time, ""), |digits| (digits, "Z"));
+        (digits.len() == 6 && is_digits(digits))
+            .then(|| format!("{}:{}:{}{utc}", &digits[..2], &digits[2..4], &digits[4..]))
+    };
+    let date = |date: &str| -> Option<String> {
+        (date.len() == 8 && is_digits(date))
+            .then(|| format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+    };
+
+    let result = match value_type {
+        ValueType::Date => date(value),
+        ValueType::DateTime => value.split_once(['T', 't'])
+            .and_then(|(date_part, time_part)| Some(format!("{}T{}", date(date_part)?, time(time_part)?)))
+            // The UNTIL of a RECUR can be a DATE
+            .or_else(|| date(value)),
+        ValueType::Time => time(value),
+        ValueType::UtcOffset => {
+            let (sign, digits) = value.split_at(value.len().min(1));
+            (matches!(sign, "+" | "-") && is_digits(digits) && matches!(digits.len(), 4 | 6)).then(|| {
+                let seconds = digits.get(4..).filter(|seconds| !seconds.is_empty()).map(|seconds| format!(":{seconds}")).unwrap_or_default();
+                format!("{sign}{}:{}{seconds}", &digits[..2], &digits[2..4])
+            })
+        },
+        _ => None,
+    };
+    result.unwrap_or_else(|| value.to_string())
+}
+/// Inverse of [`to_iso()`].
+fn from_iso(value: &str, value_type: ValueType) -> String {
+    match value_type {
+        // Durations (the end of a period can be one) don't have dashes or colons, except for a negative sign
+        ValueType::Date | ValueType::DateTime if !value.starts_with(['P', '+', '-']) => value.replace(['-', ':'], ""),
+        ValueType::Time | ValueType::UtcOffset => value.replace(':', ""),
+        _ => value.to_string(),
+    }
+}
+
@@ -0,0 +1,392 @@
+//! Conversion between [`Component`]s and *xCal*, the XML format for *iCalendar* ([RFC 6321](https://datatracker.ietf.org/doc/html/rfc6321)).
+//!
+//! A component is an element with a `properties` and a `components` element,
+//! and a property is an element with a `parameters` element and an element for each value, named after the type of the value.
+use quick_xml::{escape::escape, events::Event, name::ResolveResult, NsReader};
+use super::{
+    ical::{Component, Param, Property},
+    value::{self, param_type, Value, ValueType},
+};
+
+/// The namespace of all *xCal* elements.
+pub const XCAL: &str = "urn:ietf:params:xml:ns:icalendar-2.0";
+
+/// Parse the contents of an *xCal* file, whose root `icalendar` element has the `vcalendar`s.
+///
+/// Elements that are not in the [`XCAL`] namespace are ignored.
+pub fn parse(content: &str) -> Result<Vec<Component>, String> {
+    let root = parse_tree(content)?;
+    if root.name != "icalendar" {
+        return Err(format!("Expected root element icalendar, found {}", root.name));
+    }
+    root.children.iter().map(component_from_element).collect()
+}
+
+/// Write **calendars** as *xCal*.
+pub fn write(calendars: &[Component]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(&format!(r#"<icalendar xmlns="{XCAL}">"#));
+    for calendar in calendars {
+        write_component(&mut xml, calendar);
+    }
+    xml.push_str("</icalendar>");
+    xml
+}
+
+fn write_component(xml: &mut String, component: &Component) {
+    let name = component.name.to_ascii_lowercase();
+    xml.push_str(&format!("<{name}><properties>"));
+    for prop in &component.properties {
+        write_property(xml, prop);
+    }
+    xml.push_str("</properties>");
+    // Like in the examples of RFC 6321, components without subcomponents have no components element
+    if !component.components.is_empty() {
+        xml.push_str("<components>");
+        for subcomponent in &component.components {
+            write_component(xml, subcomponent);
+        }
+        xml.push_str("</components>");
+    }
+    xml.push_str(&format!("</{name}>"));
+}
+
+fn write_property(xml: &mut String, prop: &Property) {
+    let name = prop.name.to_ascii_lowercase();
+    xml.push_str(&format!("<{name}>"));
+
+    let params = prop.params.iter().filter(|param| !param.name.eq_ignore_ascii_case("VALUE")).collect::<Vec<_>>();
+    if !params.is_empty() {
+        xml.push_str("<parameters>");
+        for param in params {
+            let param_name = param.name.to_ascii_lowercase();
+            let param_type = param_type(&param.name).as_str();
+            xml.push_str(&format!("<{param_name}>"));
+            for value in &param.values {
+                xml.push_str(&format!("<{param_type}>{}</{param_type}>", escape(value)));
+            }
+            xml.push_str(&format!("</{param_name}>"));
+        }
+        xml.push_str("</parameters>");
+    }
+
+    let (value_type, values) = value::values(prop);
+    let value_type = value_type.as_str();
+    for value in values {
+        match value {
+            Value::Structured(parts) => {
+                let names: &[&str] = if prop.name.eq_ignore_ascii_case("GEO") {
+                    &["latitude", "longitude"]
+                } else {
+                    &["code", "description", "data"]
+                };
+                for (name, part) in names.iter().zip(parts) {
+                    xml.push_str(&format!("<{name}>{}</{name}>", text(&part)));
+                }
+            },
+            Value::Period(start, end) => {
+                let end_name = if end.starts_with(['P', '+', '-']) { "duration" } else { "end" };
+                xml.push_str(&format!("<period><start>{}</start><{end_name}>{}</{end_name}></period>", escape(&start), escape(&end)));
+            },
+            Value::Recur(parts) => {
+                xml.push_str("<recur>");
+                for (name, values) in parts {
+                    for value in values {
+                        xml.push_str(&format!("<{name}>{}</{name}>", text(&value)));
+                    }
+                }
+                xml.push_str("</recur>");
+            },
+            value => xml.push_str(&format!("<{value_type}>{}</{value_type}>", text(&value))),
+        }
+    }
+
+    xml.push_str(&format!("</{name}>"));
+}
+
+/// The text of an element that has a single (non-structured) value.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Text(text) => escape(text).into_owned(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Float(float) => float.to_string(),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Period(..) | Value::Recur(_) | Value::Structured(_) => String::new(),
+    }
+}
+
+/// An *xCal* element, with its name (without namespace) and the text it contains.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    text: String,
+    children: Vec<Element>,
+}
+impl Element {
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Parse the XML **content** into a tree of the elements in the [`XCAL`] namespace, and return the root.
+fn parse_tree(content: &str) -> Result<Element, String> {
+    let mut reader = NsReader::from_str(content);
+    // The elements that are currently open. An element in another namespace is None.
+    let mut stack = Vec::<Option<Element>>::new();
+
+    loop {
+        let (namespace, event) = reader.read_resolved_event()
+            .map_err(|err| format!("Invalid XML: {err}"))?;
+        let is_xcal = matches!(namespace, ResolveResult::Bound(namespace) if namespace.as_ref() == XCAL.as_bytes());
+
+        let element = match event {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                let element = is_xcal.then(|| Element {
+                    name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+                    ..Default::default()
+                });
+                if matches!(event, Event::Start(_)) {
+                    stack.push(element);
+                    continue;
+                }
+                element
+            },
+            Event::End(_) => stack.pop().flatten(),
+            Event::Text(ref t) => {
+                if let Some(Some(element)) = stack.last_mut() {
+                    element.text.push_str(&t.unescape().map_err(|err| format!("Invalid XML text: {err}"))?);
+                }
+                continue;
+            },
+            Event::CData(ref t) => {
+                if let Some(Some(element)) = stack.last_mut() {
+                    element.text.push_str(&String::from_utf8_lossy(t.as_ref()));
+                }
+                continue;
+            },
+            Event::Eof => return Err("Reached end of file without an icalendar element".to_string()),
+            _ => continue,
+        };
+
+        // The element ended
+        match stack.last_mut() {
+            Some(Some(parent)) => parent.children.extend(element),
+            // The children of elements in other namespaces are ignored
+            Some(None) => {},
+            None => match element {
+                Some(root) => return Ok(root),
+                None => continue,
+            },
+        }
+    }
+}
+
+fn component_from_element(element: &Element) -> Result<Component, String> {
+    let mut component = Component::new(&element.name);
+    for prop in element.child("properties").map(|properties| properties.children.as_slice()).unwrap_or_default() {
+        component.push(property_from_element(prop));
+    }
+    for subcomponent in element.child("components").map(|components| components.children.as_slice()).unwrap_or_default() {
+        component.components.push(component_from_element(subcomponent)?);
+    }
+    Ok(component)
+}
+
+fn property_from_element(element: &Element) -> Property {
+    let params = element.child("parameters")
+        .map(|params| params.children.iter()
+            .map(|param| Param {
+                name: param.name.to_ascii_uppercase(),
+                values: param.children.iter().map(|value| value.text.clone()).collect(),
+            })
+            .collect())
+        .unwrap_or_default();
+    let value_elements = element.children.iter().filter(|child| child.name != "parameters").collect::<Vec<_>>();
+
+    let (value_type, values) = if let (Some(latitude), Some(longitude)) = (element.child("latitude"), element.child("longitude")) {
+        (ValueType::Float, vec![Value::Structured(vec![number(&latitude.text), number(&longitude.text)])])
+    } else if element.child("code").is_some() {
+        let parts = ["code", "description", "data"].into_iter()
+            .filter_map(|name| element.child(name))
+            .map(|part| Value::Text(part.text.clone()))
+            .collect();
+        (ValueType::Text, vec![Value::Structured(parts)])
+    } else {
+        let value_type = value_elements.first()
+            .map(|value| ValueType::parse(&value.name).unwrap_or(ValueType::Unknown))
+            .unwrap_or(ValueType::Unknown);
+        (value_type, value_elements.iter().map(|value| value_from_element(value, value_type)).collect())
+    };
+
+    value::to_property(&element.name, params, value_type, &values)
+}
+
+fn value_from_element(element: &Element, value_type: ValueType) -> Value {
+    match value_type {
+        ValueType::Integer | ValueType::Float => number(&element.text),
+        ValueType::Boolean => Value::Boolean(element.text.trim().eq_ignore_ascii_case("true")),
+        ValueType::Period => Value::Period(
+            element.child("start").map(|start| start.text.clone()).unwrap_or_default(),
+            element.child("end").or_else(|| element.child("duration")).map(|end| end.text.clone()).unwrap_or_default(),
+        ),
+        ValueType::Recur => {
+            let mut parts = Vec::<(String, Vec<Value>)>::new();
+            for part in &element.children {
+                let value = Value::Text(part.text.trim().to_string());
+                match parts.iter_mut().find(|(name, _)| *name == part.name) {
+                    Some((_, values)) => values.push(value),
+                    None => parts.push((part.name.clone(), vec![value])),
+                }
+            }
+            Value::Recur(parts)
+        },
+        _ => Value::Text(element.text.clone()),
+    }
+}
+
+/// Parse a number, which is kept as text if it is not valid.
+fn number(text: &str) -> Value {
+    let text = text.trim();
+    text.parse().map(Value::Integer)
+        .or_else(|_| text.parse().map(Value::Float))
+        .unwrap_or_else(|_| Value::Text(text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical::{self, examples};
+
+    /// [`examples::SIMPLE`] in RFC 6321.
+    const SIMPLE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<icalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0">
+ <vcalendar>
+  <properties>
+   <calscale><text>GREGORIAN</text></calscale>
+   <prodid><text>-//Example Inc.//Example Calendar//EN</text></prodid>
+   <version><text>2.0</text></version>
+  </properties>
+  <components>
+   <vevent>
+    <properties>
+     <dtstamp><date-time>2008-02-05T19:12:24Z</date-time></dtstamp>
+     <dtstart><date>2008-10-06</date></dtstart>
+     <summary><text>Planning meeting</text></summary>
+     <uid><text>4088E990AD89CB3DBB484909</text></uid>
+    </properties>
+   </vevent>
+  </components>
+ </vcalendar>
+</icalendar>"#;
+
+    /// [`examples::RECURRING`] in RFC 6321.
+    const RECURRING: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<icalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0">
+ <vcalendar>
+  <properties>
+   <version><text>2.0</text></version>
+   <prodid><text>-//Example Corp.//Example Client//EN</text></prodid>
+  </properties>
+  <components>
+   <vtimezone>
+    <properties>
+     <last-modified><date-time>2004-01-10T03:28:45Z</date-time></last-modified>
+     <tzid><text>US/Eastern</text></tzid>
+    </properties>
+    <components>
+     <daylight>
+      <properties>
+       <dtstart><date-time>2000-04-04T02:00:00</date-time></dtstart>
+       <rrule><recur><freq>YEARLY</freq><byday>1SU</byday><bymonth>4</bymonth></recur></rrule>
+       <tzname><text>EDT</text></tzname>
+       <tzoffsetfrom><utc-offset>-05:00</utc-offset></tzoffsetfrom>
+       <tzoffsetto><utc-offset>-04:00</utc-offset></tzoffsetto>
+      </properties>
+     </daylight>
+     <standard>
+      <properties>
+       <dtstart><date-time>2000-10-26T02:00:00</date-time></dtstart>
+       <rrule><recur><freq>YEARLY</freq><byday>-1SU</byday><bymonth>10</bymonth></recur></rrule>
+       <tzname><text>EST</text></tzname>
+       <tzoffsetfrom><utc-offset>-04:00</utc-offset></tzoffsetfrom>
+       <tzoffsetto><utc-offset>-05:00</utc-offset></tzoffsetto>
+      </properties>
+     </standard>
+    </components>
+   </vtimezone>
+   <vevent>
+    <properties>
+     <dtstamp><date-time>2006-02-06T00:11:21Z</date-time></dtstamp>
+     <dtstart>
+      <parameters><tzid><text>US/Eastern</text></tzid></parameters>
+      <date-time>2006-01-02T12:00:00</date-time>
+     </dtstart>
+     <duration><duration>PT1H</duration></duration>
+     <rrule><recur><freq>DAILY</freq><count>5</count></recur></rrule>
+     <rdate>
+      <parameters><tzid><text>US/Eastern</text></tzid></parameters>
+      <period><start>2006-01-02T15:00:00</start><duration>PT2H</duration></period>
+     </rdate>
+     <summary><text>Event #2</text></summary>
+     <description><text>We are having a meeting all this week at 12pm for one hour, with an additional meeting on the first day 2 hours long.
+Please bring your own lunch for the 12 pm meetings.</text></description>
+     <uid><text>00959BC664CA650E933C892C@example.com</text></uid>
+    </properties>
+   </vevent>
+   <vevent>
+    <properties>
+     <dtstamp><date-time>2006-02-06T00:11:21Z</date-time></dtstamp>
+     <dtstart>
+      <parameters><tzid><text>US/Eastern</text></tzid></parameters>
+      <date-time>2006-01-04T14:00:00</date-time>
+     </dtstart>
+     <duration><duration>PT1H</duration></duration>
+     <recurrence-id>
+      <parameters><tzid><text>US/Eastern</text></tzid></parameters>
+      <date-time>2006-01-04T12:00:00</date-time>
+     </recurrence-id>
+     <summary><text>Event #2 bis</text></summary>
+     <uid><text>00959BC664CA650E933C892C@example.com</text></uid>
+    </properties>
+   </vevent>
+  </components>
+ </vcalendar>
+</icalendar>"#;
+
+    #[test]
+    fn rfc_examples() {
+        for (xcal, ics) in [(SIMPLE, examples::SIMPLE), (RECURRING, examples::RECURRING)] {
+            let calendars = ical::parse(ics).unwrap();
+            assert_eq!(parse(xcal).unwrap(), calendars);
+            // The examples are indented, but are otherwise the same
+            let unindented = xcal.lines()
+                .map(str::trim_start)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .replace(">\n", ">")
+                .replace("\n<", "<");
+            assert_eq!(write(&calendars), unindented);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for ics in [examples::SIMPLE, examples::RECURRING] {
+            let calendars = ical::parse(ics).unwrap();
+            let written = parse(&write(&calendars)).unwrap();
+            assert_eq!(written, calendars);
+            assert_eq!(written.iter().map(ToString::to_string).collect::<String>(), ics);
+        }
+    }
+
+    #[test]
+    fn ignore_other_namespaces() {
+        let xcal = SIMPLE.replace(
+            "<calscale>",
+            r#"<x:extra xmlns:x="urn:example"><x:inner><text>Ignored</text></x:inner></x:extra><calscale>"#,
+        );
+        assert_eq!(parse(&xcal).unwrap(), ical::parse(examples::SIMPLE).unwrap());
+        assert!(parse(r#"<vcalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0"/>"#).is_err());
+        assert!(parse("<icalendar").is_err());
+    }
+}
@@ -105,6 +105,12 @@ impl<'local> DocUri<'local> {
         Ok(unsafe { std::fs::File::from_raw_fd(call!(fd.detachFd() -> int)) })
     }
 
+    /// Get the *MIME type* of the file for this Uri, as reported by its Content Provider.
+    pub fn mime_type(&self, env: &mut JNIEnv, context: &JObject) -> Option<String> {
+        let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+        call!(content_resolver.getType(android.net.Uri(self.0)) -> Option<String>)
+    }
+
     /// Get the name of the file or directory for this Uri.
     /// Similar to [Path::file_name()].
     pub fn file_name(&self, env: &mut JNIEnv) -> String {
@@ -154,8 +160,11 @@ impl<'local> DocUri<'local> {
     /// 
     /// [`Uri`]: DocUri
     pub fn parent(&self, env: &mut JNIEnv<'local>) -> Self {
-        println!("Uri: \"{}\"", self.to_string(env));
-        todo!("Call me.marti.calprovexample.jni.DavSyncRsHelpers.docUriParent()");
+        Self(
+            call!(static me.marti.calprovexample.jni.DavSyncRsHelpers.docUriParent(
+                android.net.Uri(self.0)
+            ) -> android.net.Uri)
+        )
     }
 
     /// Give the Document pointed at by this [`DocUri`] different name.
@@ -166,14 +175,13 @@ impl<'local> DocUri<'local> {
     /// Returns the [`DocUri`] with the new name.
     pub fn rename(&self, context: &JObject, new_name: &str, env: &mut JNIEnv<'local>) -> io::Result<Self> {
         let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
-        // The suposed Uri of the Document under the new name
-        let expected_new_uri = self.parent(env).join(env, new_name);
+        let parent = ExternalDir::new(env.new_local_ref(context).unwrap(), self.parent(env), env)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Parent of Document is not a directory"))?;
 
         // Check if file with new_name exists
-        call!(static android.provider.DocumentsContract.isDocumentUri(
-            android.content.Context(context),
-            android.net.Uri(expected_new_uri.as_ref())
-        ) -> bool);
+        if parent.file_exists(env, new_name) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("A Document named \"{new_name}\" already exists")));
+        }
 
         // Rename Document
         let new_uri = call!(static android.provider.DocumentsContract.renameDocument(
@@ -183,22 +191,39 @@ impl<'local> DocUri<'local> {
         ) -> Result<Option<android.net.Uri>, String/*FileNotFoundException*/>)
             .map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?
             .ok_or_else(|| io::Error::other("Failed to open file because ContentProvider crashed"))?;
-        let new_uri = Self::from_doc_uri(env, new_uri)
+        // The Uri is in the same Document Tree as this one
+        let new_uri = Self::from_tree_uri(env, new_uri)
             .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
 
-        // Check that we got the expected Uri after rename
-        let new_uri_str = new_uri.to_string(env);
-        let expected_new_uri_str = expected_new_uri.to_string(env);
-        if new_uri_str != expected_new_uri_str {
-            return Err(io::Error::other(format!("Expected Uri does not match actual Uri after rename:\n\
-                Expected Uri: \"{expected_new_uri_str}\"\n\
-                Actual Uri: \"{new_uri_str}\""
+        // Check that the Document got the expected name after rename (the Provider could have picked a different one)
+        let actual_name = new_uri.file_name(env);
+        if actual_name != new_name {
+            return Err(io::Error::other(format!(
+                "Document was renamed to \"{actual_name}\" instead of \"{new_name}\""
             )))
         }
 
         Ok(new_uri)
     }
 
+    /// Delete the Document pointed at by this [`DocUri`].
+    ///
+    /// Returns [`io::ErrorKind::NotFound`] if the Document doesn't exist.
+    pub fn delete(&self, context: &JObject, env: &mut JNIEnv<'local>) -> io::Result<()> {
+        let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+        let deleted = call!(static android.provider.DocumentsContract.deleteDocument(
+            android.content.ContentResolver(content_resolver),
+            android.net.Uri(self.0)
+        ) -> Result<bool, String/*FileNotFoundException*/>)
+            .map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+
+        if deleted {
+            Ok(())
+        } else {
+            Err(io::Error::other("Failed to delete Document"))
+        }
+    }
+
     pub fn to_string(&self, env: &mut JNIEnv) -> String {
         call!((self.0).toString() -> String)
     }
@@ -368,6 +393,66 @@ impl<'local> ExternalDir<'local> {
         }
     }
 
+    /// Write **content** to a file that is a descendant of this directory in the file tree,
+    /// replacing its contents if it exists, or creating it (and its parent directories) if it doesn't.
+    ///
+    /// Similar to [`std::fs::write()`].
+    ///
+    /// The **path** must be a relative path; an absolute path will cause an error.
+    pub fn write_file_at(&self, env: &mut JNIEnv<'local>, path: impl AsRef<Path>, content: &[u8]) -> io::Result<()> {
+        use std::io::Write as _;
+
+        let path = path.as_ref();
+        let mut file = match self.create_file_at(env, path) {
+            Ok(doc_uri) => doc_uri.open_file(env, &self.context, OpenOptions::write())?,
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => self.open_file(
+                env,
+                path,
+                OpenOptions::WriteOnly { extra: Some(ExtraMode::Truncate) },
+            )?,
+            Err(error) => return Err(error),
+        };
+
+        file.write_all(content)
+    }
+
+    /// Delete a **file** that is a descendant of this directory in the file tree.
+    ///
+    /// Similar to [`std::fs::remove_file()`].
+    ///
+    /// The **path** must be a relative path; an absolute path will cause an error.
+    pub fn remove_file_at(&self, env: &mut JNIEnv<'local>, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Path argument must be a relative path; provided absolute path",
+            ));
+        }
+
+        self.doc_uri
+            .join(env, path)
+            .delete(&self.context, env)
+    }
+
+    /// Give a **file** that is a descendant of this directory in the file tree a **new_name** (with *file extension*).
+    /// See [DocUri::rename()].
+    ///
+    /// The **path** must be a relative path; an absolute path will cause an error.
+    pub fn rename_file_at(&self, env: &mut JNIEnv<'local>, path: impl AsRef<Path>, new_name: &str) -> io::Result<DocUri<'local>> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Path argument must be a relative path; provided absolute path",
+            ));
+        }
+
+        self.doc_uri
+            .join(env, path)
+            .rename(&self.context, new_name, env)
+    }
+
     /// Create a **file** in this directory.
     /// Returns [io::ErrorKind::AlreadyExists] if a file with this name already exists.
     pub fn create_file(&self, env: &mut JNIEnv<'local>, file_name: &str) -> io::Result<DocUri<'local>> {
@@ -549,7 +634,7 @@ impl<'local> ExternalDirEntry<'local> {
     }
 
     /// Get the *path-like* object for this entry.
-    pub fn uri(&self) -> &DocUri {
+    pub fn uri(&self) -> &DocUri<'local> {
         &self.doc_uri
     }
 }
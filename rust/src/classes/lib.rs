@@ -1,11 +1,17 @@
 pub mod fs;
 
-use ez_jni::call;
+use ez_jni::{call, new};
 use jni::{JNIEnv, objects::JObject};
 
 // Wrapper class for `android.database.Cursor`
 pub struct Cursor<'local>(JObject<'local>);
 impl<'local> Cursor<'local> {
+    pub const FIELD_TYPE_NULL: i32 = 0;
+    pub const FIELD_TYPE_INTEGER: i32 = 1;
+    pub const FIELD_TYPE_FLOAT: i32 = 2;
+    pub const FIELD_TYPE_STRING: i32 = 3;
+    pub const FIELD_TYPE_BLOB: i32 = 4;
+
     pub fn new(cursor: JObject<'local>) -> Self {
         Self(cursor)
     }
@@ -60,6 +66,17 @@ impl<'local> Cursor<'local> {
     pub fn get_int(&self, env: &mut JNIEnv, index: u32) -> i32 {
         call!((self.0).getInt(int(index as i32)) -> int)
     }
+    pub fn get_long(&self, env: &mut JNIEnv, index: u32) -> i64 {
+        call!((self.0).getLong(int(index as i32)) -> long)
+    }
+    pub fn is_null(&self, env: &mut JNIEnv, index: u32) -> bool {
+        call!((self.0).isNull(int(index as i32)) -> bool)
+    }
+    /// Get the type of the value of a column in the current row.
+    /// Returns one of the `Cursor.FIELD_TYPE_*` constants.
+    pub fn get_type(&self, env: &mut JNIEnv, index: u32) -> i32 {
+        call!((self.0).getType(int(index as i32)) -> int)
+    }
 
     pub fn close(self, env: &mut JNIEnv) {
         call!((self.0).close() -> void)
@@ -70,3 +87,27 @@ impl<'local> AsRef<JObject<'local>> for Cursor<'local> {
         &self.0
     }
 }
+
+// Wrapper class for `android.content.ContentValues`
+pub struct ContentValues<'local>(JObject<'local>);
+impl<'local> ContentValues<'local> {
+    pub fn new(env: &mut JNIEnv<'local>) -> Self {
+        Self(new!(android.content.ContentValues()))
+    }
+
+    pub fn put_string(&self, env: &mut JNIEnv<'local>, key: &str, value: &str) {
+        call!((self.0).put(String(key), String(value)) -> void)
+    }
+    pub fn put_long(&self, env: &mut JNIEnv<'local>, key: &str, value: i64) {
+        let value = call!(static java.lang.Long.valueOf(long(value)) -> java.lang.Long);
+        call!((self.0).put(String(key), java.lang.Long(value)) -> void)
+    }
+    pub fn put_null(&self, env: &mut JNIEnv<'local>, key: &str) {
+        call!((self.0).putNull(String(key)) -> void)
+    }
+}
+impl<'local> AsRef<JObject<'local>> for ContentValues<'local> {
+    fn as_ref(&self) -> &JObject<'local> {
+        &self.0
+    }
+}
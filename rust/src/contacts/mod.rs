@@ -0,0 +1,196 @@
+//! Reading and writing *contacts files* (vCards) and the Contacts *Content Provider* (`ContactsContract`).
+mod projections;
+pub mod vcard;
+
+use std::path::PathBuf;
+use ez_jni::{jni_fn, println};
+use jni::{JNIEnv, objects::JObject};
+use crate::{calendar::ical::{self, Component}, error::{Error, OrThrow as _}, file_type::FileType, get_app_dir, provider::{self, Row, ACCOUNT_TYPE_LOCAL}, utils::{check_permission, open_external_dir}};
+use projections::{data, raw_contacts};
+use vcard::{Contact, Version};
+
+/// The Android permissions needed to access the Contacts *Content Provider*.
+const READ_CONTACTS: &str = "android.permission.READ_CONTACTS";
+pub const WRITE_CONTACTS: &str = "android.permission.WRITE_CONTACTS";
+
+jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
+    /// Read a *Contacts file* (`.vcf`) and write its contacts to the Contacts *Content Provider*.
+    ///
+    /// The contacts are owned by this App's account, and replace the contacts that were previously written from the file with **name**.
+    pub fn write_file_data_to_contacts<'local>(
+        context: android.content.Context,
+        name: String,
+    ) {
+        write_file_data_to_contacts(env, &context, &name)
+            .or_throw(env)
+    }
+
+    /// Read the contacts that came from the *Contacts file* with **name** from the *Content Provider*
+    /// and write them to the file in the **internal** directory, and in the **external** directory if **external_dir_uri** is not `NULL`.
+    pub fn write_contacts_data_to_file<'local>(
+        context: android.content.Context,
+        name: String,
+        external_dir_uri: Option<android.net.Uri>,
+    ) {
+        write_contacts_data_to_file(env, &context, &name, external_dir_uri)
+            .or_throw(env)
+    }
+}
+
+/// Read the *Contacts file* with **name** and write its contacts to the Contacts *Content Provider*.
+pub fn write_file_data_to_contacts<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+) -> Result<(), Error> {
+    check_permission(env, context, WRITE_CONTACTS)?;
+    let file_path = get_app_dir(env, context).join(FileType::Contacts.dir()).join(format!("{name}.vcf"));
+    let content = std::fs::read_to_string(&file_path)
+        .map_err(|err| Error::io(format!("Error reading contacts file {file_path:?}"), err))?;
+
+    write_contacts_to_provider(env, context, name, &content)
+}
+
+/// Build the *Contacts file* with **name** from the contacts in the *Content Provider*
+/// and write it to the **internal** directory, and to the **external** directory if **external_dir_uri** is [`Some`].
+fn write_contacts_data_to_file<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    check_permission(env, context, READ_CONTACTS)?;
+    let file_name = format!("{name}.vcf");
+    let file_path = get_app_dir(env, context).join(FileType::Contacts.dir()).join(&file_name);
+    // Keep the vCard version each contact had in the file
+    let old_cards = std::fs::read_to_string(&file_path)
+        .ok()
+        .and_then(|content| ical::parse(&content).ok())
+        .unwrap_or_default();
+
+    let content = read_contacts_from_provider(env, context, name, &old_cards)?
+        .iter()
+        .map(Component::to_string)
+        .collect::<String>();
+
+    std::fs::write(&file_path, &content)
+        .map_err(|err| Error::io("Error writing to file in internal directory", err))?;
+    if let Some(external_dir_uri) = external_dir_uri {
+        open_external_dir(env, context, external_dir_uri)?
+            .write_file_at(env, PathBuf::from(FileType::Contacts.dir()).join(&file_name), content.as_bytes())
+            .map_err(|err| Error::io("Error writing to file in external directory", err))?;
+    }
+
+    Ok(())
+}
+
+/// Parse the **content** of a contacts file and insert its contacts as *raw contacts* of this App's account,
+/// replacing all the contacts that came from the file with **name** before.
+///
+/// Contacts that can't be read (e.g. have no name) are skipped.
+fn write_contacts_to_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    content: &str,
+) -> Result<(), Error> {
+    let cards = ical::parse(content).map_err(|err| Error::Parse(err.to_string()))?;
+    let account_name = provider::account_name(env, context);
+
+    // Remove the old contacts
+    delete_contacts(env, context, name)?;
+
+    for card in cards.iter().filter(|comp| comp.name == "VCARD") {
+        let contact = match Contact::from_component(card) {
+            Ok(contact) => contact,
+            Err(err) => {
+                println!("Skipping contact: {err}");
+                continue;
+            }
+        };
+
+        let mut row = Row::new();
+        row.put(raw_contacts::ACCOUNT_NAME, account_name.as_str());
+        row.put(raw_contacts::ACCOUNT_TYPE, ACCOUNT_TYPE_LOCAL);
+        row.put(raw_contacts::SOURCE_ID, contact.uid.as_deref());
+        row.put(raw_contacts::SYNC1, name);
+        let raw_contact_id = provider::insert(env, context, raw_contacts::CONTENT_URI, &row)?;
+
+        for mut row in contact.to_data_rows() {
+            row.put(data::RAW_CONTACT_ID, raw_contact_id);
+            provider::insert(env, context, data::CONTENT_URI, &row)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete the raw contacts of this App's account that came from the *Contacts file* with **name** (the Data rows are deleted with them).
+pub fn delete_contacts(env: &mut JNIEnv, context: &JObject, name: &str) -> Result<(), Error> {
+    let account_name = provider::account_name(env, context);
+    provider::delete(env, context,
+        raw_contacts::CONTENT_URI,
+        &format!(
+            "({} = ?) AND ({} = ?) AND ({} = ?)",
+            raw_contacts::ACCOUNT_NAME, raw_contacts::ACCOUNT_TYPE, raw_contacts::SYNC1
+        ),
+        &[&account_name, ACCOUNT_TYPE_LOCAL, name]
+    )?;
+    Ok(())
+}
+
+/// Read the raw contacts of this App's account that came from the file with **name** and build a `VCARD` for each one.
+///
+/// The contacts are written in the version of the card with the same `UID` in **old_cards**, or in [`Version::V4`].
+fn read_contacts_from_provider<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    name: &str,
+    old_cards: &[Component],
+) -> Result<Vec<Component>, Error> {
+    let account_name = provider::account_name(env, context);
+    let raw_contact_rows = provider::query(env, context,
+        raw_contacts::CONTENT_URI,
+        &raw_contacts::COPY_PROJECTION,
+        &format!(
+            "({} = ?) AND ({} = ?) AND ({} = ?) AND ({} = 0)",
+            raw_contacts::ACCOUNT_NAME, raw_contacts::ACCOUNT_TYPE, raw_contacts::SYNC1, raw_contacts::DELETED
+        ),
+        &[&account_name, ACCOUNT_TYPE_LOCAL, name]
+    )?;
+
+    let mut cards = Vec::new();
+    for row in raw_contact_rows {
+        let raw_contact_id = row
+            .get_long(raw_contacts::ID)
+            .ok_or_else(|| Error::Parse("Raw contact row has no ID".to_string()))?
+            .to_string();
+        let data_rows = provider::query(env, context,
+            data::CONTENT_URI,
+            &data::COPY_PROJECTION,
+            &format!("({} = ?)", data::RAW_CONTACT_ID),
+            &[&raw_contact_id]
+        )?;
+        let Some(mut contact) = Contact::from_data_rows(&data_rows) else {
+            println!("Skipping raw contact {raw_contact_id}: it has no name");
+            continue;
+        };
+
+        // Contacts written from a vCard without UID have no SOURCE_ID, but the UID is needed to match them when syncing
+        let uid = row.get_text(raw_contacts::SOURCE_ID)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{raw_contact_id}@davsync"));
+        contact.version = old_cards.iter()
+            .find(|card| card.value("UID") == Some(uid.as_str()))
+            .map(|card| match card.value("VERSION") {
+                Some("4.0") => Version::V4,
+                _ => Version::V3,
+            })
+            .unwrap_or_default();
+        contact.uid = Some(uid);
+
+        cards.push(contact.to_component());
+    }
+
+    Ok(cards)
+}
@@ -0,0 +1,121 @@
+//! Names of the columns of the Contacts *Content Provider* tables, and the constant values stored in them.
+//!
+//! These mirror the constants of `android.provider.ContactsContract`.
+
+pub mod raw_contacts {
+    pub const CONTENT_URI: &str = "content://com.android.contacts/raw_contacts";
+    pub const ID: &str = "_id";
+    pub const ACCOUNT_NAME: &str = "account_name";
+    pub const ACCOUNT_TYPE: &str = "account_type";
+    /// Used to store the `UID` of the vCard.
+    pub const SOURCE_ID: &str = "sourceid";
+    /// Used to store the name of the contacts file (without extension) the contact came from.
+    pub const SYNC1: &str = "sync1";
+    /// Set by the Content Provider when the user deletes a contact, so that the sync adapter can delete it in the server.
+    pub const DELETED: &str = "deleted";
+
+    /// The columns that are read to write a contact to a file.
+    pub const COPY_PROJECTION: [&str; 2] = [ID, SOURCE_ID];
+}
+
+/// The `Data` table, where each row is a piece of data of a raw contact (e.g. a phone number).
+///
+/// The meaning of the `data*` columns depends on the **mimetype** of the row, which are in the sub-modules.
+pub mod data {
+    pub const CONTENT_URI: &str = "content://com.android.contacts/data";
+    pub const RAW_CONTACT_ID: &str = "raw_contact_id";
+    pub const MIMETYPE: &str = "mimetype";
+    pub const IS_PRIMARY: &str = "is_primary";
+
+    /// The `TYPE` column of the kinds of data that have it.
+    pub const TYPE: &str = "data2";
+
+    /// The columns that are read to write a contact to a file (all the `data*` columns used by the sub-modules).
+    pub const COPY_PROJECTION: [&str; 12] = [
+        MIMETYPE, IS_PRIMARY,
+        "data1", "data2", "data3", "data4", "data5", "data6", "data7", "data8", "data9", "data10",
+    ];
+
+    pub mod structured_name {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/name";
+        pub const DISPLAY_NAME: &str = "data1";
+        pub const GIVEN_NAME: &str = "data2";
+        pub const FAMILY_NAME: &str = "data3";
+        pub const PREFIX: &str = "data4";
+        pub const MIDDLE_NAME: &str = "data5";
+        pub const SUFFIX: &str = "data6";
+    }
+
+    pub mod nickname {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/nickname";
+        pub const NAME: &str = "data1";
+    }
+
+    pub mod phone {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/phone_v2";
+        pub const NUMBER: &str = "data1";
+
+        pub const TYPE_HOME: i64 = 1;
+        pub const TYPE_MOBILE: i64 = 2;
+        pub const TYPE_WORK: i64 = 3;
+        pub const TYPE_FAX_WORK: i64 = 4;
+        pub const TYPE_FAX_HOME: i64 = 5;
+        pub const TYPE_PAGER: i64 = 6;
+        pub const TYPE_OTHER: i64 = 7;
+        pub const TYPE_MAIN: i64 = 12;
+    }
+
+    pub mod email {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/email_v2";
+        pub const ADDRESS: &str = "data1";
+
+        pub const TYPE_HOME: i64 = 1;
+        pub const TYPE_WORK: i64 = 2;
+        pub const TYPE_OTHER: i64 = 3;
+        pub const TYPE_MOBILE: i64 = 4;
+    }
+
+    pub mod structured_postal {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/postal-address_v2";
+        pub const FORMATTED_ADDRESS: &str = "data1";
+        pub const STREET: &str = "data4";
+        pub const POBOX: &str = "data5";
+        pub const NEIGHBORHOOD: &str = "data6";
+        pub const CITY: &str = "data7";
+        pub const REGION: &str = "data8";
+        pub const POSTCODE: &str = "data9";
+        pub const COUNTRY: &str = "data10";
+
+        pub const TYPE_HOME: i64 = 1;
+        pub const TYPE_WORK: i64 = 2;
+        pub const TYPE_OTHER: i64 = 3;
+    }
+
+    pub mod organization {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/organization";
+        pub const COMPANY: &str = "data1";
+        pub const TITLE: &str = "data4";
+        pub const DEPARTMENT: &str = "data5";
+
+        pub const TYPE_WORK: i64 = 1;
+    }
+
+    pub mod website {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/website";
+        pub const URL: &str = "data1";
+
+        pub const TYPE_OTHER: i64 = 7;
+    }
+
+    pub mod event {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/contact_event";
+        pub const START_DATE: &str = "data1";
+
+        pub const TYPE_BIRTHDAY: i64 = 3;
+    }
+
+    pub mod note {
+        pub const MIMETYPE: &str = "vnd.android.cursor.item/note";
+        pub const NOTE: &str = "data1";
+    }
+}
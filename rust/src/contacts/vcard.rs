@@ -0,0 +1,704 @@
+//! Typed representation of a `VCARD` ([RFC 6350](https://datatracker.ietf.org/doc/html/rfc6350) and [RFC 2426](https://datatracker.ietf.org/doc/html/rfc2426))
+//! and its conversion to and from rows of the *Data* table.
+//!
+//! vCards have the same *content lines* as iCalendar, so they are parsed into [`Component`]s with [`ical::parse()`](crate::calendar::ical::parse).
+use std::fmt::{self, Display};
+use crate::{calendar::ical::{escape_text, split_list, unescape_text, Component, Param, Property}, provider::Row};
+use super::projections::data::{self, email, event, nickname, note, organization, phone, structured_name, structured_postal, website};
+
+/// The vCard versions that can be read and written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Version {
+    /// vCard 3.0 (RFC 2426). vCard 2.1 files are also read as this version.
+    V3,
+    /// vCard 4.0 (RFC 6350).
+    #[default]
+    V4,
+}
+impl Version {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V3 => "3.0",
+            Self::V4 => "4.0",
+        }
+    }
+}
+
+/// The parts of the `N` property.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Name {
+    pub family: String,
+    pub given: String,
+    /// Middle names.
+    pub additional: String,
+    pub prefix: String,
+    pub suffix: String,
+}
+
+/// A value that can be of multiple types, like a `TEL` or an `EMAIL`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Typed<T> {
+    pub value: T,
+    /// The values of the `TYPE` parameter (lowercase, without `pref`).
+    pub types: Vec<String>,
+    /// Whether this is the preferred value (`TYPE=pref` in vCard 3.0, `PREF=1` in vCard 4.0).
+    pub pref: bool,
+}
+impl<T> Typed<T> {
+    pub fn has_type(&self, ty: &str) -> bool {
+        self.types.iter().any(|t| t == ty)
+    }
+}
+
+/// The parts of the `ADR` property.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Address {
+    pub po_box: String,
+    pub extended: String,
+    pub street: String,
+    pub locality: String,
+    pub region: String,
+    pub postal_code: String,
+    pub country: String,
+}
+impl Address {
+    /// The address as it would be written on an envelope.
+    pub fn formatted(&self) -> String {
+        let city_line = [&self.locality, &self.region, &self.postal_code]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        [&self.po_box, &self.extended, &self.street, &city_line, &self.country]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The data of a `VCARD` that can be stored in the Contacts Content Provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contact {
+    pub version: Version,
+    pub uid: Option<String>,
+    /// The `FN` property, which is the name that is displayed.
+    pub formatted_name: String,
+    pub name: Option<Name>,
+    pub nickname: Option<String>,
+    pub organization: Option<String>,
+    pub department: Option<String>,
+    pub title: Option<String>,
+    pub phones: Vec<Typed<String>>,
+    pub emails: Vec<Typed<String>>,
+    pub addresses: Vec<Typed<Address>>,
+    pub urls: Vec<String>,
+    /// In the format `YYYY-MM-DD`, or `--MM-DD` if the year is not known.
+    pub birthday: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The vCard has neither `FN` nor `N`.
+    MissingName,
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingName => write!(f, "vCard has no FN or N property"),
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+
+impl Contact {
+    pub fn from_component(card: &Component) -> Result<Self, ParseError> {
+        let version = match card.value("VERSION").map(str::trim) {
+            Some("4.0") => Version::V4,
+            _ => Version::V3,
+        };
+        let prop = |name: &'static str| properties(card, name).next();
+        let text = |name: &'static str| prop(name)
+            .map(Property::text_value)
+            .filter(|value| !value.is_empty());
+
+        let name = prop("N").map(|prop| {
+            let mut parts = split_structured(&prop.value).into_iter();
+            let mut next = || parts.next().map(|part| join_list(&part)).unwrap_or_default();
+            Name {
+                family: next(),
+                given: next(),
+                additional: next(),
+                prefix: next(),
+                suffix: next(),
+            }
+        });
+        let formatted_name = match text("FN") {
+            Some(formatted_name) => formatted_name,
+            None => name.as_ref()
+                .map(|name| [&name.prefix, &name.given, &name.additional, &name.family, &name.suffix]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                )
+                .filter(|name| !name.is_empty())
+                .ok_or(ParseError::MissingName)?,
+        };
+        let (organization, department) = match prop("ORG") {
+            Some(prop) => {
+                let parts = split_structured(&prop.value);
+                let mut parts = parts.iter().filter(|part| !part.is_empty());
+                (parts.next().cloned(), parts.next().cloned())
+            },
+            None => (None, None),
+        };
+
+        Ok(Self {
+            version,
+            uid: prop("UID").map(|prop| prop.value.trim().to_string()),
+            formatted_name,
+            name,
+            nickname: prop("NICKNAME")
+                .map(|prop| join_list(&prop.value))
+                .filter(|nickname| !nickname.is_empty()),
+            organization,
+            department,
+            title: text("TITLE"),
+            phones: properties(card, "TEL")
+                .map(|prop| typed(prop, {
+                    let value = prop.text_value();
+                    // vCard 4.0 can have phone numbers as URIs
+                    value.strip_prefix("tel:").map(str::to_string).unwrap_or(value)
+                }))
+                .collect(),
+            emails: properties(card, "EMAIL")
+                .map(|prop| typed(prop, prop.text_value()))
+                .collect(),
+            addresses: properties(card, "ADR")
+                .map(|prop| {
+                    let mut parts = split_structured(&prop.value).into_iter();
+                    let mut next = || parts.next().unwrap_or_default();
+                    typed(prop, Address {
+                        po_box: next(),
+                        extended: next(),
+                        street: next(),
+                        locality: next(),
+                        region: next(),
+                        postal_code: next(),
+                        country: next(),
+                    })
+                })
+                .collect(),
+            urls: properties(card, "URL")
+                .map(|prop| prop.text_value())
+                .collect(),
+            birthday: prop("BDAY").and_then(|prop| parse_birthday(&prop.value)),
+            note: text("NOTE"),
+        })
+    }
+
+    /// Build a `VCARD` component of this contact in the contact's [`Version`].
+    pub fn to_component(&self) -> Component {
+        let v4 = self.version == Version::V4;
+        let mut card = Component::new("VCARD");
+        card.push(Property::new("VERSION", self.version.as_str()));
+        if let Some(uid) = &self.uid {
+            card.push(Property::new("UID", uid));
+        }
+        card.push(Property::text("FN", &self.formatted_name));
+        // N is required in vCard 3.0
+        if self.name.is_some() || !v4 {
+            let name = self.name.clone().unwrap_or_default();
+            card.push(structured("N", [&name.family, &name.given, &name.additional, &name.prefix, &name.suffix]));
+        }
+        if let Some(nickname) = &self.nickname {
+            card.push(Property::text("NICKNAME", nickname));
+        }
+        if let Some(organization) = &self.organization {
+            let mut parts = vec![organization];
+            parts.extend(&self.department);
+            card.push(structured("ORG", parts));
+        }
+        if let Some(title) = &self.title {
+            card.push(Property::text("TITLE", title));
+        }
+        for phone in &self.phones {
+            card.push(with_types(Property::text("TEL", &phone.value), phone, self.version));
+        }
+        for email in &self.emails {
+            card.push(with_types(Property::text("EMAIL", &email.value), email, self.version));
+        }
+        for address in &self.addresses {
+            let adr = &address.value;
+            card.push(with_types(
+                structured("ADR", [&adr.po_box, &adr.extended, &adr.street, &adr.locality, &adr.region, &adr.postal_code, &adr.country]),
+                address,
+                self.version
+            ));
+        }
+        for url in &self.urls {
+            card.push(Property::new("URL", url));
+        }
+        if let Some(birthday) = &self.birthday {
+            // vCard 4.0 uses the basic format (without '-' separators)
+            let value = match birthday.strip_prefix("--") {
+                Some(month_day) if v4 => format!("--{}", month_day.replace('-', "")),
+                None if v4 => birthday.replace('-', ""),
+                _ => birthday.clone(),
+            };
+            card.push(Property::new("BDAY", value));
+        }
+        if let Some(note) = &self.note {
+            card.push(Property::text("NOTE", note));
+        }
+        card
+    }
+}
+
+impl Contact {
+    /// Convert the contact to rows of the *Data* table (without the `raw_contact_id`).
+    pub fn to_data_rows(&self) -> Vec<Row> {
+        fn new_row<'a>(rows: &'a mut Vec<Row>, mimetype: &'static str) -> &'a mut Row {
+            let mut row = Row::new();
+            row.put(data::MIMETYPE, mimetype);
+            rows.push(row);
+            rows.last_mut().unwrap()
+        }
+        let mut rows = Vec::new();
+
+        let row = new_row(&mut rows, structured_name::MIMETYPE);
+        row.put(structured_name::DISPLAY_NAME, self.formatted_name.as_str());
+        if let Some(name) = &self.name {
+            for (column, value) in [
+                (structured_name::GIVEN_NAME, &name.given),
+                (structured_name::FAMILY_NAME, &name.family),
+                (structured_name::MIDDLE_NAME, &name.additional),
+                (structured_name::PREFIX, &name.prefix),
+                (structured_name::SUFFIX, &name.suffix),
+            ] {
+                row.put(column, non_empty(value));
+            }
+        }
+
+        if let Some(nickname) = &self.nickname {
+            new_row(&mut rows, nickname::MIMETYPE).put(nickname::NAME, nickname.as_str());
+        }
+        if self.organization.is_some() || self.title.is_some() {
+            let row = new_row(&mut rows, organization::MIMETYPE);
+            row.put(data::TYPE, organization::TYPE_WORK);
+            row.put(organization::COMPANY, self.organization.as_deref());
+            row.put(organization::DEPARTMENT, self.department.as_deref());
+            row.put(organization::TITLE, self.title.as_deref());
+        }
+        for phone in &self.phones {
+            let row = new_row(&mut rows, phone::MIMETYPE);
+            row.put(phone::NUMBER, phone.value.as_str());
+            row.put(data::TYPE, phone_type(phone));
+            row.put(data::IS_PRIMARY, phone.pref);
+        }
+        for email in &self.emails {
+            let row = new_row(&mut rows, email::MIMETYPE);
+            row.put(email::ADDRESS, email.value.as_str());
+            row.put(data::TYPE, match () {
+                _ if email.has_type("home") => email::TYPE_HOME,
+                _ if email.has_type("work") => email::TYPE_WORK,
+                _ if email.has_type("cell") => email::TYPE_MOBILE,
+                _ => email::TYPE_OTHER,
+            });
+            row.put(data::IS_PRIMARY, email.pref);
+        }
+        for address in &self.addresses {
+            let adr = &address.value;
+            let row = new_row(&mut rows, structured_postal::MIMETYPE);
+            row.put(structured_postal::FORMATTED_ADDRESS, adr.formatted());
+            for (column, value) in [
+                (structured_postal::POBOX, &adr.po_box),
+                (structured_postal::NEIGHBORHOOD, &adr.extended),
+                (structured_postal::STREET, &adr.street),
+                (structured_postal::CITY, &adr.locality),
+                (structured_postal::REGION, &adr.region),
+                (structured_postal::POSTCODE, &adr.postal_code),
+                (structured_postal::COUNTRY, &adr.country),
+            ] {
+                row.put(column, non_empty(value));
+            }
+            row.put(data::TYPE, match () {
+                _ if address.has_type("home") => structured_postal::TYPE_HOME,
+                _ if address.has_type("work") => structured_postal::TYPE_WORK,
+                _ => structured_postal::TYPE_OTHER,
+            });
+            row.put(data::IS_PRIMARY, address.pref);
+        }
+        for url in &self.urls {
+            let row = new_row(&mut rows, website::MIMETYPE);
+            row.put(website::URL, url.as_str());
+            row.put(data::TYPE, website::TYPE_OTHER);
+        }
+        if let Some(birthday) = &self.birthday {
+            let row = new_row(&mut rows, event::MIMETYPE);
+            row.put(event::START_DATE, birthday.as_str());
+            row.put(data::TYPE, event::TYPE_BIRTHDAY);
+        }
+        if let Some(note) = &self.note {
+            new_row(&mut rows, note::MIMETYPE).put(note::NOTE, note.as_str());
+        }
+
+        rows
+    }
+
+    /// Build a contact from the rows of the *Data* table of a raw contact.
+    ///
+    /// Returns [`None`] if the contact has no name.
+    pub fn from_data_rows(rows: &[Row]) -> Option<Self> {
+        let mut contact = Self::default();
+        let text = |row: &Row, column: &str| row.get_text(column)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+        let pref = |row: &Row| row.get_long(data::IS_PRIMARY).is_some_and(|primary| primary != 0);
+        let ty = |row: &Row| row.get_long(data::TYPE);
+
+        for row in rows {
+            match row.get_text(data::MIMETYPE).unwrap_or_default() {
+                structured_name::MIMETYPE => {
+                    contact.formatted_name = text(row, structured_name::DISPLAY_NAME).unwrap_or_default();
+                    let part = |column| text(row, column).unwrap_or_default();
+                    let name = Name {
+                        family: part(structured_name::FAMILY_NAME),
+                        given: part(structured_name::GIVEN_NAME),
+                        additional: part(structured_name::MIDDLE_NAME),
+                        prefix: part(structured_name::PREFIX),
+                        suffix: part(structured_name::SUFFIX),
+                    };
+                    contact.name = Some(name).filter(|name| *name != Name::default());
+                },
+                nickname::MIMETYPE => contact.nickname = text(row, nickname::NAME),
+                organization::MIMETYPE => {
+                    contact.organization = text(row, organization::COMPANY);
+                    contact.department = text(row, organization::DEPARTMENT);
+                    contact.title = text(row, organization::TITLE);
+                },
+                phone::MIMETYPE => if let Some(number) = text(row, phone::NUMBER) {
+                    let types: &[&str] = match ty(row) {
+                        Some(phone::TYPE_HOME) => &["home"],
+                        Some(phone::TYPE_MOBILE) => &["cell"],
+                        Some(phone::TYPE_WORK) => &["work"],
+                        Some(phone::TYPE_FAX_WORK) => &["fax", "work"],
+                        Some(phone::TYPE_FAX_HOME) => &["fax", "home"],
+                        Some(phone::TYPE_PAGER) => &["pager"],
+                        Some(phone::TYPE_MAIN) => &["main"],
+                        _ => &[],
+                    };
+                    contact.phones.push(Typed { value: number, types: to_types(types), pref: pref(row) });
+                },
+                email::MIMETYPE => if let Some(address) = text(row, email::ADDRESS) {
+                    let types: &[&str] = match ty(row) {
+                        Some(email::TYPE_HOME) => &["home"],
+                        Some(email::TYPE_WORK) => &["work"],
+                        Some(email::TYPE_MOBILE) => &["cell"],
+                        _ => &[],
+                    };
+                    contact.emails.push(Typed { value: address, types: to_types(types), pref: pref(row) });
+                },
+                structured_postal::MIMETYPE => {
+                    let part = |column| text(row, column).unwrap_or_default();
+                    let mut address = Address {
+                        po_box: part(structured_postal::POBOX),
+                        extended: part(structured_postal::NEIGHBORHOOD),
+                        street: part(structured_postal::STREET),
+                        locality: part(structured_postal::CITY),
+                        region: part(structured_postal::REGION),
+                        postal_code: part(structured_postal::POSTCODE),
+                        country: part(structured_postal::COUNTRY),
+                    };
+                    // Apps that only set the formatted address
+                    if address == Address::default() {
+                        address.street = part(structured_postal::FORMATTED_ADDRESS);
+                    }
+                    let types: &[&str] = match ty(row) {
+                        Some(structured_postal::TYPE_HOME) => &["home"],
+                        Some(structured_postal::TYPE_WORK) => &["work"],
+                        _ => &[],
+                    };
+                    contact.addresses.push(Typed { value: address, types: to_types(types), pref: pref(row) });
+                },
+                website::MIMETYPE => contact.urls.extend(text(row, website::URL)),
+                event::MIMETYPE if ty(row) == Some(event::TYPE_BIRTHDAY) => {
+                    contact.birthday = row.get_text(event::START_DATE).and_then(parse_birthday);
+                },
+                note::MIMETYPE => contact.note = text(row, note::NOTE),
+                _ => {},
+            }
+        }
+
+        if contact.formatted_name.is_empty() {
+            return None;
+        }
+        Some(contact)
+    }
+}
+
+fn to_types(types: &[&str]) -> Vec<String> {
+    types.iter().map(|ty| ty.to_string()).collect()
+}
+
+fn phone_type(phone: &Typed<String>) -> i64 {
+    match () {
+        _ if phone.has_type("fax") && phone.has_type("home") => phone::TYPE_FAX_HOME,
+        _ if phone.has_type("fax") => phone::TYPE_FAX_WORK,
+        _ if phone.has_type("cell") => phone::TYPE_MOBILE,
+        _ if phone.has_type("pager") => phone::TYPE_PAGER,
+        _ if phone.has_type("home") => phone::TYPE_HOME,
+        _ if phone.has_type("work") => phone::TYPE_WORK,
+        _ if phone.has_type("main") => phone::TYPE_MAIN,
+        _ => phone::TYPE_OTHER,
+    }
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// Get the properties with **name**, ignoring their *group* (e.g. `item1.EMAIL` is an `EMAIL`).
+fn properties<'a>(card: &'a Component, name: &'a str) -> impl Iterator<Item = &'a Property> + 'a {
+    card.properties.iter().filter(move |prop| {
+        let prop_name = prop.name.rsplit_once('.').map_or(prop.name.as_str(), |(_, name)| name);
+        prop_name.eq_ignore_ascii_case(name)
+    })
+}
+
+/// Create a [`Typed`] value from the `TYPE` and `PREF` parameters of **prop**.
+fn typed<T>(prop: &Property, value: T) -> Typed<T> {
+    let mut types = prop.params.iter()
+        .filter(|param| param.name == "TYPE")
+        .flat_map(|param| param.values.iter())
+        // vCard 4.0 can have multiple types in a quoted value
+        .flat_map(|value| value.split(','))
+        .map(|ty| ty.trim().to_ascii_lowercase())
+        .filter(|ty| !ty.is_empty())
+        .collect::<Vec<_>>();
+    let pref = types.iter().any(|ty| ty == "pref") || prop.param("PREF").is_some_and(|pref| pref.trim() == "1");
+    types.retain(|ty| ty != "pref");
+    Typed { value, types, pref }
+}
+
+/// Add the `TYPE` (and `PREF`) parameters of **typed** to **prop** in the format of the **version**.
+fn with_types<T>(mut prop: Property, typed: &Typed<T>, version: Version) -> Property {
+    match version {
+        Version::V3 => {
+            let mut types = typed.types.iter().map(|ty| ty.to_ascii_uppercase()).collect::<Vec<_>>();
+            if typed.pref {
+                types.push("PREF".to_string());
+            }
+            if !types.is_empty() {
+                prop.params.push(Param { name: "TYPE".to_string(), values: types });
+            }
+        },
+        Version::V4 => {
+            if !typed.types.is_empty() {
+                prop = prop.with_param("TYPE", typed.types.join(","));
+            }
+            if typed.pref {
+                prop = prop.with_param("PREF", "1");
+            }
+        },
+    }
+    prop
+}
+
+/// Create a property with a *structured* value (e.g. `N` or `ADR`), where each part is separated by `;`.
+fn structured<'a>(name: &str, parts: impl IntoIterator<Item = &'a String>) -> Property {
+    let value = parts.into_iter()
+        .map(|part| escape_text(part))
+        .collect::<Vec<_>>()
+        .join(";");
+    Property::new(name, value)
+}
+
+/// Split a *structured* value at the `;` that are not escaped, and unescape each part.
+fn split_structured(value: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ';' => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts.into_iter().map(unescape_text).collect()
+}
+
+/// Join the values of a *list* (e.g. `NICKNAME`) with `", "`.
+fn join_list(value: &str) -> String {
+    split_list(value)
+        .into_iter()
+        .map(unescape_text)
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parse a `BDAY` in any of the formats `YYYYMMDD`, `YYYY-MM-DD`, `--MMDD` or `--MM-DD` (optionally followed by a time).
+///
+/// Returns it in the format `YYYY-MM-DD` or `--MM-DD`.
+fn parse_birthday(value: &str) -> Option<String> {
+    let value = value.trim();
+    let date = value.split_once('T').map_or(value, |(date, _)| date);
+    let (year, rest) = match date.strip_prefix("--") {
+        Some(rest) => (None, rest),
+        None if date.len() >= 8 => (Some(&date[..4]), date[4..].trim_start_matches('-')),
+        None => return None,
+    };
+    let month_day = rest.replace('-', "");
+    if month_day.len() != 4 || !month_day.chars().all(|c| c.is_ascii_digit())
+        || !year.is_none_or(|year| year.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let (month, day) = month_day.split_at(2);
+    Some(match year {
+        Some(year) => format!("{year}-{month}-{day}"),
+        None => format!("--{month}-{day}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ical;
+
+    fn parse_card(content: &str) -> Contact {
+        Contact::from_component(&ical::parse(content).unwrap()[0]).unwrap()
+    }
+
+    #[test]
+    fn parse_v3() {
+        let contact = parse_card("BEGIN:VCARD\r\n\
+            VERSION:3.0\r\n\
+            N:Doe;John;Quincy,Adams;Dr.;Jr.\r\n\
+            FN:Dr. John Doe\r\n\
+            NICKNAME:Johnny,JD\r\n\
+            ORG:Example\\, Inc.;Research\r\n\
+            TEL;TYPE=CELL,pref:+1 555 0100\r\n\
+            TEL;TYPE=home;TYPE=voice:+1 555 0101\r\n\
+            item1.EMAIL;TYPE=INTERNET:john@example.com\r\n\
+            item1.X-ABLabel:Other\r\n\
+            ADR;TYPE=WORK:;Suite 5;123 Main St\\; Back;Springfield;IL;62701;USA\r\n\
+            BDAY:1980-04-15\r\n\
+            NOTE:Line 1\\nLine 2\r\n\
+            END:VCARD\r\n");
+
+        assert_eq!(contact.version, Version::V3);
+        assert_eq!(contact.formatted_name, "Dr. John Doe");
+        assert_eq!(contact.name, Some(Name {
+            family: "Doe".to_string(),
+            given: "John".to_string(),
+            additional: "Quincy, Adams".to_string(),
+            prefix: "Dr.".to_string(),
+            suffix: "Jr.".to_string(),
+        }));
+        assert_eq!(contact.nickname.as_deref(), Some("Johnny, JD"));
+        assert_eq!(contact.organization.as_deref(), Some("Example, Inc."));
+        assert_eq!(contact.department.as_deref(), Some("Research"));
+        assert_eq!(contact.phones[0], Typed { value: "+1 555 0100".to_string(), types: vec!["cell".to_string()], pref: true });
+        assert_eq!(contact.phones[1].types, ["home", "voice"]);
+        // Grouped properties are read
+        assert_eq!(contact.emails[0].value, "john@example.com");
+        assert_eq!(contact.addresses[0].value.street, "123 Main St; Back");
+        assert_eq!(contact.addresses[0].value.formatted(), "Suite 5\n123 Main St; Back\nSpringfield IL 62701\nUSA");
+        assert_eq!(contact.birthday.as_deref(), Some("1980-04-15"));
+        assert_eq!(contact.note.as_deref(), Some("Line 1\nLine 2"));
+    }
+
+    #[test]
+    fn parse_v4() {
+        let contact = parse_card("BEGIN:VCARD\r\n\
+            VERSION:4.0\r\n\
+            UID:urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1\r\n\
+            FN:Jane Doe\r\n\
+            TEL;VALUE=uri;TYPE=\"voice,work\";PREF=1:tel:+1-555-555-5555\r\n\
+            BDAY:--0415\r\n\
+            END:VCARD\r\n");
+
+        assert_eq!(contact.version, Version::V4);
+        assert_eq!(contact.uid.as_deref(), Some("urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1"));
+        assert_eq!(contact.name, None);
+        assert_eq!(contact.phones[0], Typed { value: "+1-555-555-5555".to_string(), types: vec!["voice".to_string(), "work".to_string()], pref: true });
+        assert_eq!(contact.birthday.as_deref(), Some("--04-15"));
+    }
+
+    #[test]
+    fn formatted_name_from_n() {
+        let contact = parse_card("BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;John;;Mr.;\r\nEND:VCARD\r\n");
+        assert_eq!(contact.formatted_name, "Mr. John Doe");
+
+        let card = &ical::parse("BEGIN:VCARD\r\nVERSION:3.0\r\nEMAIL:a@example.com\r\nEND:VCARD\r\n").unwrap()[0];
+        assert_eq!(Contact::from_component(card), Err(ParseError::MissingName));
+    }
+
+    #[test]
+    fn parse_birthdays() {
+        assert_eq!(parse_birthday("19800415").as_deref(), Some("1980-04-15"));
+        assert_eq!(parse_birthday("1980-04-15T10:00:00Z").as_deref(), Some("1980-04-15"));
+        assert_eq!(parse_birthday("--04-15").as_deref(), Some("--04-15"));
+        assert_eq!(parse_birthday("April 15"), None);
+        assert_eq!(parse_birthday("1980-4-15"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let contact = Contact {
+            uid: Some("1234".to_string()),
+            formatted_name: "John Doe".to_string(),
+            name: Some(Name { family: "Doe".to_string(), given: "John".to_string(), ..Default::default() }),
+            organization: Some("Example; Inc.".to_string()),
+            phones: vec![Typed { value: "+1 555 0100".to_string(), types: vec!["cell".to_string()], pref: true }],
+            addresses: vec![Typed {
+                value: Address { street: "1 Main St".to_string(), locality: "Springfield".to_string(), ..Default::default() },
+                types: vec!["home".to_string()],
+                pref: false,
+            }],
+            birthday: Some("--04-15".to_string()),
+            note: Some("Likes, commas".to_string()),
+            ..Default::default()
+        };
+
+        for version in [Version::V3, Version::V4] {
+            let contact = Contact { version, ..contact.clone() };
+            let written = contact.to_component().to_string();
+            assert_eq!(parse_card(&written), contact, "vCard {}:\n{written}", version.as_str());
+        }
+        assert!(Contact { version: Version::V3, ..contact.clone() }.to_component().to_string().contains("TEL;TYPE=CELL,PREF:"));
+        assert!(contact.to_component().to_string().contains("BDAY:--0415\r\n"));
+    }
+
+    #[test]
+    fn data_rows_round_trip() {
+        let contact = parse_card("BEGIN:VCARD\r\n\
+            VERSION:4.0\r\n\
+            FN:John Doe\r\n\
+            N:Doe;John;;;\r\n\
+            TEL;TYPE=cell:+1 555 0100\r\n\
+            EMAIL;TYPE=work:john@example.com\r\n\
+            URL:https://example.com\r\n\
+            END:VCARD\r\n");
+        let from_rows = Contact::from_data_rows(&contact.to_data_rows()).unwrap();
+        assert_eq!(from_rows.formatted_name, contact.formatted_name);
+        assert_eq!(from_rows.name, contact.name);
+        assert_eq!(from_rows.phones, contact.phones);
+        assert_eq!(from_rows.emails, contact.emails);
+        assert_eq!(from_rows.urls, contact.urls);
+    }
+}
@@ -0,0 +1,542 @@
+//! How a [`Client`](super::Client) authenticates with the server:
+//! *Basic* (RFC 7617), *Digest* (RFC 7616, with `MD5`), or an *OAuth 2.0* bearer token (RFC 6750)
+//! that is refreshed with the account's refresh token when it expires or is rejected (RFC 6749 section 6).
+//!
+//! The secrets are never logged: the [`Debug`] implementations of the types that hold them leave them out.
+use std::{fmt::Debug, sync::{Mutex, MutexGuard}, time::{SystemTime, UNIX_EPOCH}};
+use base64::Engine as _;
+use md5::{Digest as _, Md5};
+use url::Url;
+use super::Error;
+
+/// How long before an access token expires it is refreshed, so that it doesn't expire while a request is sent.
+const EXPIRY_MARGIN_SECS: u64 = 30;
+
+/// The username and password used for *Basic* and *Digest* authentication.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The tokens of an account that authenticates with *OAuth 2.0*, and how to refresh them.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OAuth2 {
+    pub access_token: String,
+    /// When the access token expires (in seconds since the epoch), if the authorization server said so.
+    pub expires_at: Option<u64>,
+    /// [`None`] if the access token can't be refreshed, so the user has to sign in again when it expires.
+    pub refresh_token: Option<String>,
+    /// The *token endpoint* of the authorization server.
+    pub token_url: String,
+    pub client_id: String,
+    /// Only *confidential* clients have a secret.
+    pub client_secret: Option<String>,
+}
+impl OAuth2 {
+    fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| now() + EXPIRY_MARGIN_SECS >= expires_at)
+    }
+
+    /// Get a new access token from the token endpoint with the refresh token.
+    ///
+    /// Returns [`Error::Unauthorized`] if there is no refresh token, or if it expired or was revoked.
+    fn refresh(&mut self, agent: &ureq::Agent) -> Result<(), Error> {
+        let Some(refresh_token) = &self.refresh_token else {
+            return Err(Error::Unauthorized("The access token expired and there is no refresh token".to_string()));
+        };
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", self.client_id.as_str()),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+
+        let response = match agent.post(&self.token_url).set("Accept", "application/json").send_form(&form) {
+            Ok(response) => response,
+            // The error response has an "error" code, which doesn't include the token (RFC 6749 section 5.2)
+            Err(ureq::Error::Status(400 | 401, response)) => {
+                let error = response.into_string().ok()
+                    .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                    .and_then(|body| body["error"].as_str().map(str::to_string))
+                    .unwrap_or_else(|| "unknown error".to_string());
+                return Err(Error::Unauthorized(format!("The refresh token was rejected ({error}), so the user has to sign in again")));
+            },
+            Err(ureq::Error::Status(status, _)) => return Err(Error::Status { method: "POST", url: self.token_url.clone(), status }),
+            Err(ureq::Error::Transport(err)) => return Err(Error::Transport(err.to_string())),
+        };
+        let body = serde_json::from_str::<serde_json::Value>(&response.into_string()?)
+            .map_err(|err| Error::InvalidResponse(format!("Invalid token response from {}: {err}", self.token_url)))?;
+
+        let access_token = body["access_token"].as_str()
+            .ok_or_else(|| Error::InvalidResponse(format!("Token response from {} has no access_token", self.token_url)))?;
+        if let Some(token_type) = body["token_type"].as_str().filter(|token_type| !token_type.eq_ignore_ascii_case("bearer")) {
+            return Err(Error::InvalidResponse(format!("Token response from {} has token_type {token_type:?} instead of bearer", self.token_url)));
+        }
+        self.access_token = access_token.to_string();
+        self.expires_at = body["expires_in"].as_u64().map(|expires_in| now() + expires_in);
+        // The authorization server can issue a new refresh token and revoke the old one
+        if let Some(refresh_token) = body["refresh_token"].as_str() {
+            self.refresh_token = Some(refresh_token.to_string());
+        }
+
+        Ok(())
+    }
+}
+impl Debug for OAuth2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuth2")
+            .field("expires_at", &self.expires_at)
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How a [`Client`](super::Client) authenticates with the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// Send the username and password with every request.
+    Basic(Credentials),
+    /// Send a hash of the password, once the server sent a challenge in a `401 Unauthorized` response.
+    Digest(Credentials),
+    /// Send an access token, refreshing it when it expires.
+    OAuth2(OAuth2),
+}
+
+/// The [`Auth`] of a [`Client`](super::Client), and what changed while it was used:
+/// the last *Digest* challenge of the server, and the refreshed *OAuth 2.0* tokens.
+///
+/// Requests are sent with a shared reference to the client, so the state is behind a [`Mutex`].
+pub(super) struct Session {
+    state: Mutex<State>,
+}
+struct State {
+    auth: Auth,
+    challenge: Option<Challenge>,
+    /// The number of requests that were sent with the nonce of the **challenge**.
+    nonce_count: u32,
+    refreshed: bool,
+}
+impl Session {
+    pub fn new(auth: Auth) -> Self {
+        Self { state: Mutex::new(State { auth, challenge: None, nonce_count: 0, refreshed: false }) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is still valid if another thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Set the `Authorization` header of a **request** before it is sent.
+    ///
+    /// *Digest* requests are only authorized once the server sent a challenge,
+    /// and an *OAuth 2.0* access token that expired is refreshed first.
+    pub fn authorize(&self, agent: &ureq::Agent, request: ureq::Request) -> Result<ureq::Request, Error> {
+        let state = &mut *self.lock();
+        if let Auth::OAuth2(oauth) = &mut state.auth {
+            if oauth.is_expired() && oauth.refresh_token.is_some() {
+                oauth.refresh(agent)?;
+                state.refreshed = true;
+            }
+        }
+
+        Ok(match state.authorization(&request) {
+            Some(authorization) => request.set("Authorization", &authorization),
+            None => request,
+        })
+    }
+
+    /// Called when the server responded to a **request** with `401 Unauthorized`.
+    ///
+    /// Returns the request with new credentials if it should be sent again:
+    /// the server sent a new *Digest* challenge, or the *OAuth 2.0* access token was refreshed.
+    pub fn reauthorize(&self, agent: &ureq::Agent, request: &ureq::Request, response: &ureq::Response) -> Result<Option<ureq::Request>, Error> {
+        let state = &mut *self.lock();
+        let sent_authorization = request.header("Authorization").map(str::to_string);
+
+        match &mut state.auth {
+            // The password is wrong
+            Auth::Basic(_) => return Ok(None),
+            Auth::Digest(_) => {
+                let Some(challenge) = response.all("WWW-Authenticate").into_iter().find_map(Challenge::parse) else {
+                    return Ok(None);
+                };
+                // A new challenge for a request that already had a response to one means the password is wrong,
+                // unless the server says that the nonce of the old one expired
+                if sent_authorization.is_some() && !challenge.stale {
+                    return Ok(None);
+                }
+                state.challenge = Some(challenge);
+                state.nonce_count = 0;
+            },
+            Auth::OAuth2(oauth) => {
+                // The token could have been refreshed by another request already
+                if sent_authorization.is_some_and(|sent| sent == format!("Bearer {}", oauth.access_token)) {
+                    if oauth.refresh_token.is_none() {
+                        return Ok(None);
+                    }
+                    oauth.refresh(agent)?;
+                    state.refreshed = true;
+                }
+            },
+        }
+
+        Ok(state.authorization(request)
+            .map(|authorization| request.clone().set("Authorization", &authorization)))
+    }
+
+    /// The tokens of the *OAuth 2.0* account if they were refreshed, so that they can be saved for the next session.
+    pub fn refreshed_tokens(&self) -> Option<OAuth2> {
+        match &*self.lock() {
+            State { auth: Auth::OAuth2(oauth), refreshed: true, .. } => Some(oauth.clone()),
+            _ => None,
+        }
+    }
+}
+impl State {
+    /// The value of the `Authorization` header for a **request**, or [`None`] if it can't be authorized yet.
+    fn authorization(&mut self, request: &ureq::Request) -> Option<String> {
+        match &self.auth {
+            Auth::Basic(Credentials { username, password }) => {
+                let token = base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+                Some(format!("Basic {token}"))
+            },
+            Auth::Digest(credentials) => {
+                let challenge = self.challenge.as_ref()?;
+                let url = Url::parse(request.url()).ok()?;
+                self.nonce_count += 1;
+                Some(challenge.respond(credentials, request.method(), &url[url::Position::BeforePath..url::Position::AfterQuery], self.nonce_count))
+            },
+            Auth::OAuth2(oauth) => Some(format!("Bearer {}", oauth.access_token)),
+        }
+    }
+}
+
+/// A *Digest* challenge from the `WWW-Authenticate` header of a `401 Unauthorized` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    /// Whether the algorithm is `MD5-sess` instead of `MD5`.
+    session: bool,
+    /// Whether the server supports `qop=auth`. If it doesn't, the response is computed like in RFC 2069.
+    qop_auth: bool,
+    /// The nonce of the previous challenge expired, but the credentials were right.
+    stale: bool,
+}
+impl Challenge {
+    /// Parse the `Digest` challenge of a `WWW-Authenticate` header, which can also have challenges of other schemes.
+    ///
+    /// Returns [`None`] if there is no challenge with an algorithm and *quality of protection* that are supported.
+    fn parse(header: &str) -> Option<Self> {
+        let mut challenge = Self { realm: String::new(), nonce: String::new(), opaque: None, session: false, qop_auth: false, stale: false };
+        let mut qop = None;
+        let mut in_digest = false;
+        let mut found = false;
+        let mut rest = header.trim_start();
+
+        while !rest.is_empty() {
+            // Either an auth-param (name=value) or the scheme of the next challenge
+            let end = rest.find(|c: char| c == '=' || c == ',' || c.is_whitespace()).unwrap_or(rest.len());
+            let (name, after) = rest.split_at(end);
+            let after = after.trim_start();
+            let Some(value) = after.strip_prefix('=').filter(|_| !name.is_empty()) else {
+                if in_digest {
+                    break;
+                }
+                in_digest = name.eq_ignore_ascii_case("Digest");
+                found |= in_digest;
+                rest = after.trim_start_matches([',', ' ', '\t']);
+                continue;
+            };
+
+            let value = value.trim_start();
+            let (value, after) = match value.strip_prefix('"') {
+                Some(quoted) => {
+                    let mut unquoted = String::new();
+                    let mut chars = quoted.char_indices();
+                    let mut end = quoted.len();
+                    while let Some((i, c)) = chars.next() {
+                        match c {
+                            '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+                            '"' => { end = i + 1; break },
+                            c => unquoted.push(c),
+                        }
+                    }
+                    (unquoted, &quoted[end..])
+                },
+                None => {
+                    let end = value.find(|c: char| c == ',' || c.is_whitespace()).unwrap_or(value.len());
+                    (value[..end].to_string(), &value[end..])
+                },
+            };
+            rest = after.trim_start_matches([',', ' ', '\t']);
+
+            if !in_digest {
+                continue;
+            }
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => challenge.realm = value,
+                "nonce" => challenge.nonce = value,
+                "opaque" => challenge.opaque = Some(value),
+                "stale" => challenge.stale = value.eq_ignore_ascii_case("true"),
+                "qop" => qop = Some(value),
+                "algorithm" => match value.to_ascii_uppercase().as_str() {
+                    "MD5" => challenge.session = false,
+                    "MD5-SESS" => challenge.session = true,
+                    _ => return None,
+                },
+                _ => {},
+            }
+        }
+
+        challenge.qop_auth = match &qop {
+            Some(qop) => qop.split(',').any(|qop| qop.trim().eq_ignore_ascii_case("auth")),
+            None => false,
+        };
+        // auth-int would need the hash of the body of every request
+        if !found || challenge.nonce.is_empty() || (qop.is_some() && !challenge.qop_auth) {
+            return None;
+        }
+        Some(challenge)
+    }
+
+    /// The value of the `Authorization` header for a request with **method** to **uri**,
+    /// which is the **nonce_count**-th request that responds to this challenge.
+    fn respond(&self, credentials: &Credentials, method: &str, uri: &str, nonce_count: u32) -> String {
+        let cnonce = md5_hex(format!("{:?}:{nonce_count}:{}", SystemTime::now(), self.nonce).as_bytes());
+        let cnonce = &cnonce[..16];
+        let nc = format!("{nonce_count:08x}");
+        let response = self.response(credentials, method, uri, &nc, cnonce);
+
+        let mut authorization = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{response}\"",
+            quote(&credentials.username), quote(&self.realm), quote(&self.nonce), quote(uri),
+            if self.session { "MD5-sess" } else { "MD5" },
+        );
+        if self.qop_auth {
+            authorization.push_str(&format!(", qop=auth, nc={nc}, cnonce=\"{cnonce}\""));
+        }
+        if let Some(opaque) = &self.opaque {
+            authorization.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        authorization
+    }
+
+    /// The `response` parameter of the `Authorization` header, with the nonce count **nc** and client nonce **cnonce**.
+    fn response(&self, credentials: &Credentials, method: &str, uri: &str, nc: &str, cnonce: &str) -> String {
+        let mut ha1 = md5_hex(format!("{}:{}:{}", credentials.username, self.realm, credentials.password).as_bytes());
+        if self.session {
+            ha1 = md5_hex(format!("{ha1}:{}:{cnonce}", self.nonce).as_bytes());
+        }
+        let ha2 = md5_hex(format!("{method}:{uri}").as_bytes());
+        if self.qop_auth {
+            md5_hex(format!("{ha1}:{}:{nc}:{cnonce}:auth:{ha2}", self.nonce).as_bytes())
+        } else {
+            md5_hex(format!("{ha1}:{}:{ha2}", self.nonce).as_bytes())
+        }
+    }
+}
+
+/// Escape the characters that can't be in a quoted-string.
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The seconds since the epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs())
+}
+
+/// The *MD5* hash of the **data** (RFC 1321) in lowercase hex, as used by *Digest* authentication.
+fn md5_hex(data: &[u8]) -> String {
+    Md5::digest(data).iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::{test_server::{Response, Server}, Client};
+
+    const NONCE: &str = "dcd98b7102dd2f0e8b11d0f600bfb0c093";
+    const OPAQUE: &str = "5ccc069c403ebaf9f0171e9517f40e41";
+
+    fn mufasa() -> Credentials {
+        Credentials { username: "Mufasa".to_string(), password: "Circle Of Life".to_string() }
+    }
+
+    /// The value of the auth-param with **name** in an `Authorization` header (without quotes).
+    fn param(header: &str, name: &str) -> Option<String> {
+        header.trim_start_matches("Digest ").split(", ")
+            .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+            .map(|value| value.trim_matches('"').to_string())
+    }
+
+    fn oauth(server: &Server, expires_at: Option<u64>) -> Auth {
+        Auth::OAuth2(OAuth2 {
+            access_token: "old".to_string(),
+            expires_at,
+            refresh_token: Some("refresh-1".to_string()),
+            token_url: server.url("/token").to_string(),
+            client_id: "davsync".to_string(),
+            client_secret: None,
+        })
+    }
+
+    /// A server that only accepts the access token `new`, which its token endpoint issues for the refresh token `refresh-1`.
+    fn oauth_server() -> Server {
+        Server::with_handler(|request, _| match request.path.as_str() {
+            "/token" if request.body.contains("refresh_token=refresh-1") => Some(Response::new(200,
+                r#"{"access_token":"new","token_type":"Bearer","expires_in":3600,"refresh_token":"refresh-2"}"#
+            )),
+            "/token" => Some(Response::new(400, r#"{"error":"invalid_grant"}"#)),
+            _ if request.header("Authorization") != Some("Bearer new") => Some(Response::new(401, "")),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn md5_test_suite() {
+        // RFC 1321 appendix A.5
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5_hex(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        assert_eq!(md5_hex(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+        assert_eq!(md5_hex(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"), "d174ab98d277d9f5a5611c2c9f419d9f");
+        assert_eq!(
+            md5_hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn digest_response() {
+        // RFC 2617 section 3.5
+        let challenge = Challenge::parse(&format!(
+            r#"Basic realm="other", Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="{NONCE}", opaque="{OPAQUE}""#
+        )).unwrap();
+        assert_eq!(challenge, Challenge {
+            realm: "testrealm@host.com".to_string(),
+            nonce: NONCE.to_string(),
+            opaque: Some(OPAQUE.to_string()),
+            session: false,
+            qop_auth: true,
+            stale: false,
+        });
+        assert_eq!(challenge.response(&mufasa(), "GET", "/dir/index.html", "00000001", "0a4f113b"), "6629fae49393a05397450978507c4ef1");
+
+        let header = challenge.respond(&mufasa(), "GET", "/dir/index.html", 1);
+        assert_eq!(param(&header, "username").as_deref(), Some("Mufasa"));
+        assert_eq!(param(&header, "nc").as_deref(), Some("00000001"));
+        assert_eq!(param(&header, "opaque").as_deref(), Some(OPAQUE));
+        let cnonce = param(&header, "cnonce").unwrap();
+        assert_eq!(param(&header, "response"), Some(challenge.response(&mufasa(), "GET", "/dir/index.html", "00000001", &cnonce)));
+
+        assert_eq!(Challenge::parse(r#"Digest realm="x", nonce="y", qop="auth-int""#), None);
+        assert_eq!(Challenge::parse(r#"Digest realm="x", nonce="y", algorithm=SHA-256"#), None);
+        assert_eq!(Challenge::parse(r#"Basic realm="x""#), None);
+    }
+
+    #[test]
+    fn digest_authentication() {
+        let server = Server::with_handler(|request, _| {
+            let challenge = Challenge {
+                realm: "testrealm@host.com".to_string(),
+                nonce: NONCE.to_string(),
+                opaque: Some(OPAQUE.to_string()),
+                session: false,
+                qop_auth: true,
+                stale: false,
+            };
+            let authorized = request.header("Authorization").is_some_and(|header| {
+                let (Some(uri), Some(nc), Some(cnonce)) = (param(header, "uri"), param(header, "nc"), param(header, "cnonce")) else {
+                    return false;
+                };
+                param(header, "response") == Some(challenge.response(&mufasa(), &request.method, &uri, &nc, &cnonce))
+            });
+            (!authorized).then(|| Response::new(401, "")
+                .with_header("WWW-Authenticate", format!(r#"Digest realm="testrealm@host.com", qop="auth", nonce="{NONCE}", opaque="{OPAQUE}""#)))
+        });
+        server.state().put("/cal/a.ics", "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n");
+        let url = server.url("/cal/a.ics");
+
+        let client = Client::new(&server.url, Some(Auth::Digest(mufasa()))).unwrap();
+        assert!(client.get(&url, None).unwrap().is_some());
+        // The challenge is reused for the next requests
+        assert!(client.get(&url, None).unwrap().is_some());
+        let authorizations = server.state().requests.iter()
+            .map(|request| request.header("Authorization").and_then(|header| param(header, "nc")))
+            .collect::<Vec<_>>();
+        assert_eq!(authorizations, [None, Some("00000001".to_string()), Some("00000002".to_string())]);
+
+        let wrong = Credentials { password: "Hakuna Matata".to_string(), ..mufasa() };
+        let client = Client::new(&server.url, Some(Auth::Digest(wrong))).unwrap();
+        assert!(matches!(client.get(&url, None), Err(Error::Status { status: 401, .. })));
+    }
+
+    #[test]
+    fn refresh_rejected_token() {
+        let server = oauth_server();
+        server.state().put("/cal/a.ics", "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n");
+        let client = Client::new(&server.url, Some(oauth(&server, None))).unwrap();
+        assert_eq!(client.refreshed_tokens(), None);
+
+        assert!(client.get(&server.url("/cal/a.ics"), None).unwrap().is_some());
+        let requests = server.state().requests.clone();
+        let sent = requests.iter().map(|request| (request.method.as_str(), request.path.as_str())).collect::<Vec<_>>();
+        assert_eq!(sent, [("GET", "/cal/a.ics"), ("POST", "/token"), ("GET", "/cal/a.ics")]);
+        assert!(requests[1].body.contains("grant_type=refresh_token"));
+        assert!(requests[1].body.contains("client_id=davsync"));
+        assert!(!requests[1].body.contains("client_secret"));
+
+        let refreshed = client.refreshed_tokens().unwrap();
+        assert_eq!(refreshed.access_token, "new");
+        // The authorization server rotated the refresh token
+        assert_eq!(refreshed.refresh_token.as_deref(), Some("refresh-2"));
+        assert!(refreshed.expires_at.is_some_and(|expires_at| expires_at > now()));
+    }
+
+    #[test]
+    fn refresh_expired_token_first() {
+        let server = oauth_server();
+        server.state().put("/cal/a.ics", "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n");
+        let client = Client::new(&server.url, Some(oauth(&server, Some(1)))).unwrap();
+
+        assert!(client.get(&server.url("/cal/a.ics"), None).unwrap().is_some());
+        let sent = server.state().requests.iter()
+            .map(|request| format!("{} {}", request.method, request.path))
+            .collect::<Vec<_>>();
+        assert_eq!(sent, ["POST /token", "GET /cal/a.ics"]);
+        assert_eq!(client.refreshed_tokens().map(|tokens| tokens.access_token).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn refresh_token_revoked() {
+        let server = oauth_server();
+        let Auth::OAuth2(mut revoked) = oauth(&server, None) else { unreachable!() };
+        revoked.refresh_token = Some("refresh-0".to_string());
+        let client = Client::new(&server.url, Some(Auth::OAuth2(revoked.clone()))).unwrap();
+        let err = client.get(&server.url("/cal/a.ics"), None).unwrap_err();
+        assert!(matches!(&err, Error::Unauthorized(msg) if msg.contains("invalid_grant")), "{err}");
+        assert_eq!(client.refreshed_tokens(), None);
+
+        // Without a refresh token the 401 is returned as it is
+        revoked.refresh_token = None;
+        let client = Client::new(&server.url, Some(Auth::OAuth2(revoked))).unwrap();
+        assert!(matches!(client.get(&server.url("/cal/a.ics"), None), Err(Error::Status { status: 401, .. })));
+    }
+}
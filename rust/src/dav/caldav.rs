@@ -0,0 +1,88 @@
+//! Discovery of the calendars of a user in a *CalDAV* server (RFC 4791 and RFC 6764).
+use url::Url;
+use super::{xml, Client, Depth, Error};
+
+/// The path used to find the CalDAV service of a domain (RFC 6764).
+const WELL_KNOWN: &str = "/.well-known/caldav";
+
+/// A *calendar collection* in the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calendar {
+    pub url: Url,
+    pub display_name: Option<String>,
+    /// The value of the Apple `calendar-color` property (e.g. `#FF0000FF`).
+    pub color: Option<String>,
+    /// The *CTag* of the collection, which changes whenever any of its resources change.
+    pub ctag: Option<String>,
+    /// The `sync-token` of the collection (RFC 6578). Only servers that support `sync-collection` have it.
+    pub sync_token: Option<String>,
+    /// The components that the collection can store (e.g. `VEVENT`, `VTODO`).
+    /// Empty if the server didn't specify them, in which case all components are supported.
+    pub components: Vec<String>,
+}
+impl Calendar {
+    /// The name of the calendar file (without extension) where this collection will be stored.
+    ///
+    /// Uses the **display name**, or the last segment of the **URL** if it doesn't have one.
+    pub fn file_stem(&self) -> String {
+        super::file_stem(&self.url, self.display_name.as_deref(), "calendar")
+    }
+
+    /// Whether the collection can store components of type **name** (e.g. `VEVENT`).
+    pub fn supports(&self, name: &str) -> bool {
+        self.components.is_empty() || self.components.iter().any(|comp| comp.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Client {
+    /// Find the URLs of the collections that contain the calendars of the **principal**.
+    pub fn calendar_home_set(&self, principal: &Url) -> Result<Vec<Url>, Error> {
+        let responses = self.propfind(principal, Depth::Zero, &[xml::CALENDAR_HOME_SET])?;
+        responses.iter()
+            .filter_map(|response| response.prop(xml::CALENDAR_HOME_SET))
+            .flat_map(|prop| prop.hrefs.iter())
+            .map(|href| self.url(principal, href))
+            .collect()
+    }
+
+    /// List the *calendar collections* in the **home** collection.
+    pub fn calendars(&self, home: &Url) -> Result<Vec<Calendar>, Error> {
+        let responses = self.propfind(home, Depth::One, &[
+            xml::RESOURCE_TYPE,
+            xml::DISPLAY_NAME,
+            xml::CALENDAR_COLOR,
+            xml::GET_CTAG,
+            xml::SYNC_TOKEN,
+            xml::SUPPORTED_CALENDAR_COMPONENT_SET,
+        ])?;
+
+        responses.iter()
+            .filter(|response| response.prop(xml::RESOURCE_TYPE).is_some_and(|prop| prop.has(xml::CALENDAR)))
+            .map(|response| Ok(Calendar {
+                url: self.url(home, &response.href)?,
+                display_name: response.text(xml::DISPLAY_NAME).map(str::to_string),
+                color: response.text(xml::CALENDAR_COLOR).map(str::to_string),
+                ctag: response.text(xml::GET_CTAG).map(str::to_string),
+                sync_token: response.text(xml::SYNC_TOKEN).map(str::to_string),
+                components: response.prop(xml::SUPPORTED_CALENDAR_COMPONENT_SET)
+                    .map(|prop| prop.children.iter()
+                        .filter_map(|comp| comp.attribute("name"))
+                        .map(str::to_string)
+                        .collect()
+                    )
+                    .unwrap_or_default(),
+            }))
+            .collect()
+    }
+
+    /// Find all the calendars of the authenticated user.
+    pub fn discover_calendars(&self) -> Result<Vec<Calendar>, Error> {
+        let principal = self.current_user_principal(WELL_KNOWN)?;
+        let mut calendars = Vec::new();
+        for home in self.calendar_home_set(&principal)? {
+            calendars.extend(self.calendars(&home)?);
+        }
+        Ok(calendars)
+    }
+
+}
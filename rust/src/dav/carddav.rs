@@ -0,0 +1,67 @@
+//! Discovery of the address books of a user in a *CardDAV* server (RFC 6352 and RFC 6764).
+use url::Url;
+use super::{xml, Client, Depth, Error};
+
+/// The path used to find the CardDAV service of a domain (RFC 6764).
+const WELL_KNOWN: &str = "/.well-known/carddav";
+
+/// An *address book collection* in the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressBook {
+    pub url: Url,
+    pub display_name: Option<String>,
+    /// The *CTag* of the collection, which changes whenever any of its resources change.
+    pub ctag: Option<String>,
+    /// The `sync-token` of the collection (RFC 6578). Only servers that support `sync-collection` have it.
+    pub sync_token: Option<String>,
+}
+impl AddressBook {
+    /// The name of the contacts file (without extension) where this collection will be stored.
+    ///
+    /// Uses the **display name**, or the last segment of the **URL** if it doesn't have one.
+    pub fn file_stem(&self) -> String {
+        super::file_stem(&self.url, self.display_name.as_deref(), "contacts")
+    }
+}
+
+impl Client {
+    /// Find the URLs of the collections that contain the address books of the **principal**.
+    pub fn addressbook_home_set(&self, principal: &Url) -> Result<Vec<Url>, Error> {
+        let responses = self.propfind(principal, Depth::Zero, &[xml::ADDRESSBOOK_HOME_SET])?;
+        responses.iter()
+            .filter_map(|response| response.prop(xml::ADDRESSBOOK_HOME_SET))
+            .flat_map(|prop| prop.hrefs.iter())
+            .map(|href| self.url(principal, href))
+            .collect()
+    }
+
+    /// List the *address book collections* in the **home** collection.
+    pub fn address_books(&self, home: &Url) -> Result<Vec<AddressBook>, Error> {
+        let responses = self.propfind(home, Depth::One, &[
+            xml::RESOURCE_TYPE,
+            xml::DISPLAY_NAME,
+            xml::GET_CTAG,
+            xml::SYNC_TOKEN,
+        ])?;
+
+        responses.iter()
+            .filter(|response| response.prop(xml::RESOURCE_TYPE).is_some_and(|prop| prop.has(xml::ADDRESSBOOK)))
+            .map(|response| Ok(AddressBook {
+                url: self.url(home, &response.href)?,
+                display_name: response.text(xml::DISPLAY_NAME).map(str::to_string),
+                ctag: response.text(xml::GET_CTAG).map(str::to_string),
+                sync_token: response.text(xml::SYNC_TOKEN).map(str::to_string),
+            }))
+            .collect()
+    }
+
+    /// Find all the address books of the authenticated user.
+    pub fn discover_address_books(&self) -> Result<Vec<AddressBook>, Error> {
+        let principal = self.current_user_principal(WELL_KNOWN)?;
+        let mut address_books = Vec::new();
+        for home in self.addressbook_home_set(&principal)? {
+            address_books.extend(self.address_books(&home)?);
+        }
+        Ok(address_books)
+    }
+}
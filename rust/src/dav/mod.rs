@@ -0,0 +1,785 @@
+//! A client for *WebDAV* servers, with the *CalDAV* and *CardDAV* extensions needed to sync calendars and contacts with remote collections.
+//!
+//! The client only depends on the server (not on Android), so it can be used against any server (e.g. a local Radicale instance).
+mod auth;
+mod caldav;
+mod carddav;
+mod outbox;
+mod subscription;
+mod sync;
+#[cfg(test)]
+mod test_calendar;
+#[cfg(test)]
+pub(crate) mod test_server;
+mod xml;
+
+use std::{fmt::Display, io, path::{Path, PathBuf}};
+use ez_jni::{call, jni_fn, println, FromObject, ToObject};
+use jni::{JNIEnv, objects::JObject};
+use percent_encoding::percent_decode_str;
+use url::Url;
+use crate::{calendar::{self, merge::{Conflict, Side}}, error::OrThrow as _, file_type::FileType, get_app_dir, journal::{Journal, Step}, ILLEGAL_FILE_CHARACTERS};
+pub use auth::{Auth, Credentials, OAuth2};
+pub use caldav::Calendar;
+pub use carddav::AddressBook;
+pub use outbox::{record_changes, Outbox};
+pub use subscription::Subscription;
+pub use sync::{sync_address_book, sync_calendar, SyncState};
+
+/// The directory (in the internal directory) where the state of synced collections is stored.
+const STATE_DIR: &str = "dav";
+/// The number of resources that are downloaded with each multiget `REPORT` if the [`Client`] is not configured otherwise.
+pub const DEFAULT_MULTIGET_BATCH_SIZE: usize = 50;
+
+jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
+    /// Sync all the calendars of the user in the *CalDAV* server at **server_url** with the calendar files in the **internal** directory.
+    ///
+    /// Each calendar collection is stored in the file named with the collection's *display name*,
+    /// followed by the last segment of its URL if another collection has the same name.
+    /// The data of the files is **not** written to the *Content Provider*.
+    /// The changes in the outbox of each calendar are uploaded first, and the user is asked which version to keep
+    /// when they conflict with a change in the server.
+    /// The resources that changed in the server are downloaded in batches of **multiget_batch_size**
+    /// (or [`DEFAULT_MULTIGET_BATCH_SIZE`] if it is not positive).
+    /// The credentials of the account are read from the **store** (see `with_client()`).
+    pub fn sync_caldav<'local>(
+        context: android.content.Context,
+        server_url: String,
+        store: me.marti.calprovexample.jni.DavCredentialStore,
+        multiget_batch_size: int,
+    ) {
+        let app_dir = get_app_dir(env, &context);
+        with_client(env, &store, &server_url, multiget_batch_size, |env, client| sync_caldav(env, &app_dir, client))
+            .or_throw(env)
+    }
+
+    /// Sync all the address books of the user in the *CardDAV* server at **server_url** with the contacts files in the **internal** directory.
+    ///
+    /// Each address book collection is stored in the file named with the collection's *display name*,
+    /// followed by the last segment of its URL if another collection has the same name.
+    /// The data of the files is **not** written to the *Content Provider*.
+    /// The user is asked which version to keep of the contacts that changed both in the server and in the file.
+    /// The resources that changed in the server are downloaded in batches of **multiget_batch_size**
+    /// (or [`DEFAULT_MULTIGET_BATCH_SIZE`] if it is not positive).
+    /// The credentials of the account are read from the **store** (see `with_client()`).
+    pub fn sync_carddav<'local>(
+        context: android.content.Context,
+        server_url: String,
+        store: me.marti.calprovexample.jni.DavCredentialStore,
+        multiget_batch_size: int,
+    ) {
+        let app_dir = get_app_dir(env, &context);
+        with_client(env, &store, &server_url, multiget_batch_size, |env, client| sync_carddav(env, &app_dir, client))
+            .or_throw(env)
+    }
+
+    /// Create a read-only Calendar with **name** that mirrors the feed at **url** (`webcal://`, `https://` or `http://`).
+    ///
+    /// The feed is stored in the Calendar's file in the **internal** directory, and its events are written to the *Content Provider*.
+    /// If the feed doesn't have a color, the Calendar is created with **color**.
+    /// If any of these steps fails, the file and the Calendar that were created are deleted.
+    pub fn subscribe_calendar<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+        url: String,
+        color: Option<me.marti.calprovexample.Color>,
+    ) {
+        let color = color.to_object(env);
+        subscribe_calendar(env, &perm, &name, &url, &color)
+            .or_throw(env)
+    }
+
+    /// Fetch the feed of the Calendar with **name** (see `subscribe_calendar()`),
+    /// and if it changed, replace the Calendar's file and its events in the *Content Provider*.
+    ///
+    /// Returns whether the feed had changed.
+    pub fn refresh_subscription<'local>(
+        perm: me.marti.calprovexample.ui.CalendarPermissionScope,
+        name: String,
+    ) -> boolean {
+        refresh_subscription(env, &perm, &name)
+            .or_throw(env)
+    }
+}
+
+/// Create a [`Client`] for the server at **server_url** that authenticates with the credentials in the **store**
+/// (a `DavCredentialStore`, which keeps them out of the App's preferences and logs), and call **f** with it.
+///
+/// If the *OAuth 2.0* access token was refreshed while **f** used the client, the new tokens are saved to the **store**,
+/// even if **f** failed, because the old refresh token may have been revoked.
+fn with_client<'local, T>(
+    env: &mut JNIEnv<'local>,
+    store: &JObject<'local>,
+    server_url: &str,
+    multiget_batch_size: i32,
+    f: impl FnOnce(&mut JNIEnv<'local>, &Client) -> Result<T, crate::error::Error>,
+) -> Result<T, crate::error::Error> {
+    /// The `DavAuth` class in Kotlin.
+    #[derive(FromObject)]
+    enum DavAuth {
+        #[class(me.marti.calprovexample.jni.DavAuth$Basic)]
+        Basic { username: String, password: String },
+        #[class(me.marti.calprovexample.jni.DavAuth$Digest)]
+        Digest { username: String, password: String },
+        #[class(me.marti.calprovexample.jni.DavAuth$OAuth2)]
+        OAuth2 {
+            #[field(name = accessToken)]
+            access_token: String,
+            /// `0` if it's unknown.
+            #[field(name = expiresAt)]
+            expires_at: i64,
+            #[field(name = tokenUrl)]
+            token_url: String,
+            #[field(name = clientId)]
+            client_id: String,
+        },
+    }
+
+    let auth_obj = call!(store.load() -> me.marti.calprovexample.jni.DavAuth);
+    let auth = DavAuth::from_object(&auth_obj, env)
+        .map_err(|err| crate::error::Error::Parse(format!("Invalid credentials in store: {err}")))?;
+    let auth = match auth {
+        DavAuth::Basic { username, password } => Auth::Basic(Credentials { username, password }),
+        DavAuth::Digest { username, password } => Auth::Digest(Credentials { username, password }),
+        // The fields that can be null are read with their getters, because FromObject can't read nullable fields
+        DavAuth::OAuth2 { access_token, expires_at, token_url, client_id } => Auth::OAuth2(OAuth2 {
+            access_token,
+            expires_at: u64::try_from(expires_at).ok().filter(|&expires_at| expires_at != 0),
+            refresh_token: call!(auth_obj.getRefreshToken() -> Option<String>),
+            token_url,
+            client_id,
+            client_secret: call!(auth_obj.getClientSecret() -> Option<String>),
+        }),
+    };
+
+    let mut client = Client::new(server_url, Some(auth))?;
+    // Negative sizes use the default, like 0
+    client.set_multiget_batch_size(multiget_batch_size.try_into().unwrap_or(0));
+    let result = f(env, &client);
+
+    if let Some(OAuth2 { access_token, expires_at, refresh_token: Some(refresh_token), .. }) = client.refreshed_tokens() {
+        let expires_at = expires_at.map_or(0, |expires_at| expires_at as i64);
+        call!(store.saveOAuth2Tokens(String(access_token.as_str()), long(expires_at), String(refresh_token.as_str())) -> void);
+    }
+    result
+}
+
+/// Sync all the calendars in the server of the **client** with the calendar files in **app_dir**.
+///
+/// Before a calendar is synced, its [`Outbox`] is [replayed](outbox::replay()).
+/// A calendar is not synced while its outbox has conflicts that were not resolved, so that they are not overwritten by the server.
+/// Entries that could not be uploaded for another reason are dropped from the outbox, and their events are synced like the rest of the file.
+/// The user is asked which version to keep of the events that conflict when replaying the outbox or syncing.
+///
+/// Errors syncing one of the calendars are logged, so that the rest of the calendars are still synced.
+fn sync_caldav(env: &mut JNIEnv, app_dir: &Path, client: &Client) -> Result<(), crate::error::Error> {
+    let state_dir = app_dir.join(STATE_DIR).join(FileType::Calendar.dir());
+    std::fs::create_dir_all(&state_dir)
+        .map_err(|err| crate::error::Error::io(format!("Error creating directory {state_dir:?}"), err))?;
+
+    let calendars = client.discover_calendars()?
+        .into_iter()
+        // E.g. collections that only have journals
+        .filter(|calendar| calendar.supports("VEVENT") || calendar.supports("VTODO"))
+        .collect::<Vec<_>>();
+    let names = unique_file_stems(calendars.iter().map(|calendar| (&calendar.url, calendar.file_stem())));
+
+    for (calendar, name) in calendars.into_iter().zip(names) {
+        let Some(name) = name else {
+            println!("Calendar {} is not synced because another calendar has the same name", calendar.url);
+            continue;
+        };
+        let file_path = app_dir.join(FileType::Calendar.dir()).join(format!("{name}.ics"));
+        let state_path = app_dir.join(SyncState::path(FileType::Calendar, &name));
+        let outbox_path = app_dir.join(Outbox::path(&name));
+        match outbox::replay(client, &calendar.url, &file_path, &state_path, &outbox_path, |conflict| resolve(env, &name, conflict)) {
+            Ok(report) => {
+                if !report.dropped.is_empty() {
+                    println!("Dropped changes of calendar {name:?} from its outbox: {}", report.dropped.join("; "));
+                }
+                if !report.pending.is_empty() {
+                    println!("Calendar {name:?} will not be synced until its outbox is uploaded ({report}): {}", report.pending.join("; "));
+                    continue;
+                }
+                if report != outbox::ReplayReport::default() {
+                    println!("Uploaded outbox of calendar {name:?}: {report}");
+                }
+            },
+            Err(err) => {
+                println!("Error uploading outbox of calendar {name:?} to {}: {err}", calendar.url);
+                continue;
+            },
+        }
+        match sync_calendar(client, &calendar, &name, &file_path, &state_path, |conflict| resolve(env, &name, conflict)) {
+            Ok(report) => {
+                println!("Synced calendar {name:?}: {report}");
+            },
+            Err(err) => {
+                println!("Error syncing calendar {name:?} with {}: {err}", calendar.url);
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Sync all the address books in the server of the **client** with the contacts files in **app_dir**.
+///
+/// The user is asked which version to keep of the contacts that changed on both sides.
+///
+/// Errors syncing one of the address books are logged, so that the rest of the address books are still synced.
+fn sync_carddav(env: &mut JNIEnv, app_dir: &Path, client: &Client) -> Result<(), crate::error::Error> {
+    let state_dir = app_dir.join(STATE_DIR).join(FileType::Contacts.dir());
+    std::fs::create_dir_all(&state_dir)
+        .map_err(|err| crate::error::Error::io(format!("Error creating directory {state_dir:?}"), err))?;
+
+    let address_books = client.discover_address_books()?;
+    let names = unique_file_stems(address_books.iter().map(|address_book| (&address_book.url, address_book.file_stem())));
+
+    for (address_book, name) in address_books.into_iter().zip(names) {
+        let Some(name) = name else {
+            println!("Address book {} is not synced because another address book has the same name", address_book.url);
+            continue;
+        };
+        let file_path = app_dir.join(FileType::Contacts.dir()).join(format!("{name}.vcf"));
+        let state_path = app_dir.join(SyncState::path(FileType::Contacts, &name));
+        match sync_address_book(client, &address_book, &file_path, &state_path, |conflict| resolve(env, &name, conflict)) {
+            Ok(report) => {
+                println!("Synced address book {name:?}: {report}");
+            },
+            Err(err) => {
+                println!("Error syncing address book {name:?} with {}: {err}", address_book.url);
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Ask the user which version to keep of a component of the collection with **name** that changed on both sides.
+///
+/// If the user can't be asked, the local version is kept until the conflict comes up again.
+fn resolve(env: &mut JNIEnv, name: &str, conflict: Conflict) -> Option<Side> {
+    crate::show_event_conflict_dialog(env, name, &conflict.summary())
+        .unwrap_or_else(|err| {
+            println!("Could not ask which version of {:?} to keep: {err}", conflict.summary());
+            None
+        })
+}
+
+/// Create the Calendar with **name** from the feed at **url**, and store the [`Subscription`] so that it can be refreshed.
+///
+/// The steps are done in a [`Journal`], so they are undone if one fails.
+fn subscribe_calendar<'local>(
+    env: &mut JNIEnv<'local>,
+    perm: &JObject<'local>,
+    name: &str,
+    url: &str,
+    color: &JObject<'local>,
+) -> Result<(), crate::error::Error> {
+    if name.contains(ILLEGAL_FILE_CHARACTERS) {
+        return Err(crate::error::Error::InvalidUri(format!("Calendar name can't contain the following characters: {ILLEGAL_FILE_CHARACTERS:?}")));
+    }
+    let mut subscription = Subscription::new(url)?;
+    let context = call!(perm.getContext() -> android.content.Context);
+    let app_dir = get_app_dir(env, &context);
+    let file_name = format!("{name}.ics");
+    let path = PathBuf::from(FileType::Calendar.dir()).join(&file_name);
+
+    let mut journal = Journal::begin(env, &context, &format!("subscribe {file_name}"))?;
+    let result = (|| -> Result<(), crate::error::Error> {
+        std::fs::File::create_new(app_dir.join(&path))
+            .map_err(|err| crate::error::Error::io("Error creating file in internal directory", err))?;
+        journal.record(Step::CreateInternal(path.clone()))?;
+        subscription.update_file(&app_dir, name)?;
+
+        journal.record(Step::CreateCalendar(name.to_string()))?;
+        calendar::write_file_data_to_calendar(env, perm, name, color)?;
+        calendar::set_read_only(env, &context, name, true)?;
+
+        let subscription_path = Subscription::path(name);
+        subscription.write(&app_dir.join(&subscription_path))?;
+        journal.record(Step::CreateInternal(subscription_path))?;
+        Ok(())
+    })();
+
+    journal.finish(env, &context, Some(perm), result)
+}
+
+/// Fetch the feed of the Calendar with **name**, and if it changed, write it to the Calendar's file and to the *Content Provider*.
+///
+/// Returns whether the feed had changed.
+fn refresh_subscription<'local>(env: &mut JNIEnv<'local>, perm: &JObject<'local>, name: &str) -> Result<bool, crate::error::Error> {
+    let context = call!(perm.getContext() -> android.content.Context);
+    let app_dir = get_app_dir(env, &context);
+    let subscription_path = app_dir.join(Subscription::path(name));
+    let mut subscription = Subscription::read(&subscription_path)?
+        .ok_or_else(|| crate::error::Error::NotFound(format!("Calendar {name:?} is not subscribed to a feed")))?;
+
+    if !subscription.update_file(&app_dir, name)? {
+        return Ok(false);
+    }
+    calendar::write_file_data_to_calendar(env, perm, name, &JObject::null())?;
+    calendar::set_read_only(env, &context, name, true)?;
+    // The validators are only stored once the Calendar is up to date, so that the feed is fetched again if writing it failed
+    subscription.write(&subscription_path)?;
+
+    Ok(true)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A URL (or an `href` from the server) is not valid.
+    InvalidUrl(String),
+    /// Couldn't connect to the server.
+    Transport(String),
+    /// The server responded with an error **status** (other than `412`).
+    Status { method: &'static str, url: String, status: u16 },
+    /// The credentials can't be used anymore (e.g. the *OAuth 2.0* refresh token was revoked), so the user has to sign in again.
+    Unauthorized(String),
+    /// The resource at the URL changed (or already existed), so the `If-Match` or `If-None-Match` precondition failed.
+    PreconditionFailed(String),
+    /// The server's response could not be understood.
+    InvalidResponse(String),
+    Io(io::Error),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUrl(msg) => write!(f, "Invalid URL: {msg}"),
+            Self::Transport(msg) => write!(f, "Error connecting to server: {msg}"),
+            Self::Status { method, url, status } => write!(f, "{method} {url:?} failed with status {status}"),
+            Self::Unauthorized(msg) => write!(f, "Authentication failed: {msg}"),
+            Self::PreconditionFailed(url) => write!(f, "Resource {url:?} was modified in the server"),
+            Self::InvalidResponse(msg) => write!(f, "Invalid response from server: {msg}"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// An error for a local file (e.g. an outbox, a calendar file, a sync state, or a subscription) that has invalid content,
+/// which is not an [`Error::InvalidResponse`] because it didn't come from the server.
+fn invalid_data(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// The `Depth` header of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    Zero,
+    One,
+}
+impl Depth {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Zero => "0",
+            Self::One => "1",
+        }
+    }
+}
+
+/// The condition that must be met in the server for a write to happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition<'a> {
+    /// Always write.
+    None,
+    /// Only write if the resource doesn't exist (`If-None-Match: *`).
+    Create,
+    /// Only write if the resource has not changed since it had this ETag (`If-Match`).
+    Match(&'a str),
+}
+
+/// The content of a resource that was fetched from the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub url: Url,
+    pub etag: Option<String>,
+    pub data: String,
+}
+
+/// The members of a collection that changed since a `sync-token`, listed by [`Client::sync_collection()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// The token that represents the current state of the collection, to be used in the next sync.
+    pub sync_token: String,
+    /// The URLs and ETags of the members that were created or modified.
+    pub changed: Vec<(Url, Option<String>)>,
+    pub deleted: Vec<Url>,
+}
+
+/// Sends requests to a *WebDAV* server.
+pub struct Client {
+    agent: ureq::Agent,
+    base_url: Url,
+    auth: Option<auth::Session>,
+    multiget_batch_size: usize,
+}
+impl Client {
+    pub fn new(base_url: &str, auth: Option<Auth>) -> Result<Self, Error> {
+        let mut base_url = Url::parse(base_url)
+            .map_err(|err| Error::InvalidUrl(format!("{base_url:?}: {err}")))?;
+        // The base URL is a collection, so the hrefs have to be resolved relative to it and not its parent.
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        Ok(Self {
+            agent: ureq::AgentBuilder::new()
+                .user_agent(concat!("DavSync/", env!("CARGO_PKG_VERSION")))
+                .build(),
+            base_url,
+            auth: auth.map(auth::Session::new),
+            multiget_batch_size: DEFAULT_MULTIGET_BATCH_SIZE,
+        })
+    }
+
+    /// Set the maximum number of resources that are requested in each multiget `REPORT`,
+    /// or [`DEFAULT_MULTIGET_BATCH_SIZE`] if **batch_size** is 0.
+    pub fn set_multiget_batch_size(&mut self, batch_size: usize) {
+        self.multiget_batch_size = if batch_size == 0 { DEFAULT_MULTIGET_BATCH_SIZE } else { batch_size };
+    }
+
+    pub fn multiget_batch_size(&self) -> usize {
+        self.multiget_batch_size
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// The tokens of the *OAuth 2.0* account if they were refreshed while the client was used,
+    /// so that they can be saved for the next time the client is created.
+    pub fn refreshed_tokens(&self) -> Option<OAuth2> {
+        self.auth.as_ref().and_then(auth::Session::refreshed_tokens)
+    }
+
+    /// Resolve an **href** (which is usually an absolute path) relative to the **base** URL.
+    pub fn url(&self, base: &Url, href: &str) -> Result<Url, Error> {
+        base.join(href)
+            .map_err(|err| Error::InvalidUrl(format!("{href:?}: {err}")))
+    }
+
+    fn request(&self, method: &str, url: &Url) -> ureq::Request {
+        self.agent.request_url(method, url)
+    }
+
+    /// Send a **request** with the `Authorization` of the client's [`Auth`], converting the error statuses to [`Error`]s.
+    ///
+    /// If the server responds with `401 Unauthorized`, the request is sent once more if the [`Auth`] can do something about it
+    /// (answer a *Digest* challenge, or refresh the *OAuth 2.0* access token).
+    fn send(&self, method: &'static str, request: ureq::Request, body: Option<&str>) -> Result<ureq::Response, Error> {
+        let url = request.url().to_string();
+        let request = match &self.auth {
+            Some(session) => session.authorize(&self.agent, request)?,
+            None => request,
+        };
+        let result = match body {
+            Some(body) => request.clone().send_string(body),
+            None => request.clone().call(),
+        };
+        let result = match (result, &self.auth) {
+            (Err(ureq::Error::Status(401, response)), Some(session)) => match session.reauthorize(&self.agent, &request, &response)? {
+                Some(request) => match body {
+                    Some(body) => request.send_string(body),
+                    None => request.call(),
+                },
+                None => Err(ureq::Error::Status(401, response)),
+            },
+            (result, _) => result,
+        };
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(412, _)) => Err(Error::PreconditionFailed(url)),
+            Err(ureq::Error::Status(status, _)) => Err(Error::Status { method, url, status }),
+            Err(ureq::Error::Transport(err)) => Err(Error::Transport(err.to_string())),
+        }
+    }
+
+    /// Get the **props** of the resource at **url** (and of its members if **depth** is [`Depth::One`]).
+    pub fn propfind(&self, url: &Url, depth: Depth, props: &[xml::Name]) -> Result<Vec<xml::Response>, Error> {
+        let request = self.request("PROPFIND", url)
+            .set("Depth", depth.as_str())
+            .set("Content-Type", "application/xml; charset=utf-8");
+        let body = self.send("PROPFIND", request, Some(&xml::propfind(props)))?
+            .into_string()?;
+
+        xml::parse_multistatus(&body).map_err(Error::InvalidResponse)
+    }
+
+    /// Find the URL of the principal of the authenticated user.
+    ///
+    /// First tries the base URL, and then the **well_known** URL of the service (e.g. `/.well-known/caldav`, RFC 6764).
+    pub fn current_user_principal(&self, well_known: &str) -> Result<Url, Error> {
+        let well_known = self.url(self.base_url(), well_known)?;
+        let mut last_error = None;
+
+        for url in [self.base_url().clone(), well_known] {
+            match self.propfind(&url, Depth::Zero, &[xml::CURRENT_USER_PRINCIPAL]) {
+                Ok(responses) => {
+                    let href = responses.iter()
+                        .filter_map(|response| response.prop(xml::CURRENT_USER_PRINCIPAL))
+                        .find_map(|prop| prop.hrefs.first());
+                    if let Some(href) = href {
+                        return self.url(&url, href);
+                    }
+                },
+                Err(err @ Error::Status { .. }) => last_error = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| Error::InvalidResponse("Server did not return the current-user-principal".to_string())))
+    }
+
+    /// List the URLs and ETags of the resources in a **collection**.
+    pub fn resources(&self, collection: &Url) -> Result<Vec<(Url, Option<String>)>, Error> {
+        let responses = self.propfind(collection, Depth::One, &[xml::RESOURCE_TYPE, xml::GET_ETAG])?;
+
+        responses.iter()
+            // Skip the collection itself (and any sub-collections)
+            .filter(|response| !response.prop(xml::RESOURCE_TYPE).is_some_and(|prop| prop.has(xml::COLLECTION)))
+            .filter(|response| response.status.is_none_or(|status| status == 200))
+            .map(|response| Ok((
+                self.url(collection, &response.href)?,
+                response.text(xml::GET_ETAG).map(str::to_string),
+            )))
+            .filter(|result| !matches!(result, Ok((url, _)) if url == collection))
+            .collect()
+    }
+
+    /// List the members of a **collection** that changed or were deleted since it had **sync_token**,
+    /// with a `sync-collection` `REPORT` (RFC 6578). If **sync_token** is [`None`], all members are listed as changed.
+    ///
+    /// If the server truncated the results (`507` for the collection), the rest is requested with the token it returned.
+    /// Servers that don't support the report, or no longer accept the token, respond with an [`Error::Status`].
+    pub fn sync_collection(&self, collection: &Url, sync_token: Option<&str>) -> Result<Changes, Error> {
+        let mut changes = Changes::default();
+        let mut sync_token = sync_token.map(str::to_string);
+
+        loop {
+            let request = self.request("REPORT", collection)
+                .set("Depth", Depth::Zero.as_str())
+                .set("Content-Type", "application/xml; charset=utf-8");
+            let body = self.send("REPORT", request, Some(&xml::sync_collection(sync_token.as_deref(), &[xml::GET_ETAG])))?
+                .into_string()?;
+            let (responses, new_token) = xml::parse_multistatus_with_token(&body).map_err(Error::InvalidResponse)?;
+            let new_token = new_token
+                .ok_or_else(|| Error::InvalidResponse(format!("Server did not return a sync-token for {collection}")))?;
+
+            let mut truncated = false;
+            for response in &responses {
+                let url = self.url(collection, &response.href)?;
+                if url == *collection {
+                    truncated |= response.status == Some(507);
+                    continue;
+                }
+                // A later page of the results replaces what an earlier one said about the same member
+                changes.changed.retain(|(changed, _)| *changed != url);
+                changes.deleted.retain(|deleted| *deleted != url);
+                match response.status {
+                    Some(404) => changes.deleted.push(url),
+                    Some(status) if status != 200 => continue,
+                    _ => changes.changed.push((url, response.text(xml::GET_ETAG).map(str::to_string))),
+                }
+            }
+
+            // Stop if the server returned the same token, so that a misbehaving server can't make this loop forever
+            let done = !truncated || sync_token.as_deref() == Some(new_token.as_str());
+            changes.sync_token = new_token.clone();
+            if done {
+                return Ok(changes);
+            }
+            sync_token = Some(new_token);
+        }
+    }
+
+    /// Fetch the resources at **urls** in the **collection** with a multiget **report** (`calendar-multiget` or `addressbook-multiget`),
+    /// which returns their ETags and their content in the **data** property (`calendar-data` or `address-data`).
+    ///
+    /// The response is parsed while it is being read, and **on_resource** is called with each resource as soon as it is parsed.
+    /// Resources the server did not return (e.g. because they were deleted) are skipped.
+    /// Use [`multiget_batch_size()`](Self::multiget_batch_size()) to split **urls** into smaller requests.
+    pub fn multiget(
+        &self,
+        collection: &Url,
+        report: xml::Name,
+        data: xml::Name,
+        urls: &[Url],
+        mut on_resource: impl FnMut(Resource) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let hrefs = urls.iter().map(Url::path).collect::<Vec<_>>();
+        let request = self.request("REPORT", collection)
+            .set("Depth", Depth::One.as_str())
+            .set("Content-Type", "application/xml; charset=utf-8");
+        let response = self.send("REPORT", request, Some(&xml::multiget(report, &[xml::GET_ETAG, data], &hrefs)))?;
+
+        // The error of the first resource that could not be handled. The rest of the response is then ignored.
+        let mut result = Ok(());
+        xml::read_multistatus(io::BufReader::new(response.into_reader()), |response| {
+            if result.is_err() {
+                return;
+            }
+            let Some(content) = response.prop(data) else { return };
+            result = self.url(collection, &response.href)
+                .and_then(|url| on_resource(Resource {
+                    url,
+                    // Weak ETags can't be used in If-Match, like in etag_header()
+                    etag: response.text(xml::GET_ETAG)
+                        .filter(|etag| !etag.starts_with("W/"))
+                        .map(str::to_string),
+                    data: content.text.clone(),
+                }));
+        }).map_err(Error::InvalidResponse)?;
+
+        result
+    }
+
+    /// Fetch the resource at **url**.
+    ///
+    /// If **etag** is [`Some`], the request has `If-None-Match`,
+    /// and returns [`None`] if the resource has not changed since it had that ETag.
+    pub fn get(&self, url: &Url, etag: Option<&str>) -> Result<Option<Resource>, Error> {
+        let mut request = self.request("GET", url);
+        if let Some(etag) = etag {
+            request = request.set("If-None-Match", etag);
+        }
+        let response = self.send("GET", request, None)?;
+        if response.status() == 304 {
+            return Ok(None);
+        }
+
+        Ok(Some(Resource {
+            url: url.clone(),
+            etag: etag_header(&response),
+            data: response.into_string()?,
+        }))
+    }
+
+    /// Write **data** to the resource at **url**.
+    ///
+    /// Returns the new ETag of the resource if the server sent it.
+    /// Returns [`Error::PreconditionFailed`] if the **precondition** was not met.
+    pub fn put(&self, url: &Url, data: &str, content_type: &str, precondition: Precondition) -> Result<Option<String>, Error> {
+        let request = precondition.apply(self.request("PUT", url))
+            .set("Content-Type", content_type);
+        let response = self.send("PUT", request, Some(data))?;
+
+        Ok(etag_header(&response))
+    }
+
+    /// Delete the resource at **url**.
+    ///
+    /// Returns [`Error::PreconditionFailed`] if the **precondition** was not met.
+    /// Deleting a resource that doesn't exist is not an error.
+    pub fn delete(&self, url: &Url, precondition: Precondition) -> Result<(), Error> {
+        let request = precondition.apply(self.request("DELETE", url));
+        match self.send("DELETE", request, None) {
+            Ok(_) | Err(Error::Status { status: 404, .. }) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Precondition<'_> {
+    fn apply(self, request: ureq::Request) -> ureq::Request {
+        match self {
+            Self::None => request,
+            Self::Create => request.set("If-None-Match", "*"),
+            Self::Match(etag) => request.set("If-Match", etag),
+        }
+    }
+}
+
+/// Get the **ETag** of a response, only if it is a *strong* ETag.
+///
+/// Weak ETags can't be used in `If-Match`.
+fn etag_header(response: &ureq::Response) -> Option<String> {
+    response.header("ETag")
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(str::to_string)
+}
+
+/// Remove the characters from **name** that can't be in a file name.
+fn sanitize_file_name(name: &str) -> String {
+    name.replace(ILLEGAL_FILE_CHARACTERS, "")
+        .trim()
+        .to_string()
+}
+
+/// The name of the file (without extension) where a collection at **url** will be stored.
+///
+/// Uses the **display_name**, or the last segment of the **url** (or **fallback**) if it doesn't have one.
+fn file_stem(url: &Url, display_name: Option<&str>, fallback: &str) -> String {
+    display_name
+        .map(sanitize_file_name)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| last_segment(url).unwrap_or_else(|| fallback.to_string()))
+}
+
+/// The last segment of the path of **url** (decoded, and without the characters that can't be in a file name).
+fn last_segment(url: &Url) -> Option<String> {
+    url.path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .map(|segment| sanitize_file_name(&percent_decode_str(segment).decode_utf8_lossy()))
+        .filter(|segment| !segment.is_empty())
+}
+
+/// Make the [file stems](file_stem()) of the **collections** (with their URL) unique,
+/// so that two collections with the same name (e.g. in different homes) are not synced into the same file.
+///
+/// The last segment of the URL is added to the names that more than one collection has.
+/// The names are compared ignoring case, because the *external directory* can be in a file system that does.
+/// Returns [`None`] for the collections whose name is still not unique, which must not be synced.
+fn unique_file_stems<'a>(collections: impl IntoIterator<Item = (&'a Url, String)>) -> Vec<Option<String>> {
+    let collections = collections.into_iter().collect::<Vec<_>>();
+    let count = |names: &[String], name: &str| names.iter().filter(|other| other.eq_ignore_ascii_case(name)).count();
+
+    let stems = collections.iter().map(|(_, stem)| stem.clone()).collect::<Vec<_>>();
+    let names = collections.iter()
+        .map(|(url, stem)| match last_segment(url) {
+            Some(segment) if count(&stems, stem) > 1 && !segment.eq_ignore_ascii_case(stem) => format!("{stem} ({segment})"),
+            _ => stem.clone(),
+        })
+        .collect::<Vec<_>>();
+    names.iter()
+        .map(|name| (count(&names, name) == 1).then(|| name.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(path: &str) -> Url {
+        Url::parse("https://dav.example.com/").unwrap().join(path).unwrap()
+    }
+
+    #[test]
+    fn file_stems() {
+        assert_eq!(file_stem(&url("/cal/work/"), Some("Work: Team"), "calendar"), "Work Team");
+        assert_eq!(file_stem(&url("/cal/my%20cal%C3%A9/"), Some(" "), "calendar"), "my calé");
+        assert_eq!(file_stem(&url("/"), None, "calendar"), "calendar");
+    }
+
+    #[test]
+    fn unique_names() {
+        let (personal, other, work) = (url("/cal/personal/"), url("/cal/other/"), url("/cal/work/"));
+        let stems = unique_file_stems([
+            (&personal, "Personal".to_string()),
+            (&other, "Personal".to_string()),
+            (&work, "Work".to_string()),
+        ]);
+        assert_eq!(stems, [Some("Personal".to_string()), Some("Personal (other)".to_string()), Some("Work".to_string())]);
+
+        // The same name in two homes, and names that only differ in case
+        let (home1, home2) = (url("/a/personal/"), url("/b/personal/"));
+        let stems = unique_file_stems([(&home1, "Personal".to_string()), (&home2, "personal".to_string())]);
+        assert_eq!(stems, [None, None]);
+    }
+}
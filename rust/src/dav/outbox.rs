@@ -0,0 +1,613 @@
+//! The changes to a synced calendar that were made while offline, which are uploaded in order before it is synced again.
+//!
+//! When the data of the *Content Provider* is written to a calendar file, the events that changed since the last sync
+//! are added to the calendar's [`Outbox`] ([`record_changes()`]), with the ETag their resource had when they were changed.
+//! [`replay()`] uploads them before the calendar is synced, so that a change that conflicts with one made in the server
+//! (`412 Precondition Failed`) is [merged](merge::merge()) instead of being overwritten by the server's version.
+//! Entries are only removed from the outbox once they have been uploaded, or when the server rejects them.
+use std::{fmt::Display, path::{Path, PathBuf}};
+use url::Url;
+use crate::{calendar::{ical::{self, Component}, merge::{self, Conflict, Side}}, file_type::FileType};
+use super::{sync::{self, Group, Kind, SyncState}, invalid_data, Client, Error, Precondition, STATE_DIR};
+
+/// The directory (in [`STATE_DIR`]) where the [`Outbox`] of each calendar is stored.
+const OUTBOX_DIR: &str = "outbox";
+
+/// What has to be done in the server with a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+impl Operation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+
+    fn parse(operation: &str) -> Option<Self> {
+        match operation {
+            "create" => Some(Self::Create),
+            "update" => Some(Self::Update),
+            "delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// A change to a resource that has not been uploaded yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub operation: Operation,
+    /// The `UID` of the components that are stored in the resource.
+    pub uid: String,
+    pub url: String,
+    /// The ETag the resource had in the server when it was changed. Always [`None`] for new resources.
+    pub etag: Option<String>,
+    /// Why the entry could not be uploaded the last time the outbox was replayed (its conflict was not resolved).
+    pub error: Option<String>,
+}
+
+/// The changes to a calendar that have not been uploaded yet, in the order they have to be uploaded.
+///
+/// There is at most one entry for each `UID`, because the version that is uploaded is always the one currently in the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outbox {
+    pub entries: Vec<Entry>,
+}
+impl Outbox {
+    /// The path (relative to the *app's directory*) of the file where the outbox of the calendar with **name** is stored.
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(STATE_DIR).join(OUTBOX_DIR).join(format!("{name}.tsv"))
+    }
+
+    /// Read the outbox from the file at **path**, which is empty if the file does not exist.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut outbox = Self::default();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let mut fields = line.split('\t');
+            let (Some(operation), Some(url), Some(etag), Some(uid)) = (fields.next().and_then(Operation::parse), fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid_data(format!("Invalid line in {path:?}: {line:?}")));
+            };
+            outbox.entries.push(Entry {
+                operation,
+                uid: uid.to_string(),
+                url: url.to_string(),
+                etag: Some(etag).filter(|etag| !etag.is_empty()).map(str::to_string),
+                error: fields.next().map(str::to_string),
+            });
+        }
+
+        Ok(outbox)
+    }
+
+    /// Write the outbox to the file at **path**, with a line of tab-separated values for each entry.
+    /// The file is deleted if the outbox is empty.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        if self.entries.is_empty() {
+            return match std::fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let mut content = String::new();
+        for entry in &self.entries {
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}",
+                entry.operation.as_str(), entry.url, entry.etag.as_deref().unwrap_or_default(), entry.uid
+            ));
+            if let Some(error) = &entry.error {
+                // The error is the last field, so it can't have line breaks or tabs
+                content.push_str(&format!("\t{}", error.replace(['\t', '\n', '\r'], " ")));
+            }
+            content.push('\n');
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(std::fs::write(path, content)?)
+    }
+
+    /// Add a change to the resource of **uid**, combining it with the entry that is already pending for that resource.
+    fn push(&mut self, operation: Operation, uid: &str, url: &str, etag: Option<String>) {
+        let Some(i) = self.entries.iter().position(|entry| entry.uid == uid) else {
+            self.entries.push(Entry { operation, uid: uid.to_string(), url: url.to_string(), etag, error: None });
+            return;
+        };
+
+        let pending = &mut self.entries[i];
+        match (pending.operation, operation) {
+            // The resource was never uploaded, so there is nothing to delete in the server
+            (Operation::Create, Operation::Delete) => { self.entries.remove(i); },
+            (Operation::Create, _) => {},
+            // The pending entry has the ETag of the version in the server that the change is based on
+            (Operation::Update | Operation::Delete, Operation::Create | Operation::Update) => pending.operation = Operation::Update,
+            (Operation::Update | Operation::Delete, Operation::Delete) => pending.operation = Operation::Delete,
+        }
+    }
+}
+
+/// What [`replay()`] did with the entries of an outbox.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub uploaded: usize,
+    pub deleted: usize,
+    /// Entries whose resource was also changed in the server, so the two versions were merged.
+    pub merged: usize,
+    /// The entries that are still in the outbox because their conflict with the server was not resolved.
+    pub pending: Vec<String>,
+    /// The entries that could not be uploaded for another reason (e.g. the server rejected them, or the event is gone),
+    /// with that reason. They are removed from the outbox so that they don't stop the calendar from being synced,
+    /// and the sync then handles their events like any other change in the file.
+    pub dropped: Vec<String>,
+}
+impl Display for ReplayReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,
+            "{} uploaded, {} deleted, {} merged, {} pending, {} dropped",
+            self.uploaded, self.deleted, self.merged, self.pending.len(), self.dropped.len()
+        )
+    }
+}
+
+/// Add the changes that were made to the **calendar** with **name** since it was last synced to its [`Outbox`] in **app_dir**.
+///
+/// Does nothing if the calendar is not synced with a server (it has no [`SyncState`]).
+pub fn record_changes(app_dir: &Path, name: &str, calendar: &Component) -> Result<(), Error> {
+    let state_path = app_dir.join(SyncState::path(FileType::Calendar, name));
+    let Some(state) = SyncState::read(&state_path)? else {
+        return Ok(());
+    };
+    let collection_url = Url::parse(&state.url)
+        .map_err(|err| Error::InvalidUrl(format!("{:?}: {err}", state.url)))?;
+    let outbox_path = app_dir.join(Outbox::path(name));
+    let mut outbox = Outbox::read(&outbox_path)?;
+    let (groups, _) = sync::split_groups(calendar.components.clone());
+
+    for group in &groups {
+        match state.resources.iter().find(|resource| resource.uid == group.uid) {
+            Some(known) if known.hash == sync::hash(&group.components) => {},
+            Some(known) => outbox.push(Operation::Update, &group.uid, &known.url, known.etag.clone()),
+            None => {
+                let resource_name = format!("{}.{}", sync::resource_name(&group.uid), Kind::Calendar.extension());
+                let url = collection_url.join(&resource_name)
+                    .map_err(|err| Error::InvalidUrl(format!("{resource_name:?}: {err}")))?;
+                outbox.push(Operation::Create, &group.uid, url.as_str(), None);
+            },
+        }
+    }
+    for known in &state.resources {
+        if !groups.iter().any(|group| group.uid == known.uid) {
+            outbox.push(Operation::Delete, &known.uid, &known.url, known.etag.clone());
+        }
+    }
+    // Events that were created and deleted again before they were uploaded
+    outbox.entries.retain(|entry| entry.operation != Operation::Create || groups.iter().any(|group| group.uid == entry.uid));
+
+    outbox.write(&outbox_path)
+}
+
+/// Upload the entries of the [`Outbox`] at **outbox_path** to the collection at **collection_url** in order,
+/// and update the calendar file at **file_path** and the [`SyncState`] at **state_path** with the result.
+///
+/// If the resource of an entry was changed in the server too, the two versions are [merged](merge::merge())
+/// and **resolve** is called with the components that changed on both sides ([`Side::Remote`] is the server's version).
+/// Entries whose conflict was not resolved are kept in the outbox with their error, and other entries that can't be uploaded are dropped.
+/// The rest are still uploaded, unless the server can't be reached, in which case all remaining entries are kept and the error is returned.
+pub fn replay(
+    client: &Client,
+    collection_url: &Url,
+    file_path: &Path,
+    state_path: &Path,
+    outbox_path: &Path,
+    mut resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Result<ReplayReport, Error> {
+    let mut outbox = Outbox::read(outbox_path)?;
+    let mut report = ReplayReport::default();
+    if outbox.entries.is_empty() {
+        return Ok(report);
+    }
+
+    let mut state = SyncState::read(state_path)?
+        .filter(|state| state.url == collection_url.as_str())
+        .ok_or_else(|| invalid_data(format!("Calendar has changes for {collection_url}, but it is not synced with it")))?;
+    let content = std::fs::read_to_string(file_path)?;
+    let mut calendar = ical::parse(&content)
+        .map_err(|err| invalid_data(format!("Invalid calendar file {file_path:?}: {err}")))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid_data(format!("Calendar file {file_path:?} has no VCALENDAR")))?;
+    let (mut groups, others) = sync::split_groups(std::mem::take(&mut calendar.components));
+    let (mut timezones, others) = others.into_iter().partition::<Vec<_>, _>(|comp| comp.name == "VTIMEZONE");
+
+    let mut result = Ok(());
+    let mut entries = std::mem::take(&mut outbox.entries).into_iter();
+    for mut entry in entries.by_ref() {
+        match replay_entry(client, &entry, &mut groups, &mut timezones, &mut state, &mut resolve) {
+            Ok(Outcome::Uploaded) => report.uploaded += 1,
+            Ok(Outcome::Deleted) => report.deleted += 1,
+            Ok(Outcome::Merged) => report.merged += 1,
+            Ok(Outcome::Unchanged) => {},
+            Err(err @ Error::Transport(_)) => {
+                outbox.entries.push(entry);
+                result = Err(err);
+                break;
+            },
+            Err(err @ Error::PreconditionFailed(_)) => {
+                report.pending.push(format!("{}: {err}", entry.uid));
+                entry.error = Some(err.to_string());
+                outbox.entries.push(entry);
+            },
+            // Retrying won't fix it
+            Err(err) => report.dropped.push(format!("{}: {err}", entry.uid)),
+        }
+    }
+    outbox.entries.extend(entries);
+
+    // Write the changes that were merged to the file, and the new ETags to the state
+    calendar.components = timezones;
+    calendar.components.extend(others);
+    calendar.components.extend(groups.into_iter().flat_map(|group| group.components));
+    std::fs::write(file_path, calendar.to_string())?;
+    state.write(state_path)?;
+    outbox.write(outbox_path)?;
+
+    result.map(|()| report)
+}
+
+/// What happened to an [`Entry`] when it was replayed.
+enum Outcome {
+    Uploaded,
+    Deleted,
+    Merged,
+    /// The change was undone before it could be uploaded.
+    Unchanged,
+}
+
+/// Upload the change of an **entry** and update the **state** of its resource.
+fn replay_entry(
+    client: &Client,
+    entry: &Entry,
+    groups: &mut Vec<Group>,
+    timezones: &mut Vec<Component>,
+    state: &mut SyncState,
+    resolve: &mut impl FnMut(Conflict) -> Option<Side>,
+) -> Result<Outcome, Error> {
+    let url = Url::parse(&entry.url)
+        .map_err(|err| Error::InvalidUrl(format!("{:?}: {err}", entry.url)))?;
+    let precondition = match (entry.operation, entry.etag.as_deref()) {
+        (Operation::Create, _) => Precondition::Create,
+        (_, Some(etag)) => Precondition::Match(etag),
+        (_, None) => Precondition::None,
+    };
+
+    let result = match (entry.operation, groups.iter().find(|group| group.uid == entry.uid)) {
+        (Operation::Delete, _) => client.delete(&url, precondition).map(|()| None),
+        (_, Some(group)) => {
+            let hash = sync::hash(&group.components);
+            if state.resources.iter().any(|known| known.uid == entry.uid && known.etag == entry.etag && known.hash == hash) {
+                return Ok(Outcome::Unchanged);
+            }
+            sync::upload(client, Kind::Calendar, &url, group, timezones, precondition).map(Some)
+        },
+        (_, None) => return Err(invalid_data(format!("Event {:?} is no longer in the calendar file", entry.uid))),
+    };
+
+    match result {
+        Ok(uploaded) => {
+            state.resources.retain(|known| known.uid != entry.uid);
+            match (uploaded, groups.iter().find(|group| group.uid == entry.uid)) {
+                (Some(etag), Some(group)) => {
+                    state.resources.push(sync::resource_state(group, &url, etag));
+                    Ok(Outcome::Uploaded)
+                },
+                _ => Ok(Outcome::Deleted),
+            }
+        },
+        Err(Error::PreconditionFailed(_)) => {
+            merge_with_server(client, entry, &url, groups, timezones, state, resolve)?;
+            Ok(Outcome::Merged)
+        },
+        Err(err) => Err(err),
+    }
+}
+
+/// Merge the version of the resource of an **entry** in the file with the one in the server, which changed since the entry was made,
+/// and upload the merged version (or delete the resource if it was deleted in the merged version).
+///
+/// Returns [`Error::PreconditionFailed`] if **resolve** canceled the merge.
+fn merge_with_server(
+    client: &Client,
+    entry: &Entry,
+    url: &Url,
+    groups: &mut Vec<Group>,
+    timezones: &mut Vec<Component>,
+    state: &mut SyncState,
+    resolve: &mut impl FnMut(Conflict) -> Option<Side>,
+) -> Result<(), Error> {
+    let remote = match client.get(url, None) {
+        Ok(resource) => resource,
+        // Deleted in the server
+        Err(Error::Status { status: 404, .. }) => None,
+        Err(err) => return Err(err),
+    };
+
+    let mut remote_calendar = Component::new("VCALENDAR");
+    if let Some(resource) = &remote {
+        for component in Kind::Calendar.parse(url, &resource.data)? {
+            if component.name == "VTIMEZONE" {
+                if !timezones.iter().any(|tz| tz.value("TZID") == component.value("TZID")) {
+                    timezones.push(component);
+                }
+            } else if component.value("UID") == Some(&entry.uid) {
+                remote_calendar.components.push(component);
+            }
+        }
+    }
+    // The merged version is put where the local version was, so that the order of the file doesn't change
+    let position = groups.iter().position(|group| group.uid == entry.uid);
+    let mut local_calendar = Component::new("VCALENDAR");
+    if let Some(i) = position {
+        local_calendar.components = groups.remove(i).components;
+    }
+
+    let merged = match (local_calendar.components.first(), remote_calendar.components.first()) {
+        // Without a base, merge() would always keep the side that was not deleted
+        (Some(_), None) | (None, Some(_)) => match resolve(Conflict { local: local_calendar.components.first(), remote: remote_calendar.components.first() }) {
+            Some(Side::Local) => Some(local_calendar.components.clone()),
+            Some(Side::Remote) => Some(remote_calendar.components.clone()),
+            None => None,
+        },
+        _ => merge::merge(None, &local_calendar, &remote_calendar, &mut *resolve).map(|merged| merged.components),
+    };
+    let Some(merged) = merged else {
+        // Keep the local version until the conflict is resolved
+        groups.insert(position.unwrap_or(groups.len()), Group { uid: entry.uid.clone(), components: local_calendar.components });
+        return Err(Error::PreconditionFailed(url.to_string()));
+    };
+    let precondition = match &remote {
+        Some(resource) => resource.etag.as_deref().map_or(Precondition::None, Precondition::Match),
+        None => Precondition::Create,
+    };
+
+    state.resources.retain(|known| known.uid != entry.uid);
+    if merged.is_empty() {
+        if remote.is_some() {
+            client.delete(url, precondition)?;
+        }
+        return Ok(());
+    }
+    let group = Group { uid: entry.uid.clone(), components: merged };
+    let etag = match remote {
+        // The server already has the merged version
+        Some(resource) if group.components == remote_calendar.components => resource.etag,
+        _ => sync::upload(client, Kind::Calendar, url, &group, timezones, precondition)?,
+    };
+    state.resources.push(sync::resource_state(&group, url, etag));
+    groups.insert(position.unwrap_or(groups.len()), group);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::{
+        sync_calendar,
+        test_calendar::{collection, edit_file, event, new_event, remote_summary, set_summary},
+        test_server::{temp_dir, Response, Server},
+    };
+
+    fn entry(operation: Operation, uid: &str, etag: Option<&str>) -> Entry {
+        Entry { operation, uid: uid.to_string(), url: format!("http://localhost/cal/{uid}.ics"), etag: etag.map(str::to_string), error: None }
+    }
+
+    fn pushed(changes: &[(Operation, Option<&str>)]) -> Vec<Entry> {
+        let mut outbox = Outbox::default();
+        for (operation, etag) in changes {
+            outbox.push(*operation, "a", "http://localhost/cal/a.ics", etag.map(str::to_string));
+        }
+        outbox.entries
+    }
+
+    /// A calendar that was synced with a **server** that has the events `a` and `b`, in the *app's directory* **app_dir**.
+    /// Returns the paths of the calendar file, its [`SyncState`] and its [`Outbox`].
+    fn synced_calendar(server: &Server, app_dir: &Path) -> (PathBuf, PathBuf, PathBuf) {
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let file_path = app_dir.join(FileType::Calendar.dir()).join("Test.ics");
+        let state_path = app_dir.join(SyncState::path(FileType::Calendar, "Test"));
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+        let client = Client::new(&server.url, None).unwrap();
+        sync_calendar(&client, &collection(server), "Test", &file_path, &state_path, |_| None).unwrap();
+        (file_path, state_path, app_dir.join(Outbox::path("Test")))
+    }
+
+    /// Change the calendar file at **path** with **edit**, and record the changes in its outbox.
+    fn edit_local(app_dir: &Path, path: &Path, edit: impl FnOnce(&mut Vec<Component>)) {
+        let calendar = edit_file(path, edit);
+        record_changes(app_dir, "Test", &calendar).unwrap();
+    }
+
+    #[test]
+    fn push_combines_changes() {
+        use Operation::*;
+        assert_eq!(pushed(&[(Create, None), (Update, Some("\"1\""))]), [entry(Create, "a", None)]);
+        assert_eq!(pushed(&[(Create, None), (Delete, Some("\"1\""))]), []);
+        // The ETag of the first change is kept, because it is the version in the server that the changes are based on
+        assert_eq!(pushed(&[(Update, Some("\"1\"")), (Update, Some("\"2\""))]), [entry(Update, "a", Some("\"1\""))]);
+        assert_eq!(pushed(&[(Update, Some("\"1\"")), (Delete, Some("\"2\""))]), [entry(Delete, "a", Some("\"1\""))]);
+        assert_eq!(pushed(&[(Delete, Some("\"1\"")), (Create, None)]), [entry(Update, "a", Some("\"1\""))]);
+
+        let mut outbox = Outbox::default();
+        outbox.push(Update, "b", "http://localhost/cal/b.ics", Some("\"1\"".to_string()));
+        outbox.push(Create, "a", "http://localhost/cal/a.ics", None);
+        outbox.push(Delete, "b", "http://localhost/cal/b.ics", Some("\"1\"".to_string()));
+        assert_eq!(outbox.entries, [entry(Delete, "b", Some("\"1\"")), entry(Create, "a", None)]);
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = temp_dir("outbox-read");
+        let path = dir.join(Outbox::path("Test"));
+        assert_eq!(path.extension().and_then(|ext| ext.to_str()), Some("tsv"));
+        assert_eq!(Outbox::read(&path).unwrap(), Outbox::default());
+
+        let mut failed = entry(Operation::Update, "b", Some("\"2\""));
+        failed.error = Some("Resource\twas\r\nmodified".to_string());
+        let outbox = Outbox { entries: vec![entry(Operation::Create, "a", None), failed, entry(Operation::Delete, "c", Some("\"3\""))] };
+        outbox.write(&path).unwrap();
+        let mut expected = outbox.clone();
+        expected.entries[1].error = Some("Resource was  modified".to_string());
+        assert_eq!(Outbox::read(&path).unwrap(), expected);
+
+        Outbox::default().write(&path).unwrap();
+        assert!(!path.exists());
+
+        std::fs::write(&path, "move\thttp://localhost/cal/a.ics\t\ta\n").unwrap();
+        assert!(matches!(Outbox::read(&path), Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn record_and_replay_in_order() {
+        let server = Server::start();
+        let app_dir = temp_dir("outbox-replay");
+        let (file_path, state_path, outbox_path) = synced_calendar(&server, &app_dir);
+        let etag_a = server.state().resources["/cal/a.ics"].0.clone();
+        let etag_b = server.state().resources["/cal/b.ics"].0.clone();
+
+        edit_local(&app_dir, &file_path, |components| {
+            set_summary(components, "a", "A local");
+            components.retain(|comp| comp.value("UID") != Some("b"));
+            components.push(new_event("c", "C"));
+        });
+        // Created and deleted before it was uploaded
+        edit_local(&app_dir, &file_path, |components| components.push(new_event("d", "D")));
+        edit_local(&app_dir, &file_path, |components| {
+            set_summary(components, "a", "A local again");
+            components.retain(|comp| comp.value("UID") != Some("d"));
+        });
+        let outbox = Outbox::read(&outbox_path).unwrap();
+        let summary = outbox.entries.iter().map(|entry| (entry.operation, entry.uid.as_str(), entry.etag.clone())).collect::<Vec<_>>();
+        assert_eq!(summary, [
+            (Operation::Update, "a", Some(etag_a)),
+            (Operation::Create, "c", None),
+            (Operation::Delete, "b", Some(etag_b)),
+        ]);
+
+        let client = Client::new(&server.url, None).unwrap();
+        server.state().requests.clear();
+        let report = replay(&client, &server.url("/cal/"), &file_path, &state_path, &outbox_path, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, ReplayReport { uploaded: 2, deleted: 1, ..Default::default() });
+        let requests = server.state().requests.iter().map(|req| format!("{} {}", req.method, req.path)).collect::<Vec<_>>();
+        assert_eq!(requests, ["PUT /cal/a.ics", "PUT /cal/c.ics", "DELETE /cal/b.ics"]);
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A local again"));
+        assert_eq!(remote_summary(&server, "/cal/c.ics").as_deref(), Some("C"));
+        assert_eq!(remote_summary(&server, "/cal/b.ics"), None);
+        assert!(!outbox_path.exists());
+
+        // The state has the new ETags, so there is nothing to record or upload
+        let calendar = ical::parse(&std::fs::read_to_string(&file_path).unwrap()).unwrap().remove(0);
+        record_changes(&app_dir, "Test", &calendar).unwrap();
+        assert!(!outbox_path.exists());
+    }
+
+    #[test]
+    fn merge_when_changed_in_server() {
+        let server = Server::start();
+        let app_dir = temp_dir("outbox-merge");
+        let (file_path, state_path, outbox_path) = synced_calendar(&server, &app_dir);
+        edit_local(&app_dir, &file_path, |components| set_summary(components, "a", "A local"));
+        server.state().put("/cal/a.ics", &event("a", "A remote"));
+
+        let client = Client::new(&server.url, None).unwrap();
+        let mut asked = Vec::new();
+        let report = replay(&client, &server.url("/cal/"), &file_path, &state_path, &outbox_path, |conflict| {
+            asked.push(conflict.summary());
+            Some(Side::Local)
+        }).unwrap();
+        assert_eq!(asked, ["A local"]);
+        assert_eq!(report, ReplayReport { merged: 1, ..Default::default() });
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A local"));
+        assert!(!outbox_path.exists());
+    }
+
+    #[test]
+    fn keep_entries_until_conflict_is_resolved() {
+        let server = Server::start();
+        let app_dir = temp_dir("outbox-unresolved");
+        let (file_path, state_path, outbox_path) = synced_calendar(&server, &app_dir);
+        edit_local(&app_dir, &file_path, |components| set_summary(components, "a", "A local"));
+        server.state().put("/cal/a.ics", &event("a", "A remote"));
+
+        let client = Client::new(&server.url, None).unwrap();
+        let report = replay(&client, &server.url("/cal/"), &file_path, &state_path, &outbox_path, |_| None).unwrap();
+        assert_eq!((report.pending.len(), report.dropped.len()), (1, 0));
+        assert!(report.pending[0].starts_with("a: "));
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A remote"));
+
+        let outbox = Outbox::read(&outbox_path).unwrap();
+        assert_eq!(outbox.entries.len(), 1);
+        assert_eq!((outbox.entries[0].uid.as_str(), outbox.entries[0].error.is_some()), ("a", true));
+    }
+
+    #[test]
+    fn keep_entries_when_server_is_unreachable() {
+        let server = Server::start();
+        let app_dir = temp_dir("outbox-unreachable");
+        let (file_path, state_path, outbox_path) = synced_calendar(&server, &app_dir);
+        edit_local(&app_dir, &file_path, |components| {
+            set_summary(components, "a", "A local");
+            set_summary(components, "b", "B local");
+        });
+        // Nothing listens on port 1
+        let mut outbox = Outbox::read(&outbox_path).unwrap();
+        for entry in &mut outbox.entries {
+            entry.url = entry.url.replace(&server.url, "http://127.0.0.1:1/");
+        }
+        outbox.write(&outbox_path).unwrap();
+
+        let client = Client::new(&server.url, None).unwrap();
+        let result = replay(&client, &server.url("/cal/"), &file_path, &state_path, &outbox_path, |_| None);
+        assert!(matches!(result, Err(Error::Transport(_))));
+        assert_eq!(Outbox::read(&outbox_path).unwrap(), outbox);
+    }
+
+    #[test]
+    fn drop_entries_that_fail() {
+        let server = Server::with_handler(|request, _| {
+            (request.method == "PUT" && request.path == "/cal/a.ics").then(|| Response::new(403, ""))
+        });
+        let app_dir = temp_dir("outbox-fail");
+        let (file_path, state_path, outbox_path) = synced_calendar(&server, &app_dir);
+        edit_local(&app_dir, &file_path, |components| {
+            set_summary(components, "a", "A local");
+            set_summary(components, "b", "B local");
+            components.push(new_event("c", "C"));
+        });
+        // Removed without recording the change
+        edit_file(&file_path, |components| components.retain(|comp| comp.value("UID") != Some("b")));
+
+        let client = Client::new(&server.url, None).unwrap();
+        let report = replay(&client, &server.url("/cal/"), &file_path, &state_path, &outbox_path, |_| None).unwrap();
+        assert_eq!((report.uploaded, report.pending.len()), (1, 0));
+        assert_eq!(report.dropped.len(), 2);
+        assert!(report.dropped[0].starts_with("a: "));
+        assert_eq!(report.dropped[1], "b: Event \"b\" is no longer in the calendar file");
+        assert_eq!(remote_summary(&server, "/cal/c.ics").as_deref(), Some("C"));
+        assert!(!outbox_path.exists());
+    }
+}
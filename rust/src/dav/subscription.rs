@@ -0,0 +1,270 @@
+//! Read-only calendars that mirror a *feed*: a whole calendar file served over HTTP (e.g. public holidays),
+//! usually shared with a `webcal://` URL.
+//!
+//! Unlike a synced collection, the feed can't be changed, so each time it changes the calendar file is replaced by it.
+//! The URL of the feed and the validators of the last response (`ETag` and `Last-Modified`) are stored in a [`Subscription`] file,
+//! so that the feed is only downloaded again when it changed.
+use std::{io::Read as _, path::{Path, PathBuf}};
+use url::Url;
+use crate::{calendar::{color, ical::{self, Component}, CalendarFormat}, file_type::FileType};
+use super::{invalid_data, Client, Error, STATE_DIR};
+
+/// The directory (in [`STATE_DIR`]) where the [`Subscription`] files are stored.
+const SUBSCRIPTIONS_DIR: &str = "subscriptions";
+
+/// The feed a calendar is subscribed to, and what was known about it when it was last fetched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subscription {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+impl Subscription {
+    /// A subscription to the feed at **url** that has not been fetched yet.
+    ///
+    /// `webcal://` and `webcals://` URLs are fetched with `https://`.
+    /// `http://` URLs are kept as is, though feeds should be served over HTTPS.
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let url = url.trim();
+        let url = match url.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("webcal") || scheme.eq_ignore_ascii_case("webcals") => format!("https://{rest}"),
+            _ => url.to_string(),
+        };
+        let parsed = Url::parse(&url)
+            .map_err(|err| Error::InvalidUrl(format!("{url:?}: {err}")))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(Error::InvalidUrl(format!("{url:?}: Feeds must be fetched with webcal, https or http")));
+        }
+
+        Ok(Self { url: parsed.to_string(), ..Default::default() })
+    }
+
+    /// The path (relative to the *app's directory*) of the file where the subscription of the calendar with **name** is stored.
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(STATE_DIR).join(SUBSCRIPTIONS_DIR).join(format!("{name}.tsv"))
+    }
+
+    /// Read the subscription from the file at **path**.
+    /// Returns [`None`] if the file does not exist (the calendar is not subscribed to a feed).
+    pub fn read(path: &Path) -> Result<Option<Self>, Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut subscription = Self::default();
+        for line in content.lines() {
+            let mut fields = line.split('\t');
+            match fields.next() {
+                Some("url") => subscription.url = fields.next().unwrap_or_default().to_string(),
+                Some("etag") => subscription.etag = fields.next().map(str::to_string),
+                Some("last-modified") => subscription.last_modified = fields.next().map(str::to_string),
+                _ => {}
+            }
+        }
+        if subscription.url.is_empty() {
+            return Err(invalid_data(format!("Subscription file {path:?} does not have a url")));
+        }
+
+        Ok(Some(subscription))
+    }
+
+    /// Write the subscription to the file at **path**. Each line is a `key` followed by tab-separated values.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut content = format!("url\t{}\n", self.url);
+        if let Some(etag) = &self.etag {
+            content.push_str(&format!("etag\t{etag}\n"));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            content.push_str(&format!("last-modified\t{last_modified}\n"));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(std::fs::write(path, content)?)
+    }
+
+    /// Fetch the feed, with `If-None-Match` and `If-Modified-Since` if it was fetched before.
+    ///
+    /// Returns the `VCALENDAR` of the feed (which can be in any [`CalendarFormat`]),
+    /// or [`None`] if it has not changed since it was last fetched.
+    /// The validators of the response are stored in the subscription, but it is **not** written to its file.
+    pub fn fetch(&mut self) -> Result<Option<Component>, Error> {
+        let url = Url::parse(&self.url)
+            .map_err(|err| Error::InvalidUrl(format!("{:?}: {err}", self.url)))?;
+        let client = Client::new(&self.url, None)?;
+
+        let mut request = client.request("GET", &url)
+            .set("Accept", "text/calendar, application/calendar+json, application/calendar+xml;q=0.9, */*;q=0.5");
+        // Unlike If-Match, If-None-Match can be used with weak ETags
+        if let Some(etag) = &self.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+        let response = client.send("GET", request, None)?;
+        if response.status() == 304 {
+            return Ok(None);
+        }
+
+        let etag = response.header("ETag").map(str::to_string);
+        let last_modified = response.header("Last-Modified").map(str::to_string);
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+
+        let format = CalendarFormat::sniff(&data)
+            .ok_or_else(|| Error::InvalidResponse(format!("Feed {url} is not a calendar")))?;
+        let content = std::str::from_utf8(&data)
+            .map_err(|err| Error::InvalidResponse(format!("Feed {url} is not valid UTF-8: {err}")))?;
+        let mut calendars = format.parse(content)
+            .map_err(|err| Error::InvalidResponse(format!("Invalid feed {url}: {err}")))?
+            .into_iter()
+            .filter(|component| component.name.eq_ignore_ascii_case("VCALENDAR"));
+        let mut calendar = calendars.next()
+            .ok_or_else(|| Error::InvalidResponse(format!("Feed {url} does not have a VCALENDAR")))?;
+        // A calendar file has a single VCALENDAR, so the components of the others are moved to the first one
+        for other in calendars {
+            calendar.components.extend(other.components);
+        }
+
+        self.etag = etag;
+        self.last_modified = last_modified;
+        Ok(Some(calendar))
+    }
+
+    /// [Fetch](Self::fetch()) the feed and, if it changed, replace the file of the calendar with **name** in **app_dir** with it.
+    ///
+    /// The color of the calendar is kept if its file already had one.
+    /// Returns whether the file was replaced.
+    pub fn update_file(&mut self, app_dir: &Path, name: &str) -> Result<bool, Error> {
+        let Some(mut calendar) = self.fetch()? else {
+            return Ok(false);
+        };
+
+        let file_path = app_dir.join(FileType::Calendar.dir()).join(format!("{name}.ics"));
+        let old_color = std::fs::read_to_string(&file_path)
+            .ok()
+            .and_then(|content| ical::parse(&content).ok())
+            .and_then(|calendars| calendars.into_iter().next())
+            .and_then(|calendar| color::read_color(&calendar));
+        if let Some(old_color) = old_color {
+            color::write_color(&mut calendar, old_color);
+        }
+
+        std::fs::write(&file_path, calendar.to_string())?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calendar::color::Color, dav::test_server::{temp_dir, Response, Server}};
+
+    const FEED: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:new-year\r\nDTSTART;VALUE=DATE:20250101\r\nSUMMARY:New Year\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    #[test]
+    fn feed_urls() {
+        assert_eq!(Subscription::new("webcal://example.com/holidays.ics").unwrap().url, "https://example.com/holidays.ics");
+        assert_eq!(Subscription::new(" WEBCALS://example.com/a b.ics ").unwrap().url, "https://example.com/a%20b.ics");
+        assert_eq!(Subscription::new("http://example.com/holidays.ics").unwrap().url, "http://example.com/holidays.ics");
+        assert!(matches!(Subscription::new("ftp://example.com/holidays.ics"), Err(Error::InvalidUrl(_))));
+        assert!(matches!(Subscription::new("holidays.ics"), Err(Error::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let path = temp_dir("subscription-file").join(Subscription::path("Holidays"));
+        assert_eq!(Subscription::read(&path).unwrap(), None);
+
+        let subscription = Subscription {
+            url: "https://example.com/holidays.ics".to_string(),
+            etag: Some("\"1\"".to_string()),
+            last_modified: Some("Wed, 01 Jan 2025 00:00:00 GMT".to_string()),
+        };
+        subscription.write(&path).unwrap();
+        assert_eq!(Subscription::read(&path).unwrap(), Some(subscription));
+
+        std::fs::write(&path, "etag\t\"1\"\n").unwrap();
+        assert!(matches!(Subscription::read(&path), Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn fetch_only_when_changed() {
+        let server = Server::start();
+        server.state().put("/holidays.ics", FEED);
+        let mut subscription = Subscription::new(server.url("holidays.ics").as_str()).unwrap();
+
+        let calendar = subscription.fetch().unwrap().unwrap();
+        assert_eq!(calendar.components("VEVENT").next().unwrap().value("UID"), Some("new-year"));
+        let etag = subscription.etag.clone().unwrap();
+
+        // Not modified
+        assert_eq!(subscription.fetch().unwrap(), None);
+        assert_eq!(server.state().requests.last().unwrap().header("If-None-Match"), Some(etag.as_str()));
+
+        let changed = FEED.replace("New Year", "New Year's Day");
+        let new_etag = server.state().put("/holidays.ics", &changed);
+        let calendar = subscription.fetch().unwrap().unwrap();
+        assert_eq!(calendar.components("VEVENT").next().unwrap().value("SUMMARY"), Some("New Year's Day"));
+        assert_eq!(subscription.etag, Some(new_etag));
+    }
+
+    #[test]
+    fn fetch_with_last_modified() {
+        const LAST_MODIFIED: &str = "Wed, 01 Jan 2025 00:00:00 GMT";
+        // A server that doesn't send ETags
+        let server = Server::with_handler(|request, _| Some(match request.header("If-Modified-Since") {
+            Some(LAST_MODIFIED) => Response::new(304, ""),
+            _ => Response::new(200, FEED).with_header("Last-Modified", LAST_MODIFIED),
+        }));
+        let mut subscription = Subscription::new(server.url("holidays.ics").as_str()).unwrap();
+
+        assert!(subscription.fetch().unwrap().is_some());
+        assert_eq!(subscription.etag, None);
+        assert_eq!(subscription.last_modified.as_deref(), Some(LAST_MODIFIED));
+        assert_eq!(subscription.fetch().unwrap(), None);
+    }
+
+    #[test]
+    fn fetch_errors() {
+        let server = Server::start();
+        server.state().put("/page.html", "<html><body>Not a calendar</body></html>");
+        let mut subscription = Subscription::new(server.url("page.html").as_str()).unwrap();
+        assert!(matches!(subscription.fetch(), Err(Error::InvalidResponse(_))));
+
+        let mut subscription = Subscription::new(server.url("missing.ics").as_str()).unwrap();
+        assert!(matches!(subscription.fetch(), Err(Error::Status { status: 404, .. })));
+        assert_eq!(subscription.etag, None);
+    }
+
+    #[test]
+    fn update_file_keeps_color() {
+        let server = Server::start();
+        server.state().put("/holidays.ics", FEED);
+        let app_dir = temp_dir("subscription-update");
+        let calendars_dir = app_dir.join(FileType::Calendar.dir());
+        std::fs::create_dir_all(&calendars_dir).unwrap();
+
+        let mut old = ical::parse(FEED).unwrap().remove(0);
+        old.components.clear();
+        let color = Color { r: 0x12, g: 0x34, b: 0x56 };
+        color::write_color(&mut old, color);
+        std::fs::write(calendars_dir.join("Holidays.ics"), old.to_string()).unwrap();
+
+        let mut subscription = Subscription::new(server.url("holidays.ics").as_str()).unwrap();
+        assert!(subscription.update_file(&app_dir, "Holidays").unwrap());
+        let content = std::fs::read_to_string(calendars_dir.join("Holidays.ics")).unwrap();
+        let calendar = ical::parse(&content).unwrap().remove(0);
+        assert_eq!(color::read_color(&calendar), Some(color));
+        assert_eq!(calendar.components("VEVENT").count(), 1);
+
+        // The file is not written again if the feed did not change
+        std::fs::remove_file(calendars_dir.join("Holidays.ics")).unwrap();
+        assert!(!subscription.update_file(&app_dir, "Holidays").unwrap());
+        assert!(!calendars_dir.join("Holidays.ics").exists());
+    }
+}
@@ -0,0 +1,1007 @@
+//! Two-way sync between a collection in the server and a calendar (or contacts) file in the internal directory.
+//!
+//! The server stores each event (with all its overrides) or contact in its own resource, while the App stores a whole collection in a single file.
+//! So each **resource** is mapped to the components of the file with the same `UID`.
+//!
+//! What was known about the collection when it was last synced is stored in a [`SyncState`] file,
+//! which is used to tell which side changed a resource.
+//! The state also has the `sync-token` of the collection, so that only the resources that changed since then are listed (RFC 6578).
+use std::{fmt::Display, path::{Path, PathBuf}};
+use url::Url;
+use crate::calendar::{self, color::{self, Color}, ical::{self, Component, Property}, merge::{self, Conflict, Side}};
+use crate::file_type::FileType;
+use super::{xml, AddressBook, Calendar, Client, Error, Precondition, Resource, STATE_DIR};
+
+/// The number of resources that were changed on each side by [`sync_calendar()`] or [`sync_address_book()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub downloaded: usize,
+    pub uploaded: usize,
+    pub deleted_local: usize,
+    pub deleted_remote: usize,
+    /// Resources that changed on both sides.
+    /// They were merged, or kept as they are in the file if the conflict was not resolved.
+    pub conflicts: usize,
+}
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,
+            "{} downloaded, {} uploaded, {} deleted locally, {} deleted in server, {} conflicts",
+            self.downloaded, self.uploaded, self.deleted_local, self.deleted_remote, self.conflicts
+        )
+    }
+}
+
+/// The state of a synced collection after the last sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncState {
+    pub url: String,
+    pub ctag: Option<String>,
+    pub sync_token: Option<String>,
+    pub resources: Vec<ResourceState>,
+}
+/// The state of a resource after the last sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceState {
+    pub uid: String,
+    pub url: String,
+    pub etag: Option<String>,
+    /// The [`hash()`] of the components with **uid** as they were written to the file.
+    pub hash: u64,
+}
+impl SyncState {
+    /// The path (relative to the *app's directory*) of the file where the state of the collection
+    /// that is synced with the file of **file_type** with **name** is stored.
+    pub fn path(file_type: FileType, name: &str) -> PathBuf {
+        PathBuf::from(STATE_DIR).join(file_type.dir()).join(format!("{name}.{}", file_type.extension()))
+    }
+
+    /// Read the state from the file at **path**.
+    /// Returns [`None`] if the file does not exist (the collection has never been synced).
+    pub fn read(path: &Path) -> Result<Option<Self>, Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut state = Self::default();
+        for line in content.lines() {
+            let mut fields = line.split('\t');
+            match fields.next() {
+                Some("url") => state.url = fields.next().unwrap_or_default().to_string(),
+                Some("ctag") => state.ctag = fields.next().map(str::to_string),
+                Some("sync-token") => state.sync_token = fields.next().map(str::to_string),
+                Some("resource") => {
+                    let (Some(hash), Some(url), Some(etag), Some(uid)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                        return Err(Error::InvalidResponse(format!("Invalid line in {path:?}: {line:?}")));
+                    };
+                    state.resources.push(ResourceState {
+                        uid: uid.to_string(),
+                        url: url.to_string(),
+                        etag: Some(etag).filter(|etag| !etag.is_empty()).map(str::to_string),
+                        hash: u64::from_str_radix(hash, 16)
+                            .map_err(|err| Error::InvalidResponse(format!("Invalid hash in {path:?}: {err}")))?,
+                    });
+                },
+                _ => {}
+            }
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Write the state to the file at **path**. Each line is a `key` followed by tab-separated values.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut content = format!("url\t{}\n", self.url);
+        if let Some(ctag) = &self.ctag {
+            content.push_str(&format!("ctag\t{ctag}\n"));
+        }
+        if let Some(sync_token) = &self.sync_token {
+            content.push_str(&format!("sync-token\t{sync_token}\n"));
+        }
+        for resource in &self.resources {
+            content.push_str(&format!(
+                "resource\t{:016x}\t{}\t{}\t{}\n",
+                resource.hash, resource.url, resource.etag.as_deref().unwrap_or_default(), resource.uid
+            ));
+        }
+
+        Ok(std::fs::write(path, content)?)
+    }
+
+    fn resource(&self, url: &str) -> Option<&ResourceState> {
+        self.resources.iter().find(|resource| resource.url == url)
+    }
+}
+
+/// What the server said about a collection when it was discovered,
+/// which tells whether its resources changed since the last sync.
+#[derive(Debug, Clone, Copy)]
+struct Collection<'a> {
+    url: &'a Url,
+    ctag: Option<&'a str>,
+    sync_token: Option<&'a str>,
+}
+impl<'a> From<&'a Calendar> for Collection<'a> {
+    fn from(calendar: &'a Calendar) -> Self {
+        Self { url: &calendar.url, ctag: calendar.ctag.as_deref(), sync_token: calendar.sync_token.as_deref() }
+    }
+}
+impl<'a> From<&'a AddressBook> for Collection<'a> {
+    fn from(address_book: &'a AddressBook) -> Self {
+        Self { url: &address_book.url, ctag: address_book.ctag.as_deref(), sync_token: address_book.sync_token.as_deref() }
+    }
+}
+
+/// The components of a file that have the same `UID` (an event and its overrides, or a contact).
+#[derive(Debug, Clone)]
+pub(super) struct Group {
+    pub uid: String,
+    pub components: Vec<Component>,
+}
+
+/// The kind of collection that is synced, which determines the format of its resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    /// Each resource is a `VCALENDAR` with the components of one [`Group`].
+    Calendar,
+    /// Each resource is a `VCARD`.
+    AddressBook,
+}
+impl Kind {
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Calendar => "text/calendar; charset=utf-8",
+            Self::AddressBook => "text/vcard; charset=utf-8",
+        }
+    }
+
+    /// The extension of the name of new resources.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Calendar => "ics",
+            Self::AddressBook => "vcf",
+        }
+    }
+
+    /// The multiget `REPORT` that downloads resources of this kind, and the property that has their data.
+    fn multiget(self) -> (xml::Name, xml::Name) {
+        match self {
+            Self::Calendar => (xml::CALENDAR_MULTIGET, xml::CALENDAR_DATA),
+            Self::AddressBook => (xml::ADDRESSBOOK_MULTIGET, xml::ADDRESS_DATA),
+        }
+    }
+
+    /// Parse the **data** of a resource and get the components that would be in the file.
+    pub fn parse(self, url: &Url, data: &str) -> Result<Vec<Component>, Error> {
+        let components = ical::parse(data)
+            .map_err(|err| Error::InvalidResponse(format!("Invalid resource {url}: {err}")))?;
+        match self {
+            Self::Calendar => components.into_iter()
+                .next()
+                .map(|calendar| calendar.components)
+                .ok_or_else(|| Error::InvalidResponse(format!("Resource {url} has no VCALENDAR"))),
+            Self::AddressBook => Ok(components.into_iter().filter(|comp| comp.name == "VCARD").collect()),
+        }
+    }
+}
+
+/// Sync the **calendar** collection with the calendar file at **file_path**, named **name**.
+///
+/// **state_path** is the file where the [`SyncState`] of the collection is stored.
+/// If a resource was changed both in the server and in the file, the two versions are [merged](merge::merge()),
+/// calling **resolve** for the components that can't be merged.
+/// If **resolve** returns [`None`], the version in the file is kept, and the conflict comes up again in the next sync.
+pub fn sync_calendar(
+    client: &Client,
+    calendar: &Calendar,
+    name: &str,
+    file_path: &Path,
+    state_path: &Path,
+    resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Result<SyncReport, Error> {
+    let (local, state) = match std::fs::read_to_string(file_path) {
+        Ok(content) => (
+            ical::parse(&content)
+                .map_err(|err| Error::InvalidResponse(format!("Invalid calendar file {file_path:?}: {err}")))?
+                .into_iter()
+                .next(),
+            SyncState::read(state_path)?,
+        ),
+        // Without the file, the state would make every resource look deleted locally, so it is synced as if it never was
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (None, None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut local = local.unwrap_or_else(|| calendar::new_calendar(name));
+    let (mut groups, others) = split_groups(std::mem::take(&mut local.components));
+    let mut timezones = others.iter()
+        .filter(|comp| comp.name == "VTIMEZONE")
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let (report, state) = sync_collection(client, Kind::Calendar, calendar.into(), state, &mut groups, &mut timezones, resolve)?;
+
+    // Write the file
+    local.components = timezones;
+    local.components.extend(others.into_iter().filter(|comp| comp.name != "VTIMEZONE"));
+    local.components.extend(groups.into_iter().flat_map(|group| group.components));
+    if let Some(color) = calendar.color.as_deref().and_then(Color::parse) {
+        color::write_color(&mut local, color);
+    }
+    std::fs::write(file_path, local.to_string())?;
+    state.write(state_path)?;
+
+    Ok(report)
+}
+
+/// Sync the **address_book** collection with the contacts file at **file_path**.
+///
+/// Works like [`sync_calendar()`], with a resource for each `VCARD` in the file.
+/// The `VCARD`s that don't have a `UID` are given one, because it is needed to map them to a resource.
+pub fn sync_address_book(
+    client: &Client,
+    address_book: &AddressBook,
+    file_path: &Path,
+    state_path: &Path,
+    resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Result<SyncReport, Error> {
+    let (mut cards, state) = match std::fs::read_to_string(file_path) {
+        Ok(content) => (
+            ical::parse(&content)
+                .map_err(|err| Error::InvalidResponse(format!("Invalid contacts file {file_path:?}: {err}")))?,
+            SyncState::read(state_path)?,
+        ),
+        // Like in sync_calendar(), the state is forgotten so that the contacts are not deleted in the server
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (Vec::new(), None),
+        Err(err) => return Err(err.into()),
+    };
+    cards.retain(|comp| comp.name == "VCARD");
+    for card in &mut cards {
+        if card.value("UID").is_none() {
+            let uid = format!("{:016x}@davsync", hash(std::slice::from_ref(card)));
+            card.push(Property::new("UID", uid));
+        }
+    }
+    let (mut groups, _) = split_groups(cards);
+
+    let (report, state) = sync_collection(client, Kind::AddressBook, address_book.into(), state, &mut groups, &mut Vec::new(), resolve)?;
+
+    // Write the file
+    let content = groups.iter()
+        .flat_map(|group| group.components.iter())
+        .map(Component::to_string)
+        .collect::<String>();
+    std::fs::write(file_path, content)?;
+    state.write(state_path)?;
+
+    Ok(report)
+}
+
+/// Sync the resources of the **collection** with the **groups** of a file,
+/// which are modified to have the changes from the server.
+///
+/// The resources that changed on both sides are merged with [`merge_resource()`].
+///
+/// **state** is the [`SyncState`] of the last sync, or [`None`] if the collection was never synced with the file.
+/// Returns the new state, which has to be written after the file is written.
+fn sync_collection(
+    client: &Client,
+    kind: Kind,
+    collection: Collection,
+    state: Option<SyncState>,
+    groups: &mut Vec<Group>,
+    timezones: &mut Vec<Component>,
+    mut resolve: impl FnMut(Conflict) -> Option<Side>,
+) -> Result<(SyncReport, SyncState), Error> {
+    let mut report = SyncReport::default();
+    let collection_url = collection.url;
+    let state = state
+        // Forget the state if it belonged to another collection
+        .filter(|state| state.url == collection_url.as_str())
+        .unwrap_or_default();
+
+    let mut new_state = SyncState {
+        url: collection_url.to_string(),
+        ctag: collection.ctag.map(str::to_string),
+        sync_token: None,
+        resources: Vec::new(),
+    };
+    let remote = list_resources(client, collection, &state, &mut new_state.sync_token)?;
+    // The resources that changed in the server, which are downloaded together after the rest are synced
+    let mut changed = Vec::<Url>::new();
+    // The resources that also changed in the file, which are merged once they are downloaded
+    let mut conflicts = Vec::<Url>::new();
+
+    for (url, etag) in &remote {
+        let known = state.resource(url.as_str());
+        let group = known.and_then(|known| groups.iter().find(|group| group.uid == known.uid));
+
+        match known {
+            // The resource has not changed in the server since the last sync
+            Some(known) if known.etag.is_some() && known.etag == *etag => match group {
+                Some(group) if hash(&group.components) == known.hash => new_state.resources.push(known.clone()),
+                // Changed locally: upload it
+                Some(group) => match upload(client, kind, url, group, timezones, Precondition::Match(etag.as_deref().unwrap())) {
+                    Ok(etag) => {
+                        report.uploaded += 1;
+                        new_state.resources.push(resource_state(group, url, etag));
+                    },
+                    // Changed in the server after it was listed
+                    Err(Error::PreconditionFailed(_)) => conflicts.push(url.clone()),
+                    Err(err) => return Err(err),
+                },
+                // Deleted locally: delete it in the server
+                None => match client.delete(url, Precondition::Match(etag.as_deref().unwrap())) {
+                    Ok(()) => report.deleted_remote += 1,
+                    Err(Error::PreconditionFailed(_)) => conflicts.push(url.clone()),
+                    Err(err) => return Err(err),
+                },
+            },
+            // Changed in the server, and also changed or deleted locally
+            Some(known) if group.is_none_or(|group| hash(&group.components) != known.hash) => conflicts.push(url.clone()),
+            // Changed in the server (or new)
+            _ => changed.push(url.clone()),
+        }
+    }
+
+    report.conflicts += conflicts.len();
+    changed.extend(conflicts.iter().cloned());
+    report.downloaded += download(client, kind, collection_url, &changed, |resource| if conflicts.contains(&resource.url) {
+        merge_resource(client, kind, resource, &state, groups, timezones, &mut new_state, &mut resolve)
+    } else {
+        add_resource(kind, resource, groups, timezones, &mut new_state)
+    })?;
+
+    // Resources that were deleted in the server
+    for known in &state.resources {
+        if remote.iter().any(|(url, _)| url.as_str() == known.url) {
+            continue;
+        }
+        if let Some(i) = groups.iter().position(|group| group.uid == known.uid) {
+            if hash(&groups[i].components) == known.hash {
+                groups.remove(i);
+                report.deleted_local += 1;
+            } else {
+                // Changed locally, so it will be uploaded again as a new resource
+                report.conflicts += 1;
+            }
+        }
+    }
+
+    // Resources that are new in the file
+    for group in groups.iter() {
+        if new_state.resources.iter().any(|resource| resource.uid == group.uid) {
+            continue;
+        }
+        let url = client.url(collection_url, &format!("{}.{}", resource_name(&group.uid), kind.extension()))?;
+        match upload(client, kind, &url, group, timezones, Precondition::Create) {
+            Ok(etag) => {
+                report.uploaded += 1;
+                new_state.resources.push(resource_state(group, &url, etag));
+            },
+            // A resource with that name already exists, but it wasn't listed before
+            Err(Error::PreconditionFailed(_)) => report.conflicts += 1,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok((report, new_state))
+}
+
+/// List the URLs and ETags of the resources in the **collection**,
+/// using the **state** of the last sync to avoid listing all of them when possible:
+///
+/// - If the collection has a `sync-token`, only the resources that changed since the token of the last sync are requested,
+///   and the rest are the same as in the **state**. All of them are listed again if the server no longer accepts the old token.
+/// - Otherwise, if the *CTag* has not changed since the last sync, the resources are the same as in the **state**.
+/// - Otherwise, all resources are listed with a `PROPFIND`.
+///
+/// The new `sync-token` of the collection is put in **sync_token**.
+fn list_resources(client: &Client, collection: Collection, state: &SyncState, sync_token: &mut Option<String>) -> Result<Vec<(Url, Option<String>)>, Error> {
+    // The resources that were in the collection in the last sync
+    let known = || {
+        let mut resources = Vec::<(Url, Option<String>)>::new();
+        for resource in &state.resources {
+            let url = client.url(collection.url, &resource.url)?;
+            // A resource can have more than one UID
+            if !resources.iter().any(|(known, _)| *known == url) {
+                resources.push((url, resource.etag.clone()));
+            }
+        }
+        Ok::<_, Error>(resources)
+    };
+
+    if collection.sync_token.is_some() {
+        // Nothing changed in the server since the last sync
+        if state.sync_token.is_some() && state.sync_token.as_deref() == collection.sync_token {
+            sync_token.clone_from(&state.sync_token);
+            return known();
+        }
+
+        let changes = match state.sync_token.as_deref() {
+            Some(old_token) => match client.sync_collection(collection.url, Some(old_token)) {
+                Ok(changes) => Ok((known()?, changes)),
+                // The token expired, so start over
+                Err(Error::Status { .. }) => client.sync_collection(collection.url, None).map(|changes| (Vec::new(), changes)),
+                Err(err) => Err(err),
+            },
+            None => client.sync_collection(collection.url, None).map(|changes| (Vec::new(), changes)),
+        };
+        match changes {
+            Ok((mut resources, changes)) => {
+                resources.retain(|(url, _)| !changes.deleted.contains(url) && !changes.changed.iter().any(|(changed, _)| changed == url));
+                resources.extend(changes.changed);
+                *sync_token = Some(changes.sync_token);
+                return Ok(resources);
+            },
+            // The server has the sync-token property, but doesn't support the report
+            Err(Error::Status { .. }) => {},
+            Err(err) => return Err(err),
+        }
+    } else if state.ctag.is_some() && state.ctag.as_deref() == collection.ctag {
+        return known();
+    }
+
+    client.resources(collection.url)
+}
+
+/// Download the resources at **urls** in the collection at **collection_url**, and call **on_resource** with each of them.
+///
+/// The resources are downloaded with multiget `REPORT`s of [`Client::multiget_batch_size()`] resources each,
+/// so that there is no request per resource. The ones that the server doesn't return (e.g. because it doesn't support multiget)
+/// are downloaded one by one.
+///
+/// Returns the number of resources that were downloaded.
+fn download(
+    client: &Client,
+    kind: Kind,
+    collection_url: &Url,
+    urls: &[Url],
+    mut on_resource: impl FnMut(Resource) -> Result<(), Error>,
+) -> Result<usize, Error> {
+    let (report, data) = kind.multiget();
+    let mut supports_multiget = true;
+    let mut downloaded = Vec::<Url>::new();
+
+    for batch in urls.chunks(client.multiget_batch_size()) {
+        if !supports_multiget {
+            break;
+        }
+        let result = client.multiget(collection_url, report, data, batch, |resource| {
+            downloaded.push(resource.url.clone());
+            on_resource(resource)
+        });
+        match result {
+            Ok(()) => {},
+            Err(Error::Status { .. }) => supports_multiget = false,
+            Err(err) => return Err(err),
+        }
+    }
+
+    let missing = urls.iter()
+        .filter(|url| !downloaded.contains(url))
+        .collect::<Vec<_>>();
+    for url in &missing {
+        let resource = client.get(url, None)?
+            .ok_or_else(|| Error::InvalidResponse(format!("Server did not return {url}")))?;
+        on_resource(resource)?;
+    }
+
+    Ok(downloaded.len() + missing.len())
+}
+
+/// Replace the components in the file that have the same `UID`s as the ones in a downloaded **resource**.
+fn add_resource(kind: Kind, resource: Resource, groups: &mut Vec<Group>, timezones: &mut Vec<Component>, state: &mut SyncState) -> Result<(), Error> {
+    let (new_groups, others) = split_groups(kind.parse(&resource.url, &resource.data)?);
+    for timezone in others.into_iter().filter(|comp| comp.name == "VTIMEZONE") {
+        if !timezones.iter().any(|tz| tz.value("TZID") == timezone.value("TZID")) {
+            timezones.push(timezone);
+        }
+    }
+    for group in new_groups {
+        state.resources.push(resource_state(&group, &resource.url, resource.etag.clone()));
+        match groups.iter_mut().find(|old| old.uid == group.uid) {
+            Some(old) => *old = group,
+            None => groups.push(group),
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge a downloaded **resource** that changed in the server with the components in the file that have the same `UID`s,
+/// which also changed (or were deleted) since the last sync.
+///
+/// There is no base to merge with (only its [`hash()`] is kept), so components that differ are compared by their `SEQUENCE`,
+/// and **resolve** is called when that doesn't tell which one is newer, or when one side was deleted.
+/// If the merged version is not the one in the server, it is uploaded (or the resource is deleted).
+///
+/// When **resolve** cancels the merge (or the resource changed in the server again), the components in the file are kept as they are,
+/// and the resource keeps its state from the last sync in **old_state**, so that the conflict is detected again in the next sync.
+#[allow(clippy::too_many_arguments)]
+fn merge_resource(
+    client: &Client,
+    kind: Kind,
+    resource: Resource,
+    old_state: &SyncState,
+    groups: &mut Vec<Group>,
+    timezones: &mut Vec<Component>,
+    state: &mut SyncState,
+    resolve: &mut impl FnMut(Conflict) -> Option<Side>,
+) -> Result<(), Error> {
+    let keep_old_state = |state: &mut SyncState| state.resources.extend(old_state.resources.iter()
+        .filter(|known| known.url == resource.url.as_str())
+        .cloned());
+    let precondition = resource.etag.as_deref().map_or(Precondition::None, Precondition::Match);
+
+    let (remote_groups, others) = split_groups(kind.parse(&resource.url, &resource.data)?);
+    for timezone in others.into_iter().filter(|comp| comp.name == "VTIMEZONE") {
+        if !timezones.iter().any(|tz| tz.value("TZID") == timezone.value("TZID")) {
+            timezones.push(timezone);
+        }
+    }
+    // The resource was deleted locally (a resource only has one UID, but its state might have more)
+    let remote_groups = if remote_groups.is_empty() {
+        old_state.resources.iter()
+            .filter(|known| known.url == resource.url.as_str())
+            .map(|known| Group { uid: known.uid.clone(), components: Vec::new() })
+            .collect()
+    } else {
+        remote_groups
+    };
+
+    let mut merged_groups = Vec::new();
+    for remote in remote_groups {
+        let position = groups.iter().position(|group| group.uid == remote.uid);
+        let local = position.map(|i| groups[i].components.clone()).unwrap_or_default();
+
+        let merged = match (local.first(), remote.components.first()) {
+            // Without a base, merge() would always keep the side that was not deleted
+            (Some(_), None) | (None, Some(_)) => match resolve(Conflict { local: local.first(), remote: remote.components.first() }) {
+                Some(Side::Local) => Some(local),
+                Some(Side::Remote) => Some(remote.components.clone()),
+                None => None,
+            },
+            _ => {
+                let calendar = |components: Vec<Component>| Component { components, ..Component::new("VCALENDAR") };
+                merge::merge(None, &calendar(local), &calendar(remote.components.clone()), &mut *resolve)
+                    .map(|merged| merged.components)
+            },
+        };
+        let Some(merged) = merged else {
+            keep_old_state(state);
+            return Ok(());
+        };
+        merged_groups.push((position, Group { uid: remote.uid.clone(), components: merged }, remote));
+    }
+
+    // Whether the merged version has to be sent to the server
+    let changed = merged_groups.iter().any(|(_, merged, remote)| hash(&merged.components) != hash(&remote.components));
+    let mut etag = resource.etag.clone();
+    if changed {
+        let merged = merged_groups.iter()
+            .flat_map(|(_, merged, _)| merged.components.iter().cloned())
+            .collect::<Vec<_>>();
+        let result = match merged.first().and_then(|comp| comp.value("UID")) {
+            Some(uid) => upload(client, kind, &resource.url, &Group { uid: uid.to_string(), components: merged.clone() }, timezones, precondition),
+            None => client.delete(&resource.url, precondition).map(|()| None),
+        };
+        match result {
+            Ok(new_etag) => etag = new_etag,
+            // Changed in the server again. Keep the local version to merge it in the next sync
+            Err(Error::PreconditionFailed(_)) => {
+                keep_old_state(state);
+                return Ok(());
+            },
+            Err(err) => return Err(err),
+        }
+    }
+
+    // Removing groups would change the positions of the rest, so the deleted ones are removed at the end
+    for (position, merged, _) in merged_groups {
+        if merged.components.is_empty() {
+            if let Some(i) = position {
+                groups[i].components.clear();
+            }
+            continue;
+        }
+        state.resources.push(resource_state(&merged, &resource.url, etag.clone()));
+        // The merged version is put where the local version was, so that the order of the file doesn't change
+        match position {
+            Some(i) => groups[i] = merged,
+            None => groups.push(merged),
+        }
+    }
+    groups.retain(|group| !group.components.is_empty());
+
+    Ok(())
+}
+
+/// Upload the components of **group** to the resource at **url**, along with the `VTIMEZONE`s they use.
+///
+/// Returns the new ETag of the resource.
+pub(super) fn upload(client: &Client, kind: Kind, url: &Url, group: &Group, timezones: &[Component], precondition: Precondition) -> Result<Option<String>, Error> {
+    let data = match kind {
+        Kind::Calendar => {
+            let mut calendar = Component::new("VCALENDAR");
+            calendar.push(Property::new("VERSION", "2.0"));
+            calendar.push(Property::new("PRODID", calendar::PRODID));
+            calendar.components.extend(timezones.iter()
+                .filter(|tz| tz.value("TZID").is_some_and(|tzid| uses_tzid(&group.components, tzid)))
+                .cloned()
+            );
+            calendar.components.extend(group.components.iter().cloned());
+            calendar.to_string()
+        },
+        Kind::AddressBook => group.components.iter()
+            .map(Component::to_string)
+            .collect(),
+    };
+
+    client.put(url, &data, kind.content_type(), precondition)
+}
+
+/// Separate the components that have a `UID` into [`Group`]s. Returns the groups and the rest of the components.
+pub(super) fn split_groups(components: Vec<Component>) -> (Vec<Group>, Vec<Component>) {
+    let mut groups = Vec::<Group>::new();
+    let mut others = Vec::new();
+
+    for component in components {
+        let Some(uid) = component.value("UID").map(str::to_string) else {
+            others.push(component);
+            continue
+        };
+        match groups.iter_mut().find(|group| group.uid == uid) {
+            Some(group) => group.components.push(component),
+            None => groups.push(Group { uid, components: vec![component] }),
+        }
+    }
+
+    (groups, others)
+}
+
+pub(super) fn resource_state(group: &Group, url: &Url, etag: Option<String>) -> ResourceState {
+    ResourceState {
+        uid: group.uid.clone(),
+        url: url.to_string(),
+        etag,
+        hash: hash(&group.components),
+    }
+}
+
+/// Whether any of the **components** (or their sub-components) have a property with the `TZID` parameter **tzid**.
+fn uses_tzid(components: &[Component], tzid: &str) -> bool {
+    components.iter().any(|comp| {
+        comp.properties.iter().any(|prop| prop.param("TZID") == Some(tzid))
+            || uses_tzid(&comp.components, tzid)
+    })
+}
+
+/// A hash of the **components** that is used to tell whether they changed since the last sync (*FNV-1a*).
+///
+/// `DTSTAMP` is ignored because it changes every time the file is written from the Content Provider.
+pub(super) fn hash(components: &[Component]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for component in components {
+        let mut component = component.clone();
+        component.remove("DTSTAMP");
+        for byte in component.to_string().bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Get the name of the resource of a **uid**, keeping only the characters that don't have to be escaped in a URL.
+pub(super) fn resource_name(uid: &str) -> String {
+    uid.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.@".contains(c) { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use super::*;
+    use crate::dav::{
+        test_calendar::{collection, edit_file, event, local_summary, remote_summary, set_summary},
+        test_server::{handle, temp_dir, Response, Server},
+        Depth, DEFAULT_MULTIGET_BATCH_SIZE,
+    };
+
+    /// The [`collection()`] with the *CTag* and `sync-token` that the server has now, as if it was discovered again.
+    fn discover(client: &Client, server: &Server) -> Calendar {
+        let calendar = collection(server);
+        let responses = client.propfind(&calendar.url, Depth::Zero, &[xml::GET_CTAG, xml::SYNC_TOKEN]).unwrap();
+        Calendar {
+            ctag: responses[0].text(xml::GET_CTAG).map(str::to_string),
+            sync_token: responses[0].text(xml::SYNC_TOKEN).map(str::to_string),
+            ..calendar
+        }
+    }
+
+    /// The requests that the server received since the last call: the `REPORT`s by their name and the `sync-token` they have
+    /// (e.g. `sync-collection sync-2`, or `sync-collection` for the initial sync), and the other requests by their method
+    /// and `Depth` (e.g. `PROPFIND 1`).
+    fn take_requests(server: &Server) -> Vec<String> {
+        std::mem::take(&mut server.state().requests).into_iter()
+            .map(|request| match request.method.as_str() {
+                "REPORT" if request.body.contains("sync-collection") => {
+                    let token = request.body.split_once("<d:sync-token>")
+                        .and_then(|(_, rest)| rest.split_once("</d:sync-token>"))
+                        .map(|(token, _)| token);
+                    token.map_or_else(|| "sync-collection".to_string(), |token| format!("sync-collection {token}"))
+                },
+                "REPORT" => "calendar-multiget".to_string(),
+                method => match request.header("Depth") {
+                    Some(depth) => format!("{method} {depth}"),
+                    None => method.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sync_changes_on_each_side() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let dir = temp_dir("sync-changes");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        let calendar = collection(&server);
+        let no_conflicts = |conflict: Conflict| panic!("Unexpected conflict: {}", conflict.summary());
+
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, no_conflicts).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 2, ..Default::default() });
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A"));
+
+        // Nothing changed
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, no_conflicts).unwrap();
+        assert_eq!(report, SyncReport::default());
+
+        edit_file(&file, |components| set_summary(components, "a", "A local"));
+        server.state().put("/cal/b.ics", &event("b", "B remote"));
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, no_conflicts).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 1, uploaded: 1, ..Default::default() });
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A local"));
+        assert_eq!(local_summary(&file, "b").as_deref(), Some("B remote"));
+    }
+
+    #[test]
+    fn download_again_when_file_is_missing() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let dir = temp_dir("sync-missing-file");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        let calendar = collection(&server);
+        sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+
+        // The events are not deleted in the server, but downloaded again
+        std::fs::remove_file(&file).unwrap();
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 2, ..Default::default() });
+        assert_eq!(server.state().resources.keys().collect::<Vec<_>>(), ["/cal/a.ics", "/cal/b.ics"]);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A"));
+        assert_eq!(local_summary(&file, "b").as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn merge_changes_on_both_sides() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        let dir = temp_dir("sync-merge");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        let calendar = collection(&server);
+        sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+
+        edit_file(&file, |components| set_summary(components, "a", "A local"));
+        server.state().put("/cal/a.ics", &event("a", "A remote"));
+
+        // The local edit is kept until the conflict is resolved
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A local"));
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A remote"));
+
+        // The conflict comes up again in the next sync
+        let mut asked = Vec::new();
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |conflict| {
+            asked.push(conflict.summary());
+            Some(Side::Local)
+        }).unwrap();
+        assert_eq!(asked, ["A local"]);
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A local"));
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A local"));
+
+        // And it is in sync afterwards
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport::default());
+    }
+
+    #[test]
+    fn merge_when_upload_fails_precondition() {
+        // The resource changes in the server after it was listed, so the first upload fails with 412
+        let changed = AtomicBool::new(false);
+        let server = Server::with_handler(move |request, state| {
+            if request.method == "PUT" && request.header("If-Match").is_some() && !changed.swap(true, Ordering::SeqCst) {
+                state.put("/cal/a.ics", &event("a", "A remote"));
+                return Some(Response::new(412, ""));
+            }
+            None
+        });
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        let dir = temp_dir("sync-precondition");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        let calendar = collection(&server);
+        sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+
+        edit_file(&file, |components| set_summary(components, "a", "A local"));
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| Some(Side::Remote)).unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(report.uploaded, 0);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A remote"));
+        assert_eq!(remote_summary(&server, "/cal/a.ics").as_deref(), Some("A remote"));
+    }
+
+    #[test]
+    fn ask_before_restoring_deleted_resource() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        let dir = temp_dir("sync-deleted");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        let calendar = collection(&server);
+        sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+
+        // Deleted locally and changed in the server
+        edit_file(&file, |components| components.retain(|comp| comp.name != "VEVENT"));
+        server.state().put("/cal/a.ics", &event("a", "A remote"));
+
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |conflict| {
+            assert!(conflict.local.is_none());
+            Some(Side::Local)
+        }).unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(local_summary(&file, "a"), None);
+        assert!(!server.state().resources.contains_key("/cal/a.ics"));
+    }
+
+    #[test]
+    fn list_changes_since_sync_token() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let dir = temp_dir("sync-token");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+
+        let calendar = discover(&client, &server);
+        take_requests(&server);
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 2, ..Default::default() });
+        assert_eq!(take_requests(&server), ["sync-collection", "calendar-multiget"]);
+        assert_eq!(SyncState::read(&state).unwrap().unwrap().sync_token.as_deref(), Some("sync-2"));
+
+        // The sync-token didn't change, so nothing is requested
+        let calendar = discover(&client, &server);
+        take_requests(&server);
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport::default());
+        assert!(take_requests(&server).is_empty());
+
+        // Only the changes are listed, and only the changed resource is downloaded
+        server.state().put("/cal/b.ics", &event("b", "B remote"));
+        server.state().delete("/cal/a.ics");
+        let calendar = discover(&client, &server);
+        take_requests(&server);
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 1, deleted_local: 1, ..Default::default() });
+        assert_eq!(take_requests(&server), ["sync-collection sync-2", "calendar-multiget"]);
+        assert_eq!(local_summary(&file, "a"), None);
+        assert_eq!(local_summary(&file, "b").as_deref(), Some("B remote"));
+        assert_eq!(SyncState::read(&state).unwrap().unwrap().sync_token.as_deref(), Some("sync-4"));
+    }
+
+    #[test]
+    fn skip_listing_when_ctag_is_unchanged() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        let dir = temp_dir("sync-ctag");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        // As in a server that doesn't support sync-collection
+        let discover = || Calendar { sync_token: None, ..discover(&client, &server) };
+
+        let calendar = discover();
+        take_requests(&server);
+        sync_calendar(&client, &calendar, "Test", &file, &state, |_| None).unwrap();
+        assert_eq!(take_requests(&server), ["PROPFIND 1", "calendar-multiget"]);
+
+        let calendar = discover();
+        take_requests(&server);
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport::default());
+        assert!(take_requests(&server).is_empty());
+
+        // The CTag changes with the resource, so the collection is listed again
+        server.state().put("/cal/a.ics", &event("a", "A remote"));
+        let calendar = discover();
+        take_requests(&server);
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 1, ..Default::default() });
+        assert_eq!(take_requests(&server), ["PROPFIND 1", "calendar-multiget"]);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A remote"));
+    }
+
+    #[test]
+    fn start_over_when_sync_token_is_invalid() {
+        let server = Server::start();
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let dir = temp_dir("sync-invalid-token");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+        sync_calendar(&client, &discover(&client, &server), "Test", &file, &state, |_| None).unwrap();
+
+        server.state().put("/cal/b.ics", &event("b", "B remote"));
+        server.state().expire_sync_tokens();
+        let calendar = discover(&client, &server);
+        take_requests(&server);
+        // All resources are listed again, but only the one with a new ETag is downloaded
+        let report = sync_calendar(&client, &calendar, "Test", &file, &state, |_| panic!("Unexpected conflict")).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 1, ..Default::default() });
+        assert_eq!(take_requests(&server), ["sync-collection sync-2", "sync-collection", "calendar-multiget"]);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A"));
+        assert_eq!(local_summary(&file, "b").as_deref(), Some("B remote"));
+        assert_eq!(SyncState::read(&state).unwrap().unwrap().sync_token.as_deref(), Some("sync-3"));
+    }
+
+    #[test]
+    fn download_in_batches() {
+        let server = Server::start();
+        for uid in ["a", "b", "c", "d", "e"] {
+            server.state().put(&format!("/cal/{uid}.ics"), &event(uid, &uid.to_uppercase()));
+        }
+        let dir = temp_dir("sync-batches");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let mut client = Client::new(&server.url, None).unwrap();
+        client.set_multiget_batch_size(2);
+
+        let report = sync_calendar(&client, &collection(&server), "Test", &file, &state, |_| None).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 5, ..Default::default() });
+        assert_eq!(server.state().requests.iter().filter(|request| request.method == "REPORT").count(), 3);
+        assert_eq!(local_summary(&file, "e").as_deref(), Some("E"));
+
+        client.set_multiget_batch_size(0);
+        assert_eq!(client.multiget_batch_size(), DEFAULT_MULTIGET_BATCH_SIZE);
+    }
+
+    #[test]
+    fn get_resources_missing_from_multiget() {
+        // The server leaves b out of the multiget response
+        let server = Server::with_handler(|request, state| (request.method == "REPORT").then(|| {
+            let mut request = request.clone();
+            request.body = request.body.replace("<d:href>/cal/b.ics</d:href>", "");
+            handle(&request, state)
+        }));
+        server.state().put("/cal/a.ics", &event("a", "A"));
+        server.state().put("/cal/b.ics", &event("b", "B"));
+        let dir = temp_dir("sync-multiget-missing");
+        let (file, state) = (dir.join("Test.ics"), dir.join("state"));
+        let client = Client::new(&server.url, None).unwrap();
+
+        let report = sync_calendar(&client, &collection(&server), "Test", &file, &state, |_| None).unwrap();
+        assert_eq!(report, SyncReport { downloaded: 2, ..Default::default() });
+        assert_eq!(take_requests(&server), ["PROPFIND 1", "calendar-multiget", "GET"]);
+        assert_eq!(local_summary(&file, "a").as_deref(), Some("A"));
+        assert_eq!(local_summary(&file, "b").as_deref(), Some("B"));
+    }
+}
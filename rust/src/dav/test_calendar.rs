@@ -0,0 +1,55 @@
+//! The calendar and events that the tests sync with the [`Server`], and helpers to change and inspect them on each side.
+use std::path::Path;
+use super::{sync::Kind, test_server::Server, Calendar};
+use crate::calendar::ical::{self, Component, Property};
+
+/// A calendar with a `VEVENT` with **uid** and **summary**.
+pub fn event(uid: &str, summary: &str) -> String {
+    format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:test\r\nBEGIN:VEVENT\r\nUID:{uid}\r\nDTSTART:20240101T100000Z\r\nSUMMARY:{summary}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n")
+}
+
+/// The `VEVENT` of [`event()`].
+pub fn new_event(uid: &str, summary: &str) -> Component {
+    ical::parse(&event(uid, summary)).unwrap().remove(0).components.remove(0)
+}
+
+/// The calendar collection at `/cal/` in the **server**, without a *CTag* or `sync-token`.
+pub fn collection(server: &Server) -> Calendar {
+    Calendar {
+        url: server.url("/cal/"),
+        display_name: Some("Test".to_string()),
+        color: None,
+        ctag: None,
+        sync_token: None,
+        components: Vec::new(),
+    }
+}
+
+/// Change the components of the calendar file at **path** with **edit**. Returns the new calendar.
+pub fn edit_file(path: &Path, edit: impl FnOnce(&mut Vec<Component>)) -> Component {
+    let mut calendar = ical::parse(&std::fs::read_to_string(path).unwrap()).unwrap().remove(0);
+    edit(&mut calendar.components);
+    std::fs::write(path, calendar.to_string()).unwrap();
+    calendar
+}
+
+/// Change the `SUMMARY` of the event with **uid** in the **components**.
+pub fn set_summary(components: &mut [Component], uid: &str, summary: &str) {
+    let event = components.iter_mut().find(|comp| comp.value("UID") == Some(uid)).unwrap();
+    event.set(Property::text("SUMMARY", summary));
+}
+
+/// The `SUMMARY` of the event with **uid** in the calendar file at **path**.
+pub fn local_summary(path: &Path, uid: &str) -> Option<String> {
+    let calendar = ical::parse(&std::fs::read_to_string(path).unwrap()).unwrap().remove(0);
+    calendar.components.iter()
+        .find(|comp| comp.value("UID") == Some(uid))
+        .and_then(|comp| comp.property("SUMMARY"))
+        .map(|prop| prop.text_value())
+}
+
+/// The `SUMMARY` of the event in the resource at **path** in the **server**, if it exists.
+pub fn remote_summary(server: &Server, path: &str) -> Option<String> {
+    let data = server.state().resources.get(path)?.1.clone();
+    Some(Kind::Calendar.parse(&server.url(path), &data).unwrap()[0].property("SUMMARY").unwrap().text_value())
+}
@@ -0,0 +1,283 @@
+//! A *WebDAV* server that runs in a thread of the tests, so that the [`Client`](super::Client) can be tested without a real server.
+//!
+//! It stores the resources in memory, and supports what the client uses of a collection:
+//! `PROPFIND` (with the *CTag* and `sync-token` of the collection), multiget and `sync-collection` `REPORT`s,
+//! and `GET`, `PUT` and `DELETE` with their preconditions.
+//! A test can answer some requests itself with [`Server::with_handler()`] (e.g. to require authentication).
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufRead as _, BufReader, Read as _, Write as _},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, MutexGuard},
+};
+use quick_xml::escape::escape;
+
+/// A request that the server received.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl Request {
+    /// Get the value of the header with **name** (case insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl Response {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self { status, headers: Vec::new(), body: body.into() }
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+}
+
+/// The resources of the server and the requests it received.
+#[derive(Default)]
+pub struct State {
+    /// The ETag and data of each resource, by path.
+    pub resources: BTreeMap<String, (String, String)>,
+    pub requests: Vec<Request>,
+    last_etag: u64,
+    /// The path of each resource that was put or deleted, in order.
+    /// The `sync-token` `sync-N` is the state after the first `N` changes.
+    changes: Vec<String>,
+    /// The number of changes of the oldest `sync-token` that the server still accepts.
+    oldest_sync_token: usize,
+}
+impl State {
+    /// Add (or replace) the resource at **path**, and return its new ETag.
+    pub fn put(&mut self, path: &str, data: &str) -> String {
+        self.last_etag += 1;
+        let etag = format!("\"{}\"", self.last_etag);
+        self.resources.insert(path.to_string(), (etag.clone(), data.to_string()));
+        self.changes.push(path.to_string());
+        etag
+    }
+
+    /// Remove the resource at **path**. Returns whether it existed.
+    pub fn delete(&mut self, path: &str) -> bool {
+        let existed = self.resources.remove(path).is_some();
+        if existed {
+            self.changes.push(path.to_string());
+        }
+        existed
+    }
+
+    /// The current *CTag* of the collections, which changes whenever any resource changes.
+    pub fn ctag(&self) -> String {
+        format!("ctag-{}", self.changes.len())
+    }
+
+    /// The current `sync-token` of the collections.
+    pub fn sync_token(&self) -> String {
+        format!("sync-{}", self.changes.len())
+    }
+
+    /// Stop accepting the `sync-token`s given before now, as a server does when it forgets old changes.
+    pub fn expire_sync_tokens(&mut self) {
+        self.oldest_sync_token = self.changes.len();
+    }
+}
+
+type Handler = dyn Fn(&Request, &mut State) -> Option<Response> + Send + Sync;
+
+pub struct Server {
+    /// The URL of the root of the server (e.g. `http://127.0.0.1:1234/`).
+    pub url: String,
+    state: Arc<Mutex<State>>,
+}
+impl Server {
+    pub fn start() -> Self {
+        Self::with_handler(|_, _| None)
+    }
+
+    /// Start a server where **handler** is called with each request before the server handles it,
+    /// and its response is sent instead if it returns one.
+    pub fn with_handler(handler: impl Fn(&Request, &mut State) -> Option<Response> + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+        let handler = Arc::new(handler) as Arc<Handler>;
+
+        let thread_state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { return };
+                // Each connection has its own thread, because a client keeps its connections open for the next requests
+                let (state, handler) = (Arc::clone(&thread_state), Arc::clone(&handler));
+                std::thread::spawn(move || {
+                    // A connection that breaks just fails the request of the test
+                    let _ = serve(stream, &state, &*handler);
+                });
+            }
+        });
+
+        Self { url, state }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// The absolute URL of a **path**.
+    pub fn url(&self, path: &str) -> url::Url {
+        url::Url::parse(&self.url).unwrap().join(path).unwrap()
+    }
+}
+
+/// Handle the requests of a connection until the client closes it.
+fn serve(stream: TcpStream, state: &Mutex<State>, handler: &Handler) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else { return Ok(()) };
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        let mut request = Request { method: method.to_string(), path: path.to_string(), headers, body: String::new() };
+        let length = request.header("Content-Length").and_then(|len| len.parse().ok()).unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        request.body = String::from_utf8_lossy(&body).into_owned();
+
+        let response = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(request.clone());
+            handler(&request, &mut state).unwrap_or_else(|| handle(&request, &mut state))
+        };
+
+        write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: {}\r\n", response.status, response.body.len())?;
+        for (name, value) in &response.headers {
+            write!(stream, "{name}: {value}\r\n")?;
+        }
+        write!(stream, "\r\n{}", response.body)?;
+        stream.flush()?;
+    }
+}
+
+/// Handle a *WebDAV* **request** with the resources of the **state**.
+///
+/// A handler of [`Server::with_handler()`] can call this with a modified request.
+pub fn handle(request: &Request, state: &mut State) -> Response {
+    let current = state.resources.get(&request.path).cloned();
+    let precondition_failed = match (request.header("If-Match"), request.header("If-None-Match"), &current) {
+        (Some(etag), _, Some((current, _))) => etag != current,
+        (Some(_), _, None) => true,
+        (_, Some("*"), Some(_)) => true,
+        _ => false,
+    };
+
+    match request.method.as_str() {
+        "PROPFIND" => {
+            let mut body = multistatus_response(&request.path, &format!(
+                "<d:resourcetype><d:collection/></d:resourcetype><cs:getctag>{}</cs:getctag><d:sync-token>{}</d:sync-token>",
+                state.ctag(), state.sync_token(),
+            ));
+            if request.header("Depth") == Some("0") {
+                return Response::new(207, multistatus(&body));
+            }
+            for (path, (etag, _)) in state.resources.range(request.path.clone()..) {
+                if !path.starts_with(&request.path) {
+                    break;
+                }
+                body.push_str(&multistatus_response(path, &format!("<d:resourcetype/><d:getetag>{}</d:getetag>", escape(etag))));
+            }
+            Response::new(207, multistatus(&body))
+        },
+        "REPORT" if request.body.contains("multiget") => {
+            let data = if request.body.contains("addressbook-multiget") { "card:address-data" } else { "c:calendar-data" };
+            let mut body = String::new();
+            for href in request.body.split("<d:href>").skip(1).filter_map(|rest| rest.split_once("</d:href>")) {
+                if let Some((etag, content)) = state.resources.get(href.0) {
+                    body.push_str(&multistatus_response(href.0, &format!(
+                        "<d:getetag>{}</d:getetag><{data}>{}</{data}>", escape(etag), escape(content)
+                    )));
+                }
+            }
+            Response::new(207, multistatus(&body))
+        },
+        "REPORT" if request.body.contains("sync-collection") => {
+            // The paths that changed since the token, or all of them for the initial sync (an empty token)
+            let token = request.body.split_once("<d:sync-token>")
+                .and_then(|(_, rest)| rest.split_once("</d:sync-token>"))
+                .map(|(token, _)| token);
+            let paths = match token {
+                Some(token) => match token.strip_prefix("sync-").and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if (state.oldest_sync_token..=state.changes.len()).contains(&n) => state.changes[n..].iter().collect::<BTreeSet<_>>(),
+                    _ => return Response::new(403, r#"<d:error xmlns:d="DAV:"><d:valid-sync-token/></d:error>"#),
+                },
+                None => state.resources.keys().collect(),
+            };
+
+            let mut body = String::new();
+            for path in paths.into_iter().filter(|path| path.starts_with(&request.path)) {
+                match state.resources.get(path) {
+                    Some((etag, _)) => body.push_str(&multistatus_response(path, &format!("<d:getetag>{}</d:getetag>", escape(etag)))),
+                    None => body.push_str(&format!("<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>", escape(path))),
+                }
+            }
+            body.push_str(&format!("<d:sync-token>{}</d:sync-token>", state.sync_token()));
+            Response::new(207, multistatus(&body))
+        },
+        "GET" => match current {
+            Some((etag, _)) if request.header("If-None-Match") == Some(etag.as_str()) => Response::new(304, ""),
+            Some((etag, data)) => Response::new(200, data).with_header("ETag", etag),
+            None => Response::new(404, ""),
+        },
+        "PUT" if precondition_failed => Response::new(412, ""),
+        "PUT" => {
+            let etag = state.put(&request.path, &request.body);
+            Response::new(if current.is_some() { 204 } else { 201 }, "").with_header("ETag", etag)
+        },
+        "DELETE" if precondition_failed => Response::new(412, ""),
+        "DELETE" if state.delete(&request.path) => Response::new(204, ""),
+        "DELETE" => Response::new(404, ""),
+        _ => Response::new(405, ""),
+    }
+}
+
+fn multistatus(responses: &str) -> String {
+    format!(r#"<?xml version="1.0" encoding="utf-8"?><d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" xmlns:card="urn:ietf:params:xml:ns:carddav" xmlns:cs="http://calendarserver.org/ns/">{responses}</d:multistatus>"#)
+}
+
+fn multistatus_response(href: &str, props: &str) -> String {
+    format!("<d:response><d:href>{}</d:href><d:propstat><d:prop>{props}</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>", escape(href))
+}
+
+/// Create an empty directory for a test with **name**, which is not removed after the test so that it can be inspected.
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("davsync-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,319 @@
+//! Building the XML bodies of *WebDAV* requests and parsing `multistatus` responses.
+use std::io::BufRead;
+use quick_xml::{escape::escape, events::{BytesStart, Event}, name::ResolveResult, NsReader};
+
+pub const DAV: &str = "DAV:";
+pub const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+pub const CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+pub const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+pub const APPLE_ICAL: &str = "http://apple.com/ns/ical/";
+
+/// The prefixes that are declared in the root element of the requests.
+const PREFIXES: [(&str, &str); 5] = [("d", DAV), ("c", CALDAV), ("card", CARDDAV), ("cs", CALENDARSERVER), ("ic", APPLE_ICAL)];
+
+/// The qualified name of a *WebDAV* property (or any other element).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Name {
+    pub namespace: &'static str,
+    pub name: &'static str,
+}
+impl Name {
+    pub const fn new(namespace: &'static str, name: &'static str) -> Self {
+        Self { namespace, name }
+    }
+
+    fn prefixed(&self) -> String {
+        let prefix = PREFIXES.iter()
+            .find(|(_, namespace)| *namespace == self.namespace)
+            .map(|(prefix, _)| *prefix)
+            .expect("All namespaces used in requests must have a prefix");
+        format!("{prefix}:{}", self.name)
+    }
+}
+
+pub const HREF: Name = Name::new(DAV, "href");
+pub const COLLECTION: Name = Name::new(DAV, "collection");
+pub const RESOURCE_TYPE: Name = Name::new(DAV, "resourcetype");
+pub const DISPLAY_NAME: Name = Name::new(DAV, "displayname");
+pub const GET_ETAG: Name = Name::new(DAV, "getetag");
+pub const SYNC_TOKEN: Name = Name::new(DAV, "sync-token");
+pub const CURRENT_USER_PRINCIPAL: Name = Name::new(DAV, "current-user-principal");
+pub const CALENDAR: Name = Name::new(CALDAV, "calendar");
+pub const CALENDAR_HOME_SET: Name = Name::new(CALDAV, "calendar-home-set");
+pub const SUPPORTED_CALENDAR_COMPONENT_SET: Name = Name::new(CALDAV, "supported-calendar-component-set");
+pub const CALENDAR_MULTIGET: Name = Name::new(CALDAV, "calendar-multiget");
+pub const CALENDAR_DATA: Name = Name::new(CALDAV, "calendar-data");
+pub const ADDRESSBOOK: Name = Name::new(CARDDAV, "addressbook");
+pub const ADDRESSBOOK_HOME_SET: Name = Name::new(CARDDAV, "addressbook-home-set");
+pub const ADDRESSBOOK_MULTIGET: Name = Name::new(CARDDAV, "addressbook-multiget");
+pub const ADDRESS_DATA: Name = Name::new(CARDDAV, "address-data");
+pub const GET_CTAG: Name = Name::new(CALENDARSERVER, "getctag");
+pub const CALENDAR_COLOR: Name = Name::new(APPLE_ICAL, "calendar-color");
+
+/// Build the body of a `PROPFIND` request that asks for the properties in **props**.
+pub fn propfind(props: &[Name]) -> String {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    body.push_str("<d:propfind");
+    for (prefix, namespace) in PREFIXES {
+        body.push_str(&format!(r#" xmlns:{prefix}="{namespace}""#));
+    }
+    body.push_str("><d:prop>");
+    for prop in props {
+        body.push_str(&format!("<{}/>", prop.prefixed()));
+    }
+    body.push_str("</d:prop></d:propfind>");
+    body
+}
+
+/// Build the body of a `sync-collection` `REPORT` (RFC 6578) that asks for the members that changed since **sync_token**
+/// (or all of them if it's [`None`]), and their **props**.
+pub fn sync_collection(sync_token: Option<&str>, props: &[Name]) -> String {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    body.push_str("<d:sync-collection");
+    for (prefix, namespace) in PREFIXES {
+        body.push_str(&format!(r#" xmlns:{prefix}="{namespace}""#));
+    }
+    body.push('>');
+    match sync_token {
+        Some(sync_token) => body.push_str(&format!("<d:sync-token>{}</d:sync-token>", escape(sync_token))),
+        None => body.push_str("<d:sync-token/>"),
+    }
+    body.push_str("<d:sync-level>1</d:sync-level><d:prop>");
+    for prop in props {
+        body.push_str(&format!("<{}/>", prop.prefixed()));
+    }
+    body.push_str("</d:prop></d:sync-collection>");
+    body
+}
+
+/// Build the body of a multiget `REPORT` (e.g. `calendar-multiget`, RFC 4791) that asks for the **props** of the resources at **hrefs**.
+pub fn multiget(report: Name, props: &[Name], hrefs: &[&str]) -> String {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    body.push_str(&format!("<{}", report.prefixed()));
+    for (prefix, namespace) in PREFIXES {
+        body.push_str(&format!(r#" xmlns:{prefix}="{namespace}""#));
+    }
+    body.push_str("><d:prop>");
+    for prop in props {
+        body.push_str(&format!("<{}/>", prop.prefixed()));
+    }
+    body.push_str("</d:prop>");
+    for href in hrefs {
+        body.push_str(&format!("<d:href>{}</d:href>", escape(*href)));
+    }
+    body.push_str(&format!("</{}>", report.prefixed()));
+    body
+}
+
+/// A `response` element of a `multistatus`, which has the properties of the resource at **href**.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response {
+    pub href: String,
+    /// The status of the whole response. Only present if the response has no properties (e.g. the resource was deleted).
+    pub status: Option<u16>,
+    pub props: Vec<Prop>,
+}
+impl Response {
+    /// Get a property that was found (status `200`).
+    pub fn prop(&self, name: Name) -> Option<&Prop> {
+        self.props.iter().find(|prop| prop.is(name) && prop.status == 200)
+    }
+    /// Get the text value of a property that was found.
+    pub fn text(&self, name: Name) -> Option<&str> {
+        self.prop(name)
+            .map(|prop| prop.text.trim())
+            .filter(|text| !text.is_empty())
+    }
+}
+
+/// A property in a `propstat`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Prop {
+    pub namespace: String,
+    pub name: String,
+    /// The status of the `propstat` the property was in.
+    pub status: u16,
+    /// All the text in the property, except that of `href`s.
+    pub text: String,
+    /// The values of the `href` elements in the property.
+    pub hrefs: Vec<String>,
+    /// The other elements in the property (e.g. the types in `resourcetype`).
+    pub children: Vec<Element>,
+}
+impl Prop {
+    pub fn is(&self, name: Name) -> bool {
+        self.namespace == name.namespace && self.name == name.name
+    }
+    /// Whether the property has an element with **name** (e.g. `resourcetype` has `collection`).
+    pub fn has(&self, name: Name) -> bool {
+        self.children.iter().any(|child| child.namespace == name.namespace && child.name == name.name)
+    }
+}
+
+/// An element nested in a [`Prop`] and its attributes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Element {
+    pub namespace: String,
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+}
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse the body of a `207 Multi-Status` response.
+pub fn parse_multistatus(body: &str) -> Result<Vec<Response>, String> {
+    parse_multistatus_with_token(body).map(|(responses, _)| responses)
+}
+
+/// Parse the body of a `207 Multi-Status` response to a `sync-collection` `REPORT`,
+/// which also has the new `sync-token` of the collection.
+pub fn parse_multistatus_with_token(body: &str) -> Result<(Vec<Response>, Option<String>), String> {
+    let mut responses = Vec::new();
+    let sync_token = read_multistatus(body.as_bytes(), |response| responses.push(response))?;
+    Ok((responses, sync_token))
+}
+
+/// Parse the body of a `207 Multi-Status` response while it is being read from **reader**,
+/// and call **on_response** with each `response` as soon as it ends,
+/// so that a large response (e.g. with the data of many resources) doesn't have to be kept in memory.
+///
+/// Returns the `sync-token` of the `multistatus`, if it has one.
+pub fn read_multistatus(reader: impl BufRead, mut on_response: impl FnMut(Response)) -> Result<Option<String>, String> {
+    let mut reader = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    // The elements that are currently open
+    let mut stack = Vec::<(String, String)>::new();
+    let mut responses = Vec::new();
+    let mut response = None::<Response>;
+    // The properties of the current `propstat`, which don't have a status until the end of it.
+    let mut propstat = Vec::<Prop>::new();
+    let mut prop = None::<Prop>;
+    let mut text = String::new();
+    let mut sync_token = None;
+
+    loop {
+        buf.clear();
+        let (namespace, event) = reader.read_resolved_event_into(&mut buf)
+            .map_err(|err| format!("Invalid XML: {err}"))?;
+        let namespace = match namespace {
+            ResolveResult::Bound(namespace) => String::from_utf8_lossy(namespace.as_ref()).into_owned(),
+            _ => String::new(),
+        };
+
+        match event {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+                text.clear();
+
+                match &mut prop {
+                    Some(_) if namespace == HREF.namespace && name == HREF.name => {},
+                    Some(prop) => prop.children.push(Element {
+                        namespace: namespace.clone(),
+                        name: name.clone(),
+                        attributes: attributes(start),
+                    }),
+                    // The parent of the element is `prop`, so this is a property
+                    None if response.is_some() && is(&stack, DAV, "prop") => prop = Some(Prop {
+                        namespace: namespace.clone(),
+                        name: name.clone(),
+                        ..Default::default()
+                    }),
+                    None if namespace == DAV && name == "response" => response = Some(Response::default()),
+                    None => {}
+                }
+
+                if matches!(event, Event::Empty(_)) {
+                    end_element(&namespace, &name, &mut stack, &mut response, &mut responses, &mut propstat, &mut prop, &mut text);
+                } else {
+                    stack.push((namespace, name));
+                }
+            },
+            Event::End(ref end) => {
+                let name = String::from_utf8_lossy(end.local_name().as_ref()).into_owned();
+                stack.pop();
+                if namespace == SYNC_TOKEN.namespace && name == SYNC_TOKEN.name && is(&stack, DAV, "multistatus") {
+                    sync_token = Some(text.trim().to_string()).filter(|token| !token.is_empty());
+                }
+                end_element(&namespace, &name, &mut stack, &mut response, &mut responses, &mut propstat, &mut prop, &mut text);
+            },
+            Event::Text(ref t) => text.push_str(&t.unescape().map_err(|err| format!("Invalid XML text: {err}"))?),
+            Event::CData(ref t) => text.push_str(&String::from_utf8_lossy(t.as_ref())),
+            Event::Eof => break,
+            _ => {}
+        }
+
+        for response in responses.drain(..) {
+            on_response(response);
+        }
+    }
+
+    Ok(sync_token)
+}
+
+/// Handle the end of an element with **name** whose parent is the last element in **stack**.
+#[allow(clippy::too_many_arguments)]
+fn end_element(
+    namespace: &str,
+    name: &str,
+    stack: &mut [(String, String)],
+    response: &mut Option<Response>,
+    responses: &mut Vec<Response>,
+    propstat: &mut Vec<Prop>,
+    prop: &mut Option<Prop>,
+    text: &mut String,
+) {
+    let text = std::mem::take(text);
+
+    if let Some(p) = prop {
+        // End of the property
+        if is(stack, DAV, "prop") {
+            p.text.push_str(&text);
+            propstat.push(prop.take().unwrap());
+        } else if namespace == HREF.namespace && name == HREF.name {
+            p.hrefs.push(text.trim().to_string());
+        } else {
+            p.text.push_str(&text);
+        }
+        return
+    }
+
+    let Some(r) = response else { return };
+    match (namespace, name) {
+        (DAV, "href") if is(stack, DAV, "response") => r.href = text.trim().to_string(),
+        (DAV, "status") if is(stack, DAV, "response") => r.status = parse_status(&text),
+        (DAV, "status") if is(stack, DAV, "propstat") => {
+            let status = parse_status(&text).unwrap_or(0);
+            for prop in propstat.iter_mut() {
+                prop.status = status;
+            }
+        },
+        (DAV, "propstat") => r.props.append(propstat),
+        (DAV, "response") => responses.push(response.take().unwrap()),
+        _ => {}
+    }
+}
+
+/// Whether the last element in **stack** (the parent of the current element) has **name**.
+fn is(stack: &[(String, String)], namespace: &str, name: &str) -> bool {
+    stack.last().is_some_and(|(ns, n)| ns == namespace && n == name)
+}
+
+fn attributes(start: &BytesStart) -> Vec<(String, String)> {
+    start.attributes()
+        .filter_map(Result::ok)
+        .map(|attr| (
+            String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned(),
+            attr.unescape_value().map(|value| value.into_owned()).unwrap_or_default(),
+        ))
+        .collect()
+}
+
+/// Get the code of a status line such as `HTTP/1.1 200 OK`.
+fn parse_status(status: &str) -> Option<u16> {
+    status.split_whitespace().nth(1)?.parse().ok()
+}
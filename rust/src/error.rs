@@ -0,0 +1,152 @@
+//! The [`Error`] type of this crate, which the functions called from Java throw as exceptions instead of crashing the App.
+//!
+//! Each variant is thrown as a subclass of `DavSyncException` (in `jni/Errors.kt`), so the Kotlin side can tell failures apart.
+use std::{fmt::Display, io};
+use jni::JNIEnv;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or directory failed.
+    Io(String),
+    /// A *Document Uri* (or a URL) is not valid, or doesn't point to what was expected (e.g. a directory).
+    InvalidUri(String),
+    /// The App is not allowed to access a *Content Provider*, a file, or a server.
+    Permission(String),
+    /// The content of a file (or of a row of the *Content Provider*) could not be parsed.
+    Parse(String),
+    /// A file or calendar conflicts with one that already exists.
+    Conflict(String),
+    /// A file, calendar or subscription that the operation needs does not exist.
+    NotFound(String),
+    /// A query to a *Content Provider* failed.
+    Provider(String),
+    /// Couldn't communicate with a *CalDAV*/*CardDAV* server.
+    Network(String),
+    /// A call between Rust and Java through the JNI failed.
+    Jni(String),
+}
+impl Error {
+    /// Create an error for an [`io::Error`] that happened while doing what is described in **msg**.
+    ///
+    /// Errors of kind [`AlreadyExists`][io::ErrorKind::AlreadyExists] are [`Conflict`][Self::Conflict]s,
+    /// errors of kind [`NotFound`][io::ErrorKind::NotFound] are [`NotFound`][Self::NotFound] errors,
+    /// and errors of kind [`PermissionDenied`][io::ErrorKind::PermissionDenied] are [`Permission`][Self::Permission] errors.
+    pub fn io(msg: impl Display, err: io::Error) -> Self {
+        let msg = format!("{msg}: {err}");
+        match err.kind() {
+            io::ErrorKind::AlreadyExists => Self::Conflict(msg),
+            io::ErrorKind::NotFound => Self::NotFound(msg),
+            io::ErrorKind::PermissionDenied => Self::Permission(msg),
+            _ => Self::Io(msg),
+        }
+    }
+
+    /// The (binary) name of the Java class of the exception this error is thrown as.
+    fn class(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "me/marti/calprovexample/jni/DavSyncException$Io",
+            Self::InvalidUri(_) => "me/marti/calprovexample/jni/DavSyncException$InvalidUri",
+            Self::Permission(_) => "me/marti/calprovexample/jni/DavSyncException$Permission",
+            Self::Parse(_) => "me/marti/calprovexample/jni/DavSyncException$Parse",
+            Self::Conflict(_) => "me/marti/calprovexample/jni/DavSyncException$Conflict",
+            Self::NotFound(_) => "me/marti/calprovexample/jni/DavSyncException$NotFound",
+            Self::Provider(_) => "me/marti/calprovexample/jni/DavSyncException$Provider",
+            Self::Network(_) => "me/marti/calprovexample/jni/DavSyncException$Network",
+            Self::Jni(_) => "me/marti/calprovexample/jni/DavSyncException$Jni",
+        }
+    }
+
+    /// Throw this error as a Java exception.
+    /// The JNI function must return right after calling this (the value it returns is ignored by Java).
+    pub fn throw(&self, env: &mut JNIEnv) {
+        env.throw_new(self.class(), self.to_string())
+            .unwrap_or_else(|err| panic!("Failed to throw exception for error \"{self}\": {err}"))
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(msg)
+            | Self::InvalidUri(msg)
+            | Self::Permission(msg)
+            | Self::Parse(msg)
+            | Self::Conflict(msg)
+            | Self::NotFound(msg)
+            | Self::Provider(msg)
+            | Self::Network(msg)
+            | Self::Jni(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl From<jni::errors::Error> for Error {
+    fn from(err: jni::errors::Error) -> Self {
+        Self::Jni(err.to_string())
+    }
+}
+impl From<crate::dav::Error> for Error {
+    fn from(err: crate::dav::Error) -> Self {
+        use crate::dav::Error as DavError;
+        let msg = err.to_string();
+        match err {
+            DavError::InvalidUrl(_) => Self::InvalidUri(msg),
+            DavError::Status { status: 401 | 403, .. } | DavError::Unauthorized(_) => Self::Permission(msg),
+            DavError::Transport(_) | DavError::Status { .. } => Self::Network(msg),
+            DavError::PreconditionFailed(_) => Self::Conflict(msg),
+            DavError::InvalidResponse(_) => Self::Parse(msg),
+            DavError::Io(err) => Self::io("Error accessing sync state", err),
+        }
+    }
+}
+
+/// Throw the error of a [`Result`] returned by a JNI function.
+pub trait OrThrow<T> {
+    /// Returns the value if it's [`Ok`], or throws the [`Error`] and returns a *default* value (e.g. `null` for objects).
+    fn or_throw(self, env: &mut JNIEnv) -> T;
+}
+impl<T: Default> OrThrow<T> for Result<T, Error> {
+    fn or_throw(self, env: &mut JNIEnv) -> T {
+        self.unwrap_or_else(|err| {
+            err.throw(env);
+            T::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_error_kinds() {
+        let err = Error::io("Error creating \"a.ics\"", io::Error::from(io::ErrorKind::AlreadyExists));
+        assert!(matches!(&err, Error::Conflict(msg) if msg.starts_with("Error creating \"a.ics\": ")), "{err:?}");
+        assert!(matches!(Error::io("Error", io::Error::from(io::ErrorKind::PermissionDenied)), Error::Permission(_)));
+        assert!(matches!(Error::io("Error", io::Error::from(io::ErrorKind::NotFound)), Error::NotFound(_)));
+        assert!(matches!(Error::io("Error", io::Error::from(io::ErrorKind::InvalidData)), Error::Io(_)));
+        assert_eq!(Error::Parse("Invalid line".to_string()).to_string(), "Invalid line");
+    }
+
+    #[test]
+    fn exception_classes() {
+        assert_eq!(Error::Io(String::new()).class(), "me/marti/calprovexample/jni/DavSyncException$Io");
+        assert_eq!(Error::NotFound(String::new()).class(), "me/marti/calprovexample/jni/DavSyncException$NotFound");
+        assert_eq!(Error::Jni(String::new()).class(), "me/marti/calprovexample/jni/DavSyncException$Jni");
+    }
+
+    #[test]
+    fn dav_errors() {
+        use crate::dav::Error as DavError;
+        let status = |status| DavError::Status { method: "GET", url: "https://dav.example.com/".to_string(), status };
+        assert!(matches!(Error::from(status(401)), Error::Permission(_)));
+        assert!(matches!(Error::from(status(403)), Error::Permission(_)));
+        assert!(matches!(Error::from(status(500)), Error::Network(_)));
+        assert!(matches!(Error::from(DavError::Unauthorized("Revoked".to_string())), Error::Permission(_)));
+        assert!(matches!(Error::from(DavError::Transport("Connection refused".to_string())), Error::Network(_)));
+        assert!(matches!(Error::from(DavError::InvalidUrl("dav://".to_string())), Error::InvalidUri(_)));
+        assert!(matches!(Error::from(DavError::PreconditionFailed("a.ics".to_string())), Error::Conflict(_)));
+        assert!(matches!(Error::from(DavError::InvalidResponse("Not XML".to_string())), Error::Parse(_)));
+        let err = Error::from(DavError::Io(io::Error::new(io::ErrorKind::InvalidData, "Invalid line")));
+        assert!(matches!(&err, Error::Io(msg) if msg.ends_with("Invalid line")), "{err:?}");
+    }
+}
@@ -0,0 +1,145 @@
+//! Detecting whether a file is a *calendar file* or a *contacts file*,
+//! which determines the directory (one of [`DIRECTORIES`]) where it is stored.
+use std::fmt::Display;
+use crate::DIRECTORIES;
+
+/// The kinds of files that this App can sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// An iCalendar file (`.ics`).
+    Calendar,
+    /// A vCard file (`.vcf`).
+    Contacts,
+}
+impl FileType {
+    /// Detect the type of a file from its **content**, its **mime_type** (if known), and the extension of its **file_name**.
+    ///
+    /// The **content** is the most reliable, so it takes precedence.
+    /// The **mime_type** and the extension are only used for empty files (e.g. a calendar that was just created).
+    pub fn detect(file_name: &str, mime_type: Option<&str>, content: &[u8]) -> Result<Self, UnsupportedFileError> {
+        let unsupported = || UnsupportedFileError {
+            file_name: file_name.to_string(),
+            mime_type: mime_type.map(str::to_string),
+        };
+
+        if !content.iter().all(u8::is_ascii_whitespace) {
+            return Self::sniff(content).ok_or_else(unsupported);
+        }
+
+        mime_type.and_then(Self::from_mime_type)
+            .or_else(|| mime_guess::from_path(file_name).iter().find_map(|mime| Self::from_mime_type(mime.essence_str())))
+            .ok_or_else(unsupported)
+    }
+
+    /// Get the type of files with a **mime_type** (which can have parameters, e.g. `text/calendar; charset=utf-8`).
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "text/calendar" | "application/ics" | "application/calendar+json" | "application/calendar+xml" => Some(Self::Calendar),
+            "text/vcard" | "text/x-vcard" | "text/directory" => Some(Self::Contacts),
+            _ => None,
+        }
+    }
+
+    /// Get the type of a file from the first *content line*, which is `BEGIN:VCALENDAR` or `BEGIN:VCARD`.
+    fn sniff(content: &[u8]) -> Option<Self> {
+        let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+        let start = content.iter().position(|byte| !byte.is_ascii_whitespace())?;
+        let line = content[start..]
+            .split(|&byte| byte == b'\r' || byte == b'\n')
+            .next()
+            .unwrap_or_default();
+        let line = std::str::from_utf8(line).ok()?.trim_end();
+
+        if line.eq_ignore_ascii_case("BEGIN:VCALENDAR") {
+            Some(Self::Calendar)
+        } else if line.eq_ignore_ascii_case("BEGIN:VCARD") {
+            Some(Self::Contacts)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the directory (in the internal and external directories) where files of this type are stored.
+    pub const fn dir(self) -> &'static str {
+        match self {
+            Self::Calendar => DIRECTORIES[0],
+            Self::Contacts => DIRECTORIES[1],
+        }
+    }
+
+    /// The extension of the files of this type, which is used when the file is stored.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Calendar => "ics",
+            Self::Contacts => "vcf",
+        }
+    }
+
+    /// Get the type of a file that was already imported from the extension of its **file_name**.
+    pub fn of_imported(file_name: &str) -> Self {
+        match file_name.rsplit_once('.') {
+            Some((_, extension)) if extension.eq_ignore_ascii_case(Self::Contacts.extension()) => Self::Contacts,
+            _ => Self::Calendar,
+        }
+    }
+}
+
+/// A file could not be imported because it is neither a *calendar file* nor a *contacts file*.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedFileError {
+    pub file_name: String,
+    pub mime_type: Option<String>,
+}
+impl Display for UnsupportedFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "File {:?}", self.file_name)?;
+        if let Some(mime_type) = &self.mime_type {
+            write!(f, " (of type {mime_type:?})")?;
+        }
+        write!(f, " is neither a calendar nor a contacts file")
+    }
+}
+impl std::error::Error for UnsupportedFileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &[u8] = b"BEGIN:VCALENDAR\r\nVERSION:2.0\r\nEND:VCALENDAR\r\n";
+    const CARD: &[u8] = b"BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nEND:VCARD\r\n";
+
+    #[test]
+    fn content_takes_precedence() {
+        // The extension and MIME type say it's a calendar, but the content is a vCard
+        assert_eq!(FileType::detect("contacts.ics", Some("text/calendar"), CARD), Ok(FileType::Contacts));
+        assert_eq!(FileType::detect("calendar.vcf", None, CALENDAR), Ok(FileType::Calendar));
+        // A byte order mark, blank lines and lowercase are allowed
+        assert_eq!(FileType::detect("file", None, b"\xEF\xBB\xBF\r\n\r\nbegin:vcard\nEND:VCARD\n"), Ok(FileType::Contacts));
+        // Content that is neither is unsupported, even with a supported MIME type and extension
+        assert_eq!(
+            FileType::detect("notes.ics", Some("text/calendar"), b"Just some notes"),
+            Err(UnsupportedFileError { file_name: "notes.ics".to_string(), mime_type: Some("text/calendar".to_string()) })
+        );
+    }
+
+    #[test]
+    fn mime_type_then_extension_for_empty_files() {
+        assert_eq!(FileType::detect("file.ics", Some("text/vcard; charset=utf-8"), b""), Ok(FileType::Contacts));
+        assert_eq!(FileType::detect("file.vcf", Some("TEXT/CALENDAR"), b"  \r\n"), Ok(FileType::Calendar));
+        // The MIME type is not known or not supported
+        assert_eq!(FileType::detect("file.ics", None, b""), Ok(FileType::Calendar));
+        assert_eq!(FileType::detect("file.vcf", Some("application/octet-stream"), b""), Ok(FileType::Contacts));
+        assert!(FileType::detect("file.txt", Some("text/plain"), b"").is_err());
+        assert!(FileType::detect("file", None, b"").is_err());
+    }
+
+    #[test]
+    fn imported_files() {
+        assert_eq!(FileType::of_imported("Personal.ics"), FileType::Calendar);
+        assert_eq!(FileType::of_imported("Friends.VCF"), FileType::Contacts);
+        assert_eq!(FileType::of_imported("no extension"), FileType::Calendar);
+        assert_eq!(FileType::from_mime_type("application/calendar+json"), Some(FileType::Calendar));
+        assert_eq!(FileType::from_mime_type("text/plain"), None);
+    }
+}
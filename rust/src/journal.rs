@@ -0,0 +1,427 @@
+//! Operations that change the **internal** directory, the **external** directory and the *Content Provider* together
+//! (e.g. importing a file), which must not be left half done when one of their steps fails.
+//!
+//! Each step of an operation is recorded in a *journal* file right after it is done,
+//! so that the steps can be undone in reverse order if a later step fails ([`Journal::finish()`]),
+//! or the next time the App starts if it was closed in the middle of the operation ([`recover()`]).
+//! The files that a step deletes are backed up in the journal's directory until the operation is committed.
+use std::{fs::File, io::{self, Write as _}, path::{Path, PathBuf}};
+use ez_jni::{call, jni_fn, println};
+use jni::{JNIEnv, objects::JObject};
+use classes::fs::ExternalDir;
+use crate::{calendar, contacts, error::{Error, OrThrow as _}, get_app_dir, utils::open_external_dir};
+
+/// The directory (in the *app's directory*) where the journal of the operation in progress and its backups are stored.
+const JOURNAL_DIR: &str = "journal";
+/// The name of the journal file in [`JOURNAL_DIR`].
+const JOURNAL_FILE: &str = "journal";
+
+jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
+    /// Undo the steps of an operation that was interrupted the last time the App ran (e.g. it was closed while a file was being imported),
+    /// or finish it if all of its steps were done.
+    ///
+    /// This must be called when the App starts, before the files and the *Content Provider* are read.
+    /// **perm** is needed to restore Calendars that the operation deleted from the *Content Provider*;
+    /// if it's `NULL` and the operation deleted one, an exception is thrown and the operation is undone the next time this is called.
+    pub fn recover_journal<'local>(
+        context: android.content.Context,
+        perm: Option<me.marti.calprovexample.ui.CalendarPermissionScope>,
+    ) {
+        recover(env, &context, perm.as_ref())
+            .or_throw(env)
+    }
+}
+
+/// A change made by a step of an operation, which can be undone.
+///
+/// The paths of files are relative to the *app's directory* (e.g. `"calendars/name.ics"`),
+/// or to the **external** directory with the *Document Tree Uri* **dir_uri**.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// A file was created in the **internal** directory.
+    CreateInternal(PathBuf),
+    /// A file was deleted from the **internal** directory. Its content is in the **backup** file.
+    RemoveInternal { path: PathBuf, backup: String },
+    /// A file was created in the **external** directory.
+    CreateExternal { dir_uri: String, path: PathBuf },
+    /// A file was deleted from the **external** directory. Its content is in the **backup** file.
+    RemoveExternal { dir_uri: String, path: PathBuf, backup: String },
+    /// A file in the **external** directory was given a **new_name** (in the same directory).
+    RenameExternal { dir_uri: String, path: PathBuf, new_name: String },
+    /// A Calendar with this name was created in the Calendar *Content Provider*.
+    CreateCalendar(String),
+    /// The Calendar with this name was deleted from the Calendar *Content Provider*.
+    /// It is restored from its file in the **internal** directory.
+    DeleteCalendar(String),
+    /// The contacts of the *Contacts file* with this name were written to the Contacts *Content Provider*.
+    WriteContacts(String),
+}
+impl Step {
+    /// The line that represents this step in the journal file: A `key` followed by tab-separated values,
+    /// which are [escaped](escape()) so that names with tabs or line breaks don't change how the line is split.
+    fn to_line(&self) -> String {
+        let escape_path = |path: &Path| escape(&path.to_string_lossy());
+        match self {
+            Self::CreateInternal(path) => format!("create-internal\t{}", escape_path(path)),
+            Self::RemoveInternal { path, backup } => format!("remove-internal\t{}\t{}", escape(backup), escape_path(path)),
+            Self::CreateExternal { dir_uri, path } => format!("create-external\t{}\t{}", escape(dir_uri), escape_path(path)),
+            Self::RemoveExternal { dir_uri, path, backup } =>
+                format!("remove-external\t{}\t{}\t{}", escape(backup), escape(dir_uri), escape_path(path)),
+            Self::RenameExternal { dir_uri, path, new_name } =>
+                format!("rename-external\t{}\t{}\t{}", escape(new_name), escape(dir_uri), escape_path(path)),
+            Self::CreateCalendar(name) => format!("create-calendar\t{}", escape(name)),
+            Self::DeleteCalendar(name) => format!("delete-calendar\t{}", escape(name)),
+            Self::WriteContacts(name) => format!("write-contacts\t{}", escape(name)),
+        }
+    }
+
+    /// Parse a line written by [`Step::to_line()`].
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let mut next = || fields.next().map(unescape);
+        Some(match next()?.as_str() {
+            "create-internal" => Self::CreateInternal(next()?.into()),
+            "remove-internal" => Self::RemoveInternal { backup: next()?, path: next()?.into() },
+            "create-external" => Self::CreateExternal { dir_uri: next()?, path: next()?.into() },
+            "remove-external" => Self::RemoveExternal { backup: next()?, dir_uri: next()?, path: next()?.into() },
+            "rename-external" => Self::RenameExternal { new_name: next()?, dir_uri: next()?, path: next()?.into() },
+            "create-calendar" => Self::CreateCalendar(next()?),
+            "delete-calendar" => Self::DeleteCalendar(next()?),
+            "write-contacts" => Self::WriteContacts(next()?),
+            _ => return None,
+        })
+    }
+
+    /// Revert the change made by this step.
+    ///
+    /// Undoing a step that was already undone (or that was never completed) does nothing, so steps can be undone more than once.
+    fn undo<'local>(
+        &self,
+        env: &mut JNIEnv<'local>,
+        context: &JObject<'local>,
+        perm: Option<&JObject<'local>>,
+        app_dir: &Path,
+    ) -> Result<(), Error> {
+        let journal_dir = app_dir.join(JOURNAL_DIR);
+        match self {
+            Self::CreateInternal(path) => match std::fs::remove_file(app_dir.join(path)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::io(format!("Error deleting internal file {path:?}"), err)),
+                _ => Ok(()),
+            },
+            Self::RemoveInternal { path, backup } => std::fs::copy(journal_dir.join(backup), app_dir.join(path))
+                .map(|_| ())
+                .map_err(|err| Error::io(format!("Error restoring internal file {path:?}"), err)),
+            Self::CreateExternal { dir_uri, path } => match parse_external_dir(env, context, dir_uri)?.remove_file_at(env, path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::io(format!("Error deleting external file {path:?}"), err)),
+                _ => Ok(()),
+            },
+            Self::RemoveExternal { dir_uri, path, backup } => {
+                let content = std::fs::read(journal_dir.join(backup))
+                    .map_err(|err| Error::io(format!("Error reading backup of external file {path:?}"), err))?;
+                parse_external_dir(env, context, dir_uri)?
+                    .write_file_at(env, path, &content)
+                    .map_err(|err| Error::io(format!("Error restoring external file {path:?}"), err))
+            },
+            Self::RenameExternal { dir_uri, path, new_name } => {
+                let old_name = path.file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| Error::Io(format!("Invalid path of renamed external file {path:?}")))?;
+                match parse_external_dir(env, context, dir_uri)?.rename_file_at(env, path.with_file_name(new_name), old_name) {
+                    // The file already has its old name if the renamed file is not found or the old name is taken
+                    Err(err) if !matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::AlreadyExists) =>
+                        Err(Error::io(format!("Error restoring name of external file {path:?}"), err)),
+                    _ => Ok(()),
+                }
+            },
+            Self::CreateCalendar(name) => calendar::delete_calendar(env, context, name),
+            Self::DeleteCalendar(name) => match perm {
+                Some(perm) => calendar::write_file_data_to_calendar(env, perm, name, &JObject::null()),
+                None => Err(Error::Permission(format!("Calendar {name:?} can't be restored in the Content Provider without permission"))),
+            },
+            Self::WriteContacts(name) => contacts::delete_contacts(env, context, name),
+        }
+    }
+}
+
+/// Escape the backslashes, tabs and line breaks of a **field** of a line of the journal.
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo [`escape()`].
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// The record of the steps of an operation that is in progress.
+///
+/// A journal that is dropped without calling [`finish()`][Journal::finish()] is left open,
+/// so that the operation can be continued later with [`Journal::resume()`].
+pub struct Journal {
+    /// Describes the operation (e.g. `"import name.ics"`).
+    description: String,
+    steps: Vec<Step>,
+    app_dir: PathBuf,
+    /// The journal file, opened for appending.
+    file: File,
+}
+impl Journal {
+    /// Start recording an operation with **description** (e.g. `"import name.ics"`).
+    ///
+    /// Returns a [`Conflict`][Error::Conflict] if another operation was left open and not continued,
+    /// because only [`recover()`] (with the permission to restore Calendars) can undo it.
+    pub fn begin<'local>(
+        env: &mut JNIEnv<'local>,
+        context: &JObject<'local>,
+        description: &str,
+    ) -> Result<Self, Error> {
+        let app_dir = get_app_dir(env, context);
+        match Self::read(&app_dir)? {
+            // All the steps were done, only the journal was not deleted
+            Some((journal, true)) => journal.remove()?,
+            Some((journal, false)) => return Err(Error::Conflict(format!(
+                "Can't start \"{description}\" because \"{}\" was not finished; It will be undone when the App starts again",
+                journal.description
+            ))),
+            None => {},
+        }
+
+        let journal_dir = app_dir.join(JOURNAL_DIR);
+        std::fs::create_dir_all(&journal_dir)
+            .map_err(|err| Error::io("Error creating journal directory", err))?;
+        let mut file = File::create(journal_dir.join(JOURNAL_FILE))
+            .map_err(|err| Error::io("Error creating journal", err))?;
+        writeln!(file, "begin\t{}", escape(description))
+            .and_then(|()| file.sync_data())
+            .map_err(|err| Error::io("Error writing journal", err))?;
+
+        Ok(Self { description: description.to_string(), steps: Vec::new(), app_dir, file })
+    }
+
+    /// Continue the operation with **description** if it's the operation that was left open,
+    /// or [`begin()`][Journal::begin()] a new one otherwise.
+    pub fn resume<'local>(
+        env: &mut JNIEnv<'local>,
+        context: &JObject<'local>,
+        description: &str,
+    ) -> Result<Self, Error> {
+        let app_dir = get_app_dir(env, context);
+        match Self::read(&app_dir)? {
+            Some((journal, false)) if journal.description == description => Ok(journal),
+            _ => Self::begin(env, context, description),
+        }
+    }
+
+    /// Read the journal that is in the *app's directory*, and whether its operation was committed.
+    /// Returns [`None`] if there is no operation in progress.
+    fn read(app_dir: &Path) -> Result<Option<(Self, bool)>, Error> {
+        let path = app_dir.join(JOURNAL_DIR).join(JOURNAL_FILE);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::io("Error reading journal", err)),
+        };
+
+        let mut description = String::new();
+        let mut steps = Vec::new();
+        let mut committed = false;
+        for line in content.lines() {
+            if let Some(desc) = line.strip_prefix("begin\t") {
+                description = unescape(desc);
+            } else if line == "commit" {
+                committed = true;
+            } else {
+                // A line that can't be parsed is the last one, and was cut off when the App was closed while writing it,
+                // so its step was not done yet
+                steps.extend(Step::from_line(line));
+            }
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|err| Error::io("Error opening journal", err))?;
+        Ok(Some((Self { description, steps, app_dir: app_dir.to_path_buf(), file }, committed)))
+    }
+
+    /// Record that a **step** was done.
+    pub fn record(&mut self, step: Step) -> Result<(), Error> {
+        writeln!(self.file, "{}", step.to_line())
+            .and_then(|()| self.file.sync_data())
+            .map_err(|err| Error::io("Error writing journal", err))?;
+        self.steps.push(step);
+        Ok(())
+    }
+
+    /// Store the **content** of a file that is about to be deleted, so that the file can be restored.
+    /// Returns the name of the backup, which goes in a [`Step::RemoveInternal`] or [`Step::RemoveExternal`].
+    pub fn backup(&self, content: &[u8]) -> Result<String, Error> {
+        let backup = format!("{}.bak", self.steps.len());
+        std::fs::write(self.app_dir.join(JOURNAL_DIR).join(&backup), content)
+            .map_err(|err| Error::io("Error writing backup", err))?;
+        Ok(backup)
+    }
+
+    /// End the operation: Commit it if **result** is [`Ok`], or undo all its steps if it's an error.
+    ///
+    /// Returns the **result**, or the error of the rollback with the original error if the steps couldn't be undone.
+    pub fn finish<'local, T>(
+        self,
+        env: &mut JNIEnv<'local>,
+        context: &JObject<'local>,
+        perm: Option<&JObject<'local>>,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        match result {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            },
+            Err(err) => {
+                let description = self.description.clone();
+                match self.rollback(env, context, perm) {
+                    Ok(()) => Err(err),
+                    Err(rollback_err) => Err(Error::Io(format!("{err}\nFailed to undo \"{description}\": {rollback_err}"))),
+                }
+            },
+        }
+    }
+
+    /// Mark the operation as done and delete the journal and its backups.
+    fn commit(mut self) -> Result<(), Error> {
+        writeln!(self.file, "commit")
+            .and_then(|()| self.file.sync_data())
+            .map_err(|err| Error::io("Error writing journal", err))?;
+        self.remove()
+    }
+
+    /// Undo the steps of the operation in reverse order, and delete the journal.
+    ///
+    /// All the steps are undone even if some of them fail, in which case the first error is returned
+    /// and the journal is kept, so that [`recover()`] tries to undo them again the next time the App starts.
+    fn rollback<'local>(
+        self,
+        env: &mut JNIEnv<'local>,
+        context: &JObject<'local>,
+        perm: Option<&JObject<'local>>,
+    ) -> Result<(), Error> {
+        println!("Rolling back \"{}\"", self.description);
+        let mut result = Ok(());
+        for step in self.steps.iter().rev() {
+            if let Err(err) = step.undo(env, context, perm, &self.app_dir) {
+                println!("Error undoing step {step:?}: {err}");
+                result = result.and(Err(err));
+            }
+        }
+        result?;
+        self.remove()
+    }
+
+    /// Delete the journal's directory (with its backups).
+    fn remove(self) -> Result<(), Error> {
+        let journal_dir = self.app_dir.join(JOURNAL_DIR);
+        drop(self.file);
+        std::fs::remove_dir_all(&journal_dir)
+            .map_err(|err| Error::io("Error deleting journal", err))
+    }
+}
+
+/// Finish the operation that is in the journal of the *app's directory*, if there is one.
+///
+/// An operation that was committed only has its journal deleted, and the steps of an operation that wasn't are undone.
+/// **perm** is passed to [`Step::undo()`].
+pub fn recover<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    perm: Option<&JObject<'local>>,
+) -> Result<(), Error> {
+    let app_dir = get_app_dir(env, context);
+    match Journal::read(&app_dir)? {
+        Some((journal, true)) => journal.remove(),
+        Some((journal, false)) => journal.rollback(env, context, perm),
+        None => Ok(()),
+    }
+}
+
+/// Open the **external** directory from the string form of its *Document Tree Uri*, as it's stored in the journal.
+fn parse_external_dir<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    dir_uri: &str,
+) -> Result<ExternalDir<'local>, Error> {
+    let uri = call!(static android.net.Uri.parse(String(dir_uri)) -> android.net.Uri);
+    open_external_dir(env, context, uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::test_server::temp_dir;
+
+    #[test]
+    fn step_lines() {
+        let steps = [
+            Step::CreateInternal("calendars/Personal.ics".into()),
+            Step::RemoveInternal { path: "calendars/Tab\tand\nline break.ics".into(), backup: "0.bak".to_string() },
+            Step::CreateExternal { dir_uri: "content://tree/primary%3ASync".to_string(), path: "calendars/Back\\slash.ics".into() },
+            Step::RemoveExternal { dir_uri: "content://tree/primary%3ASync".to_string(), path: "contacts/Friends.vcf".into(), backup: "1.bak".to_string() },
+            Step::RenameExternal { dir_uri: "content://tree/primary%3ASync".to_string(), path: "calendars/Work.ics".into(), new_name: "Work\t2.ics".to_string() },
+            Step::CreateCalendar("Ends with \\".to_string()),
+            Step::DeleteCalendar("Personal".to_string()),
+            Step::WriteContacts("Friends".to_string()),
+        ];
+        for step in steps {
+            let line = step.to_line();
+            assert!(!line.contains('\n'), "{line:?}");
+            assert_eq!(Step::from_line(&line), Some(step));
+        }
+
+        // Lines that were cut off
+        assert_eq!(Step::from_line("remove-internal\t0.bak"), None);
+        assert_eq!(Step::from_line("create-int"), None);
+    }
+
+    #[test]
+    fn read_and_record() {
+        let app_dir = temp_dir("journal");
+        std::fs::create_dir(app_dir.join(JOURNAL_DIR)).unwrap();
+        let path = app_dir.join(JOURNAL_DIR).join(JOURNAL_FILE);
+        std::fs::write(&path, "begin\timport Tab\\tname.ics\ncreate-internal\tcalendars/Tab\\tname.ics\ncreate-cal").unwrap();
+
+        let (journal, committed) = Journal::read(&app_dir).unwrap().unwrap();
+        assert!(!committed);
+        assert_eq!(journal.description, "import Tab\tname.ics");
+        assert_eq!(journal.steps, [Step::CreateInternal("calendars/Tab\tname.ics".into())]);
+
+        std::fs::write(&path, "begin\timport a.ics\ncreate-internal\tcalendars/a.ics\n").unwrap();
+        let (mut journal, _) = Journal::read(&app_dir).unwrap().unwrap();
+        journal.record(Step::CreateCalendar("a".to_string())).unwrap();
+        let backup = journal.backup(b"BEGIN:VCALENDAR").unwrap();
+        assert_eq!(std::fs::read(app_dir.join(JOURNAL_DIR).join(&backup)).unwrap(), b"BEGIN:VCALENDAR");
+        let (journal, committed) = Journal::read(&app_dir).unwrap().unwrap();
+        assert!(!committed);
+        assert_eq!(journal.steps, [Step::CreateInternal("calendars/a.ics".into()), Step::CreateCalendar("a".to_string())]);
+
+        journal.commit().unwrap();
+        assert!(!app_dir.join(JOURNAL_DIR).exists());
+        assert!(Journal::read(&app_dir).unwrap().is_none());
+    }
+}
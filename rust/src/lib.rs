@@ -1,57 +1,35 @@
 mod calendar;
+mod contacts;
+mod dav;
+mod error;
+mod file_type;
+mod journal;
+mod provider;
 mod utils;
 
 use jni::{JNIEnv, objects::JObject};
 use ez_jni::{call, jni_fn, new, println, FromObject};
-use std::{io::{self, Read as _, Write as _}, path::PathBuf};
-use utils::get_app_dir;
-use classes::fs::{file_stem, DocUri, ExternalDir, OpenOptions};
+use std::{io::{self, Read as _, Write as _}, path::{Path, PathBuf}};
+use utils::{check_permission, get_app_dir, open_external_dir, read_base_snapshot, write_base_snapshot};
+use classes::fs::{file_stem, DocUri, ExternalDir, ExternalDirEntry, OpenOptions};
+use error::{Error, OrThrow as _};
+use calendar::CalendarFormat;
+use file_type::{FileType, UnsupportedFileError};
+use journal::{Journal, Step};
 
-/// These are the names of the directories where synced data will be stored
+/// These are the names of the directories where synced data will be stored.
+/// The directory of each file is picked by its [`FileType`]. E.g.: `"<app_dir>/calendars"`.
 const DIRECTORIES: [&str; 2] = ["calendars", "contacts"];
-
-/// The name that is appended to the directories' path to get the destination directory. E.g.: `"<app_dir>/calendars"`.
-// TODO: will change so that it is automatically detected whether to use "calendar" or "contacts" depending on the file type.
-const SUFFIX_DIR: &str = "calendars";
 const ILLEGAL_FILE_CHARACTERS: [char; 3] = ['/', '*', ':'];
 
 jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
     /// Initialize the **internal** and **external** directories by creating all necessary sub-directories (e.g. calendars and contacts directories).
     ///
     /// ### Parameters
-    /// - **external_dir_fd** is the *file descriptor* for the directory in shared storage the user picked to sync files.
-    /// - **app_dir** is the internal directory where all of this app's files are stored.
+    /// - **external_dir_uri** is the *Uri* for the directory in shared storage the user picked to sync files.
     pub fn initialize_dirs<'local>(context: android.content.Context, external_dir_uri: Option<android.net.Uri>) {
-        // -- Initialize internal directory (app_dir)
-        let app_dir = get_app_dir(env, &context);
-
-        let entries = std::fs::read_dir(&app_dir)
-            .unwrap_or_else(|err| panic!("Error reading internal directory: {err}"))
-            .filter_map(Result::ok)
-            .filter_map(|entry| entry.file_name().to_str().map(str::to_string)) // Ignore entries that re not UTF-8
-            .collect::<Box<[_]>>();
-        // Find the DIRECTORIES that are missing from entries and create them
-        for &dir in DIRECTORIES
-            .iter()
-            .filter(|&&dir| !entries.iter().any(|e| e == dir))
-        {
-            std::fs::create_dir(app_dir.join(dir))
-                .unwrap_or_else(|error| panic!("Error creating directory: {error}"))
-        }
-
-        // -- Initialize external directory (shared storage)
-        if let Some(external_dir_uri) = external_dir_uri {
-            let external_dir_uri = DocUri::from_tree_uri(env, external_dir_uri).unwrap();
-            let external_dir = ExternalDir::new(context, external_dir_uri, env)
-                .unwrap_or_else(|| panic!("Couldn't open external directory"));
-    
-            // Don't have to find missing directories; will not return error if directories already exist.
-            for &dir in &DIRECTORIES {
-                external_dir
-                    .create_dir(env, dir)
-                    .unwrap_or_else(|error| panic!("Error creating directory: {error}"));
-            }
-        }
+        initialize_dirs(env, &context, external_dir_uri.as_ref())
+            .or_throw(env)
     }
 
     /// Copy files between the **internal** and **external** directories and resolve conflicts for files that exist in both.
@@ -61,199 +39,17 @@ jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
     /// 
     /// After they are merged, the changes are written to the *Calendar Content Provider*.
     pub fn merge_dirs<'local>(activity: me.marti.calprovexample.MainActivity, external_dir_uri: android.net.Uri) {
-        let context = call!(activity.getBaseContext() -> android.content.Context);
-        let app_dir = get_app_dir(env, &context);
-        let external_dir_uri = DocUri::from_tree_uri(env, external_dir_uri).unwrap();
-        println!("ExternalDir Uri: \"{}\"", external_dir_uri.to_string(env));
-        let internal_dir = app_dir.join("calendars");
-        let external_dir = {
-            ExternalDir::new(env.new_local_ref(&context).unwrap(), external_dir_uri.join(env, "calendars"), env)
-                .unwrap_or_else(|| panic!("Couldn't open external directory"))
-        };
-        let calendars_list = call!(activity.getUserCalendars() -> Option<me.marti.calprovexample.ui.MutableCalendarsList>);
-    
-        let internal_files = Result::<Vec<_>, _>::from_iter(
-            internal_dir
-                .read_dir()
-                .unwrap_or_else(|err| panic!("Failed reading directory: {err}"))
-        )
-            .unwrap_or_else(|err| panic!("Failed getting directory entry: {err}"))
-            .into_iter()
-            .filter(|entry| entry.metadata().ok().is_some_and(|meta| meta.is_file()))
-            .collect::<Box<[_]>>();
-        let external_files = IntoIterator::into_iter(external_dir.entries(env))
-            .filter(|entry| !entry.is_dir())
-            .collect::<Box<[_]>>();
-    
-        // Find the files that are in one directory but not in the other, and copy them to the other.
-        // Filter by files that are NOT in the internal directory
-        let copy_to_internal = external_files.iter().filter(|external_entry| {
-            !internal_files
-                .iter()
-                .any(|internal_entry| internal_entry.file_name() == external_entry.file_name())
-        });
-        // Filter by files that are NOT in the external directory
-        let copy_to_external = internal_files.iter().filter(|internal_entry| {
-            !external_files
-                .iter()
-                .any(|external_entry| internal_entry.file_name() == external_entry.file_name())
-        });
-        // Filter by files that IN BOTH directories
-        let files_to_merge = external_files.iter().filter(|external_entry| {
-            internal_files
-                .iter()
-                .any(|internal_entry| internal_entry.file_name() == external_entry.file_name())
-        });
-    
-        // -- Copy the files
-    
-        // Copy external files to internal directory
-        for entry in copy_to_internal.clone() {
-            let mut external_file = entry
-                .open_file(env, &context, OpenOptions::ReadOnly)
-                .unwrap_or_else(|err| panic!("Failed to open file in external directory: {err}"));
-    
-            // Open the file to copy to in the internal directory
-            let mut internal_file = match std::fs::File::create_new(internal_dir.join(entry.file_name())) {
-                Ok(file) => file,
-                Err(error) =>
-                    if error.kind() == io::ErrorKind::AlreadyExists {
-                        panic!("Unreachable: Already checked that file does not exist in internal directory")
-                    } else {
-                        panic!("Error opening file in internal dir: {error}")
-                    },
-            };
-    
-            // Copy file's contents to the destination
-            std::io::copy(&mut external_file, &mut internal_file)
-                .unwrap_or_else(|error| panic!("Error copying to file in internal directory: {error}"));
-        }
-    
-        // Copy internal files to external directory
-        for entry in copy_to_external {
-            let mut internal_file = std::fs::File::open(entry.path())
-                .unwrap_or_else(|err| panic!("Failed to open file in internal directory: {err}"));
-    
-            // Open the file to copy to in the external directory
-            let mut external_file = match external_dir.create_file(
-                env,
-                entry.file_name().to_str().expect("File name must be UTF-8"),
-            ) {
-                Ok(file) => file
-                    .open_file(env, &context, OpenOptions::write())
-                    .unwrap_or_else(|err| panic!("Failed to open newly created file in external directory: {err}")),
-                Err(error) =>
-                    if error.kind() == io::ErrorKind::AlreadyExists {
-                        panic!("Unreachable: Already checked that file does not exist in external directory")
-                    } else {
-                        panic!("Error opening file in external dir: {error}")
-                    },
-            };
-    
-            // Copy file's contents to the destination
-            std::io::copy(&mut internal_file, &mut external_file)
-                .unwrap_or_else(|error| panic!("Error copying to file in external directory: {error}"));
-        }
-    
-        
-        // Check if common files (files in both directories) are different, and ask user whether to accept incoming or keep internal
-        for external_file in files_to_merge {
-            let file_name = external_file.file_name();
-            println!("Merging file \"{}\".", file_name);
-            let internal_file = internal_dir.join(&file_name);
-
-            // Read file contents
-            let mut internal_file_content = Vec::new();
-            let mut external_file_content = Vec::new();
-            std::fs::File::open(&internal_file)
-                .unwrap_or_else(|err| panic!("Failed to open file in internal directory: {err}"))
-                .read_to_end(&mut internal_file_content)
-                .unwrap_or_else(|err| panic!("Failed read contents of file in internal directory: {err}"));
-            external_file.open_file(env, &context, OpenOptions::ReadOnly)
-                .unwrap_or_else(|err| panic!("Failed to open file in external directory: {err}"))
-                .read_to_end(&mut external_file_content)
-                .unwrap_or_else(|err| panic!("Failed read contents of file in external directory: {err}"));
-
-            if internal_file_content != external_file_content {
-                use FileConflictResponse::*;
-                #[derive(Debug, FromObject)]
-                enum FileConflictResponse {
-                    #[class(me.marti.calprovexample.jni.FileConflictResponse$Canceled)]
-                    Canceled,
-                    #[class(me.marti.calprovexample.jni.FileConflictResponse$OverWrite)]
-                    OverWrite,
-                    #[class(me.marti.calprovexample.jni.FileConflictResponse$Rename)]
-                    Rename(#[field(name = newName)] String)
-                }
-                let user_choice = FileConflictResponse::from_object(
-                    &call!(static me.marti.calprovexample.jni.DavSyncRsKt.showFileConflictDialog(String(external_file.file_stem())) -> me.marti.calprovexample.jni.FileConflictResponse),
-                env)
-                    .unwrap_or_else(|err| panic!("{err}"));
-                println!("User selected {user_choice:?}");
-
-                // Show dialog to user and wait for a response
-                match user_choice {
-                    // User chose to keep internal file; Rename external file and create new calendar with it
-                    Rename(new_name) => {
-                        todo!();
-                        external_file.uri(); //.rename(&context, &new_name, env);
-                            // .unwrap_or_else(|err| panic!("Failed to rename External file \"{}\": {err}", external_file.file_name()));
-                    },
-                    // User chose to overwrite internal file with external file.
-                    OverWrite => {
-                        if let Some(calendars_list) = &calendars_list {
-                            call!(calendars_list.remove(String(external_file.file_stem())) -> me.marti.calprovexample.calendar.InternalUserCalendar);
-                            call!(calendars_list.addFile(android.net.Uri(external_dir_uri.as_ref())) -> void);
-                        } else {
-                            println!("Overwrite Internal file (no userCalendars)");
-                            // The path that the Internal file will go to when it is deleted
-                            let deleted_path = app_dir.join("deleted").join(SUFFIX_DIR).join(&file_name);
-                            // Move Internal file to "Recycle Bin"
-                            std::fs::copy(&internal_file, &deleted_path)
-                                .unwrap_or_else(|err| panic!("Failed to copy Internal file to deleted directory: {err}"));
-                            // Write External file to Internal file
-                            std::fs::OpenOptions::new()
-                                .write(true)
-                                .truncate(true)
-                                .open(&internal_file)
-                                .unwrap_or_else(|err| panic!("Failed to open Internal file \"{}\" with write+trunc: {err}", internal_file.display()))
-                                .write_all(&external_file_content)
-                                .unwrap_or_else(|err| panic!("Failed to write contents of External file to Internal file \"{}\": {err}", internal_file.display()));
-                            // Create a SnackBar to undo this action
-                            call!(activity.showOverwriteSnackBar(String(deleted_path.to_str().unwrap()), String(internal_file.to_str().unwrap())) -> void);
-                        }
-                    },
-                    Canceled => { },
-                }
-            }
-        }
-    
-        // Add calendars from external directory to Content Provider
-        // Calendars in internal dir are should already be in the Content Provider, so no need to do this for copyToExternal too.
-        let perm_manager = env.get_field(&activity,
-                "calendarPermission", "Lme/marti/calprovexample/ui/CalendarPermission;",
-            ).unwrap().l().unwrap();
-        if let Some(perm) = call!(perm_manager.usePermission() -> Option<me.marti.calprovexample.ui.CalendarPermissionScope>) {
-            for entry in copy_to_internal {
-                let name = entry.file_stem();
-                let davsyncrs = env.get_static_field("me/marti/calprovexample/jni/DavSyncRs", "INSTANCE", "Lme/marti/calprovexample/jni/DavSyncRs;")
-                    .unwrap().l().unwrap();
-                call!(davsyncrs.write_file_data_to_calendar(
-                    me.marti.calprovexample.ui.CalendarPermission(perm),
-                    String(name),
-                    me.marti.calprovexample.Color(null)
-                ) -> void);
-            }
-    
-            if let Some(calendars_list) = calendars_list {
-                call!(calendars_list.syncWithProvider() -> void)
-            }
-        };
+        merge_dirs(env, &activity, external_dir_uri)
+            .or_throw(env)
     }
 
-    /// Copy an *`.ics`* file's content into the internal *app's directory*.
+    /// Copy an *`.ics`* or *`.vcf`* file's content into the internal *app's directory*.
+    /// The [`FileType`] of the file decides whether it goes to the *`calendars`* or *`contacts`* directory.
     ///
-    /// A *successful* call to this function should be subsequently followed by a call to [`import_file_external()`]
+    /// A *successful* call to this function must be subsequently followed by a call to [`import_file_external()`]
+    /// with the *fileName* of the result, which finishes the import.
+    /// Otherwise, the import is undone the next time the App starts (see [`journal::recover()`](crate::journal::recover)),
+    /// and every other file operation fails with a *Conflict* until then.
     ///
     /// ### Parameters
     /// **file_uri** is the *Document Uri* of the file to be imported.
@@ -261,39 +57,87 @@ jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
     /// **context**: `android.content.Context`.
     ///
     /// ### Return
-    /// Returns [`ImportResult::FileExists`] if the file couln't be imported because a file with that name already exists in the internal directory.
+    /// Returns `ImportFileResult.FileExists` if the file couln't be imported because a file with that name already exists in the internal directory,
+    /// and `ImportFileResult.Unsupported` if the file is neither a calendar nor a contacts file.
     pub fn import_file_internal<'local>(context: android.content.Context, file_uri: android.net.Uri, file_name: Option<String>) -> me.marti.calprovexample.jni.ImportFileResult {
-        let file_uri = DocUri::from_doc_uri(env, file_uri).unwrap();
+        let file_uri = match DocUri::from_doc_uri(env, file_uri) {
+            Ok(file_uri) => file_uri,
+            Err(err) => return Err(Error::InvalidUri(err)).or_throw(env),
+        };
         let file_name = file_name.unwrap_or_else(|| file_uri.file_name(env));
-        let cal_name = file_stem(&file_name);
 
-        if import_file_internal(env, file_uri, &file_name, context)
-            .unwrap_or_else(|err| panic!("{err}"))
-        {
-            println!("file '{file_name}' imported successfully");
-            new!(me.marti.calprovexample.jni.ImportFileResult$Success(String(cal_name)))
-        } else {
-            println!("'{file_name}' is already imported. Overwrite?");
-            new!(me.marti.calprovexample.jni.ImportFileResult$FileExists(String(cal_name)))
+        match import_file_internal(env, file_uri, &file_name, context) {
+            Ok((file_name, true)) => {
+                println!("file '{file_name}' imported successfully");
+                new!(me.marti.calprovexample.jni.ImportFileResult$Success(String(file_stem(&file_name)), String(file_name.as_str())))
+            },
+            Ok((file_name, false)) => {
+                println!("'{file_name}' is already imported. Overwrite?");
+                new!(me.marti.calprovexample.jni.ImportFileResult$FileExists(String(file_stem(&file_name)), String(file_name.as_str())))
+            },
+            Err(ImportError::Unsupported(err)) => {
+                println!("Can't import file: {err}");
+                new!(me.marti.calprovexample.jni.ImportFileResult$Unsupported(String(file_name.as_str())))
+            },
+            Err(ImportError::Other(err)) => Err(err).or_throw(env),
+        }
+    }
+
+    /// Import a spreadsheet in CSV as a new Calendar file in the *internal directory*, converting each row to an event.
+    ///
+    /// **columns** are the names of the columns of the CSV header that have the fields of the events.
+    /// The rows that can't be converted are skipped, and their errors are returned along with the result, which is the same as for [`import_file_internal()`].
+    /// The import is finished by [`import_file_external()`].
+    pub fn import_csv_internal<'local>(
+        context: android.content.Context,
+        file_uri: android.net.Uri,
+        file_name: Option<String>,
+        columns: me.marti.calprovexample.jni.CsvColumns,
+    ) -> me.marti.calprovexample.jni.CsvImportResult {
+        let file_uri = match DocUri::from_doc_uri(env, file_uri) {
+            Ok(file_uri) => file_uri,
+            Err(err) => return Err(Error::InvalidUri(err)).or_throw(env),
+        };
+        let file_name = file_name.unwrap_or_else(|| file_uri.file_name(env));
+        let columns = match calendar::csv::Columns::from_object(&columns, env) {
+            Ok(columns) => columns,
+            Err(err) => return Err(Error::Parse(format!("Error getting CSV columns: {err}"))).or_throw(env),
+        };
+
+        let (result, row_errors) = match import_csv_internal(env, file_uri, &file_name, &columns, &context) {
+            Ok(((file_name, true), row_errors)) => {
+                println!("file '{file_name}' imported successfully");
+                (new!(me.marti.calprovexample.jni.ImportFileResult$Success(String(file_stem(&file_name)), String(file_name.as_str()))), row_errors)
+            },
+            Ok(((file_name, false), row_errors)) => {
+                println!("'{file_name}' is already imported. Overwrite?");
+                (new!(me.marti.calprovexample.jni.ImportFileResult$FileExists(String(file_stem(&file_name)), String(file_name.as_str()))), row_errors)
+            },
+            Err(err) => return Err(err).or_throw(env),
+        };
+        let row_errors = row_errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        for row_error in &row_errors {
+            println!("Skipping row of '{file_name}': {row_error}");
         }
+        let row_errors = row_errors.iter().map(String::as_str).collect::<Vec<_>>();
+        new!(me.marti.calprovexample.jni.CsvImportResult(me.marti.calprovexample.jni.ImportFileResult(result), [String](row_errors.as_slice())))
     }
 
-    /// Copy a file named **file_name** from the *internal directory* to the **external directory** in Shared Storage.
+    /// Finish importing the file named **file_name** that was copied to the *internal directory* by [`import_file_internal()`].
+    ///
+    /// Copies the file to the **external directory** in Shared Storage (if **external_dir_uri** is not `NULL`),
+    /// and writes its data to the *Content Provider*:
+    /// Calendar files only if **perm** is not `NULL`, and Contacts files only if the App has the `WRITE_CONTACTS` permission.
+    ///
+    /// If any of these steps fails, all the steps of the import are undone (including the copy in the *internal directory*).
     pub fn import_file_external<'local>(
         context: android.content.Context,
+        perm: Option<me.marti.calprovexample.ui.CalendarPermissionScope>,
         file_name: String,
-        external_dir_uri: android.net.Uri,
+        external_dir_uri: Option<android.net.Uri>,
     ) {
-        let external_dir_uri = DocUri::from_tree_uri(env, external_dir_uri).unwrap();
-        if let Err(err) = import_file_external(env, &file_name, external_dir_uri, &context) {
-            // Failed to complete import because couldn't copy to external file.
-            // Delete the imported file in the internal directory.
-            let app_dir = get_app_dir(env, &context);
-            if let Err(err) = std::fs::remove_file(app_dir.join(SUFFIX_DIR).join(file_name)) {
-                panic!("Failed to delete internal imported file: {err}");
-            };
-            panic!("Failed to write to external file; deleted internal file.\nError: {err}");
-        }
+        import_file_external(env, &context, perm.as_ref(), &file_name, external_dir_uri)
+            .or_throw(env)
     }
 
     // pub fn new_calendar_from_file<'local>(context: JObject, name: JString) -> jobject {
@@ -317,67 +161,559 @@ jni_fn! { me.marti.calprovexample.jni.DavSyncRs =>
     // }
 }
 
+/// Create the [`DIRECTORIES`] in the *app's directory*, and in the **external** directory if **external_dir_uri** is [`Some`].
+pub fn initialize_dirs<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    external_dir_uri: Option<&JObject<'local>>,
+) -> Result<(), Error> {
+    // -- Initialize internal directory (app_dir)
+    let app_dir = get_app_dir(env, context);
+
+    let entries = std::fs::read_dir(&app_dir)
+        .map_err(|err| Error::io("Error reading internal directory", err))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string)) // Ignore entries that re not UTF-8
+        .collect::<Box<[_]>>();
+    // Find the DIRECTORIES that are missing from entries and create them
+    for &dir in DIRECTORIES
+        .iter()
+        .filter(|&&dir| !entries.iter().any(|e| e == dir))
+    {
+        std::fs::create_dir(app_dir.join(dir))
+            .map_err(|error| Error::io("Error creating directory", error))?;
+    }
+
+    // -- Initialize external directory (shared storage)
+    if let Some(external_dir_uri) = external_dir_uri {
+        let external_dir_uri = env.new_local_ref(external_dir_uri)?;
+        let external_dir = open_external_dir(env, context, external_dir_uri)?;
+
+        // Don't have to find missing directories; will not return error if directories already exist.
+        for &dir in &DIRECTORIES {
+            external_dir
+                .create_dir(env, dir)
+                .map_err(|error| Error::io("Error creating directory", error))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy files between the **internal** and **external** directories and merge the files that exist in both.
+///
+/// The Calendars of the files that were copied or changed in the **internal** directory are written to the *Content Provider*
+/// if the **activity** has the Calendar permission.
+fn merge_dirs<'local>(
+    env: &mut JNIEnv<'local>,
+    activity: &JObject<'local>,
+    external_dir_uri: JObject<'local>,
+) -> Result<(), Error> {
+    let context = call!(activity.getBaseContext() -> android.content.Context);
+    let app_dir = get_app_dir(env, &context);
+    let external_dir_uri = DocUri::from_tree_uri(env, external_dir_uri).map_err(Error::InvalidUri)?;
+    println!("ExternalDir Uri: \"{}\"", external_dir_uri.to_string(env));
+    let internal_dir = app_dir.join(FileType::Calendar.dir());
+    let external_dir = {
+        ExternalDir::new(env.new_local_ref(&context)?, external_dir_uri.join(env, FileType::Calendar.dir()), env)
+            .ok_or_else(|| Error::InvalidUri("Couldn't open external directory".to_string()))?
+    };
+    let calendars_list = call!(activity.getUserCalendars() -> Option<me.marti.calprovexample.ui.MutableCalendarsList>);
+
+    let internal_files = Result::<Vec<_>, _>::from_iter(
+        internal_dir
+            .read_dir()
+            .map_err(|err| Error::io("Failed reading directory", err))?
+    )
+        .map_err(|err| Error::io("Failed getting directory entry", err))?
+        .into_iter()
+        .filter(|entry| entry.metadata().ok().is_some_and(|meta| meta.is_file()))
+        .collect::<Box<[_]>>();
+    let external_files = IntoIterator::into_iter(external_dir.entries(env))
+        .filter(|entry| !entry.is_dir())
+        .collect::<Box<[_]>>();
+
+    // Find the files that are in one directory but not in the other, and copy them to the other.
+    // Filter by files that are NOT in the internal directory
+    let copy_to_internal = external_files.iter().filter(|external_entry| {
+        !internal_files
+            .iter()
+            .any(|internal_entry| internal_entry.file_name() == external_entry.file_name())
+    });
+    // Filter by files that are NOT in the external directory
+    let copy_to_external = internal_files.iter().filter(|internal_entry| {
+        !external_files
+            .iter()
+            .any(|external_entry| internal_entry.file_name() == external_entry.file_name())
+    });
+    // Filter by files that IN BOTH directories
+    let files_to_merge = external_files.iter().filter(|external_entry| {
+        internal_files
+            .iter()
+            .any(|internal_entry| internal_entry.file_name() == external_entry.file_name())
+    });
+
+    // -- Copy the files
+
+    // Copy external files to internal directory
+    for entry in copy_to_internal.clone() {
+        let mut external_file = entry
+            .open_file(env, &context, OpenOptions::ReadOnly)
+            .map_err(|err| Error::io("Failed to open file in external directory", err))?;
+
+        // Open the file to copy to in the internal directory
+        // The file could have been created after the directories were listed, which is a Conflict
+        let mut internal_file = std::fs::File::create_new(internal_dir.join(entry.file_name()))
+            .map_err(|error| Error::io("Error opening file in internal dir", error))?;
+
+        // Copy file's contents to the destination
+        std::io::copy(&mut external_file, &mut internal_file)
+            .map_err(|error| Error::io("Error copying to file in internal directory", error))?;
+        std::fs::read(internal_dir.join(entry.file_name()))
+            .and_then(|content| write_base_snapshot(&app_dir, FileType::Calendar.dir(), entry.file_name(), &content))
+            .map_err(|error| Error::io(format!("Error writing base snapshot of \"{}\"", entry.file_name()), error))?;
+    }
+
+    // Copy internal files to external directory
+    for entry in copy_to_external {
+        let mut internal_file = std::fs::File::open(entry.path())
+            .map_err(|err| Error::io("Failed to open file in internal directory", err))?;
+
+        // Open the file to copy to in the external directory
+        // The file could have been created after the directories were listed, which is a Conflict
+        let file_name = entry.file_name().into_string()
+            .map_err(|file_name| Error::Io(format!("File name {file_name:?} is not UTF-8")))?;
+        let mut external_file = external_dir.create_file(env, &file_name)
+            .map_err(|error| Error::io("Error opening file in external dir", error))?
+            .open_file(env, &context, OpenOptions::write())
+            .map_err(|err| Error::io("Failed to open newly created file in external directory", err))?;
+
+        // Copy file's contents to the destination
+        std::io::copy(&mut internal_file, &mut external_file)
+            .map_err(|error| Error::io("Error copying to file in external directory", error))?;
+        std::fs::read(entry.path())
+            .and_then(|content| write_base_snapshot(&app_dir, FileType::Calendar.dir(), &entry.file_name().to_string_lossy(), &content))
+            .map_err(|error| Error::io(format!("Error writing base snapshot of {:?}", entry.file_name()), error))?;
+    }
+
+    // Calendars whose internal file changed when it was merged, so they have to be written to the Content Provider.
+    let mut merged_calendars = Vec::new();
+    let perm_manager = call!(activity.getCalendarPermission() -> me.marti.calprovexample.ui.CalendarPermission);
+    let perm = call!(perm_manager.usePermission() -> Option<me.marti.calprovexample.ui.CalendarPermissionScope>);
+
+    // Check if common files (files in both directories) are different, and merge them.
+    // Ask user whether to accept incoming or keep internal for the events that were changed in both files.
+    for external_file in files_to_merge {
+        let file_name = external_file.file_name();
+        println!("Merging file \"{}\".", file_name);
+        let internal_file = internal_dir.join(file_name);
+
+        // Read file contents
+        let mut internal_file_content = Vec::new();
+        let mut external_file_content = Vec::new();
+        std::fs::File::open(&internal_file)
+            .and_then(|mut file| file.read_to_end(&mut internal_file_content))
+            .map_err(|err| Error::io("Failed read contents of file in internal directory", err))?;
+        external_file.open_file(env, &context, OpenOptions::ReadOnly)
+            .and_then(|mut file| file.read_to_end(&mut external_file_content))
+            .map_err(|err| Error::io("Failed read contents of file in external directory", err))?;
+
+        if internal_file_content == external_file_content {
+            write_base_snapshot(&app_dir, FileType::Calendar.dir(), file_name, &internal_file_content)
+                .map_err(|err| Error::io(format!("Error writing base snapshot of \"{file_name}\""), err))?;
+            continue;
+        }
+
+        // Merge the events of both files with the version they had the last time they were synced.
+        let base = read_base_snapshot(&app_dir, FileType::Calendar.dir(), file_name);
+        // The merge is canceled if the dialog fails, and then the error is returned
+        let mut dialog_error = None;
+        let merged = match (std::str::from_utf8(&internal_file_content), std::str::from_utf8(&external_file_content)) {
+            (Ok(internal_content), Ok(external_content)) => calendar::merge::merge_files(
+                base.as_deref(),
+                internal_content,
+                external_content,
+                |conflict| show_event_conflict_dialog(env, external_file.file_stem(), &conflict.summary())
+                    .unwrap_or_else(|err| {
+                        dialog_error = Some(err);
+                        None
+                    }),
+            ),
+            _ => Err("File is not UTF-8".to_string()),
+        };
+        if let Some(err) = dialog_error {
+            return Err(err);
+        }
+        match merged {
+            Ok(Some(merged)) => {
+                if merged.as_bytes() != internal_file_content {
+                    std::fs::write(&internal_file, &merged)
+                        .map_err(|err| Error::io(format!("Failed to write merged file \"{}\"", internal_file.display()), err))?;
+                    merged_calendars.push(external_file.file_stem().to_string());
+                }
+                if merged.as_bytes() != external_file_content {
+                    external_dir.write_file_at(env, file_name, merged.as_bytes())
+                        .map_err(|err| Error::io(format!("Failed to write merged file \"{file_name}\" in external directory"), err))?;
+                }
+                write_base_snapshot(&app_dir, FileType::Calendar.dir(), file_name, merged.as_bytes())
+                    .map_err(|err| Error::io(format!("Error writing base snapshot of \"{file_name}\""), err))?;
+                continue;
+            },
+            Ok(None) => {
+                println!("Merge of \"{file_name}\" was canceled");
+                continue;
+            },
+            // Fall back to choosing one of the files
+            Err(err) => {
+                println!("Could not merge the events of \"{file_name}\": {err}");
+            },
+        }
+
+        use FileConflictResponse::*;
+        #[derive(Debug, FromObject)]
+        enum FileConflictResponse {
+            #[class(me.marti.calprovexample.jni.FileConflictResponse$Canceled)]
+            Canceled,
+            #[class(me.marti.calprovexample.jni.FileConflictResponse$OverWrite)]
+            OverWrite,
+            #[class(me.marti.calprovexample.jni.FileConflictResponse$Rename)]
+            Rename(#[field(name = newName)] String)
+        }
+        let user_choice = FileConflictResponse::from_object(
+            &call!(static me.marti.calprovexample.jni.DavSyncRsKt.showFileConflictDialog(String(external_file.file_stem())) -> me.marti.calprovexample.jni.FileConflictResponse),
+        env)
+            .map_err(|err| Error::Parse(format!("Invalid response from file conflict dialog: {err}")))?;
+        println!("User selected {user_choice:?}");
+
+        // Show dialog to user and wait for a response
+        match user_choice {
+            // User chose to keep internal file; Rename external file and create new calendar with it
+            Rename(new_name) => {
+                let dir_uri = external_dir_uri.to_string(env);
+                let new_file_name = rename_external_file(
+                    env, &context, perm.as_ref(), &dir_uri, &external_dir, &internal_dir, external_file, &new_name, &internal_file_content
+                )?;
+                write_base_snapshot(&app_dir, FileType::Calendar.dir(), file_name, &internal_file_content)
+                    .and_then(|()| write_base_snapshot(&app_dir, FileType::Calendar.dir(), &new_file_name, &external_file_content))
+                    .map_err(|err| Error::io(format!("Error writing base snapshot of \"{file_name}\""), err))?;
+            },
+            // User chose to overwrite internal file with external file.
+            OverWrite => {
+                if let Some(calendars_list) = &calendars_list {
+                    call!(calendars_list.remove(String(external_file.file_stem())) -> me.marti.calprovexample.calendar.InternalUserCalendar);
+                    call!(calendars_list.addFile(android.net.Uri(external_dir_uri.as_ref())) -> void);
+                } else {
+                    println!("Overwrite Internal file (no userCalendars)");
+                    // The path that the Internal file will go to when it is deleted
+                    let deleted_path = app_dir.join("deleted").join(FileType::Calendar.dir()).join(file_name);
+                    // Move Internal file to "Recycle Bin"
+                    std::fs::copy(&internal_file, &deleted_path)
+                        .map_err(|err| Error::io("Failed to copy Internal file to deleted directory", err))?;
+                    // Write External file to Internal file
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .truncate(true)
+                        .open(&internal_file)
+                        .and_then(|mut file| file.write_all(&external_file_content))
+                        .map_err(|err| Error::io(format!("Failed to write contents of External file to Internal file \"{}\"", internal_file.display()), err))?;
+                    // Create a SnackBar to undo this action
+                    let (Some(deleted_path), Some(internal_path)) = (deleted_path.to_str(), internal_file.to_str()) else {
+                        return Err(Error::Io(format!("Path of Internal file \"{}\" is not UTF-8", internal_file.display())));
+                    };
+                    call!(activity.showOverwriteSnackBar(String(deleted_path), String(internal_path)) -> void);
+                }
+            },
+            Canceled => { },
+        }
+    }
+
+    // Add calendars from external directory to Content Provider
+    // Calendars in internal dir are should already be in the Content Provider, so no need to do this for copyToExternal too.
+    if let Some(perm) = perm {
+        let names = copy_to_internal
+            .map(|entry| entry.file_stem().to_string())
+            .chain(merged_calendars);
+        for name in names {
+            calendar::write_file_data_to_calendar(env, &perm, &name, &JObject::null())?;
+        }
+
+        if let Some(calendars_list) = calendars_list {
+            call!(calendars_list.syncWithProvider() -> void)
+        }
+    };
+
+    Ok(())
+}
+
+/// Error returned by [`import_file_internal()`].
+enum ImportError {
+    /// The file was not imported because it is not a calendar or contacts file.
+    Unsupported(UnsupportedFileError),
+    Other(Error),
+}
+impl From<UnsupportedFileError> for ImportError {
+    fn from(err: UnsupportedFileError) -> Self {
+        Self::Unsupported(err)
+    }
+}
+impl From<Error> for ImportError {
+    fn from(err: Error) -> Self {
+        Self::Other(err)
+    }
+}
+
+/// Copy the file at **file_uri** to the directory of its [`FileType`] in the *internal directory*.
+///
+/// The file is stored with **file_name**, but with the extension of its type (e.g. a calendar file `"name.txt"` is stored as `"name.ics"`).
+/// Calendar files in *jCal* (`.json`) or *xCal* (`.xml`) are converted to *iCalendar*.
+/// Returns the name the file was stored with, and `false` if it was not copied because a file with that name already exists.
 fn import_file_internal<'local>(
     env: &mut JNIEnv<'local>,
     file_uri: DocUri<'local>,
     file_name: &str,
     context: JObject<'local>,
-) -> Result<bool, String> {
-    let internal_dir = get_app_dir(env, &context).join(SUFFIX_DIR);
-    let file_path = internal_dir.join(file_name);
+) -> Result<(String, bool), ImportError> {
+    // Read the file that the user picked to import, which is needed to know its type
+    let mut content = Vec::new();
+    file_uri
+        .open_file(env, &context, OpenOptions::ReadOnly)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .map_err(|err| Error::io("Failed to read file to import", err))?;
+    // Calendars in jCal and xCal are converted to iCalendar, which is the format calendar files are stored in
+    let content = match CalendarFormat::sniff(&content) {
+        Some(format @ (CalendarFormat::JCal | CalendarFormat::XCal)) => format.to_icalendar(&content)?.into_bytes(),
+        _ => content,
+    };
+    let mime_type = file_uri.mime_type(env, &context);
+    let file_type = FileType::detect(file_name, mime_type.as_deref(), &content)?;
+
+    Ok(copy_to_internal_dir(env, &context, file_name, file_type, &content)?)
+}
+
+/// Import the spreadsheet in CSV at **file_uri** as a Calendar file in the *internal directory* (see [`calendar::csv::import()`]).
+///
+/// The file is stored with **file_name**, but with the `.ics` extension.
+/// Returns the same as [`import_file_internal()`], and the errors of the rows that were skipped.
+fn import_csv_internal<'local>(
+    env: &mut JNIEnv<'local>,
+    file_uri: DocUri<'local>,
+    file_name: &str,
+    columns: &calendar::csv::Columns,
+    context: &JObject<'local>,
+) -> Result<((String, bool), Vec<calendar::csv::RowError>), Error> {
+    let mut content = Vec::new();
+    file_uri
+        .open_file(env, context, OpenOptions::ReadOnly)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .map_err(|err| Error::io("Failed to read file to import", err))?;
+    let (content, row_errors) = calendar::csv_to_calendar(env, &content, columns, file_stem(file_name))?;
+
+    Ok((copy_to_internal_dir(env, context, file_name, FileType::Calendar, content.as_bytes())?, row_errors))
+}
+
+/// Copy the **content** of a file that is being imported to the directory of its **file_type** in the *internal directory*,
+/// and begin the [`Journal`] of the import.
+///
+/// Returns the name the file was stored with (**file_name** with the extension of **file_type**),
+/// and `false` if it was not copied because a file with that name already exists.
+fn copy_to_internal_dir<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    file_name: &str,
+    file_type: FileType,
+    content: &[u8],
+) -> Result<(String, bool), Error> {
+    let file_name = format!("{}.{}", file_stem(file_name), file_type.extension());
+    let internal_dir = get_app_dir(env, context).join(file_type.dir());
 
     // Ensure the destination directory is created (internal)
     std::fs::create_dir_all(&internal_dir).map_err(|error| {
-        format!("Error creating directories leading up to {internal_dir:?}: {error}")
+        Error::io(format!("Error creating directories leading up to {internal_dir:?}"), error)
     })?;
 
-    // The file that the user picked ot import
-    let mut file = file_uri
-        .open_file(env, &context, OpenOptions::ReadOnly)
-        .map_err(|err| format!("Failed to open file to import: {err}"))?;
-
+    let mut journal = Journal::begin(env, context, &format!("import {file_name}"))?;
     // Open the file to copy to in the internal directory
-    let mut internal_file = match std::fs::File::create_new(file_path) {
+    let mut internal_file = match std::fs::File::create_new(internal_dir.join(&file_name)) {
         Ok(file) => file,
         Err(error) => {
+            // Nothing was imported
+            journal.finish(env, context, None, Ok(()))?;
             return if error.kind() == io::ErrorKind::AlreadyExists {
-                Ok(false)
+                Ok((file_name, false))
             } else {
-                Err(format!("Error opening file in internal dir: {error}"))
+                Err(Error::io("Error opening file in internal dir", error))
             }
         }
     };
+    journal.record(Step::CreateInternal(PathBuf::from(file_type.dir()).join(&file_name)))?;
 
     // Copy file's contents to the destination
-    std::io::copy(&mut file, &mut internal_file)
-        .map_err(|error| format!("Error copying to file in App dir: {error}"))?;
+    let result = internal_file.write_all(content)
+        .map_err(|error| Error::io("Error copying to file in App dir", error));
+    if result.is_err() {
+        journal.finish(env, context, None, result)?;
+    }
 
-    Ok(true)
+    // The journal is left open until the import is finished by import_file_external()
+    Ok((file_name, true))
 }
 
-/// Write the contents of the file already imported in the *internal directory* to the new file created in *sync directory* (external).
+/// Write the contents of the file already imported in the *internal directory* to the new file created in *sync directory* (external),
+/// and write its data to the *Content Provider*.
+///
+/// The steps are recorded in the journal of the import that was started by [`import_file_internal()`],
+/// so that they are undone along with the copy in the *internal directory* if one fails.
 fn import_file_external<'local>(
     env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    perm: Option<&JObject<'local>>,
     file_name: &str,
-    external_dir_uri: DocUri<'local>,
+    external_dir_uri: Option<JObject<'local>>,
+) -> Result<(), Error> {
+    let mut journal = Journal::resume(env, context, &format!("import {file_name}"))?;
+    let file_type = FileType::of_imported(file_name);
+    let path = PathBuf::from(file_type.dir()).join(file_name);
+    let internal_path = get_app_dir(env, context).join(&path);
+    let name = file_stem(file_name);
+
+    let result = (|| -> Result<(), Error> {
+        if let Some(external_dir_uri) = external_dir_uri {
+            let dir_uri = call!(external_dir_uri.toString() -> String);
+            // Open the file to copy FROM in the internal directory
+            let mut internal_file = std::fs::File::open(&internal_path)
+                .map_err(|err| Error::io("Error opening file in internal dir", err))?;
+            // Open the file to copy TO in the sync directory (external)
+            let external_file = open_external_dir(env, context, external_dir_uri)?
+                .create_file_at(env, &path)
+                .map_err(|err| Error::io("Error creating external file", err))?;
+            journal.record(Step::CreateExternal { dir_uri, path: path.clone() })?;
+            let mut external_file = external_file.open_file(env, context, OpenOptions::write())
+                .map_err(|err| Error::io("Error opening newly created external file", err))?;
+
+            // Copy file's contents to the destination
+            std::io::copy(&mut internal_file, &mut external_file)
+                .map_err(|err| Error::io("Error copying to file in App dir", err))?;
+        } else {
+            println!("syncDir is NULL; Can't add external file for \"{file_name}\"; Will add it later");
+        }
+
+        match file_type {
+            FileType::Calendar => match perm {
+                Some(perm) => {
+                    journal.record(Step::CreateCalendar(name.to_string()))?;
+                    calendar::write_file_data_to_calendar(env, perm, name, &JObject::null())?;
+                },
+                None => { println!("Calendar permission not granted; Calendar \"{name}\" was not added"); },
+            },
+            FileType::Contacts => match check_permission(env, context, contacts::WRITE_CONTACTS) {
+                Ok(()) => {
+                    journal.record(Step::WriteContacts(name.to_string()))?;
+                    contacts::write_file_data_to_contacts(env, context, name)?;
+                },
+                Err(_) => { println!("WRITE_CONTACTS permission not granted; Contacts of \"{name}\" were not added"); },
+            },
+        }
+
+        Ok(())
+    })();
+
+    journal.finish(env, context, perm, result)
+}
+
+/// Ask the user which version of an event of the Calendar with **cal_name** to keep
+/// when it was changed in both the **internal** and **external** files.
+///
+/// Returns [`None`] if the user canceled the merge of the file.
+fn show_event_conflict_dialog(env: &mut JNIEnv, cal_name: &str, summary: &str) -> Result<Option<calendar::merge::Side>, Error> {
+    use calendar::merge::Side;
+    #[derive(Debug, FromObject)]
+    enum EventConflictResponse {
+        #[class(me.marti.calprovexample.jni.EventConflictResponse$Canceled)]
+        Canceled,
+        #[class(me.marti.calprovexample.jni.EventConflictResponse$KeepInternal)]
+        KeepInternal,
+        #[class(me.marti.calprovexample.jni.EventConflictResponse$KeepExternal)]
+        KeepExternal,
+    }
+
+    let user_choice = EventConflictResponse::from_object(
+        &call!(static me.marti.calprovexample.jni.DavSyncRsKt.showEventConflictDialog(String(cal_name), String(summary)) -> me.marti.calprovexample.jni.EventConflictResponse),
+    env)
+        .map_err(|err| Error::Parse(format!("Invalid response from event conflict dialog: {err}")))?;
+    println!("User selected {user_choice:?} for event \"{summary}\"");
+
+    Ok(match user_choice {
+        EventConflictResponse::Canceled => None,
+        EventConflictResponse::KeepInternal => Some(Side::Local),
+        EventConflictResponse::KeepExternal => Some(Side::Remote),
+    })
+}
+
+/// Resolve a conflict between an internal and an external file (with the same name) by keeping both,
+/// giving the **external_file** a **new_name** and creating a new Calendar with it.
+///
+/// The renamed file is copied to the **internal_dir** and written to the *Content Provider* (only if there is **perm**ission).
+/// The internal file keeps its name, so its **internal_content** is written to the **external_dir** in place of the renamed file.
+/// These steps are done in a [`Journal`] (with the *Document Tree Uri* **dir_uri** of the **external** directory),
+/// so if any of them fail the external file is renamed back and the steps that were done are undone.
+///
+/// Returns the new name of the file (with extension).
+#[allow(clippy::too_many_arguments)]
+fn rename_external_file<'local>(
+    env: &mut JNIEnv<'local>,
     context: &JObject<'local>,
-) -> Result<(), String> {
-    // Open the file to copy FROM in the internal directory
-    let mut internal_file =
-        std::fs::File::open(get_app_dir(env, context).join(SUFFIX_DIR).join(file_name))
-            .map_err(|err| format!("Error opening file in internal dir: {err}"))?;
-    // Open the file to copy TO in the sync directory (external)
-    let mut external_file =
-        ExternalDir::new(env.new_local_ref(context).unwrap(), external_dir_uri, env)
-            .ok_or("external_dir_uri does not point to a directory")?
-            .create_file_at(env, PathBuf::from(SUFFIX_DIR).join(file_name))
-            .map_err(|err| format!("Error creating external file: {err}"))?
-            .open_file(env, context, OpenOptions::write())
-            .map_err(|err| format!("Error opening newly created external file: {err}"))?;
+    perm: Option<&JObject<'local>>,
+    dir_uri: &str,
+    external_dir: &ExternalDir<'local>,
+    internal_dir: &Path,
+    external_file: &ExternalDirEntry<'local>,
+    new_name: &str,
+    internal_content: &[u8],
+) -> Result<String, Error> {
+    if new_name.trim().is_empty() || new_name.contains(ILLEGAL_FILE_CHARACTERS) {
+        return Err(Error::InvalidUri(format!("Calendar name can't be empty or contain the following characters: {ILLEGAL_FILE_CHARACTERS:?}")));
+    }
+    let old_file_name = external_file.file_name();
+    let new_file_name = format!("{new_name}.{}", FileType::Calendar.extension());
+    let new_internal_file = internal_dir.join(&new_file_name);
+    if new_internal_file.exists() {
+        return Err(Error::Conflict(format!("Can't rename External file \"{old_file_name}\": A calendar named \"{new_name}\" already exists")));
+    }
 
-    // Copy file's contents to the destination
-    std::io::copy(&mut internal_file, &mut external_file)
-        .map_err(|err| format!("Error copying to file in App dir: {err}"))?;
+    let mut journal = Journal::begin(env, context, &format!("rename {old_file_name}"))?;
+    let result = (|| -> Result<(), Error> {
+        let renamed = external_file.uri().rename(context, &new_file_name, env)
+            .map_err(|err| Error::io(format!("Failed to rename External file \"{old_file_name}\""), err))?;
+        journal.record(Step::RenameExternal {
+            dir_uri: dir_uri.to_string(),
+            path: PathBuf::from(FileType::Calendar.dir()).join(old_file_name),
+            new_name: new_file_name.clone(),
+        })?;
 
-    Ok(())
-}
\ No newline at end of file
+        let mut content = Vec::new();
+        renamed.open_file(env, context, OpenOptions::ReadOnly)
+            .and_then(|mut file| file.read_to_end(&mut content))
+            .map_err(|err| Error::io(format!("Failed to read renamed External file \"{new_file_name}\""), err))?;
+        let mut file = std::fs::File::create_new(&new_internal_file)
+            .map_err(|err| Error::io("Failed to copy renamed External file to internal directory", err))?;
+        journal.record(Step::CreateInternal(PathBuf::from(FileType::Calendar.dir()).join(&new_file_name)))?;
+        file.write_all(&content)
+            .map_err(|err| Error::io("Failed to copy renamed External file to internal directory", err))?;
+
+        let replacement = external_dir.create_file(env, old_file_name)
+            .map_err(|err| Error::io(format!("Failed to create Internal file \"{old_file_name}\" in external directory"), err))?;
+        journal.record(Step::CreateExternal { dir_uri: dir_uri.to_string(), path: PathBuf::from(FileType::Calendar.dir()).join(old_file_name) })?;
+        replacement.open_file(env, context, OpenOptions::write())
+            .and_then(|mut file| file.write_all(internal_content))
+            .map_err(|err| Error::io(format!("Failed to write Internal file \"{old_file_name}\" to external directory"), err))?;
+
+        if let Some(perm) = perm {
+            journal.record(Step::CreateCalendar(new_name.to_string()))?;
+            calendar::write_file_data_to_calendar(env, perm, new_name, &JObject::null())?;
+        }
+        Ok(())
+    })();
+
+    journal.finish(env, context, perm, result)
+        .map(|()| new_file_name)
+}
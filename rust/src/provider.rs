@@ -0,0 +1,264 @@
+//! Access to the *Content Providers* (e.g. Calendar) from Rust.
+//!
+//! All writes are done **as sync adapter** of this App's local account (same as `Uri.asSyncAdapter()` in `calendar/Calendar.kt`).
+use jni::{JNIEnv, objects::JObject};
+use ez_jni::{call, new};
+use classes::{ContentValues, Cursor};
+use crate::error::Error;
+
+pub const CALLER_IS_SYNCADAPTER: &str = "caller_is_syncadapter";
+/// The column (and query parameter) with the name of the account that owns a row.
+pub const ACCOUNT_NAME: &str = "account_name";
+/// The column (and query parameter) with the type of the account that owns a row.
+pub const ACCOUNT_TYPE: &str = "account_type";
+pub const ACCOUNT_TYPE_LOCAL: &str = "LOCAL";
+
+/// Get the name of this App's account (the `account_name` string resource).
+pub fn account_name(env: &mut JNIEnv, context: &JObject) -> String {
+    let res_id = env.get_static_field("me/marti/calprovexample/R$string", "account_name", "I")
+        .unwrap().i().unwrap();
+    call!(context.getString(int(res_id)) -> String)
+}
+
+/// Get a [`android.net.Uri`] for a *Content Provider* table that can be used to write data as a sync adapter.
+fn sync_adapter_uri<'local>(env: &mut JNIEnv<'local>, context: &JObject, table_uri: &str) -> JObject<'local> {
+    let account_name = account_name(env, context);
+    let account_name = call!(static android.net.Uri.encode(String(account_name)) -> String);
+    let uri = format!(
+        "{table_uri}?{CALLER_IS_SYNCADAPTER}=true&{}={account_name}&{}={ACCOUNT_TYPE_LOCAL}",
+        ACCOUNT_NAME, ACCOUNT_TYPE
+    );
+    call!(static android.net.Uri.parse(String(uri)) -> android.net.Uri)
+}
+
+/// Query the rows of a table of the Content Provider with uri **table_uri** that match the **selection**,
+/// reading the columns in **projection**.
+///
+/// Similar to `Cursor.loadRowData()` in `calendar/Projections.kt`, `NULL` values are omitted from the [`Row`]s.
+pub fn query(
+    env: &mut JNIEnv,
+    context: &JObject,
+    table_uri: &str,
+    projection: &[&'static str],
+    selection: &str,
+    selection_args: &[&str],
+) -> Result<Vec<Row>, Error> {
+    let cursor = Cursor::query_str(env, context, table_uri, projection, selection, selection_args, "")
+        .map_err(|err| Error::Provider(format!("Error querying {table_uri:?}: {err}")))?;
+
+    let mut rows = Vec::with_capacity(cursor.row_count(env));
+    while cursor.next(env) {
+        let mut row = Row::new();
+        for (i, &column) in projection.iter().enumerate() {
+            let i = i as u32;
+            match cursor.get_type(env, i) {
+                Cursor::FIELD_TYPE_INTEGER => row.put(column, cursor.get_long(env, i)),
+                Cursor::FIELD_TYPE_FLOAT | Cursor::FIELD_TYPE_STRING => row.put(column, cursor.get_string(env, i)),
+                // Blobs are not used by any of the columns this crate reads
+                _ => {}
+            }
+        }
+        rows.push(row);
+    }
+    cursor.close(env);
+
+    Ok(rows)
+}
+
+/// Insert a **row** to a table of the Content Provider with uri **table_uri**.
+///
+/// Returns the **ID** of the new row.
+pub fn insert(env: &mut JNIEnv, context: &JObject, table_uri: &str, row: &Row) -> Result<i64, Error> {
+    let uri = sync_adapter_uri(env, context, table_uri);
+    let values = content_values(env, row);
+
+    let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+    let new_uri = call!(content_resolver.insert(
+        android.net.Uri(uri),
+        android.content.ContentValues(values.as_ref())
+    ) -> Result<Option<android.net.Uri>, String>)
+        .map_err(|err| Error::Provider(format!("Error inserting row into {table_uri:?}: {err}")))?
+        .ok_or_else(|| Error::Provider(format!("Failed to insert row into {table_uri:?}")))?;
+
+    Ok(call!(static android.content.ContentUris.parseId(android.net.Uri(new_uri)) -> long))
+}
+
+/// Set the values of the columns in **row** to the row with **id** of a table of the Content Provider with uri **table_uri**.
+///
+/// Returns whether the row was updated.
+pub fn update(env: &mut JNIEnv, context: &JObject, table_uri: &str, id: i64, row: &Row) -> Result<bool, Error> {
+    let uri = sync_adapter_uri(env, context, table_uri);
+    let values = content_values(env, row);
+    let id = id.to_string();
+
+    let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+    let count = call!(content_resolver.update(
+        android.net.Uri(uri),
+        android.content.ContentValues(values.as_ref()),
+        String("(_id = ?)"),
+        [String](&[id.as_str()])
+    ) -> Result<int, String>)
+        .map_err(|err| Error::Provider(format!("Error updating row {id} of {table_uri:?}: {err}")))?;
+
+    Ok(count > 0)
+}
+
+/// Delete the rows of a table of the Content Provider with uri **table_uri** that match the **selection**.
+///
+/// Returns the number of rows deleted.
+pub fn delete(
+    env: &mut JNIEnv,
+    context: &JObject,
+    table_uri: &str,
+    selection: &str,
+    selection_args: &[&str],
+) -> Result<usize, Error> {
+    let uri = sync_adapter_uri(env, context, table_uri);
+    let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+    let count = call!(content_resolver.delete(
+        android.net.Uri(uri),
+        String(selection),
+        [String](selection_args)
+    ) -> Result<int, String>)
+        .map_err(|err| Error::Provider(format!("Error deleting rows of {table_uri:?}: {err}")))?;
+
+    Ok(count as usize)
+}
+
+/// A write to a table of the Content Provider, applied together with others by [`apply_batch()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Insert a **row**, and set each column of **back_references** to the **ID** of the row
+    /// inserted by the operation at that index of the batch.
+    Insert {
+        table_uri: &'static str,
+        row: Row,
+        back_references: Vec<(&'static str, usize)>,
+    },
+    /// Delete the rows that match the **selection**.
+    Delete {
+        table_uri: &'static str,
+        selection: String,
+        selection_args: Vec<String>,
+    },
+}
+
+/// Apply all the **operations** in a single `applyBatch()` to the Content Provider with **authority**.
+///
+/// The Calendar provider applies a batch in a single transaction, so either all the operations are applied or none of them is.
+pub fn apply_batch(env: &mut JNIEnv, context: &JObject, authority: &str, operations: &[Operation]) -> Result<(), Error> {
+    let list = new!(java.util.ArrayList(int(operations.len() as i32)));
+    for operation in operations {
+        let builder = match operation {
+            Operation::Insert { table_uri, row, back_references } => {
+                let uri = sync_adapter_uri(env, context, table_uri);
+                let values = content_values(env, row);
+                let builder = call!(static android.content.ContentProviderOperation.newInsert(android.net.Uri(uri))
+                    -> android.content.ContentProviderOperation$Builder);
+                call!(builder.withValues(android.content.ContentValues(values.as_ref())) -> android.content.ContentProviderOperation$Builder);
+                for &(column, index) in back_references {
+                    call!(builder.withValueBackReference(String(column), int(index as i32)) -> android.content.ContentProviderOperation$Builder);
+                }
+                builder
+            },
+            Operation::Delete { table_uri, selection, selection_args } => {
+                let uri = sync_adapter_uri(env, context, table_uri);
+                let selection_args = selection_args.iter().map(String::as_str).collect::<Vec<_>>();
+                let builder = call!(static android.content.ContentProviderOperation.newDelete(android.net.Uri(uri))
+                    -> android.content.ContentProviderOperation$Builder);
+                call!(builder.withSelection(String(selection.as_str()), [String](selection_args.as_slice())) -> android.content.ContentProviderOperation$Builder);
+                builder
+            },
+        };
+        let operation = call!(builder.build() -> android.content.ContentProviderOperation);
+        call!(list.add(java.lang.Object(operation)) -> boolean);
+    }
+
+    let content_resolver = call!(context.getContentResolver() -> android.content.ContentResolver);
+    call!(content_resolver.applyBatch(String(authority), java.util.ArrayList(list)) -> Result<[android.content.ContentProviderResult], String>)
+        .map_err(|err| Error::Provider(format!("Error applying {} operations to {authority:?}: {err}", operations.len())))?;
+
+    Ok(())
+}
+
+fn content_values<'local>(env: &mut JNIEnv<'local>, row: &Row) -> ContentValues<'local> {
+    let values = ContentValues::new(env);
+    for (column, value) in &row.0 {
+        match value {
+            Value::Null => values.put_null(env, column),
+            Value::Long(value) => values.put_long(env, column, *value),
+            Value::Text(value) => values.put_string(env, column, value),
+        }
+    }
+    values
+}
+
+/// The value of a *column* in a row of the Content Provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    Long(i64),
+    Text(String),
+}
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Long(value)
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Long(value as i64)
+    }
+}
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+
+/// The data of a row that will be inserted into (or was read from) the Content Provider.
+///
+/// Equivalent to `android.content.ContentValues`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Row(pub Vec<(&'static str, Value)>);
+impl Row {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Set the **value** of a **column**, replacing any previous value.
+    pub fn put(&mut self, column: &'static str, value: impl Into<Value>) {
+        let value = value.into();
+        match self.0.iter_mut().find(|(col, _)| *col == column) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((column, value)),
+        }
+    }
+
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.0.iter().find(|(col, _)| *col == column).map(|(_, value)| value)
+    }
+    pub fn get_long(&self, column: &str) -> Option<i64> {
+        match self.get(column)? {
+            Value::Long(value) => Some(*value),
+            Value::Text(value) => value.parse().ok(),
+            Value::Null => None,
+        }
+    }
+    pub fn get_text(&self, column: &str) -> Option<&str> {
+        match self.get(column)? {
+            Value::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
@@ -1,9 +1,58 @@
 use jni::{JNIEnv, objects::JObject};
 use ez_jni::call;
-use std::path::PathBuf;
+use std::{io, path::{Path, PathBuf}};
+use classes::fs::{DocUri, ExternalDir};
+use crate::error::Error;
+
+/// The directory (in the *app's directory*) where the *base snapshots* of the synced files are stored.
+const BASE_DIR: &str = "base";
 
 /// Returns the directory owned by this App (where it's files are stored) in the Android System.
 pub fn get_app_dir(env: &mut JNIEnv, context: &JObject) -> PathBuf {
     let app_dir = call!(context.getFilesDir() -> java.io.File);
     PathBuf::from(call!(app_dir.getPath() -> String))
 }
+
+/// The path of the *base snapshot* of a file, which is the content the file had the last time
+/// the **internal** and **external** directories were in sync.
+///
+/// **dir** is one of [`DIRECTORIES`][crate::DIRECTORIES]. E.g.: `"<app_dir>/base/calendars/<file_name>"`.
+pub fn base_snapshot_path(app_dir: &Path, dir: &str, file_name: &str) -> PathBuf {
+    app_dir.join(BASE_DIR).join(dir).join(file_name)
+}
+
+/// Read the *base snapshot* of a file. Returns [`None`] if the file was never synced.
+pub fn read_base_snapshot(app_dir: &Path, dir: &str, file_name: &str) -> Option<String> {
+    std::fs::read_to_string(base_snapshot_path(app_dir, dir, file_name)).ok()
+}
+
+/// Store the **content** of a file as its *base snapshot*, after it was written to both the **internal** and **external** directories.
+pub fn write_base_snapshot(app_dir: &Path, dir: &str, file_name: &str, content: &[u8]) -> io::Result<()> {
+    let path = base_snapshot_path(app_dir, dir, file_name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)
+}
+
+/// Open the directory in Shared Storage that the *Document Tree* **uri** points to (e.g. the **external** directory).
+pub fn open_external_dir<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject<'local>,
+    uri: JObject<'local>,
+) -> Result<ExternalDir<'local>, Error> {
+    let doc_uri = DocUri::from_tree_uri(env, uri).map_err(Error::InvalidUri)?;
+    ExternalDir::new(env.new_local_ref(context)?, doc_uri, env)
+        .ok_or_else(|| Error::InvalidUri("Uri of external directory does not point to a directory".to_string()))
+}
+
+/// Check that the user granted the App an Android **permission** (e.g. `"android.permission.WRITE_CONTACTS"`).
+pub fn check_permission(env: &mut JNIEnv, context: &JObject, permission: &str) -> Result<(), Error> {
+    // PackageManager.PERMISSION_GRANTED
+    const PERMISSION_GRANTED: i32 = 0;
+    if call!(context.checkSelfPermission(String(permission)) -> int) == PERMISSION_GRANTED {
+        Ok(())
+    } else {
+        Err(Error::Permission(format!("Permission {permission} was not granted")))
+    }
+}